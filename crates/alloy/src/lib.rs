@@ -83,7 +83,7 @@ pub use alloy_node_bindings as node_bindings;
 /// Interface with an Ethereum blockchain.
 ///
 /// See [`alloy_provider`] for more details.
-#[cfg(feature = "providers")]
+#[cfg(any(feature = "providers", feature = "icp-minimal"))]
 pub mod providers {
     #[doc(inline)]
     pub use alloy_provider::*;
@@ -110,7 +110,7 @@ pub mod pubsub {
 /// Ethereum JSON-RPC client and types.
 #[cfg(feature = "rpc")]
 pub mod rpc {
-    #[cfg(feature = "rpc-client")]
+    #[cfg(any(feature = "rpc-client", feature = "icp-minimal"))]
     #[doc(inline)]
     pub use alloy_rpc_client as client;
 
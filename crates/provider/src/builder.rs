@@ -345,7 +345,8 @@ impl<L, F, N> ProviderBuilder<L, F, N> {
         self.on_client(client)
     }
 
-    /// Build this provider using an [`IcpTransport`] with the given [`IcpConfig`].
+    /// Build this provider using an [`IcpTransport`] with the given [`IcpConfig`], e.g. a chain
+    /// preset such as `IcpConfig::sepolia()`.
     ///
     /// [`IcpTransport`]: alloy_transport_icp::IcpTransport
     /// [`IcpConfig`]: alloy_transport_icp::IcpConfig
@@ -360,6 +361,44 @@ impl<L, F, N> ProviderBuilder<L, F, N> {
         self.on_client(client)
     }
 
+    /// Build this provider using an [`IcpTransport`] with the given [`IcpProviderConfig`].
+    ///
+    /// This configures the transport as well as the poll interval of the client, see
+    /// [`IcpProviderConfig::icp_config`]. The chain ID of the config is not applied, since
+    /// fillers are part of the provider type: add it with [`ProviderBuilder::with_chain_id`] if
+    /// needed.
+    ///
+    /// [`IcpTransport`]: alloy_transport_icp::IcpTransport
+    /// [`IcpProviderConfig`]: alloy_transport_icp::IcpProviderConfig
+    /// [`IcpProviderConfig::icp_config`]: alloy_transport_icp::IcpProviderConfig::icp_config
+    #[cfg(any(test, feature = "icp"))]
+    pub fn on_icp_config(self, config: alloy_transport_icp::IcpProviderConfig) -> F::Provider
+    where
+        L: ProviderLayer<crate::IcpProvider<N>, alloy_transport_icp::IcpTransport, N>,
+        F: TxFiller<N> + ProviderLayer<L::Provider, alloy_transport_icp::IcpTransport, N>,
+        N: Network,
+    {
+        self.on_icp(config.icp_config())
+    }
+
+    /// Build this provider using an [`IcpHttpTransport`] making HTTPS outcalls to the given URL
+    /// directly, instead of through the EVM RPC canister.
+    ///
+    /// To add headers or set the cycles of the outcalls, build the transport and pass it to
+    /// [`ProviderBuilder::on_client`] instead.
+    ///
+    /// [`IcpHttpTransport`]: alloy_transport_icp::IcpHttpTransport
+    #[cfg(feature = "icp-http")]
+    pub fn on_icp_http(self, url: impl Into<String>) -> F::Provider
+    where
+        L: ProviderLayer<crate::IcpHttpProvider<N>, alloy_transport_icp::IcpHttpTransport, N>,
+        F: TxFiller<N> + ProviderLayer<L::Provider, alloy_transport_icp::IcpHttpTransport, N>,
+        N: Network,
+    {
+        let client = ClientBuilder::default().icp_http(url);
+        self.on_client(client)
+    }
+
     /// Build this provider with an Hyper HTTP transport.
     #[cfg(feature = "hyper")]
     pub fn on_hyper_http(self, url: url::Url) -> F::Provider
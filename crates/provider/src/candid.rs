@@ -0,0 +1,152 @@
+//! [`CandidType`] wrappers for alloy primitives.
+//!
+//! They exist for the same reason as the [`Storable` wrappers](crate::stable): neither the trait
+//! nor the primitive types are defined in this crate. The RPC types implement [`CandidType`]
+//! themselves, with the `candid` feature of `alloy-rpc-types-eth`.
+//!
+//! The encoding follows the EVM RPC canister: addresses, hashes and byte strings are hex `text`,
+//! and numbers are `nat`.
+//!
+//! ```ignore
+//! #[ic_cdk::query]
+//! fn balance_of(owner: CandidAddress) -> CandidU256 {
+//!     BALANCES.with_borrow(|balances| balances.get(&owner.0)).unwrap_or_default().into()
+//! }
+//! ```
+
+use alloy_primitives::{hex, Address, Bytes, B256, U256};
+use candid::{
+    types::{Serializer, Type, TypeInner},
+    CandidType, Nat,
+};
+use serde::{de::Error as _, Deserialize, Deserializer};
+use std::{fmt, ops::Deref, str::FromStr};
+
+macro_rules! candid_wrapper {
+    ($(#[$attr:meta])* $name:ident($inner:ty)) => {
+        $(#[$attr])*
+        pub struct $name(pub $inner);
+
+        impl From<$inner> for $name {
+            fn from(value: $inner) -> Self {
+                Self(value)
+            }
+        }
+
+        impl From<$name> for $inner {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl Deref for $name {
+            type Target = $inner;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+    };
+}
+
+/// Implements [`CandidType`] and [`Deserialize`] for a wrapper encoded as hex `text`.
+macro_rules! candid_hex {
+    ($name:ident) => {
+        impl CandidType for $name {
+            fn _ty() -> Type {
+                TypeInner::Text.into()
+            }
+
+            fn idl_serialize<S: Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+                serializer.serialize_text(&hex::encode_prefixed(&self.0))
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let text = String::deserialize(deserializer)?;
+                FromStr::from_str(&text).map(Self).map_err(D::Error::custom)
+            }
+        }
+    };
+}
+
+candid_wrapper!(
+    /// A [`CandidType`] [`Address`], encoded as hex `text`.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    CandidAddress(Address)
+);
+candid_hex!(CandidAddress);
+
+candid_wrapper!(
+    /// A [`CandidType`] [`B256`], e.g. a transaction or block hash, encoded as hex `text`.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    CandidB256(B256)
+);
+candid_hex!(CandidB256);
+
+candid_wrapper!(
+    /// A [`CandidType`] [`Bytes`], e.g. calldata or a signed transaction, encoded as hex `text`.
+    #[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    CandidBytes(Bytes)
+);
+candid_hex!(CandidBytes);
+
+candid_wrapper!(
+    /// A [`CandidType`] [`U256`], encoded as a `nat`.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    CandidU256(U256)
+);
+
+impl CandidType for CandidU256 {
+    fn _ty() -> Type {
+        TypeInner::Nat.into()
+    }
+
+    fn idl_serialize<S: Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+        to_nat(self.0).idl_serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CandidU256 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        from_nat(&Nat::deserialize(deserializer)?).map(Self).map_err(D::Error::custom)
+    }
+}
+
+fn to_nat(value: impl fmt::Display) -> Nat {
+    Nat::parse(value.to_string().as_bytes()).expect("decimal digits are a valid nat")
+}
+
+fn from_nat<T: TryFrom<U256>>(nat: &Nat) -> Result<T, String> {
+    let bytes = nat.0.to_bytes_be();
+    if bytes.len() > 32 {
+        return Err(format!("{nat} does not fit in 256 bits"));
+    }
+    T::try_from(U256::from_be_slice(&bytes)).map_err(|_| format!("{nat} is out of range"))
+}
+
+pub(crate) fn from_opt_nat<T: TryFrom<U256>>(nat: &Option<Nat>) -> Result<Option<T>, String> {
+    nat.as_ref().map(from_nat).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{address, b256, bytes};
+
+    fn roundtrip<T: CandidType + for<'de> Deserialize<'de> + PartialEq + fmt::Debug>(value: T) {
+        let bytes = candid::encode_one(&value).unwrap();
+        assert_eq!(candid::decode_one::<T>(&bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn primitives_roundtrip() {
+        roundtrip(CandidAddress(address!("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")));
+        roundtrip(CandidB256(b256!(
+            "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+        )));
+        roundtrip(CandidU256(U256::MAX));
+        roundtrip(CandidBytes(bytes!("a9059cbb")));
+    }
+}
@@ -0,0 +1,325 @@
+//! Helpers for the [ckETH and ckERC20](https://internetcomputer.org/docs/current/developer-docs/multi-chain/chain-fusion/ethereum/using-eth/cketh)
+//! flows, bridging ETH and ERC-20 tokens to their chain-key twins on the Internet Computer.
+//!
+//! Tokens are deposited by calling the deposit helper contract of the ckETH minter with the
+//! principal, and optionally the subaccount, of the ICRC account to mint to. The minter scrapes
+//! the logs of the helper contract and mints the tokens once it has scraped the block of the
+//! deposit.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let helper = CkEthMinter::mainnet().minter_info().await?.deposit_helper_contract()?;
+//! let tx = eth_deposit::<Ethereum>(helper, &ic_cdk::caller(), B256::ZERO, amount);
+//! let receipt = provider.send_transaction(tx).await?.get_receipt().await?;
+//! let block_number = receipt.block_number.expect("mined receipts have a block number");
+//! CkEthMinter::mainnet()
+//!     .wait_for_deposit(block_number, Duration::from_secs(60), Duration::from_secs(3600))
+//!     .await?;
+//! ```
+
+use alloy_network::{Network, TransactionBuilder};
+use alloy_primitives::{Address, Bytes, B256, U256};
+use alloy_sol_types::{sol, SolCall};
+use alloy_transport_icp::sleep;
+use candid::{CandidType, Nat, Principal};
+use ic_cdk::api::call::RejectionCode;
+use serde::Deserialize;
+use std::time::Duration;
+
+sol! {
+    function depositEth(bytes32 principal, bytes32 subaccount) external payable;
+    function depositErc20(address erc20Address, uint256 amount, bytes32 principal, bytes32 subaccount) external;
+    function approve(address spender, uint256 amount) external returns (bool);
+}
+
+/// The ckETH minter canister on the Internet Computer mainnet, minting against Ethereum mainnet.
+pub const MINTER_ID: Principal = Principal::from_slice(&[0, 0, 0, 0, 2, 48, 0, 156, 1, 1]); // sv3dd-oaaaa-aaaar-qacoa-cai
+
+/// The ckSepoliaETH minter canister on the Internet Computer mainnet, minting against Sepolia.
+pub const SEPOLIA_MINTER_ID: Principal = Principal::from_slice(&[0, 0, 0, 0, 2, 48, 0, 63, 1, 1]); // jzenf-aiaaa-aaaar-qaa7q-cai
+
+/// Errors which may occur when querying the ckETH minter.
+#[derive(Debug, thiserror::Error)]
+pub enum CkEthError {
+    /// The call to the minter failed.
+    #[error("minter call failed: {code:?}: {message}")]
+    Call {
+        /// The rejection code of the call.
+        code: RejectionCode,
+        /// The rejection message of the call.
+        message: String,
+    },
+
+    /// The minter did not report the given field.
+    #[error("minter info is missing {0}")]
+    MissingInfo(&'static str),
+
+    /// The minter reported an invalid value for the given field.
+    #[error("minter info has an invalid {0}")]
+    InvalidInfo(&'static str),
+
+    /// The minter did not scrape the block of the deposit before the timeout.
+    #[error("deposit in block {block_number} not minted after {timeout:?}")]
+    Timeout {
+        /// The block of the deposit.
+        block_number: u64,
+        /// The time waited for the deposit.
+        timeout: Duration,
+    },
+}
+
+/// Encode a principal as the `bytes32` expected by the deposit helper contract: the length of
+/// the principal, followed by its bytes, right-padded with zeros.
+pub fn principal_to_bytes32(principal: &Principal) -> B256 {
+    let bytes = principal.as_slice();
+    let mut encoded = B256::ZERO;
+    encoded[0] = bytes.len() as u8;
+    encoded[1..=bytes.len()].copy_from_slice(bytes);
+    encoded
+}
+
+/// Returns the calldata of a deposit of ETH to the account of the principal and subaccount.
+///
+/// The subaccount is [`B256::ZERO`] for the default account of the principal.
+pub fn eth_deposit_calldata(principal: &Principal, subaccount: B256) -> Bytes {
+    depositEthCall { principal: principal_to_bytes32(principal), subaccount }.abi_encode().into()
+}
+
+/// Returns the calldata of a deposit of an amount of an ERC-20 token to the account of the
+/// principal and subaccount.
+///
+/// The subaccount is [`B256::ZERO`] for the default account of the principal.
+pub fn erc20_deposit_calldata(
+    token: Address,
+    amount: U256,
+    principal: &Principal,
+    subaccount: B256,
+) -> Bytes {
+    depositErc20Call {
+        erc20Address: token,
+        amount,
+        principal: principal_to_bytes32(principal),
+        subaccount,
+    }
+    .abi_encode()
+    .into()
+}
+
+/// Build the transaction depositing an amount of ETH, in wei, with the helper contract.
+pub fn eth_deposit<N: Network>(
+    helper: Address,
+    principal: &Principal,
+    subaccount: B256,
+    amount: U256,
+) -> N::TransactionRequest {
+    N::TransactionRequest::default()
+        .with_to(helper)
+        .with_value(amount)
+        .with_input(eth_deposit_calldata(principal, subaccount))
+}
+
+/// Build the transaction approving the helper contract to transfer an amount of the ERC-20
+/// token, to send before the [`erc20_deposit`] transaction.
+pub fn erc20_approval<N: Network>(
+    token: Address,
+    helper: Address,
+    amount: U256,
+) -> N::TransactionRequest {
+    N::TransactionRequest::default()
+        .with_to(token)
+        .with_input(approveCall { spender: helper, amount }.abi_encode())
+}
+
+/// Build the transaction depositing an amount of the ERC-20 token with the helper contract.
+///
+/// The helper contract must first be approved to transfer the amount, see [`erc20_approval`].
+pub fn erc20_deposit<N: Network>(
+    helper: Address,
+    token: Address,
+    amount: U256,
+    principal: &Principal,
+    subaccount: B256,
+) -> N::TransactionRequest {
+    N::TransactionRequest::default()
+        .with_to(helper)
+        .with_input(erc20_deposit_calldata(token, amount, principal, subaccount))
+}
+
+/// The information reported by the `get_minter_info` method of the ckETH minter.
+///
+/// Only the fields used by the deposit flow are decoded, the others are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize)]
+pub struct MinterInfo {
+    /// The address of the helper contract for deposits of ETH without subaccounts.
+    pub eth_helper_contract_address: Option<String>,
+    /// The address of the helper contract for deposits of ERC-20 tokens without subaccounts.
+    pub erc20_helper_contract_address: Option<String>,
+    /// The address of the helper contract for deposits of ETH and ERC-20 tokens with
+    /// subaccounts.
+    pub deposit_with_subaccount_helper_contract_address: Option<String>,
+    /// The last block whose logs of the ETH helper contract were scraped.
+    pub last_eth_scraped_block_number: Option<Nat>,
+    /// The last block whose logs of the ERC-20 helper contract were scraped.
+    pub last_erc20_scraped_block_number: Option<Nat>,
+    /// The last block whose logs of the helper contract with subaccounts were scraped.
+    pub last_deposit_with_subaccount_scraped_block_number: Option<Nat>,
+    /// The last finalized block observed by the minter.
+    pub last_observed_block_number: Option<Nat>,
+    /// The minimum amount of a withdrawal of ETH, in wei.
+    pub minimum_withdrawal_amount: Option<Nat>,
+}
+
+impl MinterInfo {
+    /// Returns the address of the helper contract for deposits with subaccounts, the contract
+    /// used by [`eth_deposit`] and [`erc20_deposit`].
+    pub fn deposit_helper_contract(&self) -> Result<Address, CkEthError> {
+        const FIELD: &str = "deposit_with_subaccount_helper_contract_address";
+        self.deposit_with_subaccount_helper_contract_address
+            .as_deref()
+            .ok_or(CkEthError::MissingInfo(FIELD))?
+            .parse()
+            .map_err(|_| CkEthError::InvalidInfo(FIELD))
+    }
+
+    /// Returns the last block whose deposits to the helper contract with subaccounts were
+    /// minted.
+    pub fn deposits_scraped_block_number(&self) -> Result<u64, CkEthError> {
+        const FIELD: &str = "last_deposit_with_subaccount_scraped_block_number";
+        let block_number = self
+            .last_deposit_with_subaccount_scraped_block_number
+            .as_ref()
+            .ok_or(CkEthError::MissingInfo(FIELD))?;
+        u64::try_from(&block_number.0).map_err(|_| CkEthError::InvalidInfo(FIELD))
+    }
+}
+
+/// A ckETH minter canister.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CkEthMinter {
+    id: Principal,
+}
+
+impl CkEthMinter {
+    /// Create a client of the minter with the given canister ID.
+    pub const fn new(id: Principal) -> Self {
+        Self { id }
+    }
+
+    /// The ckETH minter, see [`MINTER_ID`].
+    pub const fn mainnet() -> Self {
+        Self::new(MINTER_ID)
+    }
+
+    /// The ckSepoliaETH minter, see [`SEPOLIA_MINTER_ID`].
+    pub const fn sepolia() -> Self {
+        Self::new(SEPOLIA_MINTER_ID)
+    }
+
+    /// Returns the canister ID of the minter.
+    pub const fn id(&self) -> Principal {
+        self.id
+    }
+
+    /// Query the information of the minter.
+    pub async fn minter_info(&self) -> Result<MinterInfo, CkEthError> {
+        let (info,): (MinterInfo,) = ic_cdk::call(self.id, "get_minter_info", ())
+            .await
+            .map_err(|(code, message)| CkEthError::Call { code, message })?;
+        Ok(info)
+    }
+
+    /// Wait until the minter has minted the deposits made with the helper contract in the given
+    /// block, polling its information every interval.
+    ///
+    /// The minter only scrapes finalized blocks, so a deposit is usually minted around twenty
+    /// minutes after its transaction is mined on Ethereum mainnet.
+    pub async fn wait_for_deposit(
+        &self,
+        block_number: u64,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<(), CkEthError> {
+        let deadline = ic_cdk::api::time().saturating_add(timeout.as_nanos() as u64);
+        loop {
+            if self.minter_info().await?.deposits_scraped_block_number()? >= block_number {
+                return Ok(());
+            }
+            if ic_cdk::api::time() >= deadline {
+                return Err(CkEthError::Timeout { block_number, timeout });
+            }
+            sleep(poll_interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_network::Ethereum;
+    use alloy_primitives::address;
+
+    #[test]
+    fn encodes_principals() {
+        let principal = Principal::from_text("2chl6-4hpzw-vqaaa-aaaaa-c").unwrap();
+        let encoded = principal_to_bytes32(&principal);
+        assert_eq!(encoded[0] as usize, principal.as_slice().len());
+        assert_eq!(&encoded[1..=principal.as_slice().len()], principal.as_slice());
+        assert!(encoded[principal.as_slice().len() + 1..].iter().all(|byte| *byte == 0));
+
+        let principal = Principal::from_slice(&[0xab; 29]);
+        let encoded = principal_to_bytes32(&principal);
+        assert_eq!(encoded[0], 29);
+        assert_eq!(encoded[1..30], [0xab; 29]);
+        assert_eq!(encoded[30..], [0, 0]);
+    }
+
+    #[test]
+    fn builds_deposits() {
+        let helper = address!("18901044688D3756C35Ed2b36D93e6a5B8e00E68");
+        let token = address!("dAC17F958D2ee523a2206206994597C13D831ec7");
+        let principal = Principal::from_slice(&[1, 2, 3]);
+        let amount = U256::from(1_000_000u64);
+
+        let tx = eth_deposit::<Ethereum>(helper, &principal, B256::ZERO, amount);
+        assert_eq!(tx.to, Some(helper.into()));
+        assert_eq!(tx.value, Some(amount));
+        let call = depositEthCall::abi_decode(tx.input.input().unwrap(), true).unwrap();
+        assert_eq!(call.principal, principal_to_bytes32(&principal));
+
+        let approval = erc20_approval::<Ethereum>(token, helper, amount);
+        assert_eq!(approval.to, Some(token.into()));
+        let tx = erc20_deposit::<Ethereum>(helper, token, amount, &principal, B256::ZERO);
+        let call = depositErc20Call::abi_decode(tx.input.input().unwrap(), true).unwrap();
+        assert_eq!(call.erc20Address, token);
+        assert_eq!(call.amount, amount);
+    }
+
+    #[test]
+    fn decodes_minter_info_subset() {
+        #[derive(CandidType)]
+        struct FullInfo {
+            smart_contract_address: Option<String>,
+            deposit_with_subaccount_helper_contract_address: Option<String>,
+            last_deposit_with_subaccount_scraped_block_number: Option<Nat>,
+        }
+        let bytes = candid::encode_one(FullInfo {
+            smart_contract_address: Some("0x0".into()),
+            deposit_with_subaccount_helper_contract_address: Some(
+                "0x18901044688D3756C35Ed2b36D93e6a5B8e00E68".into(),
+            ),
+            last_deposit_with_subaccount_scraped_block_number: Some(Nat::from(21_000_000u64)),
+        })
+        .unwrap();
+        let info: MinterInfo = candid::decode_one(&bytes).unwrap();
+        assert_eq!(
+            info.deposit_helper_contract().unwrap(),
+            address!("18901044688D3756C35Ed2b36D93e6a5B8e00E68")
+        );
+        assert_eq!(info.deposits_scraped_block_number().unwrap(), 21_000_000);
+        assert!(matches!(
+            MinterInfo::default().deposits_scraped_block_number(),
+            Err(CkEthError::MissingInfo(_))
+        ));
+    }
+}
@@ -1,15 +1,19 @@
 use crate::Provider;
 use alloy_network::Network;
-use alloy_primitives::{Address, Bytes};
+use alloy_primitives::{Address, Bytes, ChainId, B256};
 use alloy_rpc_types_eth::erc4337::{
     SendUserOperation, SendUserOperationResponse, UserOperationGasEstimation, UserOperationReceipt,
 };
+use alloy_signer::Signer;
 use alloy_transport::{Transport, TransportResult};
 
 /// ERC-4337 Account Abstraction API
 ///
 /// This module provides support for the `eth_sendUserOperation` RPC method
 /// as defined in ERC-4337.
+///
+/// Bundler methods are not supported by the providers of the EVM RPC canister, so canisters
+/// using the ICP transport must point it at a bundler with a custom `RpcService`.
 #[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
 #[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
 pub trait Erc4337Api<N, T>: Send + Sync {
@@ -94,3 +98,58 @@ where
         }
     }
 }
+
+/// Sign the user operation for the given entry point and chain, setting its signature, and
+/// returns its hash.
+///
+/// The hash of the user operation is signed as an EIP-191 message, as expected by the
+/// `validateUserOp` method of most smart accounts, such as the reference `SimpleAccount`. The
+/// signer is the owner of the smart account, e.g. an `IcpSigner` signing with the threshold ECDSA
+/// key of the canister.
+pub async fn sign_user_operation<S>(
+    signer: &S,
+    user_op: &mut SendUserOperation,
+    entry_point: Address,
+    chain_id: ChainId,
+) -> alloy_signer::Result<B256>
+where
+    S: Signer + Send + Sync + ?Sized,
+{
+    let hash = user_op.hash(entry_point, chain_id);
+    let signature = signer.sign_message(hash.as_slice()).await?;
+    user_op.set_signature(signature.as_bytes().into());
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::U256;
+    use alloy_rpc_types_eth::erc4337::UserOperation;
+    use alloy_signer_local::PrivateKeySigner;
+
+    #[tokio::test]
+    async fn signs_user_operation() {
+        let signer = PrivateKeySigner::random();
+        let mut user_op = SendUserOperation::EntryPointV06(UserOperation {
+            sender: Address::with_last_byte(1),
+            nonce: U256::ZERO,
+            init_code: Bytes::new(),
+            call_data: Bytes::new(),
+            call_gas_limit: U256::from(100_000),
+            verification_gas_limit: U256::from(100_000),
+            pre_verification_gas: U256::from(50_000),
+            max_fee_per_gas: U256::from(1),
+            max_priority_fee_per_gas: U256::from(1),
+            paymaster_and_data: Bytes::new(),
+            signature: Bytes::new(),
+        });
+        let entry_point = Address::with_last_byte(0x77);
+        let hash = sign_user_operation(&signer, &mut user_op, entry_point, 1).await.unwrap();
+        assert_eq!(hash, user_op.hash(entry_point, 1));
+
+        let signature =
+            alloy_primitives::PrimitiveSignature::try_from(&user_op.signature()[..]).unwrap();
+        assert_eq!(signature.recover_address_from_msg(hash).unwrap(), signer.address());
+    }
+}
@@ -0,0 +1,198 @@
+use crate::Provider;
+use alloy_network::Network;
+use alloy_primitives::{hex, keccak256};
+use alloy_rpc_types_mev::{EthBundleHash, EthSendBundle, SendBundleRequest, SendBundleResponse};
+use alloy_signer::Signer;
+use alloy_transport::{Transport, TransportResult};
+
+/// The HTTP header authenticating requests to Flashbots relays, see [`flashbots_signature`].
+pub const FLASHBOTS_SIGNATURE_HEADER: &str = "X-Flashbots-Signature";
+
+/// MEV API, to submit private bundles to relays and matchmakers such as Flashbots.
+///
+/// Bundles are sent to the relay the transport points at, without being broadcast to the public
+/// mempool. Most relays require requests to be authenticated with a signature of the request
+/// body, see [`IcpMevApi`] to send signed requests from a canister.
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+pub trait MevApi<N, T>: Send + Sync {
+    /// Sends a bundle of signed transactions to the relay with `eth_sendBundle`.
+    async fn send_bundle(&self, bundle: EthSendBundle) -> TransportResult<EthBundleHash>;
+
+    /// Sends a bundle to the matchmaker with `mev_sendBundle`.
+    async fn send_mev_bundle(
+        &self,
+        bundle: SendBundleRequest,
+    ) -> TransportResult<SendBundleResponse>;
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+impl<N, T, P> MevApi<N, T> for P
+where
+    N: Network,
+    T: Transport + Clone,
+    P: Provider<T, N>,
+{
+    async fn send_bundle(&self, bundle: EthSendBundle) -> TransportResult<EthBundleHash> {
+        self.client().request("eth_sendBundle", (bundle,)).await
+    }
+
+    async fn send_mev_bundle(
+        &self,
+        bundle: SendBundleRequest,
+    ) -> TransportResult<SendBundleResponse> {
+        self.client().request("mev_sendBundle", (bundle,)).await
+    }
+}
+
+/// Returns the value of the [`FLASHBOTS_SIGNATURE_HEADER`] of a request with the given body,
+/// `<address>:<signature>`, where the signature is the EIP-191 signature of the hex encoded
+/// hash of the body.
+///
+/// The signer only identifies the searcher to the relay, building reputation, and does not need
+/// to hold any funds.
+pub async fn flashbots_signature<S>(signer: &S, body: &[u8]) -> alloy_signer::Result<String>
+where
+    S: Signer + Send + Sync + ?Sized,
+{
+    let message = keccak256(body).to_string();
+    let signature = signer.sign_message(message.as_bytes()).await?;
+    Ok(format!("{}:{}", signer.address(), hex::encode_prefixed(signature.as_bytes())))
+}
+
+#[cfg(feature = "icp")]
+pub use icp::IcpMevApi;
+
+#[cfg(feature = "icp")]
+mod icp {
+    use super::*;
+    use alloy_json_rpc::{Request, ResponsePacket, ResponsePayload, RpcParam, RpcReturn};
+    use alloy_transport::{TransportError, TransportErrorKind};
+    use alloy_transport_icp::{HttpHeader, IcpTransport};
+
+    /// MEV API over the ICP transport, authenticating requests to the relay with the
+    /// [`FLASHBOTS_SIGNATURE_HEADER`].
+    ///
+    /// The transport must use a custom `RpcService` pointing at the relay, e.g.
+    /// `https://relay.flashbots.net`. The header is signed by the given signer, e.g. an
+    /// `IcpSigner` signing with the threshold ECDSA key of the canister, over the exact body of
+    /// the request.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let bundle = EthSendBundle { txs: vec![raw_tx], block_number, ..Default::default() };
+    /// let bundle_hash = relay_provider.send_bundle_signed(bundle, &searcher_signer).await?;
+    /// ```
+    #[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+    #[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+    pub trait IcpMevApi<N>: Send + Sync {
+        /// Sends a bundle of signed transactions to the relay with `eth_sendBundle`.
+        async fn send_bundle_signed<S>(
+            &self,
+            bundle: EthSendBundle,
+            signer: &S,
+        ) -> TransportResult<EthBundleHash>
+        where
+            S: Signer + Clone + Send + Sync + 'static;
+
+        /// Sends a bundle to the matchmaker with `mev_sendBundle`.
+        async fn send_mev_bundle_signed<S>(
+            &self,
+            bundle: SendBundleRequest,
+            signer: &S,
+        ) -> TransportResult<SendBundleResponse>
+        where
+            S: Signer + Clone + Send + Sync + 'static;
+    }
+
+    #[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+    #[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+    impl<N, P> IcpMevApi<N> for P
+    where
+        N: Network,
+        P: Provider<IcpTransport, N>,
+    {
+        async fn send_bundle_signed<S>(
+            &self,
+            bundle: EthSendBundle,
+            signer: &S,
+        ) -> TransportResult<EthBundleHash>
+        where
+            S: Signer + Clone + Send + Sync + 'static,
+        {
+            signed_request(self, "eth_sendBundle", (bundle,), signer).await
+        }
+
+        async fn send_mev_bundle_signed<S>(
+            &self,
+            bundle: SendBundleRequest,
+            signer: &S,
+        ) -> TransportResult<SendBundleResponse>
+        where
+            S: Signer + Clone + Send + Sync + 'static,
+        {
+            signed_request(self, "mev_sendBundle", (bundle,), signer).await
+        }
+    }
+
+    /// Make a request with the [`FLASHBOTS_SIGNATURE_HEADER`] of its body.
+    async fn signed_request<P, N, Params, Resp, S>(
+        provider: &P,
+        method: &'static str,
+        params: Params,
+        signer: &S,
+    ) -> TransportResult<Resp>
+    where
+        P: Provider<IcpTransport, N>,
+        N: Network,
+        Params: RpcParam,
+        Resp: RpcReturn,
+        S: Signer + Clone + Send + Sync + 'static,
+    {
+        let client = provider.client();
+        let request = Request::new(method, client.next_id(), params)
+            .serialize()
+            .map_err(TransportError::ser_err)?;
+        let signer = signer.clone();
+        let response = client
+            .transport()
+            .request_with_headers(request, move |body| {
+                Box::pin(async move {
+                    let value =
+                        flashbots_signature(&signer, &body).await.map_err(|err| err.to_string())?;
+                    Ok(vec![HttpHeader { name: FLASHBOTS_SIGNATURE_HEADER.into(), value }])
+                })
+            })
+            .await?;
+        let ResponsePacket::Single(response) = response else {
+            return Err(TransportErrorKind::custom_str("unexpected batch response"));
+        };
+        match response.payload {
+            ResponsePayload::Success(result) => serde_json::from_str(result.get())
+                .map_err(|err| TransportError::deser_err(err, result.get())),
+            ResponsePayload::Failure(err) => Err(TransportError::ErrorResp(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::PrimitiveSignature;
+    use alloy_signer_local::PrivateKeySigner;
+
+    #[tokio::test]
+    async fn signs_flashbots_header() {
+        let signer = PrivateKeySigner::random();
+        let body = br#"{"jsonrpc":"2.0","id":1,"method":"eth_sendBundle","params":[]}"#;
+        let header = flashbots_signature(&signer, body).await.unwrap();
+
+        let (address, signature) = header.split_once(':').unwrap();
+        assert_eq!(address, signer.address().to_string());
+        let signature = PrimitiveSignature::try_from(&hex::decode(signature).unwrap()[..]).unwrap();
+        let message = keccak256(body).to_string();
+        assert_eq!(signature.recover_address_from_msg(message).unwrap(), signer.address());
+    }
+}
@@ -43,4 +43,11 @@ pub use txpool::TxPoolApi;
 #[cfg(feature = "erc4337-api")]
 mod erc4337;
 #[cfg(feature = "erc4337-api")]
-pub use erc4337::Erc4337Api;
+pub use erc4337::{sign_user_operation, Erc4337Api};
+
+#[cfg(feature = "mev-api")]
+mod mev;
+#[cfg(all(feature = "mev-api", feature = "icp"))]
+pub use mev::IcpMevApi;
+#[cfg(feature = "mev-api")]
+pub use mev::{flashbots_signature, MevApi, FLASHBOTS_SIGNATURE_HEADER};
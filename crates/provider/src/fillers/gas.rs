@@ -213,10 +213,18 @@ impl<N: Network> TxFiller<N> for GasFiller {
         } else if tx.blob_sidecar().is_some() {
             self.prepare_4844(provider, tx).await
         } else {
+            let constants = provider.root().chain_constants();
+            if constants.eip1559() == Some(false) {
+                return self.prepare_legacy(provider, tx).await;
+            }
             match self.prepare_1559(provider, tx).await {
                 // fallback to legacy
                 Ok(estimate) => Ok(estimate),
-                Err(RpcError::UnsupportedFeature(_)) => self.prepare_legacy(provider, tx).await,
+                Err(RpcError::UnsupportedFeature(_)) => {
+                    // Skip the failing EIP-1559 estimation for the next transactions.
+                    constants.set_eip1559(false);
+                    self.prepare_legacy(provider, tx).await
+                }
                 Err(e) => Err(e),
             }
         }
@@ -15,6 +15,11 @@ pub use wallet::WalletFiller;
 mod nonce;
 pub use nonce::{CachedNonceManager, NonceFiller, NonceManager, SimpleNonceManager};
 
+#[cfg(feature = "icp")]
+mod stable_nonce;
+#[cfg(feature = "icp")]
+pub use stable_nonce::{NonceConflictResolution, StableNonceManager, StableNonces};
+
 mod gas;
 pub use gas::{GasFillable, GasFiller};
 
@@ -0,0 +1,243 @@
+use crate::{fillers::NonceManager, Provider};
+use alloy_network::Network;
+use alloy_primitives::Address;
+use alloy_transport::{Transport, TransportResult};
+use async_trait::async_trait;
+use dashmap::DashMap;
+use futures::lock::Mutex;
+use ic_stable_structures::{Memory, StableBTreeMap};
+use std::{cell::RefCell, fmt, sync::Arc, thread::LocalKey};
+
+/// The stable map holding the last nonce used by each address.
+pub type StableNonces<M> = StableBTreeMap<[u8; 20], u64, M>;
+
+/// How a [`StableNonceManager`] resolves a stored nonce that disagrees with the transaction
+/// count reported by the chain.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NonceConflictResolution {
+    /// Use the higher of the two. Transactions sent before an upgrade that have not reached the
+    /// provider's mempool yet are not reused, at the risk of leaving a gap if they were dropped.
+    #[default]
+    Highest,
+    /// Always use the transaction count reported by the chain.
+    OnChain,
+}
+
+/// Stable memory nonce manager
+///
+/// This [`NonceManager`] implementation stores the last nonce used by each address in a
+/// [`StableBTreeMap`], so that nonces survive canister upgrades.
+///
+/// The first time an address is used after the canister is installed or upgraded, the stored
+/// nonce is reconciled with the pending transaction count of the address, according to the
+/// [`NonceConflictResolution`]. Afterwards, nonces are incremented locally like with the
+/// [`CachedNonceManager`].
+///
+/// The map is owned by a thread local, as stable structures are not [`Send`].
+///
+/// # Example
+///
+/// ```ignore
+/// thread_local! {
+///     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+///         RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+///     static NONCES: RefCell<StableNonces<VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+///         StableBTreeMap::init(MEMORY_MANAGER.with_borrow(|m| m.get(MemoryId::new(0)))),
+///     );
+/// }
+///
+/// let provider = ProviderBuilder::new()
+///     .filler(NonceFiller::new(StableNonceManager::new(&NONCES)))
+///     .wallet(wallet)
+///     .on_icp(config);
+/// ```
+///
+/// [`CachedNonceManager`]: crate::fillers::CachedNonceManager
+pub struct StableNonceManager<M: Memory + 'static> {
+    nonces: &'static LocalKey<RefCell<StableNonces<M>>>,
+    /// Whether each address has been reconciled with the chain since the canister started.
+    reconciled: Arc<DashMap<Address, Arc<Mutex<bool>>>>,
+    resolution: NonceConflictResolution,
+}
+
+impl<M: Memory + 'static> StableNonceManager<M> {
+    /// Creates a new [`StableNonceManager`] storing nonces in the given map.
+    pub fn new(nonces: &'static LocalKey<RefCell<StableNonces<M>>>) -> Self {
+        Self { nonces, reconciled: Default::default(), resolution: Default::default() }
+    }
+
+    /// Sets how stored nonces are reconciled with the chain.
+    pub const fn with_conflict_resolution(mut self, resolution: NonceConflictResolution) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    /// Returns the last nonce used by the given address, if any.
+    pub fn nonce(&self, address: Address) -> Option<u64> {
+        self.nonces.with_borrow(|nonces| nonces.get(&address.into_array()))
+    }
+
+    /// Forgets the stored nonce of the given address. The next nonce is fetched from the chain.
+    pub fn forget(&self, address: Address) {
+        self.nonces.with_borrow_mut(|nonces| nonces.remove(&address.into_array()));
+        self.reconciled.remove(&address);
+    }
+}
+
+impl<M: Memory + 'static> Clone for StableNonceManager<M> {
+    fn clone(&self) -> Self {
+        Self {
+            nonces: self.nonces,
+            reconciled: self.reconciled.clone(),
+            resolution: self.resolution,
+        }
+    }
+}
+
+impl<M: Memory + 'static> fmt::Debug for StableNonceManager<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StableNonceManager")
+            .field("reconciled", &self.reconciled)
+            .field("resolution", &self.resolution)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<M: Memory + 'static> NonceManager for StableNonceManager<M> {
+    async fn get_next_nonce<P, T, N>(&self, provider: &P, address: Address) -> TransportResult<u64>
+    where
+        P: Provider<T, N>,
+        N: Network,
+        T: Transport + Clone,
+    {
+        // Don't hold the dashmap lock through the await point below.
+        let reconciled = {
+            let rm = self.reconciled.entry(address).or_default();
+            Arc::clone(rm.value())
+        };
+
+        let mut reconciled = reconciled.lock().await;
+        let stored = self.nonce(address);
+        let new_nonce = match stored {
+            Some(nonce) if *reconciled => nonce + 1,
+            _ => {
+                let on_chain = provider.get_transaction_count(address).pending().await?;
+                match (self.resolution, stored) {
+                    (NonceConflictResolution::Highest, Some(nonce)) => on_chain.max(nonce + 1),
+                    _ => on_chain,
+                }
+            }
+        };
+        self.nonces.with_borrow_mut(|nonces| nonces.insert(address.into_array(), new_nonce));
+        *reconciled = true;
+        Ok(new_nonce)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RootProvider;
+    use alloy_json_rpc::{RequestPacket, ResponsePacket};
+    use alloy_network::Ethereum;
+    use alloy_rpc_client::RpcClient;
+    use alloy_transport::{TransportError, TransportFut};
+    use ic_stable_structures::VectorMemory;
+    use std::{
+        sync::atomic::{AtomicU64, Ordering},
+        task::{Context, Poll},
+    };
+
+    thread_local! {
+        static NONCES: RefCell<StableNonces<VectorMemory>> =
+            RefCell::new(StableBTreeMap::init(VectorMemory::default()));
+    }
+
+    const ADDRESS: Address = Address::repeat_byte(0x11);
+
+    /// A transport answering `eth_getTransactionCount` with a fixed count, counting its calls.
+    #[derive(Clone, Default)]
+    struct TransactionCount {
+        count: Arc<AtomicU64>,
+        calls: Arc<AtomicU64>,
+    }
+
+    impl tower::Service<RequestPacket> for TransactionCount {
+        type Response = ResponsePacket;
+        type Error = TransportError;
+        type Future = TransportFut<'static>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: RequestPacket) -> Self::Future {
+            let RequestPacket::Single(req) = req else { panic!("unexpected batch") };
+            assert_eq!(req.method(), "eth_getTransactionCount");
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            let body = format!(
+                r#"{{"jsonrpc":"2.0","id":{},"result":"{:#x}"}}"#,
+                req.id(),
+                self.count.load(Ordering::Relaxed)
+            );
+            Box::pin(async move { Ok(serde_json::from_str(&body).unwrap()) })
+        }
+    }
+
+    fn provider(count: u64) -> (RootProvider<TransactionCount, Ethereum>, TransactionCount) {
+        let transport = TransactionCount::default();
+        transport.count.store(count, Ordering::Relaxed);
+        (RootProvider::new(RpcClient::new(transport.clone(), true)), transport)
+    }
+
+    #[tokio::test]
+    async fn reconciles_on_first_use() {
+        let (provider, transport) = provider(5);
+        let manager = StableNonceManager::new(&NONCES);
+        assert_eq!(manager.get_next_nonce(&provider, ADDRESS).await.unwrap(), 5);
+        assert_eq!(manager.get_next_nonce(&provider, ADDRESS).await.unwrap(), 6);
+        assert_eq!(manager.nonce(ADDRESS), Some(6));
+        assert_eq!(transport.calls.load(Ordering::Relaxed), 1);
+
+        // After a restart, the stored nonce is reconciled again.
+        transport.count.store(9, Ordering::Relaxed);
+        let restarted = StableNonceManager::new(&NONCES);
+        assert_eq!(restarted.get_next_nonce(&provider, ADDRESS).await.unwrap(), 9);
+        assert_eq!(transport.calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn resolves_conflicts() {
+        let (provider, transport) = provider(5);
+        NONCES.with_borrow_mut(|nonces| nonces.insert(ADDRESS.into_array(), 10));
+
+        // Transactions sent before the restart may not have reached the chain yet.
+        let highest = StableNonceManager::new(&NONCES);
+        assert_eq!(highest.get_next_nonce(&provider, ADDRESS).await.unwrap(), 11);
+
+        let on_chain = StableNonceManager::new(&NONCES)
+            .with_conflict_resolution(NonceConflictResolution::OnChain);
+        assert_eq!(on_chain.get_next_nonce(&provider, ADDRESS).await.unwrap(), 5);
+        assert_eq!(on_chain.get_next_nonce(&provider, ADDRESS).await.unwrap(), 6);
+
+        // The chain is ahead, e.g. transactions were sent by another signer.
+        transport.count.store(20, Ordering::Relaxed);
+        let highest = StableNonceManager::new(&NONCES);
+        assert_eq!(highest.get_next_nonce(&provider, ADDRESS).await.unwrap(), 20);
+    }
+
+    #[tokio::test]
+    async fn forget_refetches_from_chain() {
+        let (provider, transport) = provider(3);
+        let manager = StableNonceManager::new(&NONCES);
+        assert_eq!(manager.get_next_nonce(&provider, ADDRESS).await.unwrap(), 3);
+        assert_eq!(manager.get_next_nonce(&provider, ADDRESS).await.unwrap(), 4);
+
+        manager.forget(ADDRESS);
+        assert_eq!(manager.nonce(ADDRESS), None);
+        assert_eq!(manager.get_next_nonce(&provider, ADDRESS).await.unwrap(), 3);
+        assert_eq!(transport.calls.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,381 @@
+//! An RPC gateway, exposing the read API of a provider as Candid methods to other canisters.
+//!
+//! A single well-funded gateway canister pays for the HTTPS outcalls of many consumer canisters,
+//! which call its `eth_call`, `eth_getBalance`, `eth_getLogs` and `eth_sendRawTransaction`
+//! methods instead of running their own provider. Callers are checked against the
+//! [`GatewayAccess`] of the gateway; the controllers of the gateway canister are always allowed.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! thread_local! {
+//!     static GATEWAY: RpcGateway<IcpProvider, IcpTransport> =
+//!         RpcGateway::new(provider(), GatewayAccess::restricted([consumer_id()]));
+//! }
+//!
+//! fn gateway() -> RpcGateway<IcpProvider, IcpTransport> {
+//!     GATEWAY.with(Clone::clone)
+//! }
+//!
+//! alloy_provider::export_rpc_gateway!(gateway());
+//! ```
+//!
+//! The exported methods have the following Candid interface:
+//!
+//! ```text
+//! type CallArgs = record { transaction : TransactionRequest; block : opt nat };
+//! type BalanceArgs = record { address : text; block : opt nat };
+//! type LogsArgs = record {
+//!   from_block : opt nat;
+//!   to_block : opt nat;
+//!   addresses : vec text;
+//!   topics : vec vec text;
+//! };
+//! type GatewayError = variant { Unauthorized : principal; InvalidArgument : text; Rpc : text };
+//!
+//! service : {
+//!   eth_call : (CallArgs) -> (variant { Ok : text; Err : GatewayError });
+//!   eth_getBalance : (BalanceArgs) -> (variant { Ok : nat; Err : GatewayError });
+//!   eth_getLogs : (LogsArgs) -> (variant { Ok : vec LogEntry; Err : GatewayError });
+//!   eth_sendRawTransaction : (text) -> (variant { Ok : text; Err : GatewayError });
+//! }
+//! ```
+
+use crate::{
+    candid::{from_opt_nat, CandidAddress, CandidB256, CandidBytes, CandidU256},
+    Provider,
+};
+use alloy_eips::BlockId;
+use alloy_network::Ethereum;
+use alloy_rpc_types_eth::{Filter, Log, TransactionRequest};
+use alloy_transport::{Transport, TransportError};
+use candid::{CandidType, Nat, Principal};
+use serde::Deserialize;
+use std::{cell::RefCell, collections::BTreeSet, marker::PhantomData, rc::Rc};
+
+/// The maximum number of topic positions of a log filter.
+const MAX_TOPICS: usize = 4;
+
+/// Errors returned by the methods of an [`RpcGateway`].
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize, thiserror::Error)]
+pub enum GatewayError {
+    /// The caller is not allowed to use the gateway.
+    #[error("caller {0} is not allowed to use the gateway")]
+    Unauthorized(Principal),
+
+    /// An argument of the method is invalid.
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+
+    /// The RPC request failed.
+    #[error("RPC request failed: {0}")]
+    Rpc(String),
+}
+
+impl From<TransportError> for GatewayError {
+    fn from(err: TransportError) -> Self {
+        Self::Rpc(err.to_string())
+    }
+}
+
+/// The callers allowed to use an [`RpcGateway`], in addition to the controllers of the canister.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub enum GatewayAccess {
+    /// Every caller, except the anonymous principal, is allowed.
+    Public,
+    /// Only the listed callers are allowed.
+    Restricted(BTreeSet<Principal>),
+}
+
+impl Default for GatewayAccess {
+    fn default() -> Self {
+        Self::Restricted(BTreeSet::new())
+    }
+}
+
+impl GatewayAccess {
+    /// Allow only the given callers.
+    pub fn restricted(callers: impl IntoIterator<Item = Principal>) -> Self {
+        Self::Restricted(callers.into_iter().collect())
+    }
+
+    /// Returns `true` if the caller is allowed, not counting the controllers of the canister.
+    pub fn allows(&self, caller: &Principal) -> bool {
+        match self {
+            Self::Public => *caller != Principal::anonymous(),
+            Self::Restricted(callers) => callers.contains(caller),
+        }
+    }
+}
+
+/// The arguments of the `eth_call` method of an [`RpcGateway`].
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct CallArgs {
+    /// The transaction to call.
+    #[serde(deserialize_with = "alloy_rpc_types_eth::candid::deserialize")]
+    pub transaction: TransactionRequest,
+    /// The number of the block to call the transaction at, the latest block if `None`.
+    pub block: Option<Nat>,
+}
+
+/// The arguments of the `eth_getBalance` method of an [`RpcGateway`].
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct BalanceArgs {
+    /// The account to get the balance of.
+    pub address: CandidAddress,
+    /// The number of the block to get the balance at, the latest block if `None`.
+    pub block: Option<Nat>,
+}
+
+/// The arguments of the `eth_getLogs` method of an [`RpcGateway`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize)]
+pub struct LogsArgs {
+    /// The first block of the range, the latest block if `None`.
+    pub from_block: Option<Nat>,
+    /// The last block of the range, the latest block if `None`.
+    pub to_block: Option<Nat>,
+    /// The contracts emitting the logs, any contract if empty.
+    pub addresses: Vec<CandidAddress>,
+    /// The accepted topics of the logs at each position, any topic if empty.
+    pub topics: Vec<Vec<CandidB256>>,
+}
+
+impl TryFrom<LogsArgs> for Filter {
+    type Error = GatewayError;
+
+    fn try_from(args: LogsArgs) -> Result<Self, Self::Error> {
+        if args.topics.len() > MAX_TOPICS {
+            return Err(GatewayError::InvalidArgument(format!(
+                "at most {MAX_TOPICS} topics are supported, got {}",
+                args.topics.len()
+            )));
+        }
+        let mut filter =
+            Self::new().address(args.addresses.into_iter().map(Into::into).collect::<Vec<_>>());
+        if let Some(block) = block_number(&args.from_block)? {
+            filter = filter.from_block(block);
+        }
+        if let Some(block) = block_number(&args.to_block)? {
+            filter = filter.to_block(block);
+        }
+        for (topic, values) in filter.topics.iter_mut().zip(args.topics) {
+            *topic = values.into_iter().map(Into::into).collect::<Vec<_>>().into();
+        }
+        Ok(filter)
+    }
+}
+
+fn block_number(block: &Option<Nat>) -> Result<Option<u64>, GatewayError> {
+    from_opt_nat(block).map_err(GatewayError::InvalidArgument)
+}
+
+fn block_id(block: &Option<Nat>) -> Result<BlockId, GatewayError> {
+    Ok(block_number(block)?.map(BlockId::number).unwrap_or_default())
+}
+
+/// A gateway serving the read API of a provider, and the broadcast of signed transactions, to
+/// the allowed callers.
+///
+/// The gateway is cheap to clone, and clones share their [`GatewayAccess`], so a canister keeps
+/// one in a `thread_local` and clones it into each method, see [`export_rpc_gateway`].
+///
+/// [`export_rpc_gateway`]: crate::export_rpc_gateway
+#[derive(Debug)]
+pub struct RpcGateway<P, T> {
+    provider: P,
+    access: Rc<RefCell<GatewayAccess>>,
+    _transport: PhantomData<T>,
+}
+
+impl<P: Clone, T> Clone for RpcGateway<P, T> {
+    fn clone(&self) -> Self {
+        Self {
+            provider: self.provider.clone(),
+            access: self.access.clone(),
+            _transport: PhantomData,
+        }
+    }
+}
+
+impl<P, T> RpcGateway<P, T>
+where
+    P: Provider<T, Ethereum>,
+    T: Transport + Clone,
+{
+    /// Create a gateway serving the provider to the allowed callers.
+    pub fn new(provider: P, access: GatewayAccess) -> Self {
+        Self { provider, access: Rc::new(RefCell::new(access)), _transport: PhantomData }
+    }
+
+    /// Returns the provider of the gateway.
+    pub const fn provider(&self) -> &P {
+        &self.provider
+    }
+
+    /// Returns the callers allowed to use the gateway.
+    pub fn access(&self) -> GatewayAccess {
+        self.access.borrow().clone()
+    }
+
+    /// Replace the callers allowed to use the gateway.
+    pub fn set_access(&self, access: GatewayAccess) {
+        *self.access.borrow_mut() = access;
+    }
+
+    /// Allow the caller to use the gateway. Has no effect if the gateway is public.
+    pub fn allow(&self, caller: Principal) {
+        if let GatewayAccess::Restricted(callers) = &mut *self.access.borrow_mut() {
+            callers.insert(caller);
+        }
+    }
+
+    /// Revoke the access of the caller to the gateway. Has no effect if the gateway is public.
+    pub fn revoke(&self, caller: &Principal) {
+        if let GatewayAccess::Restricted(callers) = &mut *self.access.borrow_mut() {
+            callers.remove(caller);
+        }
+    }
+
+    /// Returns `true` if the caller is allowed to use the gateway.
+    pub fn is_allowed(&self, caller: &Principal) -> bool {
+        self.access.borrow().allows(caller) || ic_cdk::api::is_controller(caller)
+    }
+
+    fn authorize(&self, caller: Principal) -> Result<(), GatewayError> {
+        if self.is_allowed(&caller) {
+            Ok(())
+        } else {
+            Err(GatewayError::Unauthorized(caller))
+        }
+    }
+
+    /// Execute a call on behalf of the caller, see [`Provider::call`].
+    pub async fn eth_call(
+        &self,
+        caller: Principal,
+        args: CallArgs,
+    ) -> Result<CandidBytes, GatewayError> {
+        self.authorize(caller)?;
+        let block = block_id(&args.block)?;
+        Ok(self.provider.call(&args.transaction).block(block).await?.into())
+    }
+
+    /// Get the balance of an account on behalf of the caller, see [`Provider::get_balance`].
+    pub async fn get_balance(
+        &self,
+        caller: Principal,
+        args: BalanceArgs,
+    ) -> Result<CandidU256, GatewayError> {
+        self.authorize(caller)?;
+        let block = block_id(&args.block)?;
+        Ok(self.provider.get_balance(args.address.0).block_id(block).await?.into())
+    }
+
+    /// Get the logs matching a filter on behalf of the caller, see [`Provider::get_logs`].
+    pub async fn get_logs(
+        &self,
+        caller: Principal,
+        args: LogsArgs,
+    ) -> Result<Vec<Log>, GatewayError> {
+        self.authorize(caller)?;
+        let filter = args.try_into()?;
+        Ok(self.provider.get_logs(&filter).await?)
+    }
+
+    /// Broadcast a signed, EIP-2718 encoded transaction on behalf of the caller, returning its
+    /// hash, see [`Provider::send_raw_transaction`].
+    pub async fn send_raw_transaction(
+        &self,
+        caller: Principal,
+        transaction: CandidBytes,
+    ) -> Result<CandidB256, GatewayError> {
+        self.authorize(caller)?;
+        Ok((*self.provider.send_raw_transaction(&transaction).await?.tx_hash()).into())
+    }
+}
+
+#[doc(hidden)]
+pub mod __private {
+    pub use alloy_rpc_types_eth::Log;
+}
+
+/// Export the methods of an [`RpcGateway`](crate::gateway::RpcGateway) as update methods of the
+/// canister, authorizing the caller of each call.
+///
+/// The expression is evaluated for every call and must return the gateway by value, e.g. a
+/// clone of a gateway kept in a `thread_local`.
+#[macro_export]
+macro_rules! export_rpc_gateway {
+    ($gateway:expr) => {
+        #[::ic_cdk::update(name = "eth_call")]
+        async fn __rpc_gateway_eth_call(
+            args: $crate::gateway::CallArgs,
+        ) -> ::core::result::Result<$crate::candid::CandidBytes, $crate::gateway::GatewayError> {
+            $gateway.eth_call(::ic_cdk::caller(), args).await
+        }
+
+        #[::ic_cdk::update(name = "eth_getBalance")]
+        async fn __rpc_gateway_get_balance(
+            args: $crate::gateway::BalanceArgs,
+        ) -> ::core::result::Result<$crate::candid::CandidU256, $crate::gateway::GatewayError> {
+            $gateway.get_balance(::ic_cdk::caller(), args).await
+        }
+
+        #[::ic_cdk::update(name = "eth_getLogs")]
+        async fn __rpc_gateway_get_logs(
+            args: $crate::gateway::LogsArgs,
+        ) -> ::core::result::Result<
+            ::std::vec::Vec<$crate::gateway::__private::Log>,
+            $crate::gateway::GatewayError,
+        > {
+            $gateway.get_logs(::ic_cdk::caller(), args).await
+        }
+
+        #[::ic_cdk::update(name = "eth_sendRawTransaction")]
+        async fn __rpc_gateway_send_raw_transaction(
+            transaction: $crate::candid::CandidBytes,
+        ) -> ::core::result::Result<$crate::candid::CandidB256, $crate::gateway::GatewayError> {
+            $gateway.send_raw_transaction(::ic_cdk::caller(), transaction).await
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{address, b256};
+
+    #[test]
+    fn access() {
+        let consumer = Principal::from_slice(&[1; 10]);
+        let restricted = GatewayAccess::restricted([consumer]);
+        assert!(restricted.allows(&consumer));
+        assert!(!restricted.allows(&Principal::from_slice(&[2; 10])));
+        assert!(!GatewayAccess::default().allows(&consumer));
+        assert!(GatewayAccess::Public.allows(&consumer));
+        assert!(!GatewayAccess::Public.allows(&Principal::anonymous()));
+    }
+
+    #[test]
+    fn logs_args_to_filter() {
+        let token = address!("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48");
+        let transfer = b256!("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef");
+        let args = LogsArgs {
+            from_block: Some(Nat::from(100u64)),
+            to_block: None,
+            addresses: vec![token.into()],
+            topics: vec![vec![transfer.into()], vec![]],
+        };
+        let filter = Filter::try_from(args).unwrap();
+        assert_eq!(
+            filter,
+            Filter::new().address(vec![token]).from_block(100).event_signature(transfer)
+        );
+
+        let args = LogsArgs { topics: vec![vec![]; 5], ..Default::default() };
+        assert!(matches!(Filter::try_from(args), Err(GatewayError::InvalidArgument(_))));
+
+        let args = LogsArgs { from_block: Some(Nat::from(u128::MAX)), ..Default::default() };
+        assert!(matches!(Filter::try_from(args), Err(GatewayError::InvalidArgument(_))));
+    }
+}
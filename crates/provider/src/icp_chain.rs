@@ -0,0 +1,327 @@
+//! A watcher of the canonical chain, reporting reorgs to its handler.
+
+use alloy_eips::BlockNumberOrTag;
+use alloy_primitives::{B256, U64};
+use alloy_rpc_client::{IcpPollerBuilder, IcpPollerHandle, PollBackoff, WeakClient};
+use alloy_transport::{Transport, TransportError, TransportErrorKind, TransportResult};
+use alloy_transport_icp::RequestPriority;
+use serde::Deserialize;
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    ops::ControlFlow,
+    rc::Rc,
+    time::Duration,
+};
+
+/// The header fields of a block needed to follow the chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChainBlock {
+    number: U64,
+    hash: B256,
+    parent_hash: B256,
+}
+
+/// An event of the chain followed by a [`ChainWatcher`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChainEvent {
+    /// A block extending the followed chain.
+    Block {
+        /// The number of the block.
+        number: u64,
+        /// The hash of the block.
+        hash: B256,
+    },
+    /// The followed chain was replaced from a common ancestor.
+    Reorg {
+        /// The number of blocks removed from the followed chain.
+        depth: u64,
+        /// The hashes of the removed blocks, oldest first.
+        old: Vec<B256>,
+        /// The hashes of the blocks replacing them, oldest first, up to the new head.
+        new: Vec<B256>,
+    },
+}
+
+/// The most recent blocks of the followed chain.
+#[derive(Debug)]
+struct ChainTracker {
+    blocks: VecDeque<ChainBlock>,
+    depth: usize,
+}
+
+impl ChainTracker {
+    const fn new(depth: usize) -> Self {
+        Self { blocks: VecDeque::new(), depth }
+    }
+
+    fn contains(&self, hash: B256) -> bool {
+        self.blocks.iter().any(|block| block.hash == hash)
+    }
+
+    /// Returns `true` if no parent of the block needs to be requested to apply it: the chain is
+    /// empty, or the parent of the block is in the chain.
+    fn connects(&self, block: &ChainBlock) -> bool {
+        self.blocks.is_empty() || self.contains(block.parent_hash)
+    }
+
+    /// Apply the blocks leading to a new head, oldest first, returning the events of the change.
+    fn apply(&mut self, new: Vec<ChainBlock>) -> Vec<ChainEvent> {
+        let Some(first) = new.first() else { return Vec::new() };
+        if new.last().is_some_and(|head| self.contains(head.hash)) {
+            return Vec::new();
+        }
+        let old: Vec<_> = match self.blocks.iter().position(|block| block.hash == first.parent_hash)
+        {
+            Some(ancestor) => self.blocks.drain(ancestor + 1..).map(|block| block.hash).collect(),
+            None => {
+                let tip = self.blocks.back().map_or(0, |block| block.number.to::<u64>());
+                let old = self
+                    .blocks
+                    .iter()
+                    .filter(|block| block.number >= first.number)
+                    .map(|block| block.hash)
+                    .collect();
+                if first.number.to::<u64>() > tip + 1 && !self.blocks.is_empty() {
+                    ic_cdk::println!(
+                        "Chain watcher skipped blocks {} to {}.",
+                        tip + 1,
+                        first.number.to::<u64>() - 1
+                    );
+                }
+                self.blocks.clear();
+                old
+            }
+        };
+
+        let events = if old.is_empty() {
+            new.iter()
+                .map(|block| ChainEvent::Block { number: block.number.to(), hash: block.hash })
+                .collect()
+        } else {
+            vec![ChainEvent::Reorg {
+                depth: old.len() as u64,
+                old,
+                new: new.iter().map(|block| block.hash).collect(),
+            }]
+        };
+        self.blocks.extend(new);
+        let excess = self.blocks.len().saturating_sub(self.depth);
+        self.blocks.drain(..excess);
+        events
+    }
+}
+
+/// Follows the canonical chain by polling the provider with `eth_getBlockByNumber("latest")`,
+/// see [`Provider::watch_chain`].
+///
+/// The watcher keeps the hashes of the most recent blocks, see
+/// [`with_reorg_depth`](Self::with_reorg_depth). When the head moves, the blocks between the
+/// followed chain and the new head are requested by parent hash. The handler is called with a
+/// [`ChainEvent::Block`] for each block extending the chain, or a single [`ChainEvent::Reorg`]
+/// when the chain it has been following is replaced, so the handler can roll back the state
+/// derived from the removed blocks.
+///
+/// When the head moves by more blocks than the reorg depth between two polls, the blocks in
+/// between are skipped. A reorg deeper than the reorg depth is reported as a reorg of every
+/// kept block.
+///
+/// [`Provider::watch_chain`]: crate::Provider::watch_chain
+#[derive(Debug)]
+pub struct ChainWatcher<T> {
+    client: WeakClient<T>,
+    poller: IcpPollerBuilder<T, (BlockNumberOrTag, bool), Option<ChainBlock>>,
+    reorg_depth: u64,
+}
+
+impl<T> ChainWatcher<T>
+where
+    T: Transport + Clone + 'static,
+{
+    /// Create a watcher of the chain of the client.
+    pub fn new(client: WeakClient<T>) -> Self {
+        Self {
+            poller: IcpPollerBuilder::new(
+                client.clone(),
+                "eth_getBlockByNumber",
+                (BlockNumberOrTag::Latest, false),
+            ),
+            client,
+            reorg_depth: 64,
+        }
+    }
+
+    /// Sets the number of recent blocks whose hashes are kept to detect reorgs. Defaults to 64.
+    pub const fn with_reorg_depth(mut self, reorg_depth: u64) -> Self {
+        self.reorg_depth = if reorg_depth == 0 { 1 } else { reorg_depth };
+        self
+    }
+
+    /// Sets the duration between polls.
+    pub fn with_poll_interval(self, poll_interval: Duration) -> Self {
+        Self { poller: self.poller.with_poll_interval(poll_interval), ..self }
+    }
+
+    /// Sets a limit on the number of successful polls.
+    pub fn with_limit(self, limit: Option<usize>) -> Self {
+        Self { poller: self.poller.with_limit(limit), ..self }
+    }
+
+    /// Sets the priority of the poll requests, see [`IcpPollerBuilder::with_priority`].
+    pub fn with_priority(self, priority: RequestPriority) -> Self {
+        Self { poller: self.poller.with_priority(priority), ..self }
+    }
+
+    /// Sets the backoff policy of failed polls, see [`IcpPollerBuilder::with_backoff`].
+    pub fn with_backoff(self, backoff: PollBackoff) -> Self {
+        Self { poller: self.poller.with_backoff(backoff), ..self }
+    }
+
+    /// Sets the handler of failed polls, see [`IcpPollerBuilder::with_error_handler`].
+    pub fn with_error_handler<E>(self, error_handler: E) -> Self
+    where
+        E: FnMut(TransportError) -> ControlFlow<()> + 'static,
+    {
+        Self { poller: self.poller.with_error_handler(error_handler), ..self }
+    }
+
+    /// Starts the watcher, calling the handler with the events of each poll, oldest first.
+    ///
+    /// A poll made while the blocks of the previous one are still being requested is skipped.
+    pub fn start<F>(self, handler: F) -> Result<IcpPollerHandle, String>
+    where
+        F: FnMut(ChainEvent) + 'static,
+    {
+        let depth = usize::try_from(self.reorg_depth).unwrap_or(usize::MAX);
+        let follower = Rc::new(ChainFollower {
+            client: self.client,
+            tracker: RefCell::new(ChainTracker::new(depth)),
+            handler: RefCell::new(handler),
+            following: Cell::new(false),
+        });
+        self.poller.start_async(move |head: Option<ChainBlock>| {
+            let follower = follower.clone();
+            async move {
+                let Some(head) = head else { return };
+                if follower.following.replace(true) {
+                    return;
+                }
+                if let Err(e) = follower.follow(head).await {
+                    ic_cdk::println!("Chain watcher failed to follow block {}: {e:?}", head.hash);
+                }
+                follower.following.set(false);
+            }
+        })
+    }
+}
+
+/// The state of a started [`ChainWatcher`].
+struct ChainFollower<T, F> {
+    client: WeakClient<T>,
+    tracker: RefCell<ChainTracker>,
+    handler: RefCell<F>,
+    /// Whether the blocks of a poll are being requested.
+    following: Cell<bool>,
+}
+
+impl<T, F> ChainFollower<T, F>
+where
+    T: Transport + Clone,
+    F: FnMut(ChainEvent),
+{
+    /// Request the blocks between the followed chain and the new head, and hand the events of
+    /// the change to the handler.
+    async fn follow(&self, head: ChainBlock) -> TransportResult<()> {
+        if self.tracker.borrow().contains(head.hash) {
+            return Ok(());
+        }
+        let depth = self.tracker.borrow().depth;
+        let mut new = vec![head];
+        while let Some(block) = new
+            .last()
+            .filter(|block| !self.tracker.borrow().connects(block) && block.number > U64::ZERO)
+        {
+            if new.len() >= depth {
+                break;
+            }
+            let parent_hash = block.parent_hash;
+            let client = self.client.upgrade().ok_or_else(TransportErrorKind::backend_gone)?;
+            let parent: Option<ChainBlock> =
+                client.request("eth_getBlockByHash", (parent_hash, false)).await?;
+            let parent = parent.ok_or_else(|| {
+                TransportErrorKind::custom_str(&format!("block {parent_hash} not found"))
+            })?;
+            new.push(parent);
+        }
+        new.reverse();
+
+        let events = self.tracker.borrow_mut().apply(new);
+        let mut handler = self.handler.borrow_mut();
+        for event in events {
+            handler(event);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(number: u64, fork: u8) -> ChainBlock {
+        // The fork replaces the blocks from block 4.
+        let hash = |number: u64, fork| {
+            B256::left_padding_from(&[if number >= 4 { fork } else { 0 }, number as u8])
+        };
+        ChainBlock {
+            number: U64::from(number),
+            hash: hash(number, fork),
+            parent_hash: hash(number.wrapping_sub(1), fork),
+        }
+    }
+
+    #[test]
+    fn tracker_reports_blocks_and_reorgs() {
+        let mut tracker = ChainTracker::new(4);
+        let first = tracker.apply(vec![block(1, 0), block(2, 0), block(3, 0)]);
+        assert_eq!(first.len(), 3);
+        assert_eq!(first[0], ChainEvent::Block { number: 1, hash: block(1, 0).hash });
+
+        assert_eq!(
+            tracker.apply(vec![block(4, 0), block(5, 0)]),
+            [
+                ChainEvent::Block { number: 4, hash: block(4, 0).hash },
+                ChainEvent::Block { number: 5, hash: block(5, 0).hash },
+            ]
+        );
+        assert!(tracker.apply(vec![block(5, 0)]).is_empty());
+        assert_eq!(tracker.blocks.len(), 4);
+
+        // Blocks 4 and 5 are replaced by a fork from block 3.
+        let fork = vec![block(4, 1), block(5, 1), block(6, 1)];
+        assert!(!tracker.connects(&block(5, 1)));
+        assert!(tracker.connects(&fork[0]));
+        assert_eq!(
+            tracker.apply(fork.clone()),
+            [ChainEvent::Reorg {
+                depth: 2,
+                old: vec![block(4, 0).hash, block(5, 0).hash],
+                new: fork.iter().map(|block| block.hash).collect(),
+            }]
+        );
+        assert_eq!(tracker.blocks.back(), Some(&block(6, 1)));
+    }
+
+    #[test]
+    fn deserializes_block_headers() {
+        let block: ChainBlock = serde_json::from_str(&format!(
+            r#"{{"number":"0x10","hash":"{}","parentHash":"{}","transactions":[]}}"#,
+            B256::repeat_byte(1),
+            B256::repeat_byte(2)
+        ))
+        .unwrap();
+        assert_eq!(block.number, U64::from(16));
+        assert_eq!(block.parent_hash, B256::repeat_byte(2));
+    }
+}
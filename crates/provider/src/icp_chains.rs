@@ -0,0 +1,276 @@
+//! A manager of the providers and signers of several chains, sharing one cycles budget.
+
+use crate::{
+    fillers::{
+        ChainIdFiller, FillProvider, GasFiller, JoinFill, NonceFiller, StableNonceManager,
+        WalletFiller,
+    },
+    IcpAlloyStatus, IcpProvider, Identity, Provider, ProviderBuilder,
+};
+use alloy_network::{Ethereum, EthereumWallet};
+use alloy_primitives::{Address, ChainId};
+use alloy_signer::Signer;
+use alloy_signer_icp::{IcpSigner, IcpSignerError};
+use alloy_transport_icp::{CyclesAlert, CyclesBudget, IcpConfig, IcpTransport};
+use ic_stable_structures::Memory;
+use std::{collections::BTreeMap, fmt};
+
+/// The fillers of the providers of a [`ChainManager`]: gas estimation, stable nonce management,
+/// the chain ID of the chain, and the signer of the chain.
+pub type ChainFiller<M> = JoinFill<
+    JoinFill<
+        JoinFill<JoinFill<Identity, GasFiller>, NonceFiller<StableNonceManager<M>>>,
+        ChainIdFiller,
+    >,
+    WalletFiller<EthereumWallet>,
+>;
+
+/// A provider of a chain managed by a [`ChainManager`].
+pub type ChainProvider<M> =
+    FillProvider<ChainFiller<M>, IcpProvider<Ethereum>, IcpTransport, Ethereum>;
+
+/// Returns the default derivation path of the signer of a chain: the prefix followed by the
+/// big-endian chain ID, so each chain is signed for by a distinct address.
+pub fn chain_derivation_path(prefix: &[Vec<u8>], chain_id: ChainId) -> Vec<Vec<u8>> {
+    prefix.iter().cloned().chain(std::iter::once(chain_id.to_be_bytes().to_vec())).collect()
+}
+
+/// The provider and signer of a chain managed by a [`ChainManager`].
+struct Chain<M: Memory + 'static> {
+    provider: ChainProvider<M>,
+    signer: IcpSigner,
+}
+
+/// Owns the provider, signer and nonce management of several chains behind one API, so a
+/// cross-chain canister does not duplicate the whole stack for each chain.
+///
+/// Each chain gets its own provider and [`IcpSigner`], with a distinct derivation path, see
+/// [`chain_derivation_path`]. The nonces of every chain are kept in the same
+/// [`StableNonceManager`], as nonces are keyed by address, and the transports of every chain
+/// share a single [`CyclesBudget`] and [`CyclesAlert`]. Pollers and watchers are started on the
+/// client of the provider of their chain.
+///
+/// # Examples
+///
+/// ```ignore
+/// let manager = ChainManager::builder("key_1", StableNonceManager::new(&NONCES))
+///     .chain(1, IcpConfig::new(RpcService::EthMainnet(EthMainnetService::Alchemy)))
+///     .chain(8453, IcpConfig::new(RpcService::BaseMainnet(L2MainnetService::Alchemy)))
+///     .with_cycles_budget(CyclesBudget::new().with_min_balance(1_000_000_000_000))
+///     .build()
+///     .await?;
+///
+/// let tx = TransactionRequest::default().with_to(recipient).with_value(amount);
+/// let pending = manager.on(8453).send_transaction(tx).await?;
+/// ```
+pub struct ChainManager<M: Memory + 'static> {
+    chains: BTreeMap<ChainId, Chain<M>>,
+}
+
+impl<M: Memory + 'static> fmt::Debug for ChainManager<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let signers: BTreeMap<_, _> =
+            self.chains.iter().map(|(chain_id, chain)| (chain_id, &chain.signer)).collect();
+        f.debug_struct("ChainManager").field("signers", &signers).finish_non_exhaustive()
+    }
+}
+
+impl<M: Memory + 'static> ChainManager<M> {
+    /// Create a builder of a manager signing with the given ECDSA key, and keeping the nonces of
+    /// every chain in the given nonce manager.
+    pub fn builder(
+        ecdsa_key_name: impl Into<String>,
+        nonce_manager: StableNonceManager<M>,
+    ) -> ChainManagerBuilder<M> {
+        ChainManagerBuilder {
+            ecdsa_key_name: ecdsa_key_name.into(),
+            derivation_path_prefix: Vec::new(),
+            nonce_manager,
+            cycles_budget: None,
+            cycles_alert: None,
+            chains: Vec::new(),
+        }
+    }
+
+    /// Returns the provider of the chain.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chain is not managed, see [`try_on`](Self::try_on).
+    pub fn on(&self, chain_id: ChainId) -> &ChainProvider<M> {
+        self.try_on(chain_id).unwrap_or_else(|| panic!("chain {chain_id} is not managed"))
+    }
+
+    /// Returns the provider of the chain, if it is managed.
+    pub fn try_on(&self, chain_id: ChainId) -> Option<&ChainProvider<M>> {
+        self.chains.get(&chain_id).map(|chain| &chain.provider)
+    }
+
+    /// Returns the signer of the chain, if it is managed.
+    pub fn signer(&self, chain_id: ChainId) -> Option<&IcpSigner> {
+        self.chains.get(&chain_id).map(|chain| &chain.signer)
+    }
+
+    /// Returns the address signing for the chain, if it is managed.
+    pub fn address(&self, chain_id: ChainId) -> Option<Address> {
+        self.signer(chain_id).map(Signer::address)
+    }
+
+    /// Returns the IDs of the managed chains, in ascending order.
+    pub fn chain_ids(&self) -> impl Iterator<Item = ChainId> + '_ {
+        self.chains.keys().copied()
+    }
+
+    /// Returns the transport of any chain, all of which share the cycles budget.
+    fn transport(&self) -> Option<&IcpTransport> {
+        self.chains.values().next().map(|chain| chain.provider.client().transport())
+    }
+
+    /// Replace the cycles budget shared by every chain.
+    pub fn set_cycles_budget(&self, cycles_budget: CyclesBudget) {
+        if let Some(transport) = self.transport() {
+            transport.set_cycles_budget(cycles_budget);
+        }
+    }
+
+    /// Returns the cycles spent by every chain in the current period of the cycles budget.
+    pub fn cycles_budget_spent(&self) -> u128 {
+        self.transport().map_or(0, IcpTransport::cycles_budget_spent)
+    }
+
+    /// Returns the status of each chain, with the signing health of its signer.
+    pub fn status(&self) -> Vec<(ChainId, IcpAlloyStatus)> {
+        self.chains
+            .iter()
+            .map(|(chain_id, chain)| {
+                (*chain_id, IcpAlloyStatus::new(&chain.provider).with_signer(&chain.signer))
+            })
+            .collect()
+    }
+}
+
+/// A builder of a [`ChainManager`], see [`ChainManager::builder`].
+pub struct ChainManagerBuilder<M: Memory + 'static> {
+    ecdsa_key_name: String,
+    derivation_path_prefix: Vec<Vec<u8>>,
+    nonce_manager: StableNonceManager<M>,
+    cycles_budget: Option<CyclesBudget>,
+    cycles_alert: Option<CyclesAlert>,
+    chains: Vec<ChainDefinition>,
+}
+
+/// A chain added to a [`ChainManagerBuilder`].
+struct ChainDefinition {
+    chain_id: ChainId,
+    config: IcpConfig,
+    derivation_path: Option<Vec<Vec<u8>>>,
+}
+
+impl<M: Memory + 'static> fmt::Debug for ChainManagerBuilder<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChainManagerBuilder")
+            .field("ecdsa_key_name", &self.ecdsa_key_name)
+            .field("derivation_path_prefix", &self.derivation_path_prefix)
+            .field("cycles_budget", &self.cycles_budget)
+            .field("chains", &self.chains.iter().map(|chain| chain.chain_id).collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<M: Memory + 'static> ChainManagerBuilder<M> {
+    /// Add a chain, signed for with the default derivation path of the chain, see
+    /// [`chain_derivation_path`].
+    ///
+    /// Every chain shares the cycles budget and alert of the manager, see
+    /// [`with_cycles_budget`](Self::with_cycles_budget). If none is set on the manager, those
+    /// of the config of the first chain added are shared instead.
+    pub fn chain(mut self, chain_id: ChainId, config: IcpConfig) -> Self {
+        self.chains.push(ChainDefinition { chain_id, config, derivation_path: None });
+        self
+    }
+
+    /// Add a chain, signed for with the given derivation path.
+    pub fn chain_with_derivation_path(
+        mut self,
+        chain_id: ChainId,
+        config: IcpConfig,
+        derivation_path: Vec<Vec<u8>>,
+    ) -> Self {
+        self.chains.push(ChainDefinition {
+            chain_id,
+            config,
+            derivation_path: Some(derivation_path),
+        });
+        self
+    }
+
+    /// Set the prefix of the default derivation paths of the chains. Empty by default.
+    pub fn with_derivation_path_prefix(mut self, prefix: Vec<Vec<u8>>) -> Self {
+        self.derivation_path_prefix = prefix;
+        self
+    }
+
+    /// Set the cycles budget shared by every chain. Unlimited by default.
+    pub const fn with_cycles_budget(mut self, cycles_budget: CyclesBudget) -> Self {
+        self.cycles_budget = Some(cycles_budget);
+        self
+    }
+
+    /// Set the cycles alert shared by every chain.
+    pub fn with_cycles_alert(mut self, cycles_alert: CyclesAlert) -> Self {
+        self.cycles_alert = Some(cycles_alert);
+        self
+    }
+
+    /// Create the signer and provider of every chain.
+    ///
+    /// The public key of each signer is requested from the management canister, so this must
+    /// be called from an update method or a timer, not from `init` or `post_upgrade`.
+    pub async fn build(self) -> Result<ChainManager<M>, IcpSignerError> {
+        let mut chains = BTreeMap::new();
+        let mut shared: Option<IcpTransport> = None;
+        for ChainDefinition { chain_id, mut config, derivation_path } in self.chains {
+            config = match &shared {
+                Some(transport) => config.share_cycles_budget(transport),
+                None => {
+                    if let Some(cycles_budget) = self.cycles_budget {
+                        config = config.set_cycles_budget(cycles_budget);
+                    }
+                    if let Some(cycles_alert) = self.cycles_alert.clone() {
+                        config = config.set_cycles_alert(cycles_alert);
+                    }
+                    config
+                }
+            };
+            let derivation_path = derivation_path
+                .unwrap_or_else(|| chain_derivation_path(&self.derivation_path_prefix, chain_id));
+            let signer =
+                IcpSigner::new(derivation_path, &self.ecdsa_key_name, Some(chain_id)).await?;
+            let provider = ProviderBuilder::new()
+                .filler(GasFiller)
+                .filler(NonceFiller::new(self.nonce_manager.clone()))
+                .filler(ChainIdFiller::new(Some(chain_id)))
+                .wallet(EthereumWallet::from(signer.clone()))
+                .on_icp(config);
+            shared.get_or_insert_with(|| provider.client().transport().clone());
+            chains.insert(chain_id, Chain { provider, signer });
+        }
+        Ok(ChainManager { chains })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derivation_paths_are_distinct_per_chain() {
+        let prefix = vec![b"treasury".to_vec()];
+        assert_eq!(
+            chain_derivation_path(&prefix, 1),
+            [b"treasury".to_vec(), vec![0, 0, 0, 0, 0, 0, 0, 1]]
+        );
+        assert_ne!(chain_derivation_path(&prefix, 1), chain_derivation_path(&prefix, 8453));
+        assert_eq!(chain_derivation_path(&[], 10), [vec![0, 0, 0, 0, 0, 0, 0, 10]]);
+    }
+}
@@ -0,0 +1,694 @@
+//! An event indexer for ICP, writing decoded contract events to stable memory.
+
+use crate::stable::StableB256;
+use alloy_eips::BlockNumberOrTag;
+use alloy_primitives::{Address, LogData, B256, U64};
+use alloy_rpc_client::{BatchRequest, RpcClientInner, WeakClient};
+use alloy_rpc_types_eth::{Block, Filter, Log};
+use alloy_sol_types::SolEvent;
+use alloy_transport::{Transport, TransportErrorKind, TransportResult};
+use alloy_transport_icp::{RequestContext, RequestPriority, TraceId};
+use ic_cdk_timers::{set_timer_interval, TimerId};
+use ic_stable_structures::{storable::Bound, Memory, StableBTreeMap, Storable};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    fmt,
+    ops::{Bound as RangeBound, RangeBounds},
+    rc::Rc,
+    thread::LocalKey,
+    time::Duration,
+};
+
+/// The position of an indexed event on chain. Keys order events as they were emitted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EventKey {
+    /// The block the event was emitted in.
+    pub block_number: u64,
+    /// The index of the log of the event in its block.
+    pub log_index: u64,
+}
+
+impl EventKey {
+    const fn first_of(block_number: u64) -> Self {
+        Self { block_number, log_index: 0 }
+    }
+
+    const fn last_of(block_number: u64) -> Self {
+        Self { block_number, log_index: u64::MAX }
+    }
+}
+
+impl Storable for EventKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = Vec::with_capacity(16);
+        bytes.extend_from_slice(&self.block_number.to_be_bytes());
+        bytes.extend_from_slice(&self.log_index.to_be_bytes());
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        let (block_number, log_index) = bytes.split_at(8);
+        Self {
+            block_number: u64::from_be_bytes(block_number.try_into().unwrap()),
+            log_index: u64::from_be_bytes(log_index.try_into().unwrap()),
+        }
+    }
+
+    const BOUND: Bound = Bound::Bounded { max_size: 16, is_fixed_size: true };
+}
+
+/// A log indexed by an [`Indexer`], as persisted in stable memory.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedLog {
+    /// The name of the event definition the log was indexed for.
+    pub event: String,
+    /// The contract that emitted the log.
+    pub address: Address,
+    /// The hash of the block the log was emitted in.
+    pub block_hash: B256,
+    /// The hash of the transaction that emitted the log.
+    pub transaction_hash: Option<B256>,
+    /// The topics and data of the log.
+    pub data: LogData,
+}
+
+impl IndexedLog {
+    /// Decode the log as the given event.
+    pub fn decode<E: SolEvent>(&self) -> alloy_sol_types::Result<E> {
+        E::decode_log_data(&self.data, true)
+    }
+}
+
+impl Storable for IndexedLog {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(serde_json::to_vec(self).expect("indexed logs are serializable"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        serde_json::from_slice(&bytes).expect("invalid indexed log in stable memory")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// An event read from an [`IndexerStore`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexedEvent<E> {
+    /// The position of the event on chain.
+    pub key: EventKey,
+    /// The contract that emitted the event.
+    pub address: Address,
+    /// The hash of the block the event was emitted in.
+    pub block_hash: B256,
+    /// The hash of the transaction that emitted the event.
+    pub transaction_hash: Option<B256>,
+    /// The decoded event.
+    pub event: E,
+}
+
+/// The stable map holding the logs indexed by an [`Indexer`].
+pub type StableIndexedLogs<M> = StableBTreeMap<EventKey, IndexedLog, M>;
+
+/// The stable map holding the hashes of the recent blocks indexed by an [`Indexer`], used to
+/// detect reorgs.
+pub type StableBlockHashes<M> = StableBTreeMap<u64, StableB256, M>;
+
+/// The tables written by an [`Indexer`], with helpers to query the indexed events.
+///
+/// The maps are owned by thread locals, as stable structures are not [`Send`].
+pub struct IndexerStore<M: Memory + 'static> {
+    logs: &'static LocalKey<RefCell<StableIndexedLogs<M>>>,
+    blocks: &'static LocalKey<RefCell<StableBlockHashes<M>>>,
+}
+
+impl<M: Memory + 'static> Clone for IndexerStore<M> {
+    fn clone(&self) -> Self {
+        Self { logs: self.logs, blocks: self.blocks }
+    }
+}
+
+impl<M: Memory + 'static> fmt::Debug for IndexerStore<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IndexerStore").finish_non_exhaustive()
+    }
+}
+
+impl<M: Memory + 'static> IndexerStore<M> {
+    /// Create a new store keeping indexed logs and recent block hashes in the given maps.
+    pub const fn new(
+        logs: &'static LocalKey<RefCell<StableIndexedLogs<M>>>,
+        blocks: &'static LocalKey<RefCell<StableBlockHashes<M>>>,
+    ) -> Self {
+        Self { logs, blocks }
+    }
+
+    /// Returns the last block indexed, if any.
+    pub fn last_indexed_block(&self) -> Option<u64> {
+        self.blocks.with_borrow(|blocks| blocks.last_key_value().map(|(number, _)| number))
+    }
+
+    /// Returns the number of logs indexed, for every event.
+    pub fn len(&self) -> u64 {
+        self.logs.with_borrow(|logs| logs.len())
+    }
+
+    /// Returns `true` if no log has been indexed.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the log indexed at the given position, if any.
+    pub fn get(&self, key: &EventKey) -> Option<IndexedLog> {
+        self.logs.with_borrow(|logs| logs.get(key))
+    }
+
+    /// Returns the events with the given name emitted in the given blocks, oldest first.
+    ///
+    /// Logs that no longer decode as the event, e.g. after its definition changed, are skipped.
+    pub fn events<E: SolEvent>(
+        &self,
+        name: &str,
+        blocks: impl RangeBounds<u64>,
+    ) -> Vec<IndexedEvent<E>> {
+        let start = match blocks.start_bound() {
+            RangeBound::Included(block) => RangeBound::Included(EventKey::first_of(*block)),
+            RangeBound::Excluded(block) => RangeBound::Excluded(EventKey::last_of(*block)),
+            RangeBound::Unbounded => RangeBound::Unbounded,
+        };
+        let end = match blocks.end_bound() {
+            RangeBound::Included(block) => RangeBound::Included(EventKey::last_of(*block)),
+            RangeBound::Excluded(block) => RangeBound::Excluded(EventKey::first_of(*block)),
+            RangeBound::Unbounded => RangeBound::Unbounded,
+        };
+        self.logs.with_borrow(|logs| {
+            logs.range((start, end)).filter_map(|(key, log)| decode(name, key, log)).collect()
+        })
+    }
+
+    /// Returns the latest events with the given name, up to the given number, newest first.
+    ///
+    /// Logs that no longer decode as the event are skipped.
+    pub fn latest_events<E: SolEvent>(&self, name: &str, limit: usize) -> Vec<IndexedEvent<E>> {
+        self.logs.with_borrow(|logs| {
+            logs.iter().rev().filter_map(|(key, log)| decode(name, key, log)).take(limit).collect()
+        })
+    }
+
+    /// Write the matching logs of a range of blocks, and the hash of its last block.
+    fn index(
+        &self,
+        events: &[EventDefinition],
+        logs: Vec<Log>,
+        block_number: u64,
+        block_hash: B256,
+    ) -> TransportResult<()> {
+        // The block was replaced between the requests for its logs and its hash.
+        if logs
+            .iter()
+            .any(|log| log.block_number == Some(block_number) && log.block_hash != Some(block_hash))
+        {
+            return Err(TransportErrorKind::custom_str(&format!(
+                "block {block_number} was reorged while indexing"
+            )));
+        }
+        self.logs.with_borrow_mut(|table| {
+            self.blocks.with_borrow_mut(|blocks| {
+                for log in logs {
+                    let Some(definition) = events.iter().find(|event| event.matches(&log)) else {
+                        continue;
+                    };
+                    let (Some(number), Some(log_index), Some(hash)) =
+                        (log.block_number, log.log_index, log.block_hash)
+                    else {
+                        continue;
+                    };
+                    let indexed = IndexedLog {
+                        event: definition.name.clone(),
+                        address: log.address(),
+                        block_hash: hash,
+                        transaction_hash: log.transaction_hash,
+                        data: log.inner.data,
+                    };
+                    table.insert(EventKey { block_number: number, log_index }, indexed);
+                    blocks.insert(number, hash.into());
+                }
+                blocks.insert(block_number, block_hash.into());
+            })
+        });
+        Ok(())
+    }
+
+    /// Remove the logs and block hashes after the given block.
+    fn rewind(&self, block_number: u64) {
+        self.logs.with_borrow_mut(|logs| {
+            let removed: Vec<_> = logs
+                .keys_range((
+                    RangeBound::Excluded(EventKey::last_of(block_number)),
+                    RangeBound::Unbounded,
+                ))
+                .collect();
+            for key in removed {
+                logs.remove(&key);
+            }
+        });
+        self.blocks.with_borrow_mut(|blocks| {
+            let removed: Vec<_> = blocks.keys_range(block_number + 1..).collect();
+            for number in removed {
+                blocks.remove(&number);
+            }
+        });
+    }
+
+    /// Forget the hashes of the blocks before the given block.
+    fn prune_block_hashes(&self, before: u64) {
+        self.blocks.with_borrow_mut(|blocks| {
+            let pruned: Vec<_> = blocks.keys_range(..before).collect();
+            for number in pruned {
+                blocks.remove(&number);
+            }
+        });
+    }
+
+    /// Returns the recent block hashes, newest first.
+    fn block_hashes(&self) -> Vec<(u64, B256)> {
+        self.blocks.with_borrow(|blocks| {
+            blocks.iter().rev().map(|(number, hash)| (number, hash.into())).collect()
+        })
+    }
+}
+
+fn decode<E: SolEvent>(name: &str, key: EventKey, log: IndexedLog) -> Option<IndexedEvent<E>> {
+    if log.event != name {
+        return None;
+    }
+    Some(IndexedEvent {
+        key,
+        event: log.decode().ok()?,
+        address: log.address,
+        block_hash: log.block_hash,
+        transaction_hash: log.transaction_hash,
+    })
+}
+
+/// An event indexed by an [`Indexer`].
+struct EventDefinition {
+    name: String,
+    addresses: Vec<Address>,
+    signature: B256,
+    decodes: fn(&LogData) -> bool,
+}
+
+impl fmt::Debug for EventDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventDefinition")
+            .field("name", &self.name)
+            .field("addresses", &self.addresses)
+            .field("signature", &self.signature)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EventDefinition {
+    fn matches(&self, log: &Log) -> bool {
+        !log.removed
+            && log.topic0() == Some(&self.signature)
+            && (self.addresses.is_empty() || self.addresses.contains(&log.address()))
+            && (self.decodes)(&log.inner.data)
+    }
+}
+
+/// Indexes contract events into stable memory: backfills the logs of past blocks, follows the
+/// chain, and rewinds the indexed events of blocks removed by a reorg.
+///
+/// Each event is defined by a name, a [`SolEvent`] and the contracts emitting it. Every poll
+/// requests the logs of all events with a single filter, batched with the header of the last
+/// block of the range, and writes the logs that decode as their event to an [`IndexerStore`],
+/// keyed by their position on chain. Events are read back, decoded, with
+/// [`IndexerStore::events`] and [`IndexerStore::latest_events`].
+///
+/// The hashes of the last [`reorg_depth`](Self::with_reorg_depth) indexed blocks are kept. Each
+/// poll first checks that the last indexed block is still part of the chain; if it is not, the
+/// events after the latest kept block still on chain are removed and indexed again. Set
+/// [`confirmations`](Self::with_confirmations) to only index blocks unlikely to be reorged.
+///
+/// Like a [`CursorWatcher`](crate::CursorWatcher), the indexer resumes from the last indexed
+/// block after an upgrade or downtime, as its progress is kept in stable memory.
+///
+/// # Examples
+///
+/// ```ignore
+/// thread_local! {
+///     static LOGS: RefCell<StableIndexedLogs<Memory>> = RefCell::new(
+///         StableBTreeMap::init(MEMORY_MANAGER.with_borrow(|m| m.get(MemoryId::new(5)))),
+///     );
+///     static BLOCKS: RefCell<StableBlockHashes<Memory>> = RefCell::new(
+///         StableBTreeMap::init(MEMORY_MANAGER.with_borrow(|m| m.get(MemoryId::new(6)))),
+///     );
+/// }
+///
+/// fn store() -> IndexerStore<Memory> {
+///     IndexerStore::new(&LOGS, &BLOCKS)
+/// }
+///
+/// // Called from both `init` and `post_upgrade`.
+/// fn start_indexer() {
+///     Indexer::new(provider().weak_client(), store())
+///         .with_event::<Transfer>("usdc_transfers", [usdc_address])
+///         .with_start_block(20_000_000)
+///         .start()
+///         .unwrap();
+/// }
+///
+/// #[ic_cdk::query]
+/// fn recent_transfers() -> Vec<String> {
+///     let transfers = store().latest_events::<Transfer>("usdc_transfers", 10);
+///     transfers.into_iter().map(|t| format!("{} -> {}: {}", t.event.from, t.event.to, t.event.value)).collect()
+/// }
+/// ```
+pub struct Indexer<T, M: Memory + 'static> {
+    client: WeakClient<T>,
+    store: IndexerStore<M>,
+    events: Vec<EventDefinition>,
+    poll_interval: Duration,
+    max_block_range: u64,
+    start_block: Option<u64>,
+    confirmations: u64,
+    reorg_depth: u64,
+}
+
+impl<T, M: Memory + 'static> fmt::Debug for Indexer<T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Indexer")
+            .field("events", &self.events)
+            .field("poll_interval", &self.poll_interval)
+            .field("max_block_range", &self.max_block_range)
+            .field("start_block", &self.start_block)
+            .field("confirmations", &self.confirmations)
+            .field("reorg_depth", &self.reorg_depth)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, M> Indexer<T, M>
+where
+    T: Transport + Clone,
+    M: Memory + 'static,
+{
+    /// Create an indexer writing to the given store, without any event.
+    pub fn new(client: WeakClient<T>, store: IndexerStore<M>) -> Self {
+        let poll_interval =
+            client.upgrade().map_or_else(|| Duration::from_secs(7), |c| c.poll_interval());
+        Self {
+            client,
+            store,
+            events: Vec::new(),
+            poll_interval,
+            max_block_range: 500,
+            start_block: None,
+            confirmations: 0,
+            reorg_depth: 64,
+        }
+    }
+
+    /// Index the event emitted by the given contracts, or by any contract if none is given,
+    /// under the given name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the event is anonymous, as anonymous events can't be filtered by signature.
+    pub fn with_event<E: SolEvent>(
+        mut self,
+        name: impl Into<String>,
+        addresses: impl IntoIterator<Item = Address>,
+    ) -> Self {
+        assert!(!E::ANONYMOUS, "anonymous events can't be indexed");
+        self.events.push(EventDefinition {
+            name: name.into(),
+            addresses: addresses.into_iter().collect(),
+            signature: E::SIGNATURE_HASH,
+            decodes: |data| E::decode_log_data(data, true).is_ok(),
+        });
+        self
+    }
+
+    /// Sets the duration between polls.
+    pub const fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets the maximum number of blocks whose logs are requested at once. Defaults to 500.
+    pub const fn with_max_block_range(mut self, max_block_range: u64) -> Self {
+        self.max_block_range = if max_block_range == 0 { 1 } else { max_block_range };
+        self
+    }
+
+    /// Sets the first block to index if nothing has been indexed yet. Defaults to the latest
+    /// block when the indexer first polls.
+    pub const fn with_start_block(mut self, block: u64) -> Self {
+        self.start_block = Some(block);
+        self
+    }
+
+    /// Sets the number of blocks on top of a block before it is indexed. Defaults to 0,
+    /// indexing up to the latest block.
+    pub const fn with_confirmations(mut self, confirmations: u64) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    /// Sets the number of recent blocks whose hashes are kept to detect reorgs. A reorg deeper
+    /// than this fails every poll until the indexer is reset. Defaults to 64.
+    pub const fn with_reorg_depth(mut self, reorg_depth: u64) -> Self {
+        self.reorg_depth = if reorg_depth == 0 { 1 } else { reorg_depth };
+        self
+    }
+
+    /// Returns the store the indexer writes to.
+    pub const fn store(&self) -> &IndexerStore<M> {
+        &self.store
+    }
+
+    /// The filter matching the logs of every event.
+    fn filter(&self) -> Filter {
+        let signatures: Vec<_> = self.events.iter().map(|event| event.signature).collect();
+        let filter = Filter::new().event_signature(signatures);
+        if self.events.iter().any(|event| event.addresses.is_empty()) {
+            return filter;
+        }
+        filter.address(
+            self.events.iter().flat_map(|event| event.addresses.clone()).collect::<Vec<_>>(),
+        )
+    }
+
+    /// Starts the indexer.
+    ///
+    /// Like a [`CursorWatcher`](crate::CursorWatcher), the indexer only holds a [`WeakClient`],
+    /// and stops once the client is dropped. Timers do not survive upgrades, so the indexer must
+    /// be started again in `post_upgrade`, e.g. with
+    /// [`IcpState::with_indexer`](crate::IcpState::with_indexer).
+    pub fn start(self) -> Result<TimerId, String>
+    where
+        T: 'static,
+    {
+        if self.client.strong_count() == 0 {
+            return Err("Client has been dropped.".into());
+        }
+        if self.events.is_empty() {
+            return Err("No event to index.".into());
+        }
+        let poll_interval = self.poll_interval;
+        let indexer = Rc::new(self);
+        let polling = Rc::new(Cell::new(false));
+        let timer_id = Rc::new(Cell::new(None));
+
+        let poll = {
+            let timer_id = timer_id.clone();
+            move || {
+                let Some(client) = indexer.client.upgrade() else {
+                    if let Some(timer_id) = timer_id.take() {
+                        ic_cdk::println!("Client has been dropped, stopping indexer.");
+                        ic_cdk_timers::clear_timer(timer_id);
+                    }
+                    return;
+                };
+                // A slow backfill may still be running.
+                if polling.replace(true) {
+                    return;
+                }
+
+                let (indexer, polling) = (indexer.clone(), polling.clone());
+                ic_cdk::spawn(async move {
+                    let trace_id = TraceId::new();
+                    let context = RequestContext::new()
+                        .with_priority(RequestPriority::Polling)
+                        .with_trace_id(trace_id);
+                    if let Err(e) = context.scope(indexer.poll(&client)).await {
+                        ic_cdk::println!("[trace {trace_id}] Indexer failed: {:?}", e);
+                    }
+                    polling.set(false);
+                });
+            }
+        };
+
+        let id = set_timer_interval(poll_interval, poll.clone());
+        timer_id.set(Some(id));
+        poll();
+        Ok(id)
+    }
+
+    /// Index every block from the last indexed block up to the latest confirmed block.
+    async fn poll(&self, client: &RpcClientInner<T>) -> TransportResult<()> {
+        let latest = client.request::<_, U64>("eth_blockNumber", ()).await?.to::<u64>();
+        let head = latest.saturating_sub(self.confirmations);
+        let mut next = match self.store.last_indexed_block() {
+            Some(block) => self.resolve_reorg(client, block).await? + 1,
+            None => self.start_block.unwrap_or(head),
+        };
+        let filter = self.filter();
+        while next <= head {
+            let to = head.min(next.saturating_add(self.max_block_range - 1));
+            let number = BlockNumberOrTag::Number(to);
+            let mut batch = BatchRequest::new(client);
+            let logs = batch.add_call::<_, Vec<Log>>(
+                "eth_getLogs",
+                &(filter.clone().from_block(next).to_block(to),),
+            )?;
+            let block =
+                batch.add_call::<_, Option<Block>>("eth_getBlockByNumber", &(number, false))?;
+            batch.send().await?;
+
+            let (logs, Some(block)) = (logs.await?, block.await?) else {
+                return Err(TransportErrorKind::custom_str(&format!("block {to} not found")));
+            };
+            self.store.index(&self.events, logs, to, block.header.hash)?;
+            self.store.prune_block_hashes(to.saturating_sub(self.reorg_depth - 1));
+            next = to + 1;
+        }
+        Ok(())
+    }
+
+    /// Check that the last indexed block is still on chain, rewinding to the latest kept block
+    /// that is if not. Returns the last indexed block after the rewind.
+    async fn resolve_reorg(&self, client: &RpcClientInner<T>, last: u64) -> TransportResult<u64> {
+        let number = BlockNumberOrTag::Number(last);
+        let block: Option<Block> = client.request("eth_getBlockByNumber", (number, false)).await?;
+        let hashes = self.store.block_hashes();
+        if block.is_some_and(|block| Some(block.header.hash) == hashes.first().map(|(_, h)| *h)) {
+            return Ok(last);
+        }
+
+        // Request every older kept block at once, to find the latest one still on chain.
+        let mut batch = BatchRequest::new(client);
+        let mut blocks = Vec::with_capacity(hashes.len());
+        for (number, _) in hashes.iter().skip(1) {
+            let number = BlockNumberOrTag::Number(*number);
+            blocks.push(
+                batch.add_call::<_, Option<Block>>("eth_getBlockByNumber", &(number, false))?,
+            );
+        }
+        if !blocks.is_empty() {
+            batch.send().await?;
+        }
+        for ((number, hash), block) in hashes.into_iter().skip(1).zip(blocks) {
+            if block.await?.is_some_and(|block| block.header.hash == hash) {
+                ic_cdk::println!(
+                    "Reorg detected, rewinding indexer from block {last} to {number}."
+                );
+                self.store.rewind(number);
+                return Ok(number);
+            }
+        }
+        Err(TransportErrorKind::custom_str(&format!(
+            "reorg deeper than the {} blocks kept by the indexer",
+            self.reorg_depth
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{address, U256};
+    use alloy_sol_types::sol;
+    use ic_stable_structures::VectorMemory;
+
+    sol! {
+        event Transfer(address indexed from, address indexed to, uint256 value);
+    }
+
+    thread_local! {
+        static LOGS: RefCell<StableIndexedLogs<VectorMemory>> =
+            RefCell::new(StableBTreeMap::init(VectorMemory::default()));
+        static BLOCKS: RefCell<StableBlockHashes<VectorMemory>> =
+            RefCell::new(StableBTreeMap::init(VectorMemory::default()));
+    }
+
+    const TOKEN: Address = address!("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48");
+
+    fn transfer(block_number: u64, log_index: u64, value: u64) -> Log {
+        let event = Transfer { from: Address::ZERO, to: TOKEN, value: U256::from(value) };
+        Log {
+            inner: alloy_primitives::Log { address: TOKEN, data: event.encode_log_data() },
+            block_hash: Some(B256::with_last_byte(block_number as u8)),
+            block_number: Some(block_number),
+            log_index: Some(log_index),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn event_keys_order_by_position() {
+        let keys = [
+            EventKey { block_number: 1, log_index: 300 },
+            EventKey { block_number: 256, log_index: 0 },
+            EventKey { block_number: 256, log_index: 1 },
+        ];
+        for pair in keys.windows(2) {
+            assert!(pair[0].to_bytes() < pair[1].to_bytes());
+            assert_eq!(EventKey::from_bytes(pair[0].to_bytes()), pair[0]);
+        }
+    }
+
+    #[test]
+    fn indexes_queries_and_rewinds() {
+        let store = IndexerStore::new(&LOGS, &BLOCKS);
+        let events = [EventDefinition {
+            name: "transfers".into(),
+            addresses: vec![TOKEN],
+            signature: Transfer::SIGNATURE_HASH,
+            decodes: |data| Transfer::decode_log_data(data, true).is_ok(),
+        }];
+
+        let mut other = transfer(10, 1, 0);
+        other.inner.address = Address::ZERO;
+        let logs = vec![transfer(10, 0, 1), other, transfer(12, 3, 2), transfer(20, 0, 3)];
+        store.index(&events, logs, 20, B256::with_last_byte(20)).unwrap();
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.last_indexed_block(), Some(20));
+
+        let values = |events: Vec<IndexedEvent<Transfer>>| {
+            events.into_iter().map(|event| event.event.value.to::<u64>()).collect::<Vec<_>>()
+        };
+        assert_eq!(values(store.events("transfers", 10..=12)), [1, 2]);
+        assert_eq!(values(store.events("transfers", 11..)), [2, 3]);
+        assert_eq!(values(store.latest_events("transfers", 2)), [3, 2]);
+        assert!(store.events::<Transfer>("approvals", ..).is_empty());
+
+        // The last block of the range was replaced while its logs were requested.
+        assert!(store.index(&events, vec![transfer(21, 0, 4)], 21, B256::ZERO).is_err());
+
+        store.rewind(12);
+        assert_eq!(values(store.events("transfers", ..)), [1, 2]);
+        assert_eq!(store.last_indexed_block(), Some(12));
+        assert_eq!(
+            store.block_hashes(),
+            [(12, B256::with_last_byte(12)), (10, B256::with_last_byte(10))]
+        );
+
+        store.prune_block_hashes(12);
+        assert_eq!(store.block_hashes(), [(12, B256::with_last_byte(12))]);
+    }
+}
@@ -0,0 +1,125 @@
+//! A watcher of pending transactions for RPC providers without pending transaction filters.
+
+use alloy_eips::BlockNumberOrTag;
+use alloy_primitives::B256;
+use alloy_rpc_client::{IcpPollerBuilder, IcpPollerHandle, PollBackoff, WeakClient};
+use alloy_transport::{Transport, TransportError};
+use alloy_transport_icp::RequestPriority;
+use serde::Deserialize;
+use std::{collections::HashSet, ops::ControlFlow, time::Duration};
+
+/// The transactions of a pending block. Pending blocks have no hash or number, so they are not
+/// deserialized as a whole block.
+#[derive(Clone, Debug, Default, Deserialize)]
+struct PendingBlock {
+    #[serde(default)]
+    transactions: Vec<B256>,
+}
+
+/// The hashes of the transactions of the last pending block, to deliver only new ones.
+#[derive(Debug, Default)]
+struct SeenTransactions(HashSet<B256>);
+
+impl SeenTransactions {
+    /// Returns the hashes not in the previous pending block, in block order, and remembers the
+    /// hashes of this one.
+    fn update(&mut self, hashes: Vec<B256>) -> Vec<B256> {
+        let new = hashes.iter().filter(|hash| !self.0.contains(*hash)).copied().collect();
+        self.0 = hashes.into_iter().collect();
+        new
+    }
+}
+
+/// Watches for new pending transactions by polling the provider with
+/// `eth_getBlockByNumber("pending")`, see [`Provider::watch_pending_block_transactions`].
+///
+/// Each poll delivers the hashes of the transactions of the pending block that were not in the
+/// pending block of the previous poll. Only the previous pending block is remembered, so a
+/// transaction leaving the pending block and entering it again is delivered twice.
+///
+/// [`Provider::watch_pending_block_transactions`]: crate::Provider::watch_pending_block_transactions
+#[derive(Debug)]
+pub struct PendingTransactionsWatcher<T> {
+    poller: IcpPollerBuilder<T, (BlockNumberOrTag, bool), Option<PendingBlock>>,
+}
+
+impl<T> PendingTransactionsWatcher<T>
+where
+    T: Transport + Clone + 'static,
+{
+    /// Create a watcher of the pending transactions of the client.
+    pub fn new(client: WeakClient<T>) -> Self {
+        Self {
+            poller: IcpPollerBuilder::new(
+                client,
+                "eth_getBlockByNumber",
+                (BlockNumberOrTag::Pending, false),
+            ),
+        }
+    }
+
+    /// Sets the duration between polls.
+    pub fn with_poll_interval(self, poll_interval: Duration) -> Self {
+        Self { poller: self.poller.with_poll_interval(poll_interval) }
+    }
+
+    /// Sets a limit on the number of successful polls.
+    pub fn with_limit(self, limit: Option<usize>) -> Self {
+        Self { poller: self.poller.with_limit(limit) }
+    }
+
+    /// Sets the priority of the poll requests, see [`IcpPollerBuilder::with_priority`].
+    pub fn with_priority(self, priority: RequestPriority) -> Self {
+        Self { poller: self.poller.with_priority(priority) }
+    }
+
+    /// Sets the backoff policy of failed polls, see [`IcpPollerBuilder::with_backoff`].
+    pub fn with_backoff(self, backoff: PollBackoff) -> Self {
+        Self { poller: self.poller.with_backoff(backoff) }
+    }
+
+    /// Sets the handler of failed polls, see [`IcpPollerBuilder::with_error_handler`].
+    pub fn with_error_handler<E>(self, error_handler: E) -> Self
+    where
+        E: FnMut(TransportError) -> ControlFlow<()> + 'static,
+    {
+        Self { poller: self.poller.with_error_handler(error_handler) }
+    }
+
+    /// Starts the watcher, calling the handler with the hashes of the new pending transactions
+    /// of each poll. The handler is not called when there are none.
+    pub fn start<F>(self, mut handler: F) -> Result<IcpPollerHandle, String>
+    where
+        F: FnMut(Vec<B256>) + 'static,
+    {
+        let mut seen = SeenTransactions::default();
+        self.poller.start(move |block: Option<PendingBlock>| {
+            let new = seen.update(block.unwrap_or_default().transactions);
+            if !new.is_empty() {
+                handler(new);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delivers_only_new_pending_transactions() {
+        let (a, b, c) = (B256::repeat_byte(1), B256::repeat_byte(2), B256::repeat_byte(3));
+        let mut seen = SeenTransactions::default();
+
+        assert_eq!(seen.update(vec![a, b]), [a, b]);
+        assert_eq!(seen.update(vec![a, b, c]), [c]);
+        assert_eq!(seen.update(vec![c]), [] as [B256; 0]);
+        assert_eq!(seen.update(vec![a, c]), [a]);
+
+        let block: PendingBlock = serde_json::from_str(&format!(
+            r#"{{"hash":null,"number":null,"transactions":["{a}"]}}"#
+        ))
+        .unwrap();
+        assert_eq!(block.transactions, [a]);
+    }
+}
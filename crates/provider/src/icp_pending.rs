@@ -0,0 +1,474 @@
+//! Upgrade-safe tracking of pending transactions for ICP.
+
+use alloy_network::{Ethereum, Network, ReceiptResponse};
+use alloy_primitives::{keccak256, Bytes, TxHash, U64};
+use alloy_rpc_client::{RpcClientInner, WeakClient};
+use alloy_transport::{Transport, TransportResult};
+use alloy_transport_icp::{RequestContext, RequestPriority, TraceId};
+use ic_cdk_timers::{set_timer_interval, TimerId};
+use ic_stable_structures::{storable::Bound, Memory, StableBTreeMap, Storable};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    fmt,
+    marker::PhantomData,
+    rc::Rc,
+    thread::LocalKey,
+    time::Duration,
+};
+
+/// The status of a [`TrackedTransaction`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrackedStatus {
+    /// The transaction has not been included with enough confirmations yet.
+    Pending,
+    /// The transaction was included and executed successfully.
+    Confirmed {
+        /// The block the transaction was included in.
+        block_number: u64,
+    },
+    /// The transaction was included but reverted.
+    Reverted {
+        /// The block the transaction was included in.
+        block_number: u64,
+    },
+    /// The deadline passed before the transaction was confirmed.
+    Expired,
+}
+
+impl TrackedStatus {
+    /// Returns `true` if the transaction is still pending.
+    pub const fn is_pending(&self) -> bool {
+        matches!(self, Self::Pending)
+    }
+}
+
+/// A transaction tracked by a [`PendingTransactionTracker`], as persisted in stable memory.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackedTransaction {
+    /// The hash of the transaction.
+    pub hash: TxHash,
+    /// The signed, EIP-2718 encoded transaction, used to rebroadcast it.
+    pub raw: Bytes,
+    /// The nonce of the transaction.
+    pub nonce: u64,
+    /// The current status of the transaction.
+    pub status: TrackedStatus,
+    /// The number of confirmations required for the transaction to be confirmed.
+    pub required_confirmations: u64,
+    /// The time the transaction started being tracked, in nanoseconds since the epoch.
+    pub submitted_at_ns: u64,
+    /// The time after which the transaction expires if still pending, in nanoseconds since
+    /// the epoch.
+    pub deadline_ns: Option<u64>,
+    /// The number of times the transaction was rebroadcast.
+    pub rebroadcasts: u32,
+    /// The number of times the transaction was replaced with higher fees, see
+    /// [`PendingTransactionTracker::replace`].
+    #[serde(default)]
+    pub fee_bumps: u32,
+    /// The hashes of the transactions this one replaced, oldest first. Any of them may still be
+    /// included instead of this one.
+    #[serde(default)]
+    pub replaced: Vec<TxHash>,
+}
+
+impl TrackedTransaction {
+    /// Returns `true` if the deadline of the transaction passed at the given time, in
+    /// nanoseconds since the epoch.
+    pub fn is_expired(&self, now_ns: u64) -> bool {
+        self.deadline_ns.is_some_and(|deadline| now_ns > deadline)
+    }
+
+    /// Returns `true` if the transaction has enough confirmations once included in the given
+    /// block, the latest block counting as the first confirmation.
+    pub const fn is_confirmed(&self, block_number: u64, latest: u64) -> bool {
+        latest + 1 >= block_number + self.required_confirmations
+    }
+
+    /// Returns the hashes whose receipts are polled: this transaction, then the transactions
+    /// it replaced, most recent first.
+    fn hashes(&self) -> impl Iterator<Item = TxHash> + '_ {
+        std::iter::once(self.hash).chain(self.replaced.iter().rev().copied())
+    }
+}
+
+impl Storable for TrackedTransaction {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(serde_json::to_vec(self).expect("tracked transactions are serializable"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        serde_json::from_slice(&bytes).expect("invalid tracked transaction in stable memory")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// The stable map holding the tracked transactions, keyed by hash.
+pub type StableTrackedTransactions<M> = StableBTreeMap<[u8; 32], TrackedTransaction, M>;
+
+/// Tracks sent transactions until they are confirmed, persisting them in a [`StableBTreeMap`]
+/// so that confirmations are not lost across canister upgrades.
+///
+/// [`PendingTransactionBuilder`](crate::PendingTransactionBuilder) relies on the heartbeat,
+/// which is not available on ICP, and its state lives on the heap. Instead, the tracker polls
+/// the receipt of every pending transaction on a timer. Transactions are written to stable
+/// memory as soon as they are tracked, so no `pre_upgrade` step is needed: restarting the
+/// tracker with [`start`](Self::start) in `post_upgrade` resumes watching the transactions
+/// that are still pending.
+///
+/// The map is owned by a thread local, as stable structures are not [`Send`].
+///
+/// # Examples
+///
+/// ```ignore
+/// thread_local! {
+///     static TXS: RefCell<StableTrackedTransactions<VirtualMemory<DefaultMemoryImpl>>> =
+///         RefCell::new(StableBTreeMap::init(
+///             MEMORY_MANAGER.with_borrow(|m| m.get(MemoryId::new(3))),
+///         ));
+/// }
+///
+/// // Called from both `init` and `post_upgrade`.
+/// fn start_tracker() {
+///     tracker().start(|tx| ic_cdk::println!("{}: {:?}", tx.hash, tx.status)).unwrap();
+/// }
+///
+/// fn tracker() -> PendingTransactionTracker<IcpTransport, VirtualMemory<DefaultMemoryImpl>> {
+///     PendingTransactionTracker::new(provider().weak_client(), &TXS)
+/// }
+///
+/// let raw = tx_envelope.encoded_2718();
+/// provider.send_raw_transaction(&raw).await?;
+/// tracker().track(raw.into(), nonce, 1, Some(Duration::from_secs(600)));
+/// ```
+pub struct PendingTransactionTracker<T, M: Memory + 'static, N = Ethereum> {
+    client: WeakClient<T>,
+    transactions: &'static LocalKey<RefCell<StableTrackedTransactions<M>>>,
+    poll_interval: Duration,
+    rebroadcast: bool,
+    _pd: PhantomData<fn() -> N>,
+}
+
+impl<T, M: Memory + 'static, N> Clone for PendingTransactionTracker<T, M, N> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            transactions: self.transactions,
+            poll_interval: self.poll_interval,
+            rebroadcast: self.rebroadcast,
+            _pd: PhantomData,
+        }
+    }
+}
+
+impl<T, M: Memory + 'static, N> fmt::Debug for PendingTransactionTracker<T, M, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PendingTransactionTracker")
+            .field("poll_interval", &self.poll_interval)
+            .field("rebroadcast", &self.rebroadcast)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, M, N> PendingTransactionTracker<T, M, N>
+where
+    T: Transport + Clone,
+    M: Memory + 'static,
+    N: Network,
+{
+    /// Create a new tracker persisting transactions in the given map.
+    pub fn new(
+        client: WeakClient<T>,
+        transactions: &'static LocalKey<RefCell<StableTrackedTransactions<M>>>,
+    ) -> Self {
+        let poll_interval =
+            client.upgrade().map_or_else(|| Duration::from_secs(7), |c| c.poll_interval());
+        Self { client, transactions, poll_interval, rebroadcast: false, _pd: PhantomData }
+    }
+
+    /// Sets the duration between polls.
+    pub const fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets whether pending transactions without a receipt are rebroadcast on every poll, e.g.
+    /// because they may have been dropped from the mempool while the canister was upgraded.
+    /// Disabled by default.
+    pub const fn with_rebroadcast(mut self, rebroadcast: bool) -> Self {
+        self.rebroadcast = rebroadcast;
+        self
+    }
+
+    /// Start tracking a sent transaction, returning its hash.
+    ///
+    /// If `timeout` is set, the transaction expires if it is not confirmed in time.
+    pub fn track(
+        &self,
+        raw: Bytes,
+        nonce: u64,
+        required_confirmations: u64,
+        timeout: Option<Duration>,
+    ) -> TxHash {
+        let now = ic_cdk::api::time();
+        let tx = TrackedTransaction {
+            hash: keccak256(&raw),
+            raw,
+            nonce,
+            status: TrackedStatus::Pending,
+            required_confirmations: required_confirmations.max(1),
+            submitted_at_ns: now,
+            deadline_ns: timeout.map(|timeout| now.saturating_add(timeout.as_nanos() as u64)),
+            rebroadcasts: 0,
+            fee_bumps: 0,
+            replaced: Vec::new(),
+        };
+        let hash = tx.hash;
+        self.transactions.with_borrow_mut(|txs| txs.insert(hash.0, tx));
+        hash
+    }
+
+    /// Track a sent replacement of a pending transaction, e.g. the same transaction with bumped
+    /// fees, returning its hash. Returns `None` if the transaction is not pending.
+    ///
+    /// The replacement keeps the nonce, confirmations and deadline of the replaced transaction,
+    /// and carries its escalation state: its fee bump level is one higher, and the replaced hash
+    /// is added to its replacement history.
+    pub fn replace(&self, hash: TxHash, raw: Bytes) -> Option<TxHash> {
+        self.transactions.with_borrow_mut(|txs| {
+            let replaced = txs.get(&hash.0).filter(|tx| tx.status.is_pending())?;
+            txs.remove(&hash.0);
+            let mut history = replaced.replaced;
+            history.push(hash);
+            let tx = TrackedTransaction {
+                hash: keccak256(&raw),
+                raw,
+                rebroadcasts: 0,
+                fee_bumps: replaced.fee_bumps + 1,
+                replaced: history,
+                ..replaced
+            };
+            let hash = tx.hash;
+            txs.insert(hash.0, tx);
+            Some(hash)
+        })
+    }
+
+    /// Returns the tracked transaction with the given hash.
+    pub fn get(&self, hash: TxHash) -> Option<TrackedTransaction> {
+        self.transactions.with_borrow(|txs| txs.get(&hash.0))
+    }
+
+    /// Returns all transactions that are still pending.
+    pub fn pending(&self) -> Vec<TrackedTransaction> {
+        self.transactions.with_borrow(|txs| {
+            txs.iter().map(|(_, tx)| tx).filter(|tx| tx.status.is_pending()).collect()
+        })
+    }
+
+    /// Stop tracking the transaction with the given hash, e.g. once its final status has been
+    /// handled.
+    pub fn remove(&self, hash: TxHash) -> Option<TrackedTransaction> {
+        self.transactions.with_borrow_mut(|txs| txs.remove(&hash.0))
+    }
+
+    /// Starts polling the pending transactions, calling the handler whenever the status of a
+    /// transaction changes.
+    ///
+    /// Like [`IcpPollerBuilder`](alloy_rpc_client::IcpPollerBuilder), the tracker only holds a
+    /// [`WeakClient`], and stops once the client is dropped. Timers do not survive upgrades, so
+    /// the tracker must be started again in `post_upgrade`.
+    pub fn start<F>(&self, handler: F) -> Result<TimerId, String>
+    where
+        F: FnMut(&TrackedTransaction) + 'static,
+        T: 'static,
+    {
+        if self.client.strong_count() == 0 {
+            return Err("Client has been dropped.".into());
+        }
+        let poll_interval = self.poll_interval;
+        let tracker = Rc::new(self.clone());
+        let handler = Rc::new(RefCell::new(handler));
+        let polling = Rc::new(Cell::new(false));
+        let timer_id = Rc::new(Cell::new(None));
+
+        let poll = {
+            let timer_id = timer_id.clone();
+            move || {
+                let Some(client) = tracker.client.upgrade() else {
+                    if let Some(timer_id) = timer_id.take() {
+                        ic_cdk::println!("Client has been dropped, stopping transaction tracker.");
+                        ic_cdk_timers::clear_timer(timer_id);
+                    }
+                    return;
+                };
+                if polling.replace(true) {
+                    return;
+                }
+
+                let (tracker, handler, polling) =
+                    (tracker.clone(), handler.clone(), polling.clone());
+                ic_cdk::spawn(async move {
+                    let trace_id = TraceId::new();
+                    let context = RequestContext::new()
+                        .with_priority(RequestPriority::Polling)
+                        .with_trace_id(trace_id);
+                    if let Err(e) = context.scope(tracker.poll(&client, &handler)).await {
+                        ic_cdk::println!("[trace {trace_id}] Transaction tracker failed: {:?}", e);
+                    }
+                    polling.set(false);
+                });
+            }
+        };
+
+        let id = set_timer_interval(poll_interval, poll.clone());
+        timer_id.set(Some(id));
+        poll();
+        Ok(id)
+    }
+
+    /// Update the status of every pending transaction.
+    async fn poll<F>(&self, client: &RpcClientInner<T>, handler: &RefCell<F>) -> TransportResult<()>
+    where
+        F: FnMut(&TrackedTransaction),
+    {
+        let pending = self.pending();
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let latest = client.request::<_, U64>("eth_blockNumber", ()).await?.to::<u64>();
+
+        'txs: for mut tx in pending {
+            let mut included = None;
+            for hash in tx.hashes() {
+                let receipt: Option<N::ReceiptResponse> =
+                    match client.request("eth_getTransactionReceipt", (hash,)).await {
+                        Ok(receipt) => receipt,
+                        Err(e) => {
+                            // Retry on the next poll, without holding back the other transactions.
+                            ic_cdk::println!("Failed to get the receipt of {hash}: {:?}", e);
+                            continue 'txs;
+                        }
+                    };
+                included = receipt.and_then(|r| r.block_number().map(|b| (r, b)));
+                if included.is_some() {
+                    break;
+                }
+            }
+            match included {
+                Some((receipt, block_number)) if tx.is_confirmed(block_number, latest) => {
+                    tx.status = if receipt.status() {
+                        TrackedStatus::Confirmed { block_number }
+                    } else {
+                        TrackedStatus::Reverted { block_number }
+                    };
+                }
+                Some(_) => continue,
+                None if tx.is_expired(ic_cdk::api::time()) => {
+                    tx.status = TrackedStatus::Expired;
+                }
+                None if self.rebroadcast => {
+                    // The provider may reject the transaction as already known.
+                    let sent: TransportResult<TxHash> =
+                        client.request("eth_sendRawTransaction", (&tx.raw,)).await;
+                    if let Err(e) = sent {
+                        ic_cdk::println!("Failed to rebroadcast {}: {:?}", tx.hash, e);
+                    }
+                    tx.rebroadcasts += 1;
+                    self.transactions.with_borrow_mut(|txs| txs.insert(tx.hash.0, tx));
+                    continue;
+                }
+                None => continue,
+            }
+            self.transactions.with_borrow_mut(|txs| txs.insert(tx.hash.0, tx.clone()));
+            (handler.borrow_mut())(&tx);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_stable_structures::VectorMemory;
+
+    thread_local! {
+        static TXS: RefCell<StableTrackedTransactions<VectorMemory>> =
+            RefCell::new(StableBTreeMap::init(VectorMemory::default()));
+    }
+
+    fn tracked(raw: &'static [u8]) -> TrackedTransaction {
+        TrackedTransaction {
+            hash: keccak256(raw),
+            raw: Bytes::from_static(raw),
+            nonce: 7,
+            status: TrackedStatus::Pending,
+            required_confirmations: 3,
+            submitted_at_ns: 1_000,
+            deadline_ns: Some(5_000),
+            rebroadcasts: 2,
+            fee_bumps: 0,
+            replaced: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn counts_confirmations_and_expiry() {
+        let tx = tracked(b"tx");
+        assert!(!tx.is_confirmed(100, 101));
+        assert!(tx.is_confirmed(100, 102));
+        assert!(tx.is_confirmed(100, 200));
+        let tx = TrackedTransaction { required_confirmations: 1, ..tx };
+        assert!(tx.is_confirmed(100, 100));
+
+        assert!(!tx.is_expired(5_000));
+        assert!(tx.is_expired(5_001));
+        assert!(!TrackedTransaction { deadline_ns: None, ..tx }.is_expired(u64::MAX));
+    }
+
+    #[test]
+    fn storable_round_trip() {
+        let tx = TrackedTransaction {
+            status: TrackedStatus::Confirmed { block_number: 12 },
+            fee_bumps: 1,
+            replaced: vec![keccak256(b"replaced")],
+            ..tracked(b"tx")
+        };
+        assert_eq!(TrackedTransaction::from_bytes(tx.to_bytes()), tx);
+
+        // Transactions persisted before escalation state was tracked still decode.
+        let mut legacy = serde_json::to_value(tracked(b"tx")).unwrap();
+        legacy
+            .as_object_mut()
+            .unwrap()
+            .retain(|field, _| field != "fee_bumps" && field != "replaced");
+        let bytes = serde_json::to_vec(&legacy).unwrap();
+        assert_eq!(TrackedTransaction::from_bytes(Cow::Owned(bytes)), tracked(b"tx"));
+    }
+
+    #[test]
+    fn replacements_carry_escalation_state() {
+        let tracker = PendingTransactionTracker::<alloy_transport::BoxTransport, _>::new(
+            WeakClient::new(),
+            &TXS,
+        );
+        let tx = tracked(b"tx");
+        TXS.with_borrow_mut(|txs| txs.insert(tx.hash.0, tx.clone()));
+
+        let first = tracker.replace(tx.hash, Bytes::from_static(b"bumped")).unwrap();
+        let second = tracker.replace(first, Bytes::from_static(b"bumped again")).unwrap();
+        assert!(tracker.get(tx.hash).is_none() && tracker.get(first).is_none());
+
+        let replacement = tracker.get(second).unwrap();
+        assert_eq!(replacement.nonce, tx.nonce);
+        assert_eq!(replacement.deadline_ns, tx.deadline_ns);
+        assert_eq!(replacement.fee_bumps, 2);
+        assert_eq!(replacement.rebroadcasts, 0);
+        assert_eq!(replacement.replaced, [tx.hash, first]);
+        assert_eq!(replacement.hashes().collect::<Vec<_>>(), [second, first, tx.hash]);
+        assert_eq!(tracker.replace(tx.hash, Bytes::from_static(b"stale")), None);
+    }
+}
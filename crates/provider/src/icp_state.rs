@@ -0,0 +1,305 @@
+//! A single pair of upgrade hooks for everything the ICP integration persists.
+
+use crate::{
+    CursorStore, CursorWatcher, Indexer, LogFilterWatcher, PendingTransactionTracker,
+    TrackedTransaction,
+};
+use alloy_network::Network;
+use alloy_rpc_client::{PollerDefinition, PollerRestorer, WeakClient};
+use alloy_rpc_types_eth::Log;
+use alloy_transport::Transport;
+use ic_stable_structures::Memory;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The size of a WebAssembly page, the unit stable memory grows by.
+const WASM_PAGE_SIZE: u64 = 65536;
+
+type RestoreHook = Box<dyn FnOnce() -> Result<(), String>>;
+
+/// The heap state saved before an upgrade.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Snapshot {
+    #[serde(default)]
+    pollers: Vec<PollerDefinition>,
+}
+
+/// Gathers everything the ICP integration needs to persist behind one [`save`](Self::save) and
+/// [`restore`](Self::restore) pair, to be called from the upgrade hooks of a canister.
+///
+/// State kept in stable structures, such as the [`StableNonceManager`], the
+/// [`PendingTransactionTracker`], the cursors of [`CursorWatcher`]s, the tables of the
+/// [`Indexer`] and the signer cache of `alloy-signer-icp`, survives upgrades on its own. Heap
+/// state, such as the definitions of named pollers, is written to the given memory by `save`.
+/// Timers never survive upgrades, so `restore` restarts the pollers, trackers, watchers and
+/// indexers registered on the state.
+///
+/// Build the state the same way in both hooks, and call `restore` from `init` as well: when
+/// nothing was saved, only the registered trackers and watchers are started.
+///
+/// # Examples
+///
+/// ```ignore
+/// fn icp_state() -> IcpState<IcpTransport, VirtualMemory<DefaultMemoryImpl>> {
+///     let memory = MEMORY_MANAGER.with_borrow(|m| m.get(MemoryId::new(4)));
+///     IcpState::new(provider().weak_client(), memory)
+///         .with_poller_handler("blocks", |blocks: Vec<B256>| handle_blocks(blocks))
+///         .with_pending_transactions(tracker(), |tx| handle_tx(tx))
+///         .with_log_watcher(usdc_watcher(), |logs| handle_transfers(logs))
+/// }
+///
+/// #[ic_cdk::pre_upgrade]
+/// fn pre_upgrade() {
+///     icp_state().save().unwrap();
+/// }
+///
+/// #[ic_cdk::post_upgrade]
+/// fn post_upgrade() {
+///     for (name, result) in icp_state().restore() {
+///         if let Err(err) = result {
+///             ic_cdk::println!("Failed to restore {name}: {err}");
+///         }
+///     }
+/// }
+/// ```
+///
+/// [`StableNonceManager`]: crate::fillers::StableNonceManager
+pub struct IcpState<T, M> {
+    client: WeakClient<T>,
+    memory: M,
+    restorer: PollerRestorer<T>,
+    hooks: Vec<(String, RestoreHook)>,
+}
+
+impl<T, M> fmt::Debug for IcpState<T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IcpState")
+            .field("restorer", &self.restorer)
+            .field("hooks", &self.hooks.iter().map(|(name, _)| name).collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, M> IcpState<T, M>
+where
+    T: Transport + Clone + 'static,
+    M: Memory,
+{
+    /// Create a new state of the given client, saved to the given memory.
+    ///
+    /// The memory is owned by the state, e.g. a dedicated virtual memory of a `MemoryManager`.
+    pub fn new(client: WeakClient<T>, memory: M) -> Self {
+        Self { client, memory, restorer: PollerRestorer::new(), hooks: Vec::new() }
+    }
+
+    /// Register the response handler of the named poller with the given name, see
+    /// [`PollerRestorer::with_handler`].
+    pub fn with_poller_handler<Resp, F>(mut self, name: impl Into<String>, handler: F) -> Self
+    where
+        Resp: alloy_json_rpc::RpcReturn + Clone + 'static,
+        F: FnMut(Resp) + 'static,
+    {
+        self.restorer = self.restorer.with_handler(name, handler);
+        self
+    }
+
+    /// Register the async response handler of the named poller with the given name, see
+    /// [`PollerRestorer::with_async_handler`].
+    pub fn with_async_poller_handler<Resp, F, Fut>(
+        mut self,
+        name: impl Into<String>,
+        handler: F,
+    ) -> Self
+    where
+        Resp: alloy_json_rpc::RpcReturn + Clone + 'static,
+        F: FnMut(Resp) -> Fut + 'static,
+        Fut: std::future::Future<Output = ()> + 'static,
+    {
+        self.restorer = self.restorer.with_async_handler(name, handler);
+        self
+    }
+
+    /// Start the tracker with the given handler on restore.
+    pub fn with_pending_transactions<TM, N, F>(
+        self,
+        tracker: PendingTransactionTracker<T, TM, N>,
+        handler: F,
+    ) -> Self
+    where
+        TM: Memory + 'static,
+        N: Network,
+        F: FnMut(&TrackedTransaction) + 'static,
+    {
+        self.on_restore("pending transactions", move || tracker.start(handler).map(drop))
+    }
+
+    /// Start the block watcher with the given handler on restore.
+    pub fn with_block_watcher<S, F>(self, watcher: CursorWatcher<T, S, u64>, handler: F) -> Self
+    where
+        S: CursorStore + 'static,
+        F: FnMut(Vec<u64>) + 'static,
+    {
+        let name = watcher.name().to_string();
+        self.on_restore(name, move || watcher.start(handler).map(drop))
+    }
+
+    /// Start the log watcher with the given handler on restore.
+    pub fn with_log_watcher<S, F>(self, watcher: CursorWatcher<T, S, Log>, handler: F) -> Self
+    where
+        S: CursorStore + 'static,
+        F: FnMut(Vec<Log>) + 'static,
+    {
+        let name = watcher.name().to_string();
+        self.on_restore(name, move || watcher.start(handler).map(drop))
+    }
+
+    /// Start the log filter watcher with the given handler on restore.
+    pub fn with_log_filters<FM, F>(self, watcher: LogFilterWatcher<T, FM>, handler: F) -> Self
+    where
+        FM: Memory + 'static,
+        F: FnMut(&str, Vec<Log>) + 'static,
+    {
+        self.on_restore("log filters", move || watcher.start(handler).map(drop))
+    }
+
+    /// Start the event indexer on restore.
+    pub fn with_indexer<IM>(self, indexer: Indexer<T, IM>) -> Self
+    where
+        IM: Memory + 'static,
+    {
+        self.on_restore("indexer", move || indexer.start().map(drop))
+    }
+
+    /// Run the given hook on restore, e.g. to restart application specific timers.
+    pub fn on_restore(
+        mut self,
+        name: impl Into<String>,
+        hook: impl FnOnce() -> Result<(), String> + 'static,
+    ) -> Self {
+        self.hooks.push((name.into(), Box::new(hook)));
+        self
+    }
+
+    /// Save the heap state to stable memory. Call this from `pre_upgrade`.
+    pub fn save(&self) -> Result<(), String> {
+        let client = self.client.upgrade().ok_or("Client has been dropped.")?;
+        self.write(&Snapshot { pollers: client.poller_definitions() })
+    }
+
+    fn write(&self, snapshot: &Snapshot) -> Result<(), String> {
+        let bytes = serde_json::to_vec(snapshot).map_err(|e| e.to_string())?;
+
+        let size = 8 + bytes.len() as u64;
+        let pages = size.div_ceil(WASM_PAGE_SIZE);
+        let current = self.memory.size();
+        if current < pages && self.memory.grow(pages - current) == -1 {
+            return Err("Failed to grow stable memory.".into());
+        }
+        self.memory.write(0, &(bytes.len() as u64).to_be_bytes());
+        self.memory.write(8, &bytes);
+        Ok(())
+    }
+
+    /// Restore the saved state, and start every registered poller, tracker and watcher. Call
+    /// this from `post_upgrade`, and from `init`.
+    ///
+    /// Returns the outcome for each named poller and each registered hook. If the saved state
+    /// cannot be loaded, its error is returned under `state`, no poller is restored, and the
+    /// registered hooks still run, since their state lives in stable structures of its own.
+    pub fn restore(self) -> Vec<(String, Result<(), String>)> {
+        let mut results = Vec::new();
+        let snapshot = self.load().unwrap_or_else(|err| {
+            results.push(("state".into(), Err(err)));
+            Snapshot::default()
+        });
+        results.extend(
+            self.restorer
+                .restore(self.client, snapshot.pollers)
+                .into_iter()
+                .map(|(name, result)| (name, result.map(drop))),
+        );
+        results.extend(self.hooks.into_iter().map(|(name, hook)| (name, hook())));
+        results
+    }
+
+    fn load(&self) -> Result<Snapshot, String> {
+        if self.memory.size() == 0 {
+            return Ok(Snapshot::default());
+        }
+        let mut len = [0; 8];
+        self.memory.read(0, &mut len);
+        let len = u64::from_be_bytes(len);
+        if len == 0 {
+            return Ok(Snapshot::default());
+        }
+        if len.saturating_add(8) > self.memory.size() * WASM_PAGE_SIZE {
+            return Err("Saved state is corrupted.".into());
+        }
+        let mut bytes = vec![0; len as usize];
+        self.memory.read(8, &mut bytes);
+        serde_json::from_slice(&bytes).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_rpc_client::RpcClient;
+    use ic_stable_structures::VectorMemory;
+    use std::{cell::Cell, rc::Rc};
+
+    fn definition(name: &str) -> PollerDefinition {
+        PollerDefinition {
+            name: name.into(),
+            method: "eth_blockNumber".into(),
+            params: "[]".into(),
+            poll_interval_ms: 10_000,
+            limit: None,
+            polls: 3,
+            cursor: Some("0x2a".into()),
+        }
+    }
+
+    #[test]
+    fn saves_and_restores_the_heap_state() {
+        let client = RpcClient::new_http("http://localhost:8545".parse().unwrap());
+        let memory = VectorMemory::default();
+        let state = IcpState::new(client.get_weak(), memory.clone());
+
+        // Nothing was saved yet, e.g. on init.
+        assert_eq!(state.load().unwrap().pollers, []);
+        state.save().unwrap();
+        assert_eq!(state.load().unwrap().pollers, []);
+
+        state.write(&Snapshot { pollers: vec![definition("blocks")] }).unwrap();
+        let restored = IcpState::new(client.get_weak(), memory);
+        assert_eq!(restored.load().unwrap().pollers, [definition("blocks")]);
+        // Without a handler, the poller is reported instead of started.
+        let results = restored.restore();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, "blocks");
+        assert!(results[0].1.is_err());
+    }
+
+    #[test]
+    fn runs_hooks_when_the_state_is_corrupted() {
+        let client = RpcClient::new_http("http://localhost:8545".parse().unwrap());
+        let memory = VectorMemory::default();
+        let ran = Rc::new(Cell::new(false));
+        let hook = {
+            let ran = ran.clone();
+            move || {
+                ran.set(true);
+                Ok(())
+            }
+        };
+        let state = IcpState::new(client.get_weak(), memory.clone()).on_restore("timers", hook);
+        state.write(&Snapshot::default()).unwrap();
+        memory.write(0, &u64::MAX.to_be_bytes());
+
+        let results = state.restore();
+        assert_eq!(results[0], ("state".into(), Err("Saved state is corrupted.".into())));
+        assert_eq!(results[1], ("timers".into(), Ok(())));
+        assert!(ran.get());
+    }
+}
@@ -0,0 +1,94 @@
+//! A ready-made status of the ICP integration, to expose from a canister query.
+
+use crate::{PendingTransactionTracker, Provider};
+use alloy_network::Network;
+use alloy_rpc_client::PollerCycles;
+use alloy_signer_icp::{IcpSigner, SignerHealth};
+use alloy_transport::Transport;
+use alloy_transport_icp::{IcpMetrics, IcpTransport};
+use candid::CandidType;
+use ic_stable_structures::Memory;
+use serde::Deserialize;
+
+/// The status of the ICP integration of a canister: its pollers, pending transactions,
+/// transport metrics, cycles spend and signer health.
+///
+/// The status is Candid encodable, so a canister can expose it from an ops query in one line.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[ic_cdk::query]
+/// fn status() -> IcpAlloyStatus {
+///     IcpAlloyStatus::new(&provider()).with_pending_transactions(&tracker()).with_signer(&signer())
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize)]
+pub struct IcpAlloyStatus {
+    /// The pollers running on the client, with the cycles spent by each.
+    pub pollers: Vec<PollerCycles>,
+    /// The number of transactions still pending, if a [`PendingTransactionTracker`] was added.
+    pub pending_transactions: Option<u64>,
+    /// The number of outcalls in flight.
+    pub in_flight_requests: u64,
+    /// The number of requests waiting for an outcall slot.
+    pub queued_requests: u64,
+    /// The request metrics of the transport, by method.
+    pub metrics: IcpMetrics,
+    /// The cycles spent on the requests of the transport, since its metrics were last reset.
+    pub cycles_spent: u128,
+    /// The cycles spent in the current period of the cycles budget of the transport.
+    pub cycles_budget_spent: u128,
+    /// The percentage of the cycles budget that remains in the current period, if the spend is
+    /// limited.
+    pub cycles_budget_remaining_percent: Option<u8>,
+    /// The cycles balance of the canister.
+    pub cycles_balance: u128,
+    /// The signing health of the signer, if one was added.
+    pub signer: Option<SignerHealth>,
+}
+
+impl IcpAlloyStatus {
+    /// Collect the status of the client and transport of the provider.
+    pub fn new<P, N>(provider: &P) -> Self
+    where
+        P: Provider<IcpTransport, N>,
+        N: Network,
+    {
+        let client = provider.client();
+        let transport = client.transport();
+        let metrics = client.metrics();
+        Self {
+            pollers: client.poller_cycles(),
+            pending_transactions: None,
+            in_flight_requests: client.in_flight_requests() as u64,
+            queued_requests: client.queued_requests() as u64,
+            cycles_spent: metrics.total().cycles_spent,
+            metrics,
+            cycles_budget_spent: transport.cycles_budget_spent(),
+            cycles_budget_remaining_percent: transport.cycles_budget_remaining_percent(),
+            cycles_balance: ic_cdk::api::canister_balance128(),
+            signer: None,
+        }
+    }
+
+    /// Add the number of transactions still pending in the tracker.
+    pub fn with_pending_transactions<T, M, N>(
+        mut self,
+        tracker: &PendingTransactionTracker<T, M, N>,
+    ) -> Self
+    where
+        T: Transport + Clone,
+        M: Memory + 'static,
+        N: Network,
+    {
+        self.pending_transactions = Some(tracker.pending().len() as u64);
+        self
+    }
+
+    /// Add the signing health of the signer.
+    pub fn with_signer(mut self, signer: &IcpSigner) -> Self {
+        self.signer = Some(signer.health());
+        self
+    }
+}
@@ -0,0 +1,791 @@
+//! Block and log watchers for ICP that resume from a persisted cursor.
+
+use alloy_eips::BlockNumberOrTag;
+use alloy_primitives::U64;
+use alloy_rpc_client::{BatchRequest, RpcClientInner, WeakClient};
+use alloy_rpc_types_eth::{Block, Filter, Log, TransactionReceipt};
+use alloy_transport::{Transport, TransportError, TransportErrorKind, TransportResult};
+use alloy_transport_icp::{RequestContext, RequestPriority, TraceId};
+use ic_cdk_timers::{set_timer_interval, TimerId};
+use ic_stable_structures::{storable::Bound, Memory, StableBTreeMap, Storable};
+use serde::{
+    de::{DeserializeSeed, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use serde_json::value::RawValue;
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt,
+    marker::PhantomData,
+    ops::RangeInclusive,
+    rc::Rc,
+    thread::LocalKey,
+    time::Duration,
+};
+
+/// Persists the last block processed by each [`CursorWatcher`].
+pub trait CursorStore {
+    /// Returns the last block processed by the watcher with the given name, if any.
+    fn load(&self, name: &str) -> Option<u64>;
+
+    /// Records the last block processed by the watcher with the given name.
+    fn save(&self, name: &str, block: u64);
+}
+
+/// The stable map holding the cursor of each watcher.
+pub type StableCursors<M> = StableBTreeMap<String, u64, M>;
+
+/// A [`CursorStore`] keeping cursors in a [`StableBTreeMap`], so that they survive canister
+/// upgrades.
+///
+/// The map is owned by a thread local, as stable structures are not [`Send`].
+pub struct StableCursorStore<M: Memory + 'static> {
+    cursors: &'static LocalKey<RefCell<StableCursors<M>>>,
+}
+
+impl<M: Memory + 'static> StableCursorStore<M> {
+    /// Create a new store keeping cursors in the given map.
+    pub const fn new(cursors: &'static LocalKey<RefCell<StableCursors<M>>>) -> Self {
+        Self { cursors }
+    }
+}
+
+impl<M: Memory + 'static> Clone for StableCursorStore<M> {
+    fn clone(&self) -> Self {
+        Self { cursors: self.cursors }
+    }
+}
+
+impl<M: Memory + 'static> fmt::Debug for StableCursorStore<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StableCursorStore").finish_non_exhaustive()
+    }
+}
+
+impl<M: Memory + 'static> CursorStore for StableCursorStore<M> {
+    fn load(&self, name: &str) -> Option<u64> {
+        self.cursors.with_borrow(|cursors| cursors.get(&name.to_string()))
+    }
+
+    fn save(&self, name: &str, block: u64) {
+        self.cursors.with_borrow_mut(|cursors| cursors.insert(name.to_string(), block));
+    }
+}
+
+/// A [`CursorStore`] keeping cursors on the heap. Cursors are lost when the canister is
+/// upgraded.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryCursorStore(Rc<RefCell<HashMap<String, u64>>>);
+
+impl MemoryCursorStore {
+    /// Create a new, empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CursorStore for MemoryCursorStore {
+    fn load(&self, name: &str) -> Option<u64> {
+        self.0.borrow().get(name).copied()
+    }
+
+    fn save(&self, name: &str, block: u64) {
+        self.0.borrow_mut().insert(name.to_string(), block);
+    }
+}
+
+/// A [`CursorWatcher`] of logs keeping its cursor on the heap, see
+/// [`Provider::poll_logs`](crate::Provider::poll_logs).
+pub type LogWatcher<T> = CursorWatcher<T, MemoryCursorStore, Log>;
+
+/// Items delivered by a [`CursorWatcher`] for a range of blocks.
+trait CursorItem: Sized + 'static {
+    /// Fetch the items of the given blocks, delivering them in segments of at most `max_items`
+    /// items if set.
+    async fn fetch<T: Transport + Clone>(
+        client: &RpcClientInner<T>,
+        filter: Option<&Filter>,
+        blocks: RangeInclusive<u64>,
+        max_items: Option<usize>,
+        deliver: impl FnMut(Vec<Self>),
+    ) -> TransportResult<()>;
+}
+
+impl CursorItem for u64 {
+    async fn fetch<T: Transport + Clone>(
+        _client: &RpcClientInner<T>,
+        _filter: Option<&Filter>,
+        blocks: RangeInclusive<u64>,
+        _max_items: Option<usize>,
+        mut deliver: impl FnMut(Vec<Self>),
+    ) -> TransportResult<()> {
+        deliver(blocks.collect());
+        Ok(())
+    }
+}
+
+impl CursorItem for Log {
+    async fn fetch<T: Transport + Clone>(
+        client: &RpcClientInner<T>,
+        filter: Option<&Filter>,
+        blocks: RangeInclusive<u64>,
+        max_items: Option<usize>,
+        deliver: impl FnMut(Vec<Self>),
+    ) -> TransportResult<()> {
+        let logs = request_logs(client, filter.cloned().unwrap_or_default(), blocks).await?;
+        parse_logs(&logs, max_items, deliver)
+    }
+}
+
+/// Request the logs matching the filter in the given blocks, without deserializing them.
+async fn request_logs<T: Transport + Clone>(
+    client: &RpcClientInner<T>,
+    filter: Filter,
+    blocks: RangeInclusive<u64>,
+) -> TransportResult<Box<RawValue>> {
+    let filter = filter.from_block(*blocks.start()).to_block(*blocks.end());
+    client.request("eth_getLogs", (filter,)).await
+}
+
+/// Deserialize an `eth_getLogs` response, delivering the logs in segments of at most
+/// `max_logs` logs if set.
+///
+/// Segments are delivered while the response is parsed, so a large response is never held in
+/// memory as a whole list of logs.
+fn parse_logs(
+    logs: &RawValue,
+    max_logs: Option<usize>,
+    mut deliver: impl FnMut(Vec<Log>),
+) -> TransportResult<()> {
+    let Some(max_logs) = max_logs else {
+        let logs = serde_json::from_str(logs.get())
+            .map_err(|e| TransportError::deser_err(e, logs.get()))?;
+        deliver(logs);
+        return Ok(());
+    };
+    let mut deserializer = serde_json::Deserializer::from_str(logs.get());
+    LogSegments { max_logs, deliver }
+        .deserialize(&mut deserializer)
+        .and_then(|()| deserializer.end())
+        .map_err(|e| TransportError::deser_err(e, logs.get()))
+}
+
+/// Deserializes a list of logs, delivering them in segments, see [`parse_logs`].
+struct LogSegments<F> {
+    max_logs: usize,
+    deliver: F,
+}
+
+impl<'de, F: FnMut(Vec<Log>)> DeserializeSeed<'de> for LogSegments<F> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F: FnMut(Vec<Log>)> Visitor<'de> for LogSegments<F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a list of logs")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        let mut segment = Vec::with_capacity(self.max_logs.min(seq.size_hint().unwrap_or(0)));
+        let mut delivered = false;
+        while let Some(log) = seq.next_element()? {
+            segment.push(log);
+            if segment.len() == self.max_logs {
+                (self.deliver)(std::mem::take(&mut segment));
+                delivered = true;
+            }
+        }
+        // Like an unsegmented response, an empty response is delivered as an empty list.
+        if !segment.is_empty() || !delivered {
+            (self.deliver)(segment);
+        }
+        Ok(())
+    }
+}
+
+/// A block with the receipts of its transactions, delivered by
+/// [`CursorWatcher::blocks_with_receipts`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockWithReceipts {
+    /// The block, with the hashes of its transactions.
+    pub block: Block,
+    /// The receipts of the transactions of the block.
+    pub receipts: Vec<TransactionReceipt>,
+}
+
+impl CursorItem for BlockWithReceipts {
+    async fn fetch<T: Transport + Clone>(
+        client: &RpcClientInner<T>,
+        _filter: Option<&Filter>,
+        blocks: RangeInclusive<u64>,
+        _max_items: Option<usize>,
+        mut deliver: impl FnMut(Vec<Self>),
+    ) -> TransportResult<()> {
+        let mut items = Vec::with_capacity(blocks.clone().count());
+        for number in blocks {
+            let number = BlockNumberOrTag::Number(number);
+            let mut batch = BatchRequest::new(client);
+            let block =
+                batch.add_call::<_, Option<Block>>("eth_getBlockByNumber", &(number, false))?;
+            let receipts = batch.add_call::<_, Option<Vec<TransactionReceipt>>>(
+                "eth_getBlockReceipts",
+                &(number,),
+            )?;
+            batch.send().await?;
+
+            let (Some(mut block), Some(receipts)) = (block.await?, receipts.await?) else {
+                return Err(TransportErrorKind::custom_str(&format!("block {number} not found")));
+            };
+            // An empty list of transactions deserializes ambiguously.
+            block.transactions.convert_to_hashes();
+            items.push(Self { block, receipts });
+        }
+        deliver(items);
+        Ok(())
+    }
+}
+
+/// A watcher that polls for new blocks or logs, persisting the last processed block in a
+/// [`CursorStore`].
+///
+/// Unlike filter based watchers such as
+/// [`Provider::watch_logs`](crate::Provider::watch_logs), a cursor watcher does not rely on
+/// state held by the provider. Each poll requests the latest block number, and delivers the
+/// blocks or logs from the block after the cursor up to the latest block. After an upgrade or
+/// downtime, a watcher started with the same name and store resumes from its cursor and
+/// backfills the missed blocks, in chunks of at most
+/// [`max_block_range`](Self::with_max_block_range) blocks.
+///
+/// The cursor only advances once the handler has been called, so a failed request is retried
+/// on the next poll.
+///
+/// # Examples
+///
+/// ```ignore
+/// thread_local! {
+///     static CURSORS: RefCell<StableCursors<VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+///         StableBTreeMap::init(MEMORY_MANAGER.with_borrow(|m| m.get(MemoryId::new(2)))),
+///     );
+/// }
+///
+/// // Called from both `init` and `post_upgrade`.
+/// fn start_watcher() {
+///     let filter = Filter::new().address(usdc_address).event(Transfer::SIGNATURE);
+///     let store = StableCursorStore::new(&CURSORS);
+///     CursorWatcher::logs(provider.weak_client(), "usdc", store, filter)
+///         .with_poll_interval(Duration::from_secs(12))
+///         .start(|logs: Vec<Log>| handle_transfers(logs))
+///         .unwrap();
+/// }
+/// ```
+pub struct CursorWatcher<T, S, R> {
+    client: WeakClient<T>,
+    name: String,
+    store: S,
+    filter: Option<Filter>,
+    poll_interval: Duration,
+    max_block_range: u64,
+    max_logs_per_call: Option<usize>,
+    start_block: Option<u64>,
+    _pd: PhantomData<fn() -> R>,
+}
+
+impl<T, S: fmt::Debug, R> fmt::Debug for CursorWatcher<T, S, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CursorWatcher")
+            .field("name", &self.name)
+            .field("store", &self.store)
+            .field("filter", &self.filter)
+            .field("poll_interval", &self.poll_interval)
+            .field("max_block_range", &self.max_block_range)
+            .field("max_logs_per_call", &self.max_logs_per_call)
+            .field("start_block", &self.start_block)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, S> CursorWatcher<T, S, u64>
+where
+    T: Transport + Clone,
+    S: CursorStore + 'static,
+{
+    /// Create a watcher delivering the numbers of new blocks.
+    pub fn blocks(client: WeakClient<T>, name: impl Into<String>, store: S) -> Self {
+        Self::new(client, name.into(), store, None)
+    }
+}
+
+impl<T, S> CursorWatcher<T, S, BlockWithReceipts>
+where
+    T: Transport + Clone,
+    S: CursorStore + 'static,
+{
+    /// Create a watcher delivering new blocks with the receipts of their transactions.
+    ///
+    /// Each block is fetched with a single batched request for the block and its receipts,
+    /// making half as many outcalls as separate block and receipt requests. A backfill makes
+    /// one outcall per missed block, so consider a smaller
+    /// [`max_block_range`](Self::with_max_block_range).
+    pub fn blocks_with_receipts(client: WeakClient<T>, name: impl Into<String>, store: S) -> Self {
+        Self::new(client, name.into(), store, None)
+    }
+}
+
+impl<T, S> CursorWatcher<T, S, Log>
+where
+    T: Transport + Clone,
+    S: CursorStore + 'static,
+{
+    /// Create a watcher delivering the logs matching the filter in new blocks.
+    ///
+    /// The block range of the filter is ignored, see
+    /// [`with_start_block`](Self::with_start_block).
+    pub fn logs(client: WeakClient<T>, name: impl Into<String>, store: S, filter: Filter) -> Self {
+        Self::new(client, name.into(), store, Some(filter))
+    }
+
+    /// Sets the maximum number of logs delivered per handler call. Unset by default, delivering
+    /// the logs of up to [`max_block_range`](Self::with_max_block_range) blocks at once.
+    ///
+    /// When set, responses are parsed incrementally and the handler is called while parsing, so
+    /// a large backfill response is never held in memory as a whole list of logs. The cursor
+    /// still only advances once every log of a block range has been delivered, so a failed
+    /// request redelivers the logs already handled for its range.
+    pub const fn with_max_logs_per_call(mut self, max_logs: usize) -> Self {
+        self.max_logs_per_call = Some(if max_logs == 0 { 1 } else { max_logs });
+        self
+    }
+}
+
+#[allow(private_bounds)]
+impl<T, S, R> CursorWatcher<T, S, R>
+where
+    T: Transport + Clone,
+    S: CursorStore + 'static,
+    R: CursorItem,
+{
+    fn new(client: WeakClient<T>, name: String, store: S, filter: Option<Filter>) -> Self {
+        let poll_interval =
+            client.upgrade().map_or_else(|| Duration::from_secs(7), |c| c.poll_interval());
+        Self {
+            client,
+            name,
+            store,
+            filter,
+            poll_interval,
+            max_block_range: 500,
+            max_logs_per_call: None,
+            start_block: None,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Returns the name of the watcher, which keys its cursor in the store.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets the duration between polls.
+    pub const fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets the maximum number of blocks delivered at once. Defaults to 500.
+    pub const fn with_max_block_range(mut self, max_block_range: u64) -> Self {
+        self.max_block_range = if max_block_range == 0 { 1 } else { max_block_range };
+        self
+    }
+
+    /// Sets the first block to deliver if the store has no cursor for this watcher yet.
+    /// Defaults to the latest block when the watcher first polls.
+    pub const fn with_start_block(mut self, block: u64) -> Self {
+        self.start_block = Some(block);
+        self
+    }
+
+    /// Starts the watcher with the given handler.
+    ///
+    /// Like [`IcpPollerBuilder`](alloy_rpc_client::IcpPollerBuilder), the watcher only holds a
+    /// [`WeakClient`], and stops once the client is dropped. Timers do not survive upgrades,
+    /// so the watcher must be started again in `post_upgrade`.
+    pub fn start<F>(self, handler: F) -> Result<TimerId, String>
+    where
+        F: FnMut(Vec<R>) + 'static,
+        T: 'static,
+    {
+        if self.client.strong_count() == 0 {
+            return Err("Client has been dropped.".into());
+        }
+        let poll_interval = self.poll_interval;
+        let watcher = Rc::new(self);
+        let handler = Rc::new(RefCell::new(handler));
+        let polling = Rc::new(Cell::new(false));
+        let timer_id = Rc::new(Cell::new(None));
+
+        let poll = {
+            let timer_id = timer_id.clone();
+            move || {
+                let Some(client) = watcher.client.upgrade() else {
+                    if let Some(timer_id) = timer_id.take() {
+                        ic_cdk::println!("Client has been dropped, stopping watcher.");
+                        ic_cdk_timers::clear_timer(timer_id);
+                    }
+                    return;
+                };
+                // A slow backfill may still be running.
+                if polling.replace(true) {
+                    return;
+                }
+
+                let (watcher, handler, polling) =
+                    (watcher.clone(), handler.clone(), polling.clone());
+                ic_cdk::spawn(async move {
+                    let trace_id = TraceId::new();
+                    let context = RequestContext::new()
+                        .with_priority(RequestPriority::Polling)
+                        .with_trace_id(trace_id);
+                    if let Err(e) = context.scope(watcher.poll(&client, &handler)).await {
+                        ic_cdk::println!(
+                            "[trace {trace_id}] Watcher {} failed: {:?}",
+                            watcher.name,
+                            e
+                        );
+                    }
+                    polling.set(false);
+                });
+            }
+        };
+
+        let id = set_timer_interval(poll_interval, poll.clone());
+        timer_id.set(Some(id));
+        poll();
+        Ok(id)
+    }
+
+    /// Deliver every block from the cursor up to the latest block.
+    async fn poll<F>(&self, client: &RpcClientInner<T>, handler: &RefCell<F>) -> TransportResult<()>
+    where
+        F: FnMut(Vec<R>),
+    {
+        let latest = client.request::<_, U64>("eth_blockNumber", ()).await?.to::<u64>();
+        let mut next = self
+            .store
+            .load(&self.name)
+            .map_or_else(|| self.start_block.unwrap_or(latest), |block| block + 1);
+        while next <= latest {
+            let to = latest.min(next.saturating_add(self.max_block_range - 1));
+            R::fetch(client, self.filter.as_ref(), next..=to, self.max_logs_per_call, |items| {
+                (handler.borrow_mut())(items)
+            })
+            .await?;
+            self.store.save(&self.name, to);
+            next = to + 1;
+        }
+        Ok(())
+    }
+}
+
+/// A log filter persisted in a [`LogFilterStore`], with the last block delivered for it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogFilterDefinition {
+    /// The filter. Its block range is ignored.
+    pub filter: Filter,
+    /// The first block to deliver if no block has been delivered yet. Defaults to the latest
+    /// block when the filter is first polled.
+    pub start_block: Option<u64>,
+    /// The last block whose logs have been delivered.
+    pub delivered_up_to: Option<u64>,
+}
+
+impl Storable for LogFilterDefinition {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(serde_json::to_vec(self).expect("log filters are serializable"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        serde_json::from_slice(&bytes).expect("invalid log filter in stable memory")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// The stable map holding the log filters of a [`LogFilterStore`], keyed by name.
+pub type StableLogFilters<M> = StableBTreeMap<String, LogFilterDefinition, M>;
+
+/// A store of named log filters and their delivery offsets in a [`StableBTreeMap`], watched
+/// together by a [`LogFilterWatcher`].
+///
+/// Filters can be added and removed at any time, including while the watcher runs. The store is
+/// also a [`CursorStore`], keyed by filter name.
+///
+/// The map is owned by a thread local, as stable structures are not [`Send`].
+pub struct LogFilterStore<M: Memory + 'static> {
+    filters: &'static LocalKey<RefCell<StableLogFilters<M>>>,
+}
+
+impl<M: Memory + 'static> LogFilterStore<M> {
+    /// Create a new store keeping filters in the given map.
+    pub const fn new(filters: &'static LocalKey<RefCell<StableLogFilters<M>>>) -> Self {
+        Self { filters }
+    }
+
+    /// Add a filter, or replace the filter with the same name, keeping its delivery offset.
+    pub fn insert(&self, name: impl Into<String>, filter: Filter, start_block: Option<u64>) {
+        let name = name.into();
+        self.filters.with_borrow_mut(|filters| {
+            let delivered_up_to = filters.get(&name).and_then(|f| f.delivered_up_to);
+            filters.insert(name, LogFilterDefinition { filter, start_block, delivered_up_to });
+        });
+    }
+
+    /// Remove the filter with the given name.
+    pub fn remove(&self, name: &str) -> Option<LogFilterDefinition> {
+        self.filters.with_borrow_mut(|filters| filters.remove(&name.to_string()))
+    }
+
+    /// Returns the filter with the given name.
+    pub fn get(&self, name: &str) -> Option<LogFilterDefinition> {
+        self.filters.with_borrow(|filters| filters.get(&name.to_string()))
+    }
+
+    /// Returns all filters, ordered by name.
+    pub fn filters(&self) -> Vec<(String, LogFilterDefinition)> {
+        self.filters.with_borrow(|filters| filters.iter().collect())
+    }
+}
+
+impl<M: Memory + 'static> Clone for LogFilterStore<M> {
+    fn clone(&self) -> Self {
+        Self { filters: self.filters }
+    }
+}
+
+impl<M: Memory + 'static> fmt::Debug for LogFilterStore<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogFilterStore").finish_non_exhaustive()
+    }
+}
+
+impl<M: Memory + 'static> CursorStore for LogFilterStore<M> {
+    fn load(&self, name: &str) -> Option<u64> {
+        self.get(name).and_then(|filter| filter.delivered_up_to)
+    }
+
+    fn save(&self, name: &str, block: u64) {
+        self.filters.with_borrow_mut(|filters| {
+            let name = name.to_string();
+            if let Some(mut filter) = filters.get(&name) {
+                filter.delivered_up_to = Some(block);
+                filters.insert(name, filter);
+            }
+        });
+    }
+}
+
+/// A watcher delivering the logs of every filter in a [`LogFilterStore`] exactly once, across
+/// upgrades.
+///
+/// Each poll requests the latest block number once, then the logs of each filter from the block
+/// after its delivery offset, in chunks of at most
+/// [`max_block_range`](Self::with_max_block_range) blocks. The handler is called with the name
+/// of the filter and its logs, and the delivery offset is advanced in the same message, with no
+/// await point in between: either both take effect, or neither does, e.g. if the handler traps.
+/// Since the offsets live in stable memory, a watcher restarted after an upgrade neither skips
+/// nor repeats logs.
+///
+/// # Examples
+///
+/// ```ignore
+/// thread_local! {
+///     static FILTERS: RefCell<StableLogFilters<VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+///         StableBTreeMap::init(MEMORY_MANAGER.with_borrow(|m| m.get(MemoryId::new(5)))),
+///     );
+/// }
+///
+/// #[ic_cdk::update]
+/// fn watch_token(name: String, token: Address) {
+///     let filter = Filter::new().address(token).event(Transfer::SIGNATURE);
+///     LogFilterStore::new(&FILTERS).insert(name, filter, None);
+/// }
+///
+/// // Called from both `init` and `post_upgrade`.
+/// fn start_watcher() {
+///     LogFilterWatcher::new(provider().weak_client(), LogFilterStore::new(&FILTERS))
+///         .start(|name, logs| handle_transfers(name, logs))
+///         .unwrap();
+/// }
+/// ```
+pub struct LogFilterWatcher<T, M: Memory + 'static> {
+    client: WeakClient<T>,
+    store: LogFilterStore<M>,
+    poll_interval: Duration,
+    max_block_range: u64,
+    max_logs_per_call: Option<usize>,
+}
+
+impl<T, M: Memory + 'static> fmt::Debug for LogFilterWatcher<T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogFilterWatcher")
+            .field("poll_interval", &self.poll_interval)
+            .field("max_block_range", &self.max_block_range)
+            .field("max_logs_per_call", &self.max_logs_per_call)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, M> LogFilterWatcher<T, M>
+where
+    T: Transport + Clone,
+    M: Memory + 'static,
+{
+    /// Create a watcher for the filters of the given store.
+    pub fn new(client: WeakClient<T>, store: LogFilterStore<M>) -> Self {
+        let poll_interval =
+            client.upgrade().map_or_else(|| Duration::from_secs(7), |c| c.poll_interval());
+        Self { client, store, poll_interval, max_block_range: 500, max_logs_per_call: None }
+    }
+
+    /// Sets the duration between polls.
+    pub const fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets the maximum number of blocks requested at once per filter. Defaults to 500.
+    pub const fn with_max_block_range(mut self, max_block_range: u64) -> Self {
+        self.max_block_range = if max_block_range == 0 { 1 } else { max_block_range };
+        self
+    }
+
+    /// Sets the maximum number of logs delivered per handler call, parsing responses
+    /// incrementally. See [`CursorWatcher::with_max_logs_per_call`].
+    pub const fn with_max_logs_per_call(mut self, max_logs: usize) -> Self {
+        self.max_logs_per_call = Some(if max_logs == 0 { 1 } else { max_logs });
+        self
+    }
+
+    /// Starts the watcher with the given handler, called with the name of a filter and its new
+    /// logs.
+    ///
+    /// Like [`CursorWatcher::start`], the watcher stops once the client is dropped, and must be
+    /// started again in `post_upgrade`.
+    pub fn start<F>(self, handler: F) -> Result<TimerId, String>
+    where
+        F: FnMut(&str, Vec<Log>) + 'static,
+        T: 'static,
+    {
+        if self.client.strong_count() == 0 {
+            return Err("Client has been dropped.".into());
+        }
+        let poll_interval = self.poll_interval;
+        let watcher = Rc::new(self);
+        let handler = Rc::new(RefCell::new(handler));
+        let polling = Rc::new(Cell::new(false));
+        let timer_id = Rc::new(Cell::new(None));
+
+        let poll = {
+            let timer_id = timer_id.clone();
+            move || {
+                let Some(client) = watcher.client.upgrade() else {
+                    if let Some(timer_id) = timer_id.take() {
+                        ic_cdk::println!("Client has been dropped, stopping log filter watcher.");
+                        ic_cdk_timers::clear_timer(timer_id);
+                    }
+                    return;
+                };
+                if polling.replace(true) {
+                    return;
+                }
+
+                let (watcher, handler, polling) =
+                    (watcher.clone(), handler.clone(), polling.clone());
+                ic_cdk::spawn(async move {
+                    let trace_id = TraceId::new();
+                    let context = RequestContext::new()
+                        .with_priority(RequestPriority::Polling)
+                        .with_trace_id(trace_id);
+                    if let Err(e) = context.scope(watcher.poll(&client, &handler)).await {
+                        ic_cdk::println!("[trace {trace_id}] Log filter watcher failed: {:?}", e);
+                    }
+                    polling.set(false);
+                });
+            }
+        };
+
+        let id = set_timer_interval(poll_interval, poll.clone());
+        timer_id.set(Some(id));
+        poll();
+        Ok(id)
+    }
+
+    /// Deliver the logs of every filter up to the latest block.
+    async fn poll<F>(&self, client: &RpcClientInner<T>, handler: &RefCell<F>) -> TransportResult<()>
+    where
+        F: FnMut(&str, Vec<Log>),
+    {
+        let filters = self.store.filters();
+        if filters.is_empty() {
+            return Ok(());
+        }
+        let latest = client.request::<_, U64>("eth_blockNumber", ()).await?.to::<u64>();
+
+        for (name, definition) in filters {
+            let mut delivered_up_to = definition.delivered_up_to;
+            let mut next = delivered_up_to
+                .map_or_else(|| definition.start_block.unwrap_or(latest), |block| block + 1);
+            while next <= latest {
+                let to = latest.min(next.saturating_add(self.max_block_range - 1));
+                let logs = request_logs(client, definition.filter.clone(), next..=to).await?;
+                // The filter may have been removed or changed while the logs were requested.
+                let unchanged = self.store.get(&name).is_some_and(|current| {
+                    current.filter == definition.filter
+                        && current.delivered_up_to == delivered_up_to
+                });
+                if !unchanged {
+                    break;
+                }
+                parse_logs(&logs, self.max_logs_per_call, |logs| {
+                    (handler.borrow_mut())(&name, logs)
+                })?;
+                self.store.save(&name, to);
+                delivered_up_to = Some(to);
+                next = to + 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_logs_in_segments() {
+        let log = serde_json::to_string(&Log::<alloy_primitives::LogData>::default()).unwrap();
+        let logs = RawValue::from_string(format!("[{log},{log},{log},{log},{log}]")).unwrap();
+
+        let mut segments = Vec::new();
+        parse_logs(&logs, Some(2), |logs| segments.push(logs.len())).unwrap();
+        assert_eq!(segments, [2, 2, 1]);
+
+        segments.clear();
+        parse_logs(&logs, None, |logs| segments.push(logs.len())).unwrap();
+        assert_eq!(segments, [5]);
+
+        // An empty response is still delivered, like an unsegmented one.
+        segments.clear();
+        let empty = RawValue::from_string("[]".into()).unwrap();
+        parse_logs(&empty, Some(2), |logs| segments.push(logs.len())).unwrap();
+        assert_eq!(segments, [0]);
+    }
+}
@@ -29,6 +29,13 @@ pub type HyperProvider<N = alloy_network::Ethereum> =
 pub type IcpProvider<N = alloy_network::Ethereum> =
     crate::RootProvider<alloy_transport_icp::IcpTransport, N>;
 
+/// Type alias for a [`RootProvider`] using the [`IcpHttpTransport`] transport.
+///
+/// [`IcpHttpTransport`]: alloy_transport_icp::IcpHttpTransport
+#[cfg(feature = "icp-http")]
+pub type IcpHttpProvider<N = alloy_network::Ethereum> =
+    crate::RootProvider<alloy_transport_icp::IcpHttpTransport, N>;
+
 #[macro_use]
 extern crate tracing;
 
@@ -48,14 +55,80 @@ pub use heart::{
     PendingTransactionError, WatchTxError,
 };
 
+#[cfg(feature = "icp")]
+mod icp_chain;
+#[cfg(feature = "icp")]
+pub use icp_chain::{ChainEvent, ChainWatcher};
+
+#[cfg(feature = "icp")]
+mod icp_chains;
+#[cfg(feature = "icp")]
+pub use icp_chains::{
+    chain_derivation_path, ChainFiller, ChainManager, ChainManagerBuilder, ChainProvider,
+};
+
+#[cfg(feature = "icp")]
+mod icp_indexer;
+#[cfg(feature = "icp")]
+pub use icp_indexer::{
+    EventKey, IndexedEvent, IndexedLog, Indexer, IndexerStore, StableBlockHashes, StableIndexedLogs,
+};
+
+#[cfg(feature = "icp")]
+mod icp_mempool;
+#[cfg(feature = "icp")]
+pub use icp_mempool::PendingTransactionsWatcher;
+
+#[cfg(feature = "icp")]
+mod icp_pending;
+#[cfg(feature = "icp")]
+pub use icp_pending::{
+    PendingTransactionTracker, StableTrackedTransactions, TrackedStatus, TrackedTransaction,
+};
+
+#[cfg(feature = "icp")]
+mod icp_state;
+#[cfg(feature = "icp")]
+pub use icp_state::IcpState;
+
+#[cfg(feature = "icp")]
+mod icp_status;
+#[cfg(feature = "icp")]
+pub use icp_status::IcpAlloyStatus;
+
+#[cfg(feature = "icp")]
+mod icp_watcher;
+#[cfg(feature = "icp")]
+pub use icp_watcher::{
+    BlockWithReceipts, CursorStore, CursorWatcher, LogFilterDefinition, LogFilterStore,
+    LogFilterWatcher, LogWatcher, MemoryCursorStore, StableCursorStore, StableCursors,
+    StableLogFilters,
+};
+
 mod provider;
 pub use provider::{
-    builder, EthCall, FilterPollerBuilder, Provider, RootProvider, RpcWithBlock, SendableTx,
-    WalletProvider,
+    builder, ChainConstants, EthCall, FilterPollerBuilder, Provider, ProviderCache, RootProvider,
+    RpcWithBlock, SendableTx, WalletProvider, DEFAULT_HEADER_CACHE_SIZE,
+    DEFAULT_RECEIPT_CACHE_SIZE, MULTICALL3_ADDRESS,
 };
 
 pub mod utils;
 
+#[cfg(feature = "icp")]
+pub mod cketh;
+
+#[cfg(feature = "icp")]
+pub mod gateway;
+
+#[cfg(feature = "icp")]
+pub mod siwe;
+
+#[cfg(feature = "ic-stable-structures")]
+pub mod stable;
+
+#[cfg(feature = "candid")]
+pub mod candid;
+
 #[doc(no_inline)]
 pub use alloy_network::{self as network, Network};
 
@@ -0,0 +1,205 @@
+use alloy_network::{Network, ReceiptResponse};
+use alloy_primitives::{BlockHash, TxHash};
+use lru::LruCache;
+use std::{
+    fmt,
+    hash::Hash,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+
+/// The default number of block headers cached.
+pub const DEFAULT_HEADER_CACHE_SIZE: usize = 64;
+
+/// The default number of transaction receipts cached.
+pub const DEFAULT_RECEIPT_CACHE_SIZE: usize = 256;
+
+/// A size-bounded LRU cache, disabled when its capacity is 0.
+struct BoundedCache<K, V>(Mutex<Option<LruCache<K, V>>>);
+
+impl<K: Hash + Eq + Clone, V: Clone> BoundedCache<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self(Mutex::new(NonZeroUsize::new(capacity).map(LruCache::new)))
+    }
+
+    fn capacity(&self) -> usize {
+        self.0.lock().unwrap().as_ref().map_or(0, |cache| cache.cap().get())
+    }
+
+    fn resize(&self, capacity: usize) {
+        let mut cache = self.0.lock().unwrap();
+        match (cache.as_mut(), NonZeroUsize::new(capacity)) {
+            (Some(cache), Some(capacity)) => cache.resize(capacity),
+            (_, capacity) => *cache = capacity.map(LruCache::new),
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        self.0.lock().unwrap().as_mut().and_then(|cache| cache.get(key).cloned())
+    }
+
+    fn insert(&self, key: K, value: V) {
+        if let Some(cache) = self.0.lock().unwrap().as_mut() {
+            cache.put(key, value);
+        }
+    }
+
+    fn remove(&self, key: &K) {
+        if let Some(cache) = self.0.lock().unwrap().as_mut() {
+            cache.pop(key);
+        }
+    }
+
+    fn retain(&self, mut keep: impl FnMut(&V) -> bool) {
+        if let Some(cache) = self.0.lock().unwrap().as_mut() {
+            let removed: Vec<_> = cache
+                .iter()
+                .filter(|(_, value)| !keep(value))
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in removed {
+                cache.pop(&key);
+            }
+        }
+    }
+
+    fn clear(&self) {
+        if let Some(cache) = self.0.lock().unwrap().as_mut() {
+            cache.clear();
+        }
+    }
+}
+
+/// Size-bounded LRU caches for block headers by block hash and transaction receipts by
+/// transaction hash, shared by every provider built on the same [`RootProvider`].
+///
+/// Block headers never change for a block hash, so confirmation checks and reorg detection can
+/// reuse a header fetched before instead of requesting it again, see
+/// [`Provider::get_header_by_hash`]. Only receipts of mined transactions are cached, see
+/// [`Provider::get_transaction_receipt_cached`]. A receipt no longer holds once its block is
+/// reorged out, so call [`remove_block`](Self::remove_block) when a reorg is detected.
+///
+/// Each cached entry is held in the heap of the canister, so the capacities bound the memory
+/// used. A capacity of 0 disables a cache.
+///
+/// [`RootProvider`]: crate::RootProvider
+/// [`Provider::get_header_by_hash`]: crate::Provider::get_header_by_hash
+/// [`Provider::get_transaction_receipt_cached`]: crate::Provider::get_transaction_receipt_cached
+pub struct ProviderCache<N: Network> {
+    headers: Arc<BoundedCache<BlockHash, N::HeaderResponse>>,
+    receipts: Arc<BoundedCache<TxHash, N::ReceiptResponse>>,
+}
+
+impl<N: Network> Clone for ProviderCache<N> {
+    fn clone(&self) -> Self {
+        Self { headers: self.headers.clone(), receipts: self.receipts.clone() }
+    }
+}
+
+impl<N: Network> fmt::Debug for ProviderCache<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProviderCache")
+            .field("header_capacity", &self.header_capacity())
+            .field("receipt_capacity", &self.receipt_capacity())
+            .finish()
+    }
+}
+
+impl<N: Network> Default for ProviderCache<N> {
+    fn default() -> Self {
+        Self::new(DEFAULT_HEADER_CACHE_SIZE, DEFAULT_RECEIPT_CACHE_SIZE)
+    }
+}
+
+impl<N: Network> ProviderCache<N> {
+    /// Create caches holding at most the given number of headers and receipts.
+    pub fn new(header_capacity: usize, receipt_capacity: usize) -> Self {
+        Self {
+            headers: Arc::new(BoundedCache::new(header_capacity)),
+            receipts: Arc::new(BoundedCache::new(receipt_capacity)),
+        }
+    }
+
+    /// Returns the maximum number of headers cached.
+    pub fn header_capacity(&self) -> usize {
+        self.headers.capacity()
+    }
+
+    /// Returns the maximum number of receipts cached.
+    pub fn receipt_capacity(&self) -> usize {
+        self.receipts.capacity()
+    }
+
+    /// Set the maximum number of headers cached, evicting the least recently used headers if
+    /// needed. A capacity of 0 disables the cache.
+    pub fn set_header_capacity(&self, capacity: usize) {
+        self.headers.resize(capacity);
+    }
+
+    /// Set the maximum number of receipts cached, evicting the least recently used receipts if
+    /// needed. A capacity of 0 disables the cache.
+    pub fn set_receipt_capacity(&self, capacity: usize) {
+        self.receipts.resize(capacity);
+    }
+
+    /// Returns the cached header of the given block, if any.
+    pub fn header(&self, hash: &BlockHash) -> Option<N::HeaderResponse> {
+        self.headers.get(hash)
+    }
+
+    /// Cache the header of the given block.
+    pub fn insert_header(&self, hash: BlockHash, header: N::HeaderResponse) {
+        self.headers.insert(hash, header);
+    }
+
+    /// Returns the cached receipt of the given transaction, if any.
+    pub fn receipt(&self, hash: &TxHash) -> Option<N::ReceiptResponse> {
+        self.receipts.get(hash)
+    }
+
+    /// Cache the receipt of the given transaction, if it is mined.
+    pub fn insert_receipt(&self, hash: TxHash, receipt: N::ReceiptResponse) {
+        if receipt.block_hash().is_some() {
+            self.receipts.insert(hash, receipt);
+        }
+    }
+
+    /// Remove the header of a block reorged out, and the receipts of the transactions in it.
+    pub fn remove_block(&self, hash: &BlockHash) {
+        self.headers.remove(hash);
+        self.receipts.retain(|receipt| receipt.block_hash().as_ref() != Some(hash));
+    }
+
+    /// Remove every cached header and receipt.
+    pub fn clear(&self) {
+        self.headers.clear();
+        self.receipts.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_network::Ethereum;
+    use alloy_primitives::B256;
+
+    #[test]
+    fn bounded_header_cache() {
+        let cache = ProviderCache::<Ethereum>::new(2, 0);
+        for i in 1..=3 {
+            cache.insert_header(B256::with_last_byte(i), Default::default());
+        }
+        // The least recently used header was evicted.
+        assert!(cache.header(&B256::with_last_byte(1)).is_none());
+        assert!(cache.header(&B256::with_last_byte(3)).is_some());
+
+        cache.remove_block(&B256::with_last_byte(3));
+        assert!(cache.header(&B256::with_last_byte(3)).is_none());
+        assert!(cache.header(&B256::with_last_byte(2)).is_some());
+
+        cache.set_header_capacity(0);
+        assert_eq!(cache.header_capacity(), 0);
+        cache.insert_header(B256::with_last_byte(4), Default::default());
+        assert!(cache.header(&B256::with_last_byte(4)).is_none());
+    }
+}
@@ -261,6 +261,36 @@ where
     }
 }
 
+#[cfg(feature = "icp")]
+impl<'req, 'state, T, N, Resp, Output, Map> EthCall<'req, 'state, T, N, Resp, Output, Map>
+where
+    T: Transport + Clone,
+    N: Network,
+    Resp: RpcReturn,
+    Output: 'static,
+    Map: Fn(Resp) -> Output,
+{
+    /// Make the call with a single replica instead of every replica of the subnet, for reads
+    /// that need no consensus. See [`RequestContext::with_non_replicated`].
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let balance = provider.call(&tx).non_replicated().await?;
+    /// ```
+    ///
+    /// [`RequestContext::with_non_replicated`]: alloy_transport_icp::RequestContext::with_non_replicated
+    pub fn non_replicated(
+        self,
+    ) -> alloy_transport_icp::Scoped<EthCallFut<'req, 'state, T, N, Resp, Output, Map>> {
+        use std::future::IntoFuture;
+
+        alloy_transport_icp::RequestContext::current()
+            .with_non_replicated(true)
+            .scope(self.into_future())
+    }
+}
+
 #[cfg(test)]
 
 mod test {
@@ -0,0 +1,62 @@
+use alloy_primitives::{address, Address, ChainId};
+use std::sync::OnceLock;
+
+/// The address of the [Multicall3](https://www.multicall3.com) contract, deployed at the same
+/// address on most chains.
+pub const MULTICALL3_ADDRESS: Address = address!("cA11bde05977b3631167028862bE2a173976CA11");
+
+/// Values that never change for a chain, memoized by the [`RootProvider`] once retrieved.
+///
+/// Every provider built on the same root provider shares its constants, so fillers and helpers
+/// such as [`Provider::get_chain_id_cached`] only request each value once, removing outcalls
+/// from the critical path of every transaction after the first.
+///
+/// [`RootProvider`]: crate::RootProvider
+/// [`Provider::get_chain_id_cached`]: crate::Provider::get_chain_id_cached
+#[derive(Clone, Debug, Default)]
+pub struct ChainConstants {
+    chain_id: OnceLock<ChainId>,
+    eip1559: OnceLock<bool>,
+    multicall3: OnceLock<bool>,
+}
+
+impl ChainConstants {
+    /// Create constants with no value retrieved yet.
+    pub const fn new() -> Self {
+        Self { chain_id: OnceLock::new(), eip1559: OnceLock::new(), multicall3: OnceLock::new() }
+    }
+
+    /// Returns the chain ID, if retrieved.
+    pub fn chain_id(&self) -> Option<ChainId> {
+        self.chain_id.get().copied()
+    }
+
+    /// Returns whether the chain supports EIP-1559 transactions, if retrieved.
+    pub fn eip1559(&self) -> Option<bool> {
+        self.eip1559.get().copied()
+    }
+
+    /// Returns whether the [Multicall3](MULTICALL3_ADDRESS) contract is deployed on the chain, if
+    /// retrieved.
+    pub fn multicall3(&self) -> Option<bool> {
+        self.multicall3.get().copied()
+    }
+
+    /// Set the chain ID, e.g. when it is known upfront. Returns the memoized chain ID, which is
+    /// only set once.
+    pub fn set_chain_id(&self, chain_id: ChainId) -> ChainId {
+        *self.chain_id.get_or_init(|| chain_id)
+    }
+
+    /// Set whether the chain supports EIP-1559 transactions. Returns the memoized value, which is
+    /// only set once.
+    pub fn set_eip1559(&self, supported: bool) -> bool {
+        *self.eip1559.get_or_init(|| supported)
+    }
+
+    /// Set whether the Multicall3 contract is deployed on the chain. Returns the memoized value,
+    /// which is only set once.
+    pub fn set_multicall3(&self, deployed: bool) -> bool {
+        *self.multicall3.get_or_init(|| deployed)
+    }
+}
@@ -1,6 +1,12 @@
 mod call;
 pub use call::EthCall;
 
+mod cache;
+pub use cache::{ProviderCache, DEFAULT_HEADER_CACHE_SIZE, DEFAULT_RECEIPT_CACHE_SIZE};
+
+mod constants;
+pub use constants::{ChainConstants, MULTICALL3_ADDRESS};
+
 mod root;
 pub use root::{builder, RootProvider};
 
@@ -1,7 +1,7 @@
 use crate::{
     chain::ChainStreamPoller,
     heart::{Heartbeat, HeartbeatHandle},
-    Identity, ProviderBuilder,
+    ChainConstants, Identity, ProviderBuilder, ProviderCache,
 };
 use alloy_network::{Ethereum, Network};
 use alloy_rpc_client::{BuiltInConnectionString, ClientBuilder, ClientRef, RpcClient, WeakClient};
@@ -20,18 +20,18 @@ use alloy_pubsub::{PubSubFrontend, Subscription};
 
 /// The root provider manages the RPC client and the heartbeat. It is at the
 /// base of every provider stack.
-pub struct RootProvider<T, N = Ethereum> {
+pub struct RootProvider<T, N: Network = Ethereum> {
     /// The inner state of the root provider.
     pub(crate) inner: Arc<RootProviderInner<T, N>>,
 }
 
-impl<T, N> Clone for RootProvider<T, N> {
+impl<T, N: Network> Clone for RootProvider<T, N> {
     fn clone(&self) -> Self {
         Self { inner: self.inner.clone() }
     }
 }
 
-impl<T: fmt::Debug, N> fmt::Debug for RootProvider<T, N> {
+impl<T: fmt::Debug, N: Network> fmt::Debug for RootProvider<T, N> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("RootProvider").field("client", &self.inner.client).finish_non_exhaustive()
     }
@@ -74,6 +74,18 @@ impl<N: Network> RootProvider<BoxTransport, N> {
 }
 
 impl<T: Transport + Clone, N: Network> RootProvider<T, N> {
+    /// Returns the values memoized for the chain, shared by every provider built on this root
+    /// provider.
+    pub fn chain_constants(&self) -> &ChainConstants {
+        &self.inner.constants
+    }
+
+    /// Returns the caches of headers and receipts, shared by every provider built on this root
+    /// provider.
+    pub fn cache(&self) -> &ProviderCache<N> {
+        &self.inner.cache
+    }
+
     /// Boxes the inner client.
     ///
     /// This will create a new provider if this instance is not the only reference to the inner
@@ -133,21 +145,35 @@ impl<T: Transport + Clone, N: Network> RootProvider<T, N> {
 
 /// The root provider manages the RPC client and the heartbeat. It is at the
 /// base of every provider stack.
-pub(crate) struct RootProviderInner<T, N = Ethereum> {
+pub(crate) struct RootProviderInner<T, N: Network = Ethereum> {
     client: RpcClient<T>,
     heart: OnceLock<HeartbeatHandle>,
+    constants: ChainConstants,
+    cache: ProviderCache<N>,
     _network: PhantomData<N>,
 }
 
-impl<T, N> Clone for RootProviderInner<T, N> {
+impl<T, N: Network> Clone for RootProviderInner<T, N> {
     fn clone(&self) -> Self {
-        Self { client: self.client.clone(), heart: self.heart.clone(), _network: PhantomData }
+        Self {
+            client: self.client.clone(),
+            heart: self.heart.clone(),
+            constants: self.constants.clone(),
+            cache: self.cache.clone(),
+            _network: PhantomData,
+        }
     }
 }
 
 impl<T: Transport + Clone, N: Network> RootProviderInner<T, N> {
-    pub(crate) const fn new(client: RpcClient<T>) -> Self {
-        Self { client, heart: OnceLock::new(), _network: PhantomData }
+    pub(crate) fn new(client: RpcClient<T>) -> Self {
+        Self {
+            client,
+            heart: OnceLock::new(),
+            constants: ChainConstants::new(),
+            cache: ProviderCache::default(),
+            _network: PhantomData,
+        }
     }
 
     pub(crate) fn weak_client(&self) -> WeakClient<T> {
@@ -159,8 +185,14 @@ impl<T: Transport + Clone, N: Network> RootProviderInner<T, N> {
     }
 }
 
-impl<T: Transport + Clone, N> RootProviderInner<T, N> {
+impl<T: Transport + Clone, N: Network> RootProviderInner<T, N> {
     fn boxed(self) -> RootProviderInner<BoxTransport, N> {
-        RootProviderInner { client: self.client.boxed(), heart: self.heart, _network: PhantomData }
+        RootProviderInner {
+            client: self.client.boxed(),
+            heart: self.heart,
+            constants: self.constants,
+            cache: self.cache,
+            _network: PhantomData,
+        }
     }
 }
@@ -169,6 +169,87 @@ pub trait Provider<T: Transport + Clone = BoxTransport, N: Network = Ethereum>:
         self.client().request_noparams("eth_chainId").map_resp(crate::utils::convert_u64)
     }
 
+    /// Gets the header of the block with the given hash, reusing a header fetched before if
+    /// cached.
+    ///
+    /// See [`ProviderCache`](crate::ProviderCache).
+    async fn get_header_by_hash(
+        &self,
+        hash: BlockHash,
+    ) -> TransportResult<Option<N::HeaderResponse>> {
+        let cache = self.root().cache();
+        if let Some(header) = cache.header(&hash) {
+            return Ok(Some(header));
+        }
+        let header = self
+            .get_block_by_hash(hash, BlockTransactionsKind::Hashes)
+            .await?
+            .map(|block| block.header().clone());
+        if let Some(header) = &header {
+            cache.insert_header(hash, header.clone());
+        }
+        Ok(header)
+    }
+
+    /// Gets the receipt of the given transaction, reusing a receipt fetched before if cached.
+    ///
+    /// Only receipts of mined transactions are cached, so a pending transaction is requested
+    /// again on every call. See [`ProviderCache`](crate::ProviderCache).
+    async fn get_transaction_receipt_cached(
+        &self,
+        hash: TxHash,
+    ) -> TransportResult<Option<N::ReceiptResponse>> {
+        let cache = self.root().cache();
+        if let Some(receipt) = cache.receipt(&hash) {
+            return Ok(Some(receipt));
+        }
+        let receipt = self.get_transaction_receipt(hash).await?;
+        if let Some(receipt) = &receipt {
+            cache.insert_receipt(hash, receipt.clone());
+        }
+        Ok(receipt)
+    }
+
+    /// Gets the chain ID, requesting it only once per root provider.
+    ///
+    /// See [`ChainConstants`](crate::ChainConstants).
+    async fn get_chain_id_cached(&self) -> TransportResult<u64> {
+        let constants = self.root().chain_constants();
+        match constants.chain_id() {
+            Some(chain_id) => Ok(chain_id),
+            None => Ok(constants.set_chain_id(self.get_chain_id().await?)),
+        }
+    }
+
+    /// Returns `true` if the chain supports EIP-1559 transactions, checking the latest block for a
+    /// base fee only once per root provider.
+    ///
+    /// See [`ChainConstants`](crate::ChainConstants).
+    async fn supports_eip1559(&self) -> TransportResult<bool> {
+        let constants = self.root().chain_constants();
+        if let Some(supported) = constants.eip1559() {
+            return Ok(supported);
+        }
+        let block = self
+            .get_block_by_number(BlockNumberOrTag::Latest, false)
+            .await?
+            .ok_or(RpcError::NullResp)?;
+        Ok(constants.set_eip1559(block.header().base_fee_per_gas().is_some()))
+    }
+
+    /// Returns `true` if the [Multicall3](crate::MULTICALL3_ADDRESS) contract is deployed on the
+    /// chain, checking for its code only once per root provider.
+    ///
+    /// See [`ChainConstants`](crate::ChainConstants).
+    async fn has_multicall3(&self) -> TransportResult<bool> {
+        let constants = self.root().chain_constants();
+        if let Some(deployed) = constants.multicall3() {
+            return Ok(deployed);
+        }
+        let code = self.get_code_at(crate::MULTICALL3_ADDRESS).await?;
+        Ok(constants.set_multicall3(!code.is_empty()))
+    }
+
     /// Create an [EIP-2930] access list.
     ///
     /// [EIP-2930]: https://eips.ethereum.org/EIPS/eip-2930
@@ -376,6 +457,16 @@ pub trait Provider<T: Transport + Clone = BoxTransport, N: Network = Ethereum>:
     /// Returns a builder that is used to configure the poller. See [`PollerBuilder`] for more
     /// details.
     ///
+    /// Timers do not survive canister upgrades. Name the poller with
+    /// [`with_name`](IcpPollerBuilder::with_name) to persist its definition, and restore it in
+    /// `post_upgrade`, see
+    /// [`RpcClient::restore_pollers`](alloy_rpc_client::RpcClient::restore_pollers).
+    ///
+    /// To only handle blocks once they are a number of blocks deep, see
+    /// [`with_confirmations`](IcpPollerBuilder::with_confirmations). To poll about once per
+    /// block of the chain, see
+    /// [`with_adaptive_interval`](IcpPollerBuilder::with_adaptive_interval).
+    ///
     /// # Examples
     ///
     /// Get and print the next 3 blocks:
@@ -390,7 +481,7 @@ pub trait Provider<T: Transport + Clone = BoxTransport, N: Network = Ethereum>:
     ///     };
     ///
     ///     let poller = provider.watch_blocks().await.unwrap();
-    ///     let timer_id = poller
+    ///     let handle = poller
     ///         .with_limit(Some(10))
     ///         .with_poll_interval(Duration::from_secs(5))
     ///         .start(callback)
@@ -434,13 +525,60 @@ pub trait Provider<T: Transport + Clone = BoxTransport, N: Network = Ethereum>:
     /// [`eth_getFilterChanges`](Self::get_filter_changes).
     ///
     /// Returns a builder that is used to configure the poller. See [`PollerBuilder`] for more
-    /// details.
+    /// details. If the provider does not support pending transaction filters, see
+    /// [`watch_pending_block_transactions`](Self::watch_pending_block_transactions).
     #[cfg(feature = "icp")]
     async fn watch_pending_transactions(&self) -> TransportResult<FilterPollerBuilder<T, B256>> {
         let id = self.new_pending_transactions_filter(false).await?;
         Ok(IcpPollerBuilder::new(self.weak_client(), "eth_getFilterChanges", (id,)))
     }
 
+    /// Watch for new pending transactions by polling the provider with
+    /// [`eth_getBlockByNumber`](Self::get_block_by_number) for the pending block.
+    ///
+    /// Unlike [`watch_pending_transactions`](Self::watch_pending_transactions), this does not
+    /// rely on a filter held by the provider, so it works with providers that do not support
+    /// pending transaction filters. See
+    /// [`PendingTransactionsWatcher`](crate::PendingTransactionsWatcher) for more details.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// provider
+    ///     .watch_pending_block_transactions()
+    ///     .with_poll_interval(Duration::from_secs(5))
+    ///     .start(|hashes: Vec<B256>| handle_pending(hashes))?;
+    /// ```
+    #[cfg(feature = "icp")]
+    fn watch_pending_block_transactions(&self) -> crate::PendingTransactionsWatcher<T> {
+        crate::PendingTransactionsWatcher::new(self.weak_client())
+    }
+
+    /// Follow the canonical chain by polling the provider with
+    /// [`eth_getBlockByNumber`](Self::get_block_by_number) for the latest block, reporting
+    /// reorgs.
+    ///
+    /// Unlike [`watch_blocks`](Self::watch_blocks), the watcher tracks the parent hashes of the
+    /// recent blocks, and reports a [`ChainEvent::Reorg`](crate::ChainEvent::Reorg) when the
+    /// chain it has been following is replaced. See [`ChainWatcher`](crate::ChainWatcher) for
+    /// more details.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// provider.watch_chain().with_reorg_depth(32).start(|event| match event {
+    ///     ChainEvent::Block { number, hash } => index_block(number, hash),
+    ///     ChainEvent::Reorg { old, new, .. } => {
+    ///         roll_back(&old);
+    ///         index_blocks(&new);
+    ///     }
+    /// })?;
+    /// ```
+    #[cfg(feature = "icp")]
+    fn watch_chain(&self) -> crate::ChainWatcher<T> {
+        crate::ChainWatcher::new(self.weak_client())
+    }
+
     /// Watch for new logs using the given filter by polling the provider with
     /// [`eth_getFilterChanges`](Self::get_filter_changes).
     ///
@@ -517,7 +655,7 @@ pub trait Provider<T: Transport + Clone = BoxTransport, N: Network = Ethereum>:
     /// // `with_limit` (optional) is used to limit the number of times to poll, defaults to 3
     /// // `with_poll_interval` (optional) is used to set the interval between polls, defaults to 7 seconds
     /// let poller = provider.watch_logs(&filter).await.unwrap();
-    /// let timer_id = poller
+    /// let handle = poller
     ///     .with_limit(Some(POLL_LIMIT))
     ///     .with_poll_interval(Duration::from_secs(10))
     ///     .start(callback)
@@ -532,6 +670,43 @@ pub trait Provider<T: Transport + Clone = BoxTransport, N: Network = Ethereum>:
         Ok(IcpPollerBuilder::new(self.weak_client(), "eth_getFilterChanges", (id,)))
     }
 
+    /// Watch for new logs using the given filter by polling the provider with
+    /// [`eth_getLogs`](Self::get_logs), advancing the `fromBlock` of the filter with each poll.
+    ///
+    /// Unlike [`watch_logs`](Self::watch_logs), the watcher does not rely on a filter held by
+    /// the provider. It remembers the last block it has delivered, and each poll delivers the
+    /// logs from the next block up to the latest block, so logs are neither missed nor
+    /// delivered twice. A numeric `fromBlock` of the filter sets the first block delivered,
+    /// otherwise the watcher starts at the latest block when it first polls. The `toBlock` of
+    /// the filter is ignored.
+    ///
+    /// The last delivered block is kept on the heap and lost on upgrade. To resume after an
+    /// upgrade, use a [`CursorWatcher`](crate::CursorWatcher) with a
+    /// [`StableCursorStore`](crate::StableCursorStore) instead.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let filter = Filter::new().address(usdc_address).event(Transfer::SIGNATURE);
+    /// provider
+    ///     .poll_logs(&filter)
+    ///     .with_poll_interval(Duration::from_secs(12))
+    ///     .start(|logs: Vec<Log>| handle_transfers(logs))?;
+    /// ```
+    #[cfg(feature = "icp")]
+    fn poll_logs(&self, filter: &Filter) -> crate::LogWatcher<T> {
+        let watcher = crate::CursorWatcher::logs(
+            self.weak_client(),
+            "logs",
+            crate::MemoryCursorStore::new(),
+            filter.clone(),
+        );
+        match filter.get_from_block() {
+            Some(block) => watcher.with_start_block(block),
+            None => watcher,
+        }
+    }
+
     /// Watch for new pending transaction bodies by polling the provider with
     /// [`eth_getFilterChanges`](Self::get_filter_changes).
     ///
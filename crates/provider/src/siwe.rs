@@ -0,0 +1,560 @@
+//! Sign-In with Ethereum ([EIP-4361](https://eips.ethereum.org/EIPS/eip-4361)) message parsing,
+//! validation and signature verification.
+//!
+//! Canisters authenticating users by their Ethereum wallets receive a SIWE message and its
+//! signature, check that the message was issued for them and is still valid, and check that it
+//! was signed by the account. Externally owned accounts are verified by recovering the signer of
+//! the message. Contract wallets are verified with their
+//! [ERC-1271](https://eips.ethereum.org/EIPS/eip-1271) `isValidSignature` method, called with
+//! `eth_call`.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! #[ic_cdk::update]
+//! async fn login(message: String, signature: Vec<u8>) -> Result<String, String> {
+//!     let message: SiweMessage = message.parse().map_err(|e: SiweError| e.to_string())?;
+//!     let nonce = take_nonce(&message.address)?;
+//!     let options = SiweOptions::new().with_domain("app.example.com").with_nonce(nonce);
+//!     message.verify(&provider(), &signature, &options).await.map_err(|e| e.to_string())?;
+//!     Ok(create_session(message.address))
+//! }
+//! ```
+
+use crate::Provider;
+use alloy_network::{Network, TransactionBuilder};
+use alloy_primitives::{eip191_hash_message, Address, FixedBytes, PrimitiveSignature, B256};
+use alloy_sol_types::{sol, SolCall};
+use alloy_transport::{Transport, TransportError};
+use std::{fmt, str::FromStr};
+
+sol! {
+    function isValidSignature(bytes32 hash, bytes signature) external view returns (bytes4 magicValue);
+}
+
+/// The value returned by the ERC-1271 `isValidSignature` method for a valid signature.
+pub const ERC1271_MAGIC_VALUE: FixedBytes<4> = FixedBytes([0x16, 0x26, 0xba, 0x7e]);
+
+const PREAMBLE: &str = " wants you to sign in with your Ethereum account:";
+const URI_TAG: &str = "URI: ";
+const VERSION_TAG: &str = "Version: ";
+const CHAIN_ID_TAG: &str = "Chain ID: ";
+const NONCE_TAG: &str = "Nonce: ";
+const ISSUED_AT_TAG: &str = "Issued At: ";
+const EXPIRATION_TIME_TAG: &str = "Expiration Time: ";
+const NOT_BEFORE_TAG: &str = "Not Before: ";
+const REQUEST_ID_TAG: &str = "Request ID: ";
+const RESOURCES_TAG: &str = "Resources:";
+
+/// Errors which may occur when parsing, validating or verifying a [`SiweMessage`].
+#[derive(Debug, thiserror::Error)]
+pub enum SiweError {
+    /// The message does not follow the EIP-4361 format.
+    #[error("invalid SIWE message: {0}")]
+    InvalidMessage(String),
+
+    /// The message was issued for another domain.
+    #[error("domain mismatch: expected {expected}, found {found}")]
+    DomainMismatch {
+        /// The expected domain.
+        expected: String,
+        /// The domain of the message.
+        found: String,
+    },
+
+    /// The nonce of the message is not the expected nonce.
+    #[error("nonce mismatch")]
+    NonceMismatch,
+
+    /// The message was issued for another chain.
+    #[error("chain ID mismatch: expected {expected}, found {found}")]
+    ChainIdMismatch {
+        /// The expected chain ID.
+        expected: u64,
+        /// The chain ID of the message.
+        found: u64,
+    },
+
+    /// The expiration time of the message has passed.
+    #[error("message has expired")]
+    Expired,
+
+    /// The not before time of the message has not been reached yet.
+    #[error("message is not valid yet")]
+    NotYetValid,
+
+    /// The signature was not made by the account of the message.
+    #[error("invalid signature")]
+    InvalidSignature,
+
+    /// Underlying transport error of the ERC-1271 verification call.
+    #[error(transparent)]
+    TransportError(#[from] TransportError),
+}
+
+/// A Sign-In with Ethereum message, see [EIP-4361](https://eips.ethereum.org/EIPS/eip-4361).
+///
+/// Messages are parsed with [`FromStr`] and formatted back with [`Display`](fmt::Display). Times
+/// are kept as the RFC 3339 timestamps of the message, so the formatted message is the message
+/// that was signed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SiweMessage {
+    /// The URI scheme of the origin of the request, if given.
+    pub scheme: Option<String>,
+    /// The domain requesting the signing.
+    pub domain: String,
+    /// The account signing in.
+    pub address: Address,
+    /// A human-readable assertion the user signs, if any.
+    pub statement: Option<String>,
+    /// The subject of the signing, such as the URI of the application.
+    pub uri: String,
+    /// The version of the message format. Always `1`.
+    pub version: String,
+    /// The chain ID the account is bound to.
+    pub chain_id: u64,
+    /// A random string, chosen by the relying party to prevent replay attacks.
+    pub nonce: String,
+    /// The time the message was issued, as an RFC 3339 timestamp.
+    pub issued_at: String,
+    /// The time after which the message is no longer valid, as an RFC 3339 timestamp.
+    pub expiration_time: Option<String>,
+    /// The time the message becomes valid, as an RFC 3339 timestamp.
+    pub not_before: Option<String>,
+    /// A system-specific identifier of the request.
+    pub request_id: Option<String>,
+    /// Resources the user wishes to have resolved as part of authentication.
+    pub resources: Vec<String>,
+}
+
+impl FromStr for SiweMessage {
+    type Err = SiweError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| SiweError::InvalidMessage(reason.to_string());
+        let mut lines = s.split('\n').peekable();
+
+        let origin = lines
+            .next()
+            .and_then(|line| line.strip_suffix(PREAMBLE))
+            .ok_or_else(|| invalid("missing preamble"))?;
+        let (scheme, domain) = match origin.split_once("://") {
+            Some((scheme, domain)) => (Some(scheme.to_string()), domain),
+            None => (None, origin),
+        };
+        if domain.is_empty() || domain.contains(char::is_whitespace) {
+            return Err(invalid("invalid domain"));
+        }
+
+        let address = lines
+            .next()
+            .and_then(|line| Address::parse_checksummed(line, None).ok())
+            .ok_or_else(|| invalid("invalid address, EIP-55 checksum required"))?;
+
+        if lines.next() != Some("") {
+            return Err(invalid("missing empty line after address"));
+        }
+        let statement = match lines.next() {
+            Some("") => None,
+            Some(statement) if !statement.starts_with(URI_TAG) => {
+                if lines.next() != Some("") {
+                    return Err(invalid("missing empty line after statement"));
+                }
+                Some(statement.to_string())
+            }
+            _ => return Err(invalid("missing empty line before URI")),
+        };
+
+        let mut tagged = |tag: &str| lines.next().and_then(|line| line.strip_prefix(tag));
+        let uri = tagged(URI_TAG).ok_or_else(|| invalid("missing URI"))?.to_string();
+        let version = tagged(VERSION_TAG).ok_or_else(|| invalid("missing version"))?.to_string();
+        if version != "1" {
+            return Err(invalid("unsupported version"));
+        }
+        let chain_id = tagged(CHAIN_ID_TAG)
+            .and_then(|chain_id| chain_id.parse().ok())
+            .ok_or_else(|| invalid("missing or invalid chain ID"))?;
+        let nonce = tagged(NONCE_TAG).ok_or_else(|| invalid("missing nonce"))?.to_string();
+        if nonce.len() < 8 || !nonce.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(invalid("nonce must be at least 8 alphanumeric characters"));
+        }
+        let issued_at = tagged(ISSUED_AT_TAG).ok_or_else(|| invalid("missing issued at"))?;
+
+        let mut optional = |tag: &str| {
+            let value = lines.peek().and_then(|line| line.strip_prefix(tag)).map(str::to_string);
+            if value.is_some() {
+                lines.next();
+            }
+            value
+        };
+        let expiration_time = optional(EXPIRATION_TIME_TAG);
+        let not_before = optional(NOT_BEFORE_TAG);
+        let request_id = optional(REQUEST_ID_TAG);
+        for time in [Some(issued_at), expiration_time.as_deref(), not_before.as_deref()] {
+            if time.is_some_and(|time| parse_timestamp(time).is_none()) {
+                return Err(invalid("invalid RFC 3339 timestamp"));
+            }
+        }
+
+        let mut resources = Vec::new();
+        if lines.peek() == Some(&RESOURCES_TAG) {
+            lines.next();
+            while let Some(resource) = lines.peek().and_then(|line| line.strip_prefix("- ")) {
+                resources.push(resource.to_string());
+                lines.next();
+            }
+        }
+        if lines.any(|line| !line.is_empty()) {
+            return Err(invalid("unexpected trailing content"));
+        }
+
+        Ok(Self {
+            scheme,
+            domain: domain.to_string(),
+            address,
+            statement,
+            uri,
+            version,
+            chain_id,
+            nonce,
+            issued_at: issued_at.to_string(),
+            expiration_time,
+            not_before,
+            request_id,
+            resources,
+        })
+    }
+}
+
+impl fmt::Display for SiweMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(scheme) = &self.scheme {
+            write!(f, "{scheme}://")?;
+        }
+        writeln!(f, "{}{PREAMBLE}", self.domain)?;
+        writeln!(f, "{}", self.address.to_checksum(None))?;
+        writeln!(f)?;
+        if let Some(statement) = &self.statement {
+            writeln!(f, "{statement}")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{URI_TAG}{}", self.uri)?;
+        writeln!(f, "{VERSION_TAG}{}", self.version)?;
+        writeln!(f, "{CHAIN_ID_TAG}{}", self.chain_id)?;
+        writeln!(f, "{NONCE_TAG}{}", self.nonce)?;
+        write!(f, "{ISSUED_AT_TAG}{}", self.issued_at)?;
+        if let Some(expiration_time) = &self.expiration_time {
+            write!(f, "\n{EXPIRATION_TIME_TAG}{expiration_time}")?;
+        }
+        if let Some(not_before) = &self.not_before {
+            write!(f, "\n{NOT_BEFORE_TAG}{not_before}")?;
+        }
+        if let Some(request_id) = &self.request_id {
+            write!(f, "\n{REQUEST_ID_TAG}{request_id}")?;
+        }
+        if !self.resources.is_empty() {
+            write!(f, "\n{RESOURCES_TAG}")?;
+            for resource in &self.resources {
+                write!(f, "\n- {resource}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The expectations a [`SiweMessage`] is validated against.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SiweOptions {
+    domain: Option<String>,
+    nonce: Option<String>,
+    chain_id: Option<u64>,
+    time: Option<u64>,
+}
+
+impl SiweOptions {
+    /// Create options only checking the expiration and not before times of the message.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require the message to be issued for the given domain.
+    pub fn with_domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Require the message to hold the given nonce, e.g. the nonce issued to the user.
+    pub fn with_nonce(mut self, nonce: impl Into<String>) -> Self {
+        self.nonce = Some(nonce.into());
+        self
+    }
+
+    /// Require the message to be issued for the given chain.
+    pub const fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = Some(chain_id);
+        self
+    }
+
+    /// Validate the times of the message against the given time, in seconds since the epoch,
+    /// instead of the current time of the canister.
+    pub const fn with_time(mut self, time: u64) -> Self {
+        self.time = Some(time);
+        self
+    }
+}
+
+impl SiweMessage {
+    /// Returns the EIP-191 hash of the message, the hash signed by the account.
+    pub fn hash(&self) -> B256 {
+        eip191_hash_message(self.to_string())
+    }
+
+    /// Check the message against the options, and that it is valid at the current time.
+    pub fn validate(&self, options: &SiweOptions) -> Result<(), SiweError> {
+        if let Some(domain) = &options.domain {
+            if *domain != self.domain {
+                return Err(SiweError::DomainMismatch {
+                    expected: domain.clone(),
+                    found: self.domain.clone(),
+                });
+            }
+        }
+        if options.nonce.as_ref().is_some_and(|nonce| *nonce != self.nonce) {
+            return Err(SiweError::NonceMismatch);
+        }
+        if let Some(chain_id) = options.chain_id {
+            if chain_id != self.chain_id {
+                return Err(SiweError::ChainIdMismatch {
+                    expected: chain_id,
+                    found: self.chain_id,
+                });
+            }
+        }
+
+        let now = options.time.unwrap_or_else(|| ic_cdk::api::time() / 1_000_000_000) as i64;
+        let time = |time: &Option<String>| time.as_deref().and_then(parse_timestamp);
+        if time(&self.expiration_time).is_some_and(|expiration_time| now >= expiration_time) {
+            return Err(SiweError::Expired);
+        }
+        if time(&self.not_before).is_some_and(|not_before| now < not_before) {
+            return Err(SiweError::NotYetValid);
+        }
+        Ok(())
+    }
+
+    /// Check that the message was signed by the private key of its address.
+    ///
+    /// Only verifies signatures of externally owned accounts, see [`verify`](Self::verify) to
+    /// also verify signatures of contract wallets.
+    pub fn verify_eoa(&self, signature: &[u8]) -> Result<(), SiweError> {
+        let signature =
+            PrimitiveSignature::try_from(signature).map_err(|_| SiweError::InvalidSignature)?;
+        match signature.recover_address_from_prehash(&self.hash()) {
+            Ok(address) if address == self.address => Ok(()),
+            _ => Err(SiweError::InvalidSignature),
+        }
+    }
+
+    /// Check that the signature of the message is valid for the account of its address, calling
+    /// its ERC-1271 `isValidSignature` method if it is not the signature of an externally owned
+    /// account.
+    pub async fn verify_signature<P, T, N>(
+        &self,
+        provider: &P,
+        signature: &[u8],
+    ) -> Result<(), SiweError>
+    where
+        P: Provider<T, N>,
+        T: Transport + Clone,
+        N: Network,
+    {
+        if self.verify_eoa(signature).is_ok() {
+            return Ok(());
+        }
+        let call = isValidSignatureCall { hash: self.hash(), signature: signature.to_vec().into() };
+        let tx =
+            N::TransactionRequest::default().with_to(self.address).with_input(call.abi_encode());
+        let output = provider.call(&tx).await?;
+        match isValidSignatureCall::abi_decode_returns(&output, true) {
+            Ok(valid) if valid.magicValue == ERC1271_MAGIC_VALUE => Ok(()),
+            _ => Err(SiweError::InvalidSignature),
+        }
+    }
+
+    /// Validate the message against the options, then verify its signature. See
+    /// [`validate`](Self::validate) and [`verify_signature`](Self::verify_signature).
+    pub async fn verify<P, T, N>(
+        &self,
+        provider: &P,
+        signature: &[u8],
+        options: &SiweOptions,
+    ) -> Result<(), SiweError>
+    where
+        P: Provider<T, N>,
+        T: Transport + Clone,
+        N: Network,
+    {
+        self.validate(options)?;
+        self.verify_signature(provider, signature).await
+    }
+}
+
+/// Parse an RFC 3339 timestamp into seconds since the epoch. Fractional seconds are ignored.
+fn parse_timestamp(s: &str) -> Option<i64> {
+    if !s.is_ascii() {
+        return None;
+    }
+    let (date, time) = s.split_once(['T', 't'])?;
+    let mut date = date.splitn(3, '-');
+    let year = number(date.next()?, 4)?;
+    let month = number(date.next()?, 2)?;
+    let day = number(date.next()?, 2)?;
+
+    let (time, offset) = match time.strip_suffix(['Z', 'z']) {
+        Some(time) => (time, 0),
+        None => {
+            let (time, offset) = time.split_at(time.len().checked_sub(6)?);
+            let (sign, offset) = offset.split_at(1);
+            let (hours, minutes) = offset.split_once(':')?;
+            let offset = number(hours, 2)? * 3600 + number(minutes, 2)? * 60;
+            match sign {
+                "+" => (time, offset),
+                "-" => (time, -offset),
+                _ => return None,
+            }
+        }
+    };
+    let time = match time.split_once('.') {
+        Some((time, fraction))
+            if !fraction.is_empty() && fraction.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            time
+        }
+        Some(_) => return None,
+        None => time,
+    };
+    let mut time = time.splitn(3, ':');
+    let hour = number(time.next()?, 2)?;
+    let minute = number(time.next()?, 2)?;
+    let second = number(time.next()?, 2)?;
+
+    // Leap seconds are allowed.
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    // Days since the epoch of the civil date, see
+    // <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>.
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    Some(days * 86400 + hour * 3600 + minute * 60 + second - offset)
+}
+
+/// Parse a number of exactly the given number of digits.
+fn number(s: &str, digits: usize) -> Option<i64> {
+    (s.len() == digits && s.bytes().all(|b| b.is_ascii_digit())).then(|| s.parse().ok())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::address;
+
+    const MESSAGE: &str = "example.com wants you to sign in with your Ethereum account:
+0x9D85ca56217D2bb651b00f15e694EB7E713637D4
+
+I accept the ExampleOrg Terms of Service: https://example.com/tos
+
+URI: https://example.com/login
+Version: 1
+Chain ID: 1
+Nonce: 32891756
+Issued At: 2021-09-30T16:25:24Z
+Expiration Time: 2021-10-01T16:25:24+02:00
+Resources:
+- ipfs://bafybeiemxf5abjwjbikoz4mc3a3dla6ual3jsgpdr4cjr3oz3evfyavhwq/
+- https://example.com/my-web2-claim.json";
+
+    #[test]
+    fn parses_and_formats() {
+        let message: SiweMessage = MESSAGE.parse().unwrap();
+        assert_eq!(message.domain, "example.com");
+        assert_eq!(message.address, address!("9D85ca56217D2bb651b00f15e694EB7E713637D4"));
+        assert_eq!(
+            message.statement.as_deref(),
+            Some("I accept the ExampleOrg Terms of Service: https://example.com/tos")
+        );
+        assert_eq!(message.nonce, "32891756");
+        assert_eq!(message.resources.len(), 2);
+        assert_eq!(message.to_string(), MESSAGE);
+
+        let without_statement = MESSAGE
+            .replace("\nI accept the ExampleOrg Terms of Service: https://example.com/tos\n", "\n");
+        let message: SiweMessage = without_statement.parse().unwrap();
+        assert_eq!(message.statement, None);
+        assert_eq!(message.to_string(), without_statement);
+
+        assert!(MESSAGE.replace("Version: 1", "Version: 2").parse::<SiweMessage>().is_err());
+        assert!(MESSAGE.replace("0x9D85ca", "0x9d85ca").parse::<SiweMessage>().is_err());
+    }
+
+    #[test]
+    fn validates() {
+        let message: SiweMessage = MESSAGE.parse().unwrap();
+        // 2021-09-30T16:25:24Z and 2021-10-01T14:25:24Z.
+        let (issued_at, expires_at) = (1633019124, 1633098324);
+        let options = SiweOptions::new().with_time(issued_at);
+        message.validate(&options.clone().with_domain("example.com").with_chain_id(1)).unwrap();
+
+        assert!(matches!(
+            message.validate(&options.clone().with_domain("evil.com")),
+            Err(SiweError::DomainMismatch { .. })
+        ));
+        assert!(matches!(
+            message.validate(&options.with_nonce("12345678")),
+            Err(SiweError::NonceMismatch)
+        ));
+        assert!(matches!(
+            message.validate(&SiweOptions::new().with_time(expires_at)),
+            Err(SiweError::Expired)
+        ));
+        message.validate(&SiweOptions::new().with_time(expires_at - 1)).unwrap();
+    }
+
+    #[test]
+    fn verifies_eoa_signatures() {
+        use alloy_signer::SignerSync;
+
+        let signer = alloy_signer_local::PrivateKeySigner::random();
+        let mut message: SiweMessage = MESSAGE.parse().unwrap();
+        message.address = signer.address();
+        let signature = signer.sign_message_sync(message.to_string().as_bytes()).unwrap();
+        message.verify_eoa(&signature.as_bytes()).unwrap();
+
+        message.nonce = "87654321".into();
+        assert!(matches!(
+            message.verify_eoa(&signature.as_bytes()),
+            Err(SiweError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_timestamp("2021-09-30T16:25:24.000Z"), Some(1633019124));
+        assert_eq!(parse_timestamp("2021-09-30T18:25:24+02:00"), Some(1633019124));
+        assert_eq!(parse_timestamp("2021-09-30T14:25:24-02:00"), Some(1633019124));
+        assert_eq!(parse_timestamp("2021-09-30 16:25:24Z"), None);
+        assert_eq!(parse_timestamp("2021-13-30T16:25:24Z"), None);
+    }
+}
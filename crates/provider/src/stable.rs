@@ -0,0 +1,135 @@
+//! [`Storable`] wrappers for alloy primitives.
+//!
+//! Foreign traits can't be implemented for foreign types, and neither `Storable` nor the
+//! primitive types are defined in this crate. These wrappers are shared instead, so that
+//! projects don't each need their own to use primitives as keys and values of stable structures.
+//!
+//! Each wrapper has a fixed size bound, and converts from and into the wrapped type:
+//!
+//! ```ignore
+//! thread_local! {
+//!     static BALANCES: RefCell<StableBTreeMap<StableAddress, StableU256, Memory>> = ...;
+//! }
+//!
+//! BALANCES.with_borrow_mut(|balances| balances.insert(address.into(), balance.into()));
+//! ```
+
+use alloy_primitives::{Address, PrimitiveSignature, B256, U256};
+use ic_stable_structures::{storable::Bound, Storable};
+use std::{borrow::Cow, ops::Deref};
+
+macro_rules! stable_wrapper {
+    ($(#[$attr:meta])* $name:ident($inner:ty), $size:expr, $to_bytes:expr, $from_bytes:expr) => {
+        $(#[$attr])*
+        pub struct $name(pub $inner);
+
+        impl Storable for $name {
+            fn to_bytes(&self) -> Cow<'_, [u8]> {
+                let to_bytes: fn(&$inner) -> Vec<u8> = $to_bytes;
+                Cow::Owned(to_bytes(&self.0))
+            }
+
+            fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+                let from_bytes: fn(&[u8]) -> $inner = $from_bytes;
+                Self(from_bytes(&bytes))
+            }
+
+            const BOUND: Bound = Bound::Bounded { max_size: $size, is_fixed_size: true };
+        }
+
+        impl From<$inner> for $name {
+            fn from(value: $inner) -> Self {
+                Self(value)
+            }
+        }
+
+        impl From<$name> for $inner {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl Deref for $name {
+            type Target = $inner;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+    };
+}
+
+stable_wrapper!(
+    /// A [`Storable`] [`Address`].
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    StableAddress(Address),
+    20,
+    |address| address.to_vec(),
+    Address::from_slice
+);
+
+stable_wrapper!(
+    /// A [`Storable`] [`B256`], e.g. a transaction or block hash.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    StableB256(B256),
+    32,
+    |hash| hash.to_vec(),
+    B256::from_slice
+);
+
+stable_wrapper!(
+    /// A [`Storable`] [`U256`], stored big-endian so that the byte order matches the numeric
+    /// order.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    StableU256(U256),
+    32,
+    |value| value.to_be_bytes::<32>().to_vec(),
+    |bytes| U256::from_be_slice(bytes)
+);
+
+stable_wrapper!(
+    /// A [`Storable`] [`PrimitiveSignature`], stored as `r`, `s` and the y-parity.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    StableSignature(PrimitiveSignature),
+    65,
+    |signature| {
+        let mut bytes = signature.as_bytes();
+        bytes[64] = signature.v() as u8;
+        bytes.to_vec()
+    },
+    |bytes| PrimitiveSignature::from_bytes_and_parity(&bytes[..64], bytes[64] != 0)
+);
+
+/// A [`Storable`] transaction hash.
+pub type StableTxHash = StableB256;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{address, b256};
+
+    fn roundtrip<T: Storable + PartialEq + std::fmt::Debug>(value: T) {
+        let bytes = value.to_bytes().into_owned();
+        if let Bound::Bounded { max_size, is_fixed_size: true } = T::BOUND {
+            assert_eq!(bytes.len(), max_size as usize);
+        }
+        assert_eq!(T::from_bytes(Cow::Owned(bytes)), value);
+    }
+
+    #[test]
+    fn wrappers_roundtrip() {
+        roundtrip(StableAddress(address!("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")));
+        roundtrip(StableB256(b256!(
+            "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+        )));
+        roundtrip(StableU256(U256::from(1_000_000_007u64)));
+        roundtrip(StableSignature(PrimitiveSignature::test_signature()));
+    }
+
+    #[test]
+    fn u256_byte_order_matches_numeric_order() {
+        let small = StableU256(U256::from(255u64)).to_bytes().into_owned();
+        let large = StableU256(U256::from(256u64)).to_bytes().into_owned();
+        assert!(small < large);
+    }
+}
@@ -61,7 +61,7 @@ impl<L> ClientBuilder<L> {
     }
 
     /// Convenience function to create a new [`RpcClient`] with an [`IcpTransport`] using
-    /// the given [`IcpConfig`] details.
+    /// the given [`IcpConfig`] details, and the poll interval of the config if set.
     ///
     /// [`IcpTransport`]: alloy_transport_icp::IcpTransport
     /// [`IcpConfig`]: alloy_transport_icp::IcpConfig
@@ -71,9 +71,30 @@ impl<L> ClientBuilder<L> {
         L: Layer<alloy_transport_icp::IcpTransport>,
         L::Service: Transport,
     {
+        let poll_interval = config.poll_interval();
         let transport = alloy_transport_icp::IcpTransport::with_config(config);
         let is_local = transport.is_local();
 
+        let client = self.transport(transport, is_local);
+        match poll_interval {
+            Some(poll_interval) => client.with_poll_interval(poll_interval),
+            None => client,
+        }
+    }
+
+    /// Convenience function to create a new [`RpcClient`] with an [`IcpHttpTransport`] making
+    /// HTTPS outcalls to the given URL.
+    ///
+    /// [`IcpHttpTransport`]: alloy_transport_icp::IcpHttpTransport
+    #[cfg(feature = "icp-http")]
+    pub fn icp_http(self, url: impl Into<String>) -> RpcClient<L::Service>
+    where
+        L: Layer<alloy_transport_icp::IcpHttpTransport>,
+        L::Service: Transport,
+    {
+        let transport = alloy_transport_icp::IcpHttpTransport::new(url);
+        let is_local = transport.is_local();
+
         self.transport(transport, is_local)
     }
 
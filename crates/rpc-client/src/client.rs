@@ -1,6 +1,7 @@
 use crate::{poller::PollerBuilder, BatchRequest, ClientBuilder, RpcCall};
 use alloy_json_rpc::{Id, Request, RpcParam, RpcReturn};
 use alloy_transport::{BoxTransport, Transport};
+#[cfg(any(feature = "reqwest", feature = "hyper"))]
 #[allow(unused_imports)]
 use alloy_transport_http::Http;
 use std::{
@@ -114,6 +115,22 @@ impl<T: Transport> RpcClient<T> {
     }
 }
 
+#[cfg(feature = "icp")]
+impl<T: Transport + Clone> RpcClient<T> {
+    /// Restore pollers persisted before a canister upgrade, see
+    /// [`PollerDefinition`](crate::PollerDefinition).
+    ///
+    /// Each definition is matched to a response handler of the restorer by name. Returns the
+    /// handle of each restored poller, or the reason it could not be restored.
+    pub fn restore_pollers(
+        &self,
+        definitions: impl IntoIterator<Item = crate::PollerDefinition>,
+        restorer: crate::PollerRestorer<T>,
+    ) -> Vec<(String, Result<crate::IcpPollerHandle, String>)> {
+        restorer.restore(self.get_weak(), definitions)
+    }
+}
+
 impl<T: Transport + Clone> RpcClient<T> {
     /// Boxes the transport.
     ///
@@ -129,7 +146,7 @@ impl<T: Transport + Clone> RpcClient<T> {
     }
 }
 
-#[cfg(not(feature = "icp"))]
+#[cfg(all(not(feature = "icp"), any(feature = "reqwest", feature = "hyper")))]
 impl<T> RpcClient<Http<T>> {
     /// Create a new [`BatchRequest`] builder.
     #[inline]
@@ -147,6 +164,134 @@ impl RpcClient<alloy_transport_icp::IcpTransport> {
     }
 }
 
+#[cfg(feature = "icp")]
+impl RpcClientInner<alloy_transport_icp::IcpTransport> {
+    /// Returns a snapshot of the per-method request metrics of the underlying transport.
+    ///
+    /// See [`IcpMetrics`](alloy_transport_icp::IcpMetrics) for details.
+    pub fn metrics(&self) -> alloy_transport_icp::IcpMetrics {
+        self.transport.metrics()
+    }
+
+    /// Reset the request metrics of the underlying transport.
+    pub fn reset_metrics(&self) {
+        self.transport.reset_metrics();
+    }
+
+    /// Estimate the cycles attached to the call of a request with the given method and params,
+    /// and an expected response size in bytes, or the max response size of the method if
+    /// `None`.
+    ///
+    /// See [`IcpTransport::estimate_request_cycles`] for details.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let per_poll = client.estimate_request_cycles("eth_getLogs", (filter,), Some(50_000))?;
+    /// if ic_cdk::api::canister_balance128() < per_poll * polls {
+    ///     return Err("not enough cycles to start polling".into());
+    /// }
+    /// ```
+    ///
+    /// [`IcpTransport::estimate_request_cycles`]: alloy_transport_icp::IcpTransport::estimate_request_cycles
+    pub fn estimate_request_cycles<Params: RpcParam>(
+        &self,
+        method: impl Into<Cow<'static, str>>,
+        params: Params,
+        expected_response_size: Option<u64>,
+    ) -> alloy_transport::TransportResult<u128> {
+        let request = Request::new(method, Id::Number(0), params)
+            .serialize()
+            .map_err(alloy_transport::TransportError::ser_err)?;
+        self.transport.estimate_request_cycles(request, expected_response_size)
+    }
+
+    /// Shut the client down, e.g. before upgrading the canister.
+    ///
+    /// This stops all pollers started on this client, cancels requests waiting for an outcall
+    /// slot, rejects new requests, and then waits until the outcalls in flight complete or
+    /// `timeout` elapses. Returns `true` if all outcalls completed in time.
+    ///
+    /// `pre_upgrade` cannot await, so call this from an update method before upgrading. See
+    /// [`IcpTransport::shutdown`](alloy_transport_icp::IcpTransport::shutdown).
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        self.stop_pollers();
+        self.transport.shutdown();
+        self.transport.wait_idle(timeout).await
+    }
+
+    /// Set the serializer hook applied to the params of every request before it leaves the
+    /// underlying transport.
+    ///
+    /// See [`ParamsSerializer`](alloy_transport_icp::ParamsSerializer) for details.
+    pub fn set_params_serializer(&self, params_serializer: alloy_transport_icp::ParamsSerializer) {
+        self.transport.set_params_serializer(params_serializer);
+    }
+
+    /// Remove the serializer hook of the underlying transport.
+    pub fn clear_params_serializer(&self) {
+        self.transport.clear_params_serializer();
+    }
+
+    /// Set the method policy of the underlying transport.
+    ///
+    /// Requests for methods that are not allowed are rejected before any outcall is made. See
+    /// [`MethodPolicy`](alloy_transport_icp::MethodPolicy) for details.
+    pub fn set_method_policy(&self, method_policy: alloy_transport_icp::MethodPolicy) {
+        self.transport.set_method_policy(method_policy);
+    }
+
+    /// Limit the number of concurrent outcalls made by the underlying transport.
+    ///
+    /// The limit applies to all requests made through this client, including pollers and
+    /// batches. Requests made while the limit is reached are queued by priority, see
+    /// [`RequestPriority`](alloy_transport_icp::RequestPriority).
+    pub fn set_max_concurrent_requests(&self, max_concurrent_requests: usize) {
+        self.transport.set_max_concurrent_requests(max_concurrent_requests);
+    }
+
+    /// Remove the limit on the number of concurrent outcalls.
+    pub fn clear_max_concurrent_requests(&self) {
+        self.transport.clear_max_concurrent_requests();
+    }
+
+    /// Returns the maximum number of concurrent outcalls, if limited.
+    pub fn max_concurrent_requests(&self) -> Option<usize> {
+        self.transport.max_concurrent_requests()
+    }
+
+    /// Returns the number of outcalls currently in flight.
+    pub fn in_flight_requests(&self) -> usize {
+        self.transport.in_flight_requests()
+    }
+
+    /// Returns the number of requests waiting for an outcall slot.
+    pub fn queued_requests(&self) -> usize {
+        self.transport.queued_requests()
+    }
+
+    /// Throttle the pollers started on this client when the cycles budget of the underlying
+    /// transport runs low, and resume them when it recovers.
+    ///
+    /// Only applies if the [`CyclesBudget`](alloy_transport_icp::CyclesBudget) of the transport
+    /// limits the spend per period. See [`CyclesScheduling`](crate::CyclesScheduling).
+    pub fn set_cycles_scheduling(&self, scheduling: crate::CyclesScheduling) {
+        let transport = self.transport.clone();
+        self.pollers
+            .set_scheduling(scheduling, move || transport.cycles_budget_remaining_percent());
+    }
+
+    /// Stop throttling the pollers started on this client.
+    pub fn clear_cycles_scheduling(&self) {
+        self.pollers.clear_scheduling();
+    }
+
+    /// Returns the [`CyclesScheduling`](crate::CyclesScheduling) of the pollers, if set.
+    pub fn cycles_scheduling(&self) -> Option<crate::CyclesScheduling> {
+        self.pollers.scheduling()
+    }
+}
+
 impl<T> Deref for RpcClient<T> {
     type Target = RpcClientInner<T>;
 
@@ -177,6 +322,9 @@ pub struct RpcClientInner<T> {
     pub(crate) id: AtomicU64,
     /// The poll interval for the client in milliseconds.
     pub(crate) poll_interval: AtomicU64,
+    /// The timers of the pollers started on this client.
+    #[cfg(feature = "icp")]
+    pub(crate) pollers: crate::icp_poller::PollerRegistry,
 }
 
 impl<T> RpcClientInner<T> {
@@ -191,6 +339,8 @@ impl<T> RpcClientInner<T> {
             is_local,
             id: AtomicU64::new(0),
             poll_interval: if is_local { AtomicU64::new(250) } else { AtomicU64::new(7000) },
+            #[cfg(feature = "icp")]
+            pollers: crate::icp_poller::PollerRegistry::new(),
         }
     }
 
@@ -318,8 +468,88 @@ impl<T: Transport + Clone> RpcClientInner<T> {
             is_local: self.is_local,
             id: self.id,
             poll_interval: self.poll_interval,
+            #[cfg(feature = "icp")]
+            pollers: self.pollers,
         }
     }
+
+    /// Stop all pollers started on this client, returning the number of pollers stopped.
+    ///
+    /// This only clears the poller timers, so it can be used in `pre_upgrade`. See
+    /// [`RpcClientInner::shutdown`] to also wait for requests in flight.
+    #[cfg(feature = "icp")]
+    pub fn stop_pollers(&self) -> usize {
+        self.pollers.clear()
+    }
+
+    /// Stop the pollers with the given name started on this client, returning the number of
+    /// pollers stopped. See [`IcpPollerBuilder::with_name`](crate::IcpPollerBuilder::with_name).
+    #[cfg(feature = "icp")]
+    pub fn stop_poller(&self, name: &str) -> usize {
+        self.pollers.stop(name)
+    }
+
+    /// Returns the minimum poll interval of the pollers started on this client.
+    #[cfg(feature = "icp")]
+    pub fn min_poll_interval(&self) -> Duration {
+        self.pollers.min_poll_interval()
+    }
+
+    /// Sets the minimum poll interval of the pollers started on this client. Default: 1s.
+    ///
+    /// Pollers started with a shorter poll interval poll at the minimum instead, unless they
+    /// allow fast polling, see
+    /// [`IcpPollerBuilder::allow_fast_polling`](crate::IcpPollerBuilder::allow_fast_polling).
+    /// Pollers already running are not affected.
+    #[cfg(feature = "icp")]
+    pub fn set_min_poll_interval(&self, min_poll_interval: Duration) {
+        self.pollers.set_min_poll_interval(min_poll_interval);
+    }
+
+    /// Returns the pollers running on this client, in the order they were started.
+    #[cfg(feature = "icp")]
+    pub fn active_pollers(&self) -> Vec<crate::ActivePoller> {
+        self.pollers.active()
+    }
+
+    /// Returns the definitions of the named pollers running on this client, to persist them
+    /// across canister upgrades. See [`PollerDefinition`](crate::PollerDefinition).
+    #[cfg(feature = "icp")]
+    pub fn poller_definitions(&self) -> Vec<crate::PollerDefinition> {
+        self.pollers.definitions()
+    }
+
+    /// Set the cursor of the named poller, e.g. the last block processed by its handler.
+    ///
+    /// The cursor is persisted with the [`PollerDefinition`](crate::PollerDefinition). Returns
+    /// `false` if no poller with this name is running.
+    #[cfg(feature = "icp")]
+    pub fn set_poller_cursor(&self, name: &str, cursor: impl Into<String>) -> bool {
+        self.pollers.set_cursor(name, cursor.into())
+    }
+
+    /// Returns the cursor of the named poller, if set.
+    #[cfg(feature = "icp")]
+    pub fn poller_cursor(&self, name: &str) -> Option<String> {
+        self.pollers.cursor(name)
+    }
+
+    /// Returns the cycles spent by each poller running on this client, to find out which
+    /// poller is burning the cycles budget. See [`PollerCycles`](crate::PollerCycles).
+    ///
+    /// The cycles spent per method, by pollers and other requests alike, are part of the
+    /// metrics of the transport.
+    #[cfg(feature = "icp")]
+    pub fn poller_cycles(&self) -> Vec<crate::PollerCycles> {
+        self.pollers.cycles()
+    }
+
+    /// Returns the statistics of the pollers running on this client, in the order they were
+    /// started, e.g. to report the health of the pollers from a query method.
+    #[cfg(feature = "icp")]
+    pub fn poller_metrics(&self) -> Vec<crate::PollerMetrics> {
+        self.pollers.metrics()
+    }
 }
 
 #[cfg(feature = "pubsub")]
@@ -380,4 +610,19 @@ mod tests {
             .with_poll_interval(poll_interval);
         assert_eq!(client.poll_interval(), poll_interval);
     }
+
+    #[cfg(feature = "icp")]
+    #[test]
+    fn test_estimate_request_cycles() {
+        use alloy_transport_icp::{EthSepoliaService, IcpConfig, RpcService};
+
+        let config = IcpConfig::new(RpcService::EthSepolia(EthSepoliaService::Alchemy));
+        let client = ClientBuilder::default().icp(config.clone());
+        let small = client.estimate_request_cycles("eth_getLogs", [()], Some(1_000)).unwrap();
+        let large = client.estimate_request_cycles("eth_getLogs", [()], Some(100_000)).unwrap();
+        assert!(small < large, "{small} >= {large}");
+
+        let client = ClientBuilder::default().icp(config.set_call_cycles(1_000_000));
+        assert_eq!(client.estimate_request_cycles("eth_chainId", (), None).unwrap(), 1_000_000);
+    }
 }
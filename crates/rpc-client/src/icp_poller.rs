@@ -1,13 +1,30 @@
 use alloy_json_rpc::{RpcParam, RpcReturn};
-use alloy_transport::Transport;
-use core::panic;
-use futures::{stream, Stream};
-use ic_cdk_timers::{set_timer_interval, TimerId};
-use serde::Serialize;
+use alloy_transport::{Transport, TransportError, TransportErrorKind, TransportResult};
+use alloy_transport_icp::{CallCycles, CyclesMeter, RequestContext, RequestPriority, TraceId};
+use candid::CandidType;
+use futures::{FutureExt, Stream};
+use ic_cdk_timers::{set_timer, set_timer_interval, TimerId};
+use serde::{Deserialize, Serialize};
 use serde_json::value::RawValue;
-use std::{borrow::Cow, cell::RefCell, marker::PhantomData, rc::Rc, time::Duration};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt,
+    future::{ready, Future},
+    marker::PhantomData,
+    ops::ControlFlow,
+    pin::Pin,
+    rc::{Rc, Weak},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
 
-use crate::WeakClient;
+use crate::{BatchRequest, RpcClientInner, WeakClient};
 
 /// A poller task builder for ICP.
 ///
@@ -15,6 +32,12 @@ use crate::WeakClient;
 /// invokes a callback with the responses. By default, this is done every 10 seconds, with no
 /// limit on the number of successful polls. This is all configurable.
 ///
+/// Poll requests are made with [`RequestPriority::Polling`] by default, so they yield to
+/// interactive requests when the transport delays dispatch, see
+/// [`with_priority`](Self::with_priority). Each poll is tagged with its own [`TraceId`].
+/// The cycles spent by each poller are metered, see
+/// [`RpcClientInner::poller_cycles`](crate::RpcClientInner::poller_cycles).
+///
 /// # Examples
 ///
 /// Poll `eth_blockNumber` every 5 seconds for 10 times:
@@ -22,7 +45,6 @@ use crate::WeakClient;
 /// ```no_run
 /// #[ic_cdk::update]
 /// async fn example() -> Result<(), String> {
-///
 ///     let config = IcpConfig::new(rpc_service);
 ///     let provider = ProviderBuilder::new().on_icp(config);
 ///
@@ -35,7 +57,7 @@ use crate::WeakClient;
 ///     };
 ///
 ///     let poller = provider.watch_blocks().await.unwrap();
-///     let timer_id = poller
+///     let handle = poller
 ///         .with_limit(Some(10))
 ///         .with_poll_interval(Duration::from_secs(5))
 ///         .start(callback)
@@ -52,7 +74,146 @@ pub struct IcpPollerBuilder<Conn, Params, Resp> {
     params: Params,
     poll_interval: Duration,
     limit: usize,
+    max_attempts: usize,
     timer_id: Option<TimerId>,
+    name: Option<String>,
+    polls: usize,
+    cursor: Option<String>,
+    priority: RequestPriority,
+    backoff: Option<PollBackoff>,
+    error_handler: Option<ErrorHandler>,
+    dedup: Option<Dedup<Resp>>,
+    until: Option<Until<Resp>>,
+    on_complete: Option<CompleteHandler>,
+    params_fn: Option<ParamsFn>,
+    cycles_budget: Option<u128>,
+    budget_exhausted_handler: Option<BudgetExhaustedHandler>,
+    one_shot: Option<Duration>,
+    jitter: Duration,
+    driver: PollDriver,
+    fast_polling: bool,
+}
+
+/// Advance the SplitMix64 state, returning the next pseudo-random number.
+const fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Returns a delay between zero and the jitter, inclusive, from a pseudo-random number.
+fn jitter_delay(jitter: Duration, random: u64) -> Duration {
+    let nanos = jitter.as_nanos().min(u64::MAX as u128 - 1) as u64;
+    Duration::from_nanos(random % (nanos + 1))
+}
+
+/// Returns a seed differing between canisters and between pollers started at different times.
+fn jitter_seed() -> u64 {
+    let mut seed = ic_cdk::api::time();
+    for byte in ic_cdk::api::id().as_slice() {
+        seed = splitmix64(&mut seed) ^ *byte as u64;
+    }
+    seed
+}
+
+/// The estimated cost of executing instructions, in cycles per 10 instructions on a 13-node
+/// application subnet.
+const CYCLES_PER_10_INSTRUCTIONS: u128 = 4;
+
+/// Returns the instructions executed so far in the current message.
+#[cfg(target_arch = "wasm32")]
+fn instruction_counter() -> u64 {
+    ic_cdk::api::performance_counter(0)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+const fn instruction_counter() -> u64 {
+    0
+}
+
+/// The handler called once the cycles budget of a poller is exhausted, see
+/// [`IcpPollerBuilder::with_budget_exhausted_handler`].
+struct BudgetExhaustedHandler(Box<dyn FnOnce(u128)>);
+
+impl fmt::Debug for BudgetExhaustedHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BudgetExhaustedHandler").finish_non_exhaustive()
+    }
+}
+
+/// The cycles budget of a started poller.
+struct PollerBudget {
+    budget: u128,
+    /// The estimated cycles of the instructions executed by the polls so far.
+    instruction_cycles: Cell<u128>,
+    exhausted_handler: RefCell<Option<BudgetExhaustedHandler>>,
+}
+
+impl PollerBudget {
+    /// Charge the instructions of a poll, returning the cycles spent if the budget is now
+    /// exhausted, given the cycles metered for the calls of the poller.
+    fn charge(&self, instructions: u64, calls: CallCycles) -> Option<u128> {
+        let instruction_cycles = self
+            .instruction_cycles
+            .get()
+            .saturating_add(instructions as u128 * CYCLES_PER_10_INSTRUCTIONS / 10);
+        self.instruction_cycles.set(instruction_cycles);
+        let spent = calls.spent().saturating_add(instruction_cycles);
+        (spent >= self.budget).then_some(spent)
+    }
+}
+
+type SerializeParamsFn = Box<dyn FnMut() -> serde_json::Result<Box<RawValue>>>;
+
+/// The params of each poll, see [`IcpPollerBuilder::with_params_fn`].
+struct ParamsFn(SerializeParamsFn);
+
+impl fmt::Debug for ParamsFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParamsFn").finish_non_exhaustive()
+    }
+}
+
+type PredicateFn<Resp> = Box<dyn FnMut(&Resp) -> bool>;
+
+/// The stop condition of a poller, see [`IcpPollerBuilder::until`].
+struct Until<Resp>(PredicateFn<Resp>);
+
+impl<Resp> fmt::Debug for Until<Resp> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Until").finish_non_exhaustive()
+    }
+}
+
+type EqFn<Resp> = Box<dyn Fn(&Resp, &Resp) -> bool>;
+
+/// The comparator of the responses of a poller, see [`IcpPollerBuilder::with_dedup_by`].
+struct Dedup<Resp>(EqFn<Resp>);
+
+impl<Resp> fmt::Debug for Dedup<Resp> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dedup").finish_non_exhaustive()
+    }
+}
+
+/// The handler called once a poller completes, see [`IcpPollerBuilder::on_complete`].
+struct CompleteHandler(Box<dyn FnOnce()>);
+
+impl fmt::Debug for CompleteHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompleteHandler").finish_non_exhaustive()
+    }
+}
+
+/// The handler of the failed polls of a poller, see [`IcpPollerBuilder::with_error_handler`].
+struct ErrorHandler(Box<dyn FnMut(TransportError) -> ControlFlow<()>>);
+
+impl fmt::Debug for ErrorHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorHandler").finish_non_exhaustive()
+    }
 }
 
 impl<Conn, Params, Resp> IcpPollerBuilder<Conn, Params, Resp>
@@ -77,9 +238,76 @@ where
             _pd: PhantomData,
             poll_interval,
             limit: usize::MAX,
+            max_attempts: usize::MAX,
+            name: None,
+            polls: 0,
+            cursor: None,
+            priority: RequestPriority::Polling,
+            backoff: None,
+            error_handler: None,
+            dedup: None,
+            until: None,
+            on_complete: None,
+            params_fn: None,
+            cycles_budget: None,
+            budget_exhausted_handler: None,
+            one_shot: None,
+            jitter: Duration::ZERO,
+            driver: PollDriver::Timer,
+            fast_polling: false,
         }
     }
 
+    /// Returns the name of the poller, if set.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Sets the name of the poller.
+    ///
+    /// Named pollers are persistable: their [`PollerDefinition`] is tracked by the client and
+    /// returned by [`RpcClientInner::poller_definitions`](crate::RpcClientInner), so they can be
+    /// restored after a canister upgrade with
+    /// [`RpcClient::restore_pollers`](crate::RpcClient::restore_pollers). Names should be unique
+    /// per client. Named pollers can also be stopped by name, see
+    /// [`RpcClientInner::stop_poller`](crate::RpcClientInner::stop_poller).
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets a function returning the params of each poll, evaluated before every poll, including
+    /// the first one.
+    ///
+    /// Use this for params that change between polls, such as the block range of an
+    /// `eth_getLogs` poller advancing with the handled responses. The params given to
+    /// [`new`](Self::new) are then only used for the [`PollerDefinition`] of a named poller, so a
+    /// restored poller polls with those params. A poll whose params fail to serialize is a failed
+    /// poll.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let next = Rc::new(Cell::new(start_block));
+    /// let to = next.clone();
+    /// IcpPollerBuilder::new(client, "eth_getLogs", (filter.clone(),))
+    ///     .with_params_fn(move || (filter.clone().from_block(next.get()),))
+    ///     .start(move |logs: Vec<Log>| {
+    ///         if let Some(block) = logs.last().and_then(|log| log.block_number) {
+    ///             to.set(block + 1);
+    ///         }
+    ///         handle_logs(logs);
+    ///     })?;
+    /// ```
+    pub fn with_params_fn<P>(mut self, mut params: P) -> Self
+    where
+        P: FnMut() -> Params + 'static,
+    {
+        self.params_fn =
+            Some(ParamsFn(Box::new(move || serde_json::value::to_raw_value(&params()))));
+        self
+    }
+
     /// Returns the limit on the number of successful polls.
     pub const fn limit(&self) -> usize {
         self.limit
@@ -96,6 +324,25 @@ where
         self
     }
 
+    /// Returns the limit on the number of polls, successful or not.
+    pub const fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    /// Sets a limit on the number of polls, successful or not, so a poller of an endpoint that
+    /// keeps failing does not run forever. Unlike the [limit](Self::with_limit), reaching it does
+    /// not complete the poller, see [`on_complete`](Self::on_complete).
+    ///
+    /// Polls skipped while backing off or throttled by [`CyclesScheduling`] are not counted,
+    /// nor are the polls made before the poller was restored.
+    pub const fn with_max_attempts(mut self, max_attempts: Option<usize>) -> Self {
+        self.max_attempts = match max_attempts {
+            Some(max_attempts) => max_attempts,
+            None => usize::MAX,
+        };
+        self
+    }
+
     /// Returns the duration between polls.
     pub const fn poll_interval(&self) -> Duration {
         self.poll_interval
@@ -112,71 +359,620 @@ where
         self
     }
 
+    /// Allow a poll interval below the minimum poll interval of the client, see
+    /// [`RpcClientInner::set_min_poll_interval`](crate::RpcClientInner::set_min_poll_interval).
+    ///
+    /// By default, the poll interval is raised to the minimum when the poller is started, so a
+    /// typo such as `Duration::from_millis(5)` does not drain the cycles of the canister.
+    pub const fn allow_fast_polling(mut self) -> Self {
+        self.fast_polling = true;
+        self
+    }
+
+    /// Returns the delay of the single poll of a one-shot poller, if set.
+    pub const fn one_shot(&self) -> Option<Duration> {
+        self.one_shot
+    }
+
+    /// Poll once, after the given delay, instead of repeatedly, e.g. to check a receipt some
+    /// time after sending a transaction.
+    ///
+    /// The poll is made with a one-off timer instead of an interval timer, and the poller stops
+    /// once the poll completes, whether it succeeds or fails. The poll interval, limit and
+    /// backoff policy are ignored, and the poll is not throttled by the
+    /// [`CyclesScheduling`] of the client. One-shot pollers are not persisted, even if named.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// IcpPollerBuilder::new(provider.weak_client(), "eth_getTransactionReceipt", (tx_hash,))
+    ///     .with_one_shot(Duration::from_secs(30))
+    ///     .start(|receipt: Option<TransactionReceipt>| handle_receipt(receipt))?;
+    /// ```
+    pub const fn with_one_shot(mut self, delay: Duration) -> Self {
+        self.one_shot = Some(delay);
+        self
+    }
+
+    /// Returns the maximum random delay added to each poll.
+    pub const fn jitter(&self) -> Duration {
+        self.jitter
+    }
+
+    /// Delay each poll by a random duration between zero and the given jitter. Disabled by
+    /// default.
+    ///
+    /// Many canisters polling the same RPC provider at the same interval make their requests at
+    /// the same time. Jitter spreads their requests over a window instead. The random delays are
+    /// drawn from a cheap pseudo-random generator seeded with the time and the canister ID, not
+    /// from `raw_rand`, so they are predictable and must not be relied on for anything but load
+    /// spreading. The jitter should be shorter than the poll interval.
+    pub const fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Returns what drives the polls of the poller.
+    pub const fn driver(&self) -> PollDriver {
+        self.driver
+    }
+
+    /// Sets what drives the polls of the poller, see [`PollDriver`]. Defaults to
+    /// [`PollDriver::Timer`].
+    pub const fn with_driver(mut self, driver: PollDriver) -> Self {
+        self.driver = driver;
+        self
+    }
+
+    /// Returns the priority of the poll requests.
+    pub const fn priority(&self) -> RequestPriority {
+        self.priority
+    }
+
+    /// Sets the priority of the poll requests. Defaults to [`RequestPriority::Polling`].
+    ///
+    /// The priority also decides how the poller is scheduled when the cycles budget runs low,
+    /// see [`CyclesScheduling`]. It is not part of the [`PollerDefinition`], so it must be set
+    /// again when restoring the poller.
+    pub const fn with_priority(mut self, priority: RequestPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Returns the backoff policy of failed polls, if set.
+    pub const fn backoff(&self) -> Option<&PollBackoff> {
+        self.backoff.as_ref()
+    }
+
+    /// Sets the backoff policy of failed polls. Disabled by default, failed polls are retried
+    /// on the next tick.
+    ///
+    /// Like the priority, the backoff policy is not part of the [`PollerDefinition`], so it must
+    /// be set again when restoring the poller.
+    pub const fn with_backoff(mut self, backoff: PollBackoff) -> Self {
+        self.backoff = Some(backoff);
+        self
+    }
+
+    /// Sets the handler of failed polls.
+    ///
+    /// The handler is called with the error of each failed poll, so the canister can record
+    /// failures in its state or raise alerts. Returning [`ControlFlow::Break`] stops the poller,
+    /// e.g. after repeated errors. Without a handler, failed polls are only logged.
+    ///
+    /// Failed polls do not count towards the [limit](Self::with_limit).
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let mut consecutive_errors = 0;
+    /// poller
+    ///     .with_error_handler(move |err| {
+    ///         consecutive_errors += 1;
+    ///         STATE.with_borrow_mut(|state| state.last_poll_error = Some(err.to_string()));
+    ///         if consecutive_errors >= 5 {
+    ///             ControlFlow::Break(())
+    ///         } else {
+    ///             ControlFlow::Continue(())
+    ///         }
+    ///     })
+    ///     .start(handle_blocks)?;
+    /// ```
+    pub fn with_error_handler<E>(mut self, error_handler: E) -> Self
+    where
+        E: FnMut(TransportError) -> ControlFlow<()> + 'static,
+    {
+        self.error_handler = Some(ErrorHandler(Box::new(error_handler)));
+        self
+    }
+
+    /// Sets the maximum cycles the poller may spend. Unlimited by default.
+    ///
+    /// The cycles spent by each poll are the cycles attached to its calls and not refunded, and
+    /// an estimate of the cycles of the instructions executed to make the call and handle the
+    /// response. Once the budget is exhausted, the poller is stopped, see
+    /// [`with_budget_exhausted_handler`](Self::with_budget_exhausted_handler). The instructions
+    /// of an async response handler are only counted until its first await.
+    ///
+    /// The budget applies to this poller alone. To cap the spend of every request of the
+    /// transport, see [`CyclesBudget`](alloy_transport_icp::CyclesBudget).
+    pub const fn with_cycles_budget(mut self, cycles: u128) -> Self {
+        self.cycles_budget = Some(cycles);
+        self
+    }
+
+    /// Sets the handler called with the cycles spent once the cycles budget of the poller is
+    /// exhausted and the poller is stopped, see [`with_cycles_budget`](Self::with_cycles_budget).
+    pub fn with_budget_exhausted_handler<E>(mut self, handler: E) -> Self
+    where
+        E: FnOnce(u128) + 'static,
+    {
+        self.budget_exhausted_handler = Some(BudgetExhaustedHandler(Box::new(handler)));
+        self
+    }
+
+    /// Only invoke the response handler when the response differs from the previous one, e.g.
+    /// when polling `eth_blockNumber` more often than blocks are produced. Disabled by default.
+    ///
+    /// Skipped responses still count as successful polls towards the
+    /// [limit](Self::with_limit). With [`start_borrowed`](Self::start_borrowed) and
+    /// [`start_raw`](Self::start_raw), the raw responses are compared instead.
+    pub fn with_dedup(mut self, dedup: bool) -> Self
+    where
+        Resp: PartialEq,
+    {
+        self.dedup = dedup.then(|| Dedup(Box::new(Resp::eq)));
+        self
+    }
+
+    /// Only invoke the response handler when the given comparator returns `false` for the
+    /// previous and the new response, see [`with_dedup`](Self::with_dedup).
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// // Ignore responses with the same latest block.
+    /// poller.with_dedup_by(|previous: &Block, block: &Block| previous.header.number == block.header.number)
+    /// ```
+    pub fn with_dedup_by<E>(mut self, eq: E) -> Self
+    where
+        E: Fn(&Resp, &Resp) -> bool + 'static,
+    {
+        self.dedup = Some(Dedup(Box::new(eq)));
+        self
+    }
+
+    /// Returns a filter passing only the responses differing from the previous one, or every
+    /// response if deduplication is disabled.
+    fn take_dedup(&mut self) -> impl FnMut(&Resp) -> bool {
+        let dedup = self.dedup.take();
+        let mut previous: Option<Resp> = None;
+        move |response| {
+            let Some(Dedup(eq)) = &dedup else { return true };
+            if previous.as_ref().is_some_and(|previous| eq(previous, response)) {
+                return false;
+            }
+            previous = Some(response.clone());
+            true
+        }
+    }
+
+    /// Stop the poller once a response satisfies the predicate, e.g. once
+    /// `eth_getTransactionReceipt` returns a receipt.
+    ///
+    /// The response satisfying the predicate is still handed to the response handler, and the
+    /// poller is stopped as if its [limit](Self::with_limit) was reached. Responses skipped by
+    /// [deduplication](Self::with_dedup) are checked too. With
+    /// [`start_borrowed`](Self::start_borrowed) and [`start_raw`](Self::start_raw), each response
+    /// is also deserialized into an owned `Resp` for the predicate.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// IcpPollerBuilder::new(client, "eth_getTransactionReceipt", (tx_hash,))
+    ///     .until(|receipt: &Option<TransactionReceipt>| receipt.is_some())
+    ///     .start(|receipt| {
+    ///         if let Some(receipt) = receipt {
+    ///             handle_receipt(receipt);
+    ///         }
+    ///     })?;
+    /// ```
+    pub fn until<P>(mut self, predicate: P) -> Self
+    where
+        P: FnMut(&Resp) -> bool + 'static,
+    {
+        self.until = Some(Until(Box::new(predicate)));
+        self
+    }
+
+    /// Sets the handler called once the poller completes: when its [limit](Self::with_limit) is
+    /// reached, or its stop condition is met, see [`until`](Self::until).
+    ///
+    /// The handler is called after the response handler of the last poll, including async
+    /// handlers, see [`start_async`](Self::start_async). It is not called when the poller is
+    /// stopped otherwise, e.g. by its error handler or through its handle.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// poller
+    ///     .with_limit(Some(100))
+    ///     .on_complete(|| AIRDROP.with_borrow_mut(|airdrop| airdrop.finished = true))
+    ///     .start(handle_claims)?;
+    /// ```
+    pub fn on_complete<C>(mut self, handler: C) -> Self
+    where
+        C: FnOnce() + 'static,
+    {
+        self.on_complete = Some(CompleteHandler(Box::new(handler)));
+        self
+    }
+
+    /// Transform each response before it is handed to the response handler, changing the type
+    /// of the items the handler is called with.
+    ///
+    /// Configure the poller before mapping it: the mapped poller can only be mapped again or
+    /// started. Deduplication, see [`with_dedup`](Self::with_dedup), compares the responses
+    /// before they are transformed.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// provider
+    ///     .watch_blocks()
+    ///     .await?
+    ///     .map(|hashes: Vec<B256>| hashes.last().copied())
+    ///     .start(|newest: Option<B256>| handle_newest_block(newest))?;
+    /// ```
+    pub const fn map<U, M>(self, map: M) -> MappedPoller<Conn, Params, Resp, M>
+    where
+        M: FnMut(Resp) -> U + 'static,
+    {
+        MappedPoller { poller: self, map }
+    }
+
+    /// Hand a shared state to the response handler along with each response, instead of
+    /// capturing it in the handler or reaching for a `thread_local!`.
+    ///
+    /// The state is borrowed mutably for each call of the handler, so the handler must not
+    /// borrow it again, e.g. through a clone of the [`Rc`] captured elsewhere. Other code can
+    /// read and update the state between polls through its own clone, see
+    /// [`StatefulPoller::state`]. Pass `Rc::default()` for a state starting empty.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// #[derive(Default)]
+    /// struct Blocks {
+    ///     seen: u64,
+    ///     latest: Option<B256>,
+    /// }
+    ///
+    /// let poller = provider.watch_blocks().await?.with_state(Rc::<RefCell<Blocks>>::default());
+    /// let blocks = poller.state().clone();
+    /// poller.start(|blocks: &mut Blocks, hashes: Vec<B256>| {
+    ///     blocks.seen += hashes.len() as u64;
+    ///     blocks.latest = hashes.last().copied().or(blocks.latest);
+    /// })?;
+    /// ```
+    pub const fn with_state<T>(
+        self,
+        state: Rc<RefCell<T>>,
+    ) -> StatefulPoller<Conn, Params, Resp, T> {
+        StatefulPoller { poller: self, state }
+    }
+
     /// Starts the poller with the given response handler.
-    pub fn start<F>(mut self, response_handler: F) -> Result<TimerId, String>
+    ///
+    /// The poller only holds a [`WeakClient`]. Once the client is dropped, the poller clears its
+    /// timer on the next tick and stops.
+    ///
+    /// The params are serialized once, when the poller is started, and fail the start if they
+    /// cannot be serialized. Params set by [`with_params_fn`](Self::with_params_fn) are
+    /// serialized before each poll instead. Returns a handle to stop and inspect the poller, see
+    /// [`IcpPollerHandle`].
+    pub fn start<F>(mut self, mut response_handler: F) -> Result<IcpPollerHandle, String>
     where
         F: FnMut(Resp) + 'static,
     {
-        let poll_count = Rc::new(RefCell::new(0));
-        let client = match WeakClient::upgrade(&self.client) {
-            Some(c) => c,
-            None => return Err("Client has been dropped.".into()),
+        let mut is_new = self.take_dedup();
+        let until = self.until.take();
+        self.start_with(until, move |response| {
+            if is_new(&response) {
+                response_handler(response);
+            }
+            ready(())
+        })
+    }
+
+    /// Starts the poller with an async response handler, awaited in the task of each poll.
+    ///
+    /// The handler can make follow-up requests or inter-canister calls without spawning tasks of
+    /// its own. The next tick does not wait for the handler, so the handlers of two polls may run
+    /// concurrently when a handler takes longer than the poll interval.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let weak = provider.weak_client();
+    /// poller.start_async(move |hashes: Vec<B256>| {
+    ///     let weak = weak.clone();
+    ///     async move {
+    ///         let Some(client) = weak.upgrade() else { return };
+    ///         for hash in hashes {
+    ///             let block: Option<Block> =
+    ///                 client.request("eth_getBlockByHash", (hash, false)).await.ok().flatten();
+    ///             handle_block(block);
+    ///         }
+    ///     }
+    /// })?;
+    /// ```
+    pub fn start_async<F, Fut>(mut self, mut response_handler: F) -> Result<IcpPollerHandle, String>
+    where
+        F: FnMut(Resp) -> Fut + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let mut is_new = self.take_dedup();
+        let until = self.until.take();
+        self.start_with(until, move |response| {
+            let handled = is_new(&response).then(|| response_handler(response));
+            async move {
+                if let Some(handled) = handled {
+                    handled.await;
+                }
+            }
+        })
+    }
+
+    /// Starts the poller with a read-only response handler, deserializing each response into a
+    /// type borrowing from the raw response instead of an owned `Resp`.
+    ///
+    /// Large responses, such as blocks with transactions or big log batches, are then not
+    /// copied into owned strings and vectors on every tick. A response that fails to deserialize
+    /// into the borrowed type is logged and skipped. See [`BorrowedResponse`].
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// #[derive(Deserialize)]
+    /// struct LogRef<'a> {
+    ///     #[serde(borrow)]
+    ///     data: &'a str,
+    /// }
+    ///
+    /// struct Logs;
+    ///
+    /// impl BorrowedResponse for Logs {
+    ///     type Borrowed<'a> = Vec<LogRef<'a>>;
+    /// }
+    ///
+    /// poller.start_borrowed::<Logs, _>(|logs| handle_logs(logs))?;
+    /// ```
+    pub fn start_borrowed<B, F>(self, mut response_handler: F) -> Result<IcpPollerHandle, String>
+    where
+        B: BorrowedResponse,
+        F: for<'a> FnMut(&B::Borrowed<'a>) + 'static,
+    {
+        self.start_raw(move |raw| match serde_json::from_str::<B::Borrowed<'_>>(raw.get()) {
+            Ok(response) => response_handler(&response),
+            Err(e) => ic_cdk::println!("Failed to deserialize poll response: {e}"),
+        })
+    }
+
+    /// Starts the poller with a handler of the raw JSON responses, without deserializing them,
+    /// e.g. to forward them to a frontend as is.
+    ///
+    /// This saves the instructions of deserializing each response, and cannot fail on responses
+    /// that differ between RPC providers. `Resp` is then only used by the stop condition, see
+    /// [`until`](Self::until).
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// poller.start_raw(|block: Box<RawValue>| {
+    ///     LATEST_BLOCK_JSON.with_borrow_mut(|latest| *latest = block.get().to_string())
+    /// })?;
+    /// ```
+    pub fn start_raw<F>(mut self, mut response_handler: F) -> Result<IcpPollerHandle, String>
+    where
+        F: FnMut(Box<RawValue>) + 'static,
+    {
+        let dedup = self.dedup.take().is_some();
+        let mut previous: Option<Box<RawValue>> = None;
+        #[allow(clippy::borrowed_box)]
+        let until = self.until.take().map(|Until(mut until)| {
+            Until(Box::new(move |raw: &Box<RawValue>| {
+                serde_json::from_str::<Resp>(raw.get()).is_ok_and(|response| until(&response))
+            }) as PredicateFn<_>)
+        });
+        self.start_with(until, move |raw: Box<RawValue>| {
+            if dedup {
+                if previous.as_ref().is_some_and(|previous| previous.get() == raw.get()) {
+                    return ready(());
+                }
+                previous = Some(raw.clone());
+            }
+            response_handler(raw);
+            ready(())
+        })
+    }
+
+    /// Returns the persistable definition of the poller, if it is named and repeating.
+    fn definition(&self, params: &RawValue) -> Option<PollerDefinition> {
+        let name = self.name.as_ref().filter(|_| self.one_shot.is_none())?;
+        Some(PollerDefinition {
+            name: name.clone(),
+            method: self.method.to_string(),
+            params: params.get().to_string(),
+            poll_interval_ms: self.poll_interval.as_millis() as u64,
+            limit: (self.limit != usize::MAX).then_some(self.limit as u64),
+            polls: self.polls as u64,
+            cursor: self.cursor.clone(),
+        })
+    }
+
+    fn start_with<R, F, Fut>(
+        mut self,
+        until: Option<Until<R>>,
+        response_handler: F,
+    ) -> Result<IcpPollerHandle, String>
+    where
+        R: RpcReturn,
+        F: FnMut(R) -> Fut + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let Some(client) = self.client.upgrade() else {
+            return Err("Client has been dropped.".into());
         };
-        let params = self.params.clone();
-        let method = self.method.clone();
-        let response_handler = Rc::new(RefCell::new(response_handler));
+        if !self.fast_polling {
+            self.poll_interval = client.pollers.clamp_poll_interval(self.poll_interval);
+        }
+        drop(client);
+        // Serialize the params once, every poll sends the same bytes.
+        let params = serde_json::value::to_raw_value(&self.params).map_err(|e| e.to_string())?;
+        let definition = self.definition(&params);
+        let shared = Rc::new(PollerShared {
+            client: self.client.clone(),
+            method: self.method.clone(),
+            params,
+            params_fn: RefCell::new(self.params_fn.take()),
+            limit: self.limit,
+            max_attempts: self.max_attempts,
+            attempts: Cell::new(0),
+            priority: self.priority,
+            backoff: self.backoff,
+            one_shot: self.one_shot.is_some(),
+            jitter: self.jitter,
+            rng: Cell::new(if self.jitter.is_zero() { 0 } else { jitter_seed() }),
+            cycles: CyclesMeter::new(),
+            state: Rc::new(PollerState {
+                id: next_poller_id(),
+                poll_interval: Cell::new(self.poll_interval),
+                one_shot: self.one_shot.is_some(),
+                poll_count: Cell::new(self.polls),
+                metrics: Arc::new(Mutex::new(PollerMetrics {
+                    name: self.name.clone(),
+                    method: self.method.to_string(),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }),
+            ticks: Cell::new(0),
+            failures: Cell::new(0),
+            resume_at: Cell::new(0),
+            response_handler: RefCell::new(response_handler),
+            until: RefCell::new(until),
+            on_complete: RefCell::new(self.on_complete.take()),
+            error_handler: RefCell::new(self.error_handler.take()),
+            budget: self.cycles_budget.map(|budget| PollerBudget {
+                budget,
+                instruction_cycles: Cell::new(0),
+                exhausted_handler: RefCell::new(self.budget_exhausted_handler.take()),
+            }),
+        });
 
         let poll = {
+            let shared = shared.clone();
             move || {
-                ic_cdk::spawn({
-                    let poll_count = poll_count.clone();
-                    let client = client.clone();
-                    let params = params.clone();
-                    let method = method.clone();
-                    let response_handler = response_handler.clone();
-
-                    async move {
-                        let mut params = ParamsOnce::Typed(params);
-                        let params = match params.get() {
-                            Ok(p) => p,
-                            Err(e) => {
-                                ic_cdk::println!("Failed to get params: {:?}", e);
-                                return;
-                            }
-                        };
-
-                        let result = client.request(method, params).await;
-
-                        match result {
-                            Ok(response) => {
-                                let mut poll_count = poll_count.borrow_mut();
-                                *poll_count += 1;
-
-                                let mut handler = response_handler.borrow_mut();
-                                handler(response);
-
-                                if *poll_count >= self.limit {
-                                    // Clear the timer if limit is reached
-                                    if let Some(timer_id) = self.timer_id {
-                                        ic_cdk_timers::clear_timer(timer_id);
-                                    }
-                                }
-                            }
-                            Err(e) => ic_cdk::println!("Request failed: {:?}", e),
-                        }
+                let Some(client) = shared.client.upgrade() else {
+                    // The client has been dropped, so this poller is orphaned.
+                    if shared.state.is_active() {
+                        ic_cdk::println!("Client has been dropped, stopping poller.");
+                        shared.state.stop();
+                    }
+                    return;
+                };
+                if !shared.one_shot {
+                    let resume_at = shared.resume_at.get();
+                    if resume_at > 0 && ic_cdk::api::time() < resume_at {
+                        // Backing off after failed polls.
+                        return;
+                    }
+                    let tick = shared.ticks.replace(shared.ticks.get() + 1);
+                    if !client.pollers.should_poll(shared.priority, tick) {
+                        return;
+                    }
+                }
+                if shared.jitter.is_zero() {
+                    ic_cdk::spawn(shared.clone().poll::<Fut>(client));
+                    return;
+                }
+                let mut rng = shared.rng.get();
+                let delay = jitter_delay(shared.jitter, splitmix64(&mut rng));
+                shared.rng.set(rng);
+                let shared = shared.clone();
+                set_timer(delay, move || {
+                    // The poller may have been stopped during the delay.
+                    if let Some(client) =
+                        shared.client.upgrade().filter(|_| shared.state.is_active())
+                    {
+                        ic_cdk::spawn(shared.poll::<Fut>(client));
                     }
                 });
             }
         };
 
-        // Initial poll
-        poll();
+        let timer_id = match self.driver {
+            PollDriver::Timer => Some(self.one_shot.map_or_else(
+                // Subsequent polls
+                || set_timer_interval(self.poll_interval, poll.clone()),
+                |delay| set_timer(delay, poll.clone()),
+            )),
+            PollDriver::Heartbeat => {
+                let first = self.one_shot.unwrap_or(self.poll_interval);
+                HEARTBEAT_POLLERS.with_borrow_mut(|pollers| {
+                    pollers.push(HeartbeatPoller {
+                        state: shared.state.clone(),
+                        next_poll_at: Cell::new(time_after(first)),
+                        poll: Rc::new(poll.clone()),
+                    })
+                });
+                None
+            }
+        };
+        shared.state.timer_id.set(timer_id);
+        shared.state.active.store(true, Ordering::Relaxed);
+        if let Some(client) = self.client.upgrade() {
+            track_state(&shared.state);
+            client.pollers.register(RegisteredPoller {
+                id: shared.state.id,
+                timer_id,
+                name: self.name.clone(),
+                method: self.method.to_string(),
+                definition,
+                cycles: shared.cycles.clone(),
+                metrics: shared.state.metrics.clone(),
+                active: shared.state.active.clone(),
+            });
+        }
+        let client = self.client.clone();
+        *shared.state.unregister.borrow_mut() = Some(Box::new(move |id| {
+            if let Some(client) = client.upgrade() {
+                client.pollers.unregister(id);
+            }
+        }));
+        if timer_id.is_some() {
+            let (client, one_shot) = (self.client.clone(), self.one_shot);
+            let (id, resume) = (shared.state.id, poll.clone());
+            *shared.state.resume.borrow_mut() = Some(Box::new(move |poll_interval| {
+                let timer_id = one_shot.map_or_else(
+                    || set_timer_interval(poll_interval, resume.clone()),
+                    |delay| set_timer(delay, resume.clone()),
+                );
+                if let Some(client) = client.upgrade() {
+                    client.pollers.replace_timer(id, timer_id);
+                }
+                timer_id
+            }));
+        }
+        self.timer_id = timer_id;
 
-        // Subsequent polls
-        let id = set_timer_interval(self.poll_interval, poll);
-        self.timer_id = Some(id);
+        // Initial poll
+        if self.one_shot.is_none() {
+            poll();
+        }
 
-        Ok(id)
+        Ok(IcpPollerHandle { state: shared.state.clone() })
     }
 
     /// Stop the poller before the limit is reached.
@@ -186,35 +982,2083 @@ where
         }
     }
 
-    /// `into_stream` is not supported for ICP canisters.
-    #[allow(unreachable_code)]
-    pub fn into_stream(self) -> impl Stream<Item = Resp> + Unpin {
-        panic!("Streams cannot be used ICP canisters.");
-        stream::empty()
+    /// Starts the poller, returning a stream of its responses.
+    ///
+    /// The responses are buffered by the poller until the stream takes them, and the task
+    /// awaiting the stream is woken when a response arrives. The stream ends once the poller
+    /// stops, e.g. when its limit is reached, and dropping the stream stops the poller.
+    ///
+    /// A canister can only await a stream in an update call or a timer, and the call does not
+    /// complete until the stream yields, so drain a bounded number of items, e.g. with
+    /// [`take`](futures::StreamExt::take) or a [limit](Self::with_limit). If the poller cannot be
+    /// started, the stream is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// #[ic_cdk::update]
+    /// async fn next_blocks() -> Vec<B256> {
+    ///     let poller = provider().watch_blocks().await.unwrap();
+    ///     poller.into_stream().flat_map(futures::stream::iter).take(3).collect().await
+    /// }
+    /// ```
+    pub fn into_stream(self) -> IcpPollerStream<Resp> {
+        let buffer = Rc::new(RefCell::new(StreamBuffer { items: VecDeque::new(), waker: None }));
+        let handle = {
+            let buffer = buffer.clone();
+            self.start(move |response| {
+                let waker = {
+                    let mut buffer = buffer.borrow_mut();
+                    buffer.items.push_back(response);
+                    buffer.waker.take()
+                };
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            })
+        };
+        let handle = match handle {
+            Ok(handle) => handle,
+            Err(e) => {
+                ic_cdk::println!("Failed to start poller stream: {e}");
+                return IcpPollerStream { buffer, handle: None };
+            }
+        };
+        IcpPollerStream::new(buffer, handle)
     }
 }
 
-// Serializes the parameters only once.
-enum ParamsOnce<P> {
-    Typed(P),
-    Serialized(Box<RawValue>),
+/// The backoff policy of the failed polls of a poller, see [`IcpPollerBuilder::with_backoff`].
+///
+/// Once a poller has failed `threshold` polls in a row, it skips its ticks for a delay of
+/// `initial_delay`, multiplied by `multiplier` for each further consecutive failure and capped
+/// at `max_interval`. A successful poll resets the backoff. As polls are made on the ticks of
+/// the poller, the effective delay is rounded up to the poll interval.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PollBackoff {
+    threshold: u32,
+    initial_delay: Duration,
+    multiplier: u32,
+    max_interval: Duration,
 }
 
-impl<P: Serialize> ParamsOnce<P> {
-    #[inline]
-    fn get(&mut self) -> serde_json::Result<&RawValue> {
-        match self {
-            Self::Typed(_) => self.init(),
-            Self::Serialized(p) => Ok(p),
+impl Default for PollBackoff {
+    fn default() -> Self {
+        Self {
+            threshold: 1,
+            initial_delay: Duration::from_secs(10),
+            multiplier: 2,
+            max_interval: Duration::from_secs(600),
         }
     }
+}
+
+impl PollBackoff {
+    /// Create a policy backing off after the first failure, for 10 seconds doubling up to 10
+    /// minutes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of consecutive failures after which the poller backs off. Defaults to 1.
+    pub const fn with_threshold(mut self, threshold: u32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Set the delay after the first failure reaching the threshold. Defaults to 10 seconds.
+    pub const fn with_initial_delay(mut self, initial_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+        self
+    }
+
+    /// Set the factor by which the delay grows with each further failure. Defaults to 2.
+    pub const fn with_multiplier(mut self, multiplier: u32) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Set the upper bound on the delay. Defaults to 10 minutes.
+    pub const fn with_max_interval(mut self, max_interval: Duration) -> Self {
+        self.max_interval = max_interval;
+        self
+    }
+
+    /// Returns the delay to back off for after the given number of consecutive failures, or
+    /// `None` if the threshold is not reached.
+    pub fn delay(&self, failures: u32) -> Option<Duration> {
+        let exponent = failures.checked_sub(self.threshold.max(1))?;
+        let factor = self.multiplier.saturating_pow(exponent);
+        Some(self.initial_delay.saturating_mul(factor).min(self.max_interval))
+    }
+}
+
+/// What drives the polls of a poller, see [`IcpPollerBuilder::with_driver`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PollDriver {
+    /// The poller sets a timer, polling on each of its ticks.
+    #[default]
+    Timer,
+    /// The poller polls when [`tick_pollers`] is called once its poll interval has elapsed,
+    /// e.g. from the `canister_heartbeat` of the canister.
+    ///
+    /// Timers may be delayed when the canister is under heavy load, while the heartbeat runs
+    /// every round. The poll interval is then a minimum, rounded up to the next call of
+    /// [`tick_pollers`]. A poller driven by the heartbeat has no timer.
+    Heartbeat,
+}
+
+/// A poller driven by the heartbeat, see [`PollDriver::Heartbeat`].
+struct HeartbeatPoller {
+    state: Rc<PollerState>,
+    /// The time of the next poll, in nanoseconds.
+    next_poll_at: Cell<u64>,
+    poll: Rc<dyn Fn()>,
+}
+
+thread_local! {
+    static HEARTBEAT_POLLERS: RefCell<Vec<HeartbeatPoller>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Returns the time after the given duration from now, in nanoseconds.
+fn time_after(duration: Duration) -> u64 {
+    ic_cdk::api::time().saturating_add(duration.as_nanos().try_into().unwrap_or(u64::MAX))
+}
+
+/// Poll with every poller driven by the heartbeat whose poll interval has elapsed, returning the
+/// number of pollers that polled. See [`PollDriver::Heartbeat`].
+///
+/// Call this from the heartbeat of the canister, or from any other recurring method.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[ic_cdk::heartbeat]
+/// fn heartbeat() {
+///     alloy_rpc_client::tick_pollers();
+/// }
+/// ```
+pub fn tick_pollers() -> usize {
+    let now = ic_cdk::api::time();
+    let due: Vec<_> = HEARTBEAT_POLLERS.with_borrow_mut(|pollers| {
+        pollers.retain(|poller| poller.state.is_active());
+        pollers
+            .iter()
+            .filter(|poller| !poller.state.paused.get() && poller.next_poll_at.get() <= now)
+            .map(|poller| {
+                poller.next_poll_at.set(time_after(poller.state.poll_interval.get()));
+                poller.poll.clone()
+            })
+            .collect()
+    });
+    // Poll outside of the borrow, as the handlers of the polls may start pollers.
+    for poll in &due {
+        poll();
+    }
+    due.len()
+}
+
+/// A handle to a started poller, see [`IcpPollerBuilder::start`].
+///
+/// Handles are cheap to clone, and all clones refer to the same poller. Dropping the handles
+/// does not stop the poller.
+#[derive(Clone)]
+pub struct IcpPollerHandle {
+    state: Rc<PollerState>,
+}
+
+impl fmt::Debug for IcpPollerHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IcpPollerHandle")
+            .field("timer_id", &self.timer_id())
+            .field("is_active", &self.is_active())
+            .field("poll_count", &self.poll_count())
+            .field("metrics", &self.metrics())
+            .finish()
+    }
+}
+
+impl IcpPollerHandle {
+    /// Returns the ID of the timer of the poller, or `None` if its polls are driven by the
+    /// heartbeat, see [`PollDriver`]. The timer is replaced when the poller is resumed, see
+    /// [`resume`](Self::resume).
+    pub fn timer_id(&self) -> Option<TimerId> {
+        self.state.timer_id.get()
+    }
+
+    /// Pause the poller, clearing its timer while keeping its state, such as its poll count,
+    /// metrics and persisted definition. Returns `false` if the poller is already paused or
+    /// stopped.
+    ///
+    /// A paused poller is still active and registered with its client, see
+    /// [`is_active`](Self::is_active). A poll already in flight still hands its response to the
+    /// response handler.
+    pub fn pause(&self) -> bool {
+        self.state.pause()
+    }
+
+    /// Resume a paused poller, setting a new timer. The next poll is made one poll interval
+    /// later. Returns `false` if the poller is not paused or is stopped.
+    pub fn resume(&self) -> bool {
+        self.state.resume()
+    }
+
+    /// Returns the duration between polls.
+    pub fn poll_interval(&self) -> Duration {
+        self.state.poll_interval.get()
+    }
+
+    /// Set the duration between polls, replacing the timer of the poller. The next poll is made
+    /// one new poll interval later. A poller driven by the heartbeat polls at the new interval
+    /// after its next poll, and a one-shot poller keeps its delay.
+    ///
+    /// The interval is not clamped to the minimum of the client, see
+    /// [`RpcClientInner::set_min_poll_interval`](crate::RpcClientInner::set_min_poll_interval),
+    /// and the persisted definition of the poller keeps the interval it was started with.
+    pub fn set_poll_interval(&self, poll_interval: Duration) {
+        self.state.set_poll_interval(poll_interval);
+    }
+
+    /// Returns `true` while the poller is paused, see [`pause`](Self::pause).
+    pub fn is_paused(&self) -> bool {
+        self.state.paused.get()
+    }
+
+    /// Stop the poller, clearing its timer and unregistering it from the client. Does nothing
+    /// if the poller is already stopped.
+    ///
+    /// A poll already in flight still hands its response to the response handler.
+    pub fn stop(&self) {
+        self.state.stop();
+    }
+
+    /// Returns `true` until the poller is stopped, by [`stop`](Self::stop), by reaching its
+    /// limit, by its error handler, through its client, e.g. with
+    /// [`RpcClientInner::stop_pollers`](crate::RpcClientInner::stop_pollers), or because its
+    /// client was dropped.
+    pub fn is_active(&self) -> bool {
+        self.state.is_active()
+    }
+
+    /// Returns the number of successful polls, including those made before the poller was
+    /// restored.
+    pub fn poll_count(&self) -> usize {
+        self.state.poll_count.get()
+    }
+
+    /// Returns the error of the most recent failed poll, if any poll has failed.
+    ///
+    /// The error is kept after later successful polls.
+    pub fn last_error(&self) -> Option<String> {
+        self.state.metrics.lock().unwrap().last_error.clone()
+    }
+
+    /// Returns the statistics of the poller since it was started, see [`PollerMetrics`].
+    pub fn metrics(&self) -> PollerMetrics {
+        self.state.metrics.lock().unwrap().clone()
+    }
+}
+
+/// The statistics of a poller since it was started, see [`IcpPollerHandle::metrics`] and
+/// [`RpcClientInner::poller_metrics`](crate::RpcClientInner::poller_metrics).
+///
+/// Polls skipped while backing off or throttled by [`CyclesScheduling`] are not counted.
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize)]
+pub struct PollerMetrics {
+    /// The name of the poller, if set.
+    pub name: Option<String>,
+    /// The polled method.
+    pub method: String,
+    /// The number of polls made, successful or not.
+    pub polls: u64,
+    /// The number of successful polls.
+    pub successful_polls: u64,
+    /// The number of failed polls.
+    pub failed_polls: u64,
+    /// The time of the most recent successful poll, in nanoseconds since the epoch.
+    pub last_success_ns: Option<u64>,
+    /// The error of the most recent failed poll, kept after later successful polls.
+    pub last_error: Option<String>,
+    /// The time spent waiting for the responses of all polls, in nanoseconds.
+    pub total_latency_ns: u64,
+}
+
+impl PollerMetrics {
+    /// Returns the average time spent waiting for the response of a poll, or `None` if no poll
+    /// has been made.
+    pub fn average_latency(&self) -> Option<Duration> {
+        (self.polls > 0).then(|| Duration::from_nanos(self.total_latency_ns / self.polls))
+    }
+
+    fn record_success(&mut self, now: u64, latency: u64) {
+        self.record_poll(latency);
+        self.successful_polls += 1;
+        self.last_success_ns = Some(now);
+    }
+
+    fn record_failure(&mut self, error: String, latency: u64) {
+        self.record_poll(latency);
+        self.failed_polls += 1;
+        self.last_error = Some(error);
+    }
+
+    fn record_poll(&mut self, latency: u64) {
+        self.polls += 1;
+        self.total_latency_ns = self.total_latency_ns.saturating_add(latency);
+    }
+}
+
+type UnregisterFn = Box<dyn FnOnce(u64)>;
+
+type StopFn = Box<dyn FnOnce()>;
+
+type ResumeFn = Box<dyn Fn(Duration) -> TimerId>;
+
+thread_local! {
+    /// The states of the registered pollers by ID, so that the registry of their client, which
+    /// must be `Send`, can stop them.
+    static POLLER_STATES: RefCell<BTreeMap<u64, Weak<PollerState>>> =
+        const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Track the state of a poller registered with its client, until it is stopped or dropped.
+fn track_state(state: &Rc<PollerState>) {
+    POLLER_STATES.with_borrow_mut(|states| {
+        states.retain(|_, state| state.strong_count() > 0);
+        states.insert(state.id, Rc::downgrade(state));
+    });
+}
+
+/// Returns a new ID identifying a poller in the registry of its client.
+fn next_poller_id() -> u64 {
+    static NEXT_POLLER_ID: AtomicU64 = AtomicU64::new(1);
+    NEXT_POLLER_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The state of a started poller shared with its [`IcpPollerHandle`]s.
+#[derive(Default)]
+struct PollerState {
+    /// The ID of the poller in the registry of its client.
+    id: u64,
+    poll_interval: Cell<Duration>,
+    /// Whether the poller polls once after a delay, see [`IcpPollerBuilder::with_one_shot`].
+    one_shot: bool,
+    /// The timer of the poller, `None` if its polls are driven by the heartbeat.
+    timer_id: Cell<Option<TimerId>>,
+    /// Whether the poller is running, shared with the registry of the client, which may stop
+    /// the poller as well.
+    active: Arc<AtomicBool>,
+    poll_count: Cell<usize>,
+    /// The statistics of the poller, shared with the registry of the client.
+    metrics: Arc<Mutex<PollerMetrics>>,
+    /// Unregisters the poller from its client.
+    unregister: RefCell<Option<UnregisterFn>>,
+    /// Called once the poller is stopped.
+    on_stop: RefCell<Option<StopFn>>,
+    paused: Cell<bool>,
+    /// Sets a new timer for the poller to resume it, `None` if its polls are driven by the
+    /// heartbeat.
+    resume: RefCell<Option<ResumeFn>>,
+}
+
+impl PollerState {
+    fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    fn pause(&self) -> bool {
+        if !self.is_active() || self.paused.replace(true) {
+            return false;
+        }
+        if let Some(timer_id) = self.timer_id.get() {
+            ic_cdk_timers::clear_timer(timer_id);
+        }
+        true
+    }
+
+    fn resume(&self) -> bool {
+        if !self.is_active() || !self.paused.get() {
+            return false;
+        }
+        if let Some(resume) = &*self.resume.borrow() {
+            self.timer_id.set(Some(resume(self.poll_interval.get())));
+        }
+        self.paused.set(false);
+        true
+    }
+
+    /// Set the duration between polls, replacing the timer of a running poller.
+    fn set_poll_interval(&self, poll_interval: Duration) {
+        if self.poll_interval.replace(poll_interval) == poll_interval
+            || self.one_shot
+            || self.paused.get()
+            || !self.is_active()
+        {
+            return;
+        }
+        if let (Some(timer_id), Some(resume)) = (self.timer_id.get(), &*self.resume.borrow()) {
+            ic_cdk_timers::clear_timer(timer_id);
+            self.timer_id.set(Some(resume(poll_interval)));
+        }
+    }
+
+    /// Clear the timer of the poller and unregister it from the client.
+    fn stop(&self) {
+        if !self.active.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        if let Some(timer_id) = self.timer_id.get() {
+            ic_cdk_timers::clear_timer(timer_id);
+        }
+        if let Some(unregister) = self.unregister.take() {
+            unregister(self.id);
+        }
+        POLLER_STATES.with_borrow_mut(|states| states.remove(&self.id));
+        // The resume function holds the ticks of the poller, which hold this state.
+        self.resume.take();
+        if let Some(on_stop) = self.on_stop.take() {
+            on_stop();
+        }
+    }
+}
+
+/// The responses of a poller not yet taken by its stream.
+struct StreamBuffer<Resp> {
+    items: VecDeque<Resp>,
+    /// The waker of the task awaiting the stream, if any.
+    waker: Option<Waker>,
+}
+
+/// A stream of the responses of a poller, see [`IcpPollerBuilder::into_stream`].
+///
+/// Dropping the stream stops the poller.
+pub struct IcpPollerStream<Resp> {
+    buffer: Rc<RefCell<StreamBuffer<Resp>>>,
+    handle: Option<IcpPollerHandle>,
+}
+
+impl<Resp> fmt::Debug for IcpPollerStream<Resp> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IcpPollerStream")
+            .field("buffered", &self.buffer.borrow().items.len())
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+impl<Resp> IcpPollerStream<Resp> {
+    /// Create a stream of the responses buffered by a poller, woken once the poller stops.
+    fn new(buffer: Rc<RefCell<StreamBuffer<Resp>>>, handle: IcpPollerHandle) -> Self
+    where
+        Resp: 'static,
+    {
+        let stopped = buffer.clone();
+        *handle.state.on_stop.borrow_mut() = Some(Box::new(move || {
+            let waker = stopped.borrow_mut().waker.take();
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }));
+        Self { buffer, handle: Some(handle) }
+    }
+
+    /// Returns the handle of the poller, or `None` if the poller could not be started.
+    pub const fn handle(&self) -> Option<&IcpPollerHandle> {
+        self.handle.as_ref()
+    }
+}
+
+impl<Resp> Stream for IcpPollerStream<Resp> {
+    type Item = Resp;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Resp>> {
+        let mut buffer = self.buffer.borrow_mut();
+        if let Some(item) = buffer.items.pop_front() {
+            return Poll::Ready(Some(item));
+        }
+        if !self.handle.as_ref().is_some_and(IcpPollerHandle::is_active) {
+            return Poll::Ready(None);
+        }
+        buffer.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<Resp> Drop for IcpPollerStream<Resp> {
+    fn drop(&mut self) {
+        if let Some(handle) = &self.handle {
+            handle.stop();
+        }
+    }
+}
+
+impl<Conn, Params, R> IcpPollerBuilder<Conn, Params, Vec<R>>
+where
+    Conn: Transport + Clone + 'static,
+    Params: RpcParam + 'static,
+    R: RpcReturn + Clone + 'static,
+{
+    /// Delay the delivery of each item until `confirmations` more items have been received,
+    /// e.g. to only handle the block hashes of `Provider::watch_blocks` once they are that many
+    /// blocks deep, for reorg safety.
+    ///
+    /// The most recent items are buffered by the poller, in memory, and are lost when the poller
+    /// is stopped or the canister is upgraded. The handler is called on every poll, with the
+    /// items confirmed by that poll, which may be none.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// provider
+    ///     .watch_blocks()
+    ///     .await?
+    ///     .with_confirmations(12)
+    ///     .start(|hashes: Vec<B256>| handle_confirmed_blocks(hashes))?;
+    /// ```
+    pub fn with_confirmations(
+        self,
+        confirmations: u64,
+    ) -> MappedPoller<Conn, Params, Vec<R>, impl FnMut(Vec<R>) -> Vec<R> + 'static> {
+        let mut confirming = Confirmations::new(confirmations);
+        self.map(move |items| confirming.push(items))
+    }
+
+    /// Adjust the poll interval to the rate at which items are received, bounded by `min` and
+    /// `max`, e.g. to poll `Provider::watch_blocks` about once per block of the chain instead of
+    /// at a fixed interval.
+    ///
+    /// Each item is taken as one block. The block time is estimated from the time between the
+    /// polls receiving items, divided by the number of items, and smoothed over recent polls.
+    /// The timer of the poller is replaced once the estimate differs from the poll interval by
+    /// more than a tenth. The poller starts with its configured poll interval, and the
+    /// persisted definition of the poller keeps it.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// provider
+    ///     .watch_blocks()
+    ///     .await?
+    ///     .with_adaptive_interval(Duration::from_secs(2), Duration::from_secs(30))
+    ///     .start(|hashes: Vec<B256>| handle_blocks(hashes))?;
+    /// ```
+    pub fn with_adaptive_interval(
+        self,
+        min: Duration,
+        max: Duration,
+    ) -> AdaptivePoller<Conn, Params, R> {
+        AdaptivePoller { poller: self, interval: AdaptiveInterval::new(min, max) }
+    }
+}
+
+/// The estimated block time of an [`AdaptivePoller`].
+#[derive(Clone, Copy, Debug)]
+struct AdaptiveInterval {
+    min: Duration,
+    max: Duration,
+    /// The time of the last poll receiving items, in nanoseconds.
+    last_items_at: Option<u64>,
+    /// The estimated time between two items, in nanoseconds.
+    estimate: Option<u64>,
+}
+
+impl AdaptiveInterval {
+    fn new(min: Duration, max: Duration) -> Self {
+        Self { min, max: max.max(min), last_items_at: None, estimate: None }
+    }
+
+    /// Record a poll receiving `items` items at `now`, returning the new poll interval if it
+    /// differs enough from the current one.
+    fn observe(&mut self, now: u64, items: usize, current: Duration) -> Option<Duration> {
+        if items == 0 {
+            return None;
+        }
+        let last = self.last_items_at.replace(now)?;
+        let sample = now.saturating_sub(last) / items as u64;
+        // Weigh the new sample by a quarter, to smooth out the jitter of the polls.
+        let estimate = self.estimate.map_or(sample, |estimate| (estimate * 3 + sample) / 4);
+        self.estimate = Some(estimate);
+        let interval = Duration::from_nanos(estimate).clamp(self.min, self.max);
+        let difference = if interval > current { interval - current } else { current - interval };
+        (difference > current / 10).then_some(interval)
+    }
+}
+
+/// A poller adjusting its poll interval to the rate of its items, see
+/// [`IcpPollerBuilder::with_adaptive_interval`].
+#[derive(Debug)]
+pub struct AdaptivePoller<Conn, Params, R> {
+    poller: IcpPollerBuilder<Conn, Params, Vec<R>>,
+    interval: AdaptiveInterval,
+}
+
+impl<Conn, Params, R> AdaptivePoller<Conn, Params, R>
+where
+    Conn: Transport + Clone + 'static,
+    Params: RpcParam + 'static,
+    R: RpcReturn + Clone + 'static,
+{
+    /// Starts the poller with the given handler of the items, see [`IcpPollerBuilder::start`].
+    pub fn start<F>(self, mut handler: F) -> Result<IcpPollerHandle, String>
+    where
+        F: FnMut(Vec<R>) + 'static,
+    {
+        // The responses arrive after the poller is started, once its handle is set.
+        let handle: Rc<RefCell<Option<IcpPollerHandle>>> = Rc::default();
+        let mut interval = self.interval;
+        let started = self.poller.start({
+            let handle = handle.clone();
+            move |items: Vec<R>| {
+                if let Some(handle) = &*handle.borrow() {
+                    let now = ic_cdk::api::time();
+                    if let Some(poll_interval) =
+                        interval.observe(now, items.len(), handle.poll_interval())
+                    {
+                        handle.set_poll_interval(poll_interval);
+                    }
+                }
+                handler(items);
+            }
+        })?;
+        *handle.borrow_mut() = Some(started.clone());
+        Ok(started)
+    }
+}
+
+/// The items of a poller waiting for confirmations, see
+/// [`IcpPollerBuilder::with_confirmations`].
+#[derive(Debug)]
+struct Confirmations<R> {
+    confirmations: usize,
+    pending: VecDeque<R>,
+}
+
+impl<R> Confirmations<R> {
+    fn new(confirmations: u64) -> Self {
+        let confirmations = usize::try_from(confirmations).unwrap_or(usize::MAX);
+        Self { confirmations, pending: VecDeque::new() }
+    }
+
+    /// Buffer the new items, returning the items now followed by enough items, oldest first.
+    fn push(&mut self, items: Vec<R>) -> Vec<R> {
+        self.pending.extend(items);
+        let confirmed = self.pending.len().saturating_sub(self.confirmations);
+        self.pending.drain(..confirmed).collect()
+    }
+}
+
+/// A poller whose responses are transformed before they are handed to the response handler, see
+/// [`IcpPollerBuilder::map`].
+pub struct MappedPoller<Conn, Params, Resp, M> {
+    poller: IcpPollerBuilder<Conn, Params, Resp>,
+    map: M,
+}
+
+impl<Conn, Params, Resp, M> fmt::Debug for MappedPoller<Conn, Params, Resp, M>
+where
+    IcpPollerBuilder<Conn, Params, Resp>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MappedPoller").field("poller", &self.poller).finish_non_exhaustive()
+    }
+}
+
+impl<Conn, Params, Resp, U, M> MappedPoller<Conn, Params, Resp, M>
+where
+    Conn: Transport + Clone + 'static,
+    Params: RpcParam + 'static,
+    Resp: RpcReturn + Clone + 'static,
+    M: FnMut(Resp) -> U + 'static,
+{
+    /// Transform the transformed responses again.
+    pub fn map<V, N>(
+        self,
+        mut map: N,
+    ) -> MappedPoller<Conn, Params, Resp, impl FnMut(Resp) -> V + 'static>
+    where
+        N: FnMut(U) -> V + 'static,
+    {
+        let mut first = self.map;
+        MappedPoller { poller: self.poller, map: move |response| map(first(response)) }
+    }
+
+    /// Starts the poller with the given handler of the transformed responses, see
+    /// [`IcpPollerBuilder::start`].
+    pub fn start<F>(self, mut handler: F) -> Result<IcpPollerHandle, String>
+    where
+        F: FnMut(U) + 'static,
+    {
+        let mut map = self.map;
+        self.poller.start(move |response| handler(map(response)))
+    }
+
+    /// Starts the poller with the given async handler of the transformed responses, see
+    /// [`IcpPollerBuilder::start_async`].
+    pub fn start_async<F, Fut>(self, mut handler: F) -> Result<IcpPollerHandle, String>
+    where
+        F: FnMut(U) -> Fut + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let mut map = self.map;
+        self.poller.start_async(move |response| handler(map(response)))
+    }
+}
+
+/// A poller handing a shared state to its response handler, see
+/// [`IcpPollerBuilder::with_state`].
+pub struct StatefulPoller<Conn, Params, Resp, T> {
+    poller: IcpPollerBuilder<Conn, Params, Resp>,
+    state: Rc<RefCell<T>>,
+}
+
+impl<Conn, Params, Resp, T> fmt::Debug for StatefulPoller<Conn, Params, Resp, T>
+where
+    IcpPollerBuilder<Conn, Params, Resp>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StatefulPoller").field("poller", &self.poller).finish_non_exhaustive()
+    }
+}
+
+impl<Conn, Params, Resp, T> StatefulPoller<Conn, Params, Resp, T>
+where
+    Conn: Transport + Clone + 'static,
+    Params: RpcParam + 'static,
+    Resp: RpcReturn + Clone + 'static,
+    T: 'static,
+{
+    /// Returns the state handed to the response handler.
+    pub const fn state(&self) -> &Rc<RefCell<T>> {
+        &self.state
+    }
+
+    /// Starts the poller with the given handler of the state and the responses, see
+    /// [`IcpPollerBuilder::start`].
+    pub fn start<F>(self, mut handler: F) -> Result<IcpPollerHandle, String>
+    where
+        F: FnMut(&mut T, Resp) + 'static,
+    {
+        let state = self.state;
+        self.poller.start(move |response| handler(&mut state.borrow_mut(), response))
+    }
+}
+
+type BatchedHandler = Box<dyn FnMut(TransportResult<Box<RawValue>>)>;
+
+/// A call of a [`BatchedIcpPoller`].
+struct BatchedCall {
+    method: Cow<'static, str>,
+    params: serde_json::Result<Box<RawValue>>,
+    handler: BatchedHandler,
+}
+
+impl fmt::Debug for BatchedCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BatchedCall").field("method", &self.method).finish_non_exhaustive()
+    }
+}
+
+/// A poller of several calls, sent as one JSON-RPC batch per tick.
+///
+/// Each tick of an [`IcpPollerBuilder`] is a call to the EVM RPC canister. Batching the calls
+/// of several pollers that share a poll interval makes a single call per tick instead, and
+/// dispatches each response to the handler of its call.
+///
+/// A failed call is logged and does not reach its handler, nor fail the other calls of the
+/// batch. A failed batch is a failed poll, and only successful polls count towards the
+/// [limit](Self::with_limit). Batched pollers are not persisted, see
+/// [`IcpPollerBuilder::with_name`].
+///
+/// # Examples
+///
+/// ```ignore
+/// let handle = BatchedIcpPoller::new(provider.weak_client())
+///     .with_poll_interval(Duration::from_secs(12))
+///     .with_call("eth_blockNumber", (), |number: U64| handle_block_number(number))
+///     .with_call("eth_gasPrice", (), |price: U128| handle_gas_price(price))
+///     .with_call("eth_getBalance", (address, "latest"), |balance: U256| handle_balance(balance))
+///     .start()?;
+/// ```
+#[derive(Debug)]
+pub struct BatchedIcpPoller<Conn> {
+    client: WeakClient<Conn>,
+    calls: Vec<BatchedCall>,
+    name: Option<String>,
+    poll_interval: Duration,
+    limit: usize,
+    priority: RequestPriority,
+    fast_polling: bool,
+}
+
+impl<Conn> BatchedIcpPoller<Conn>
+where
+    Conn: Transport + Clone + 'static,
+{
+    /// Create a batched poller without calls.
+    pub fn new(client: WeakClient<Conn>) -> Self {
+        let poll_interval =
+            client.upgrade().map_or_else(|| Duration::from_secs(7), |c| c.poll_interval());
+        Self {
+            client,
+            calls: Vec::new(),
+            name: None,
+            poll_interval,
+            limit: usize::MAX,
+            priority: RequestPriority::Polling,
+            fast_polling: false,
+        }
+    }
+
+    /// Adds a call to the batch, handing each of its responses to the handler.
+    ///
+    /// The params are serialized once, and fail the start of the poller if they cannot be
+    /// serialized.
+    pub fn with_call<Params, Resp, F>(
+        mut self,
+        method: impl Into<Cow<'static, str>>,
+        params: Params,
+        mut handler: F,
+    ) -> Self
+    where
+        Params: RpcParam,
+        Resp: RpcReturn,
+        F: FnMut(Resp) + 'static,
+    {
+        let method = method.into();
+        let params = serde_json::value::to_raw_value(&params);
+        let name = method.clone();
+        let handler = Box::new(move |result: TransportResult<Box<RawValue>>| {
+            let response = result.and_then(|raw| {
+                serde_json::from_str(raw.get()).map_err(|e| TransportError::deser_err(e, raw.get()))
+            });
+            match response {
+                Ok(response) => handler(response),
+                Err(e) => ic_cdk::println!("Batched call to `{name}` failed: {e}"),
+            }
+        });
+        self.calls.push(BatchedCall { method, params, handler });
+        self
+    }
+
+    /// Returns the number of calls in the batch.
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// Returns `true` if no call was added to the batch.
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    /// Sets the name of the poller, so it can be stopped by name, see
+    /// [`RpcClientInner::stop_poller`](crate::RpcClientInner::stop_poller).
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets the duration between polls.
+    pub const fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets a limit on the number of successful polls.
+    pub fn with_limit(mut self, limit: Option<usize>) -> Self {
+        self.limit = limit.unwrap_or(usize::MAX);
+        self
+    }
+
+    /// Sets the priority of the batch requests, see [`IcpPollerBuilder::with_priority`].
+    pub const fn with_priority(mut self, priority: RequestPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Allow a poll interval below the minimum poll interval of the client, see
+    /// [`IcpPollerBuilder::allow_fast_polling`].
+    pub const fn allow_fast_polling(mut self) -> Self {
+        self.fast_polling = true;
+        self
+    }
+
+    /// Starts the poller, returning a handle to stop and inspect it.
+    ///
+    /// Fails if the client has been dropped, if no call was added, or if the params of a call
+    /// cannot be serialized.
+    pub fn start(mut self) -> Result<IcpPollerHandle, String> {
+        let Some(client) = self.client.upgrade() else {
+            return Err("Client has been dropped.".into());
+        };
+        if !self.fast_polling {
+            self.poll_interval = client.pollers.clamp_poll_interval(self.poll_interval);
+        }
+        drop(client);
+        if self.calls.is_empty() {
+            return Err("No calls to poll.".into());
+        }
+        let mut calls = Vec::with_capacity(self.calls.len());
+        let mut handlers = Vec::with_capacity(self.calls.len());
+        for BatchedCall { method, params, handler } in self.calls {
+            let params =
+                params.map_err(|e| format!("Failed to serialize the params of `{method}`: {e}"))?;
+            calls.push((method, params));
+            handlers.push(handler);
+        }
+        let methods: Vec<_> = calls.iter().map(|(method, _)| method.as_ref()).collect();
+        let method = format!("batch({})", methods.join(", "));
+
+        let shared = Rc::new(BatchShared {
+            client: self.client.clone(),
+            calls,
+            handlers: RefCell::new(handlers),
+            limit: self.limit,
+            priority: self.priority,
+            cycles: CyclesMeter::new(),
+            state: Rc::new(PollerState {
+                id: next_poller_id(),
+                poll_interval: Cell::new(self.poll_interval),
+                metrics: Arc::new(Mutex::new(PollerMetrics {
+                    name: self.name.clone(),
+                    method: method.clone(),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }),
+            ticks: Cell::new(0),
+        });
+
+        let poll = {
+            let shared = shared.clone();
+            move || {
+                let Some(client) = shared.client.upgrade() else {
+                    if shared.state.is_active() {
+                        ic_cdk::println!("Client has been dropped, stopping poller.");
+                        shared.state.stop();
+                    }
+                    return;
+                };
+                let tick = shared.ticks.replace(shared.ticks.get() + 1);
+                if client.pollers.should_poll(shared.priority, tick) {
+                    ic_cdk::spawn(shared.clone().poll(client));
+                }
+            }
+        };
+
+        let timer_id = set_timer_interval(self.poll_interval, poll.clone());
+        shared.state.timer_id.set(Some(timer_id));
+        shared.state.active.store(true, Ordering::Relaxed);
+        if let Some(client) = self.client.upgrade() {
+            track_state(&shared.state);
+            client.pollers.register(RegisteredPoller {
+                id: shared.state.id,
+                timer_id: Some(timer_id),
+                name: self.name,
+                method,
+                definition: None,
+                cycles: shared.cycles.clone(),
+                metrics: shared.state.metrics.clone(),
+                active: shared.state.active.clone(),
+            });
+        }
+        let client = self.client.clone();
+        *shared.state.unregister.borrow_mut() = Some(Box::new(move |id| {
+            if let Some(client) = client.upgrade() {
+                client.pollers.unregister(id);
+            }
+        }));
+        let client = self.client;
+        let (id, resume) = (shared.state.id, poll.clone());
+        *shared.state.resume.borrow_mut() = Some(Box::new(move |poll_interval| {
+            let timer_id = set_timer_interval(poll_interval, resume.clone());
+            if let Some(client) = client.upgrade() {
+                client.pollers.replace_timer(id, timer_id);
+            }
+            timer_id
+        }));
+
+        // Initial poll
+        poll();
+
+        Ok(IcpPollerHandle { state: shared.state.clone() })
+    }
+}
+
+/// The state of a started [`BatchedIcpPoller`], shared by all its ticks.
+struct BatchShared<Conn> {
+    client: WeakClient<Conn>,
+    calls: Vec<(Cow<'static, str>, Box<RawValue>)>,
+    handlers: RefCell<Vec<BatchedHandler>>,
+    limit: usize,
+    priority: RequestPriority,
+    cycles: CyclesMeter,
+    state: Rc<PollerState>,
+    ticks: Cell<u64>,
+}
+
+impl<Conn: Transport + Clone> BatchShared<Conn> {
+    /// Send the batch once and dispatch the responses to the handlers.
+    async fn poll(self: Rc<Self>, client: Arc<RpcClientInner<Conn>>) {
+        let trace_id = TraceId::new();
+        let context = RequestContext::new()
+            .with_priority(self.priority)
+            .with_trace_id(trace_id)
+            .with_cycles_meter(self.cycles.clone());
+        let mut batch = BatchRequest::new(&client);
+        let waiters = self
+            .calls
+            .iter()
+            .map(|(method, params)| batch.add_call::<_, Box<RawValue>>(method.clone(), params))
+            .collect::<TransportResult<Vec<_>>>();
+        let started = ic_cdk::api::time();
+        let result = match waiters {
+            Ok(waiters) => context.scope(batch.send()).await.map(|()| waiters),
+            Err(e) => Err(e),
+        };
+        let now = ic_cdk::api::time();
+        let latency = now.saturating_sub(started);
+
+        let waiters = match result {
+            Ok(waiters) => waiters,
+            Err(e) => {
+                ic_cdk::println!("[trace {trace_id}] Batch request failed: {e:?}");
+                self.state.metrics.lock().unwrap().record_failure(e.to_string(), latency);
+                return;
+            }
+        };
+        self.state.metrics.lock().unwrap().record_success(now, latency);
+        let poll_count = self.state.poll_count.get() + 1;
+        self.state.poll_count.set(poll_count);
+
+        let mut handlers = self.handlers.borrow_mut();
+        for (handler, waiter) in handlers.iter_mut().zip(waiters) {
+            // The batch has completed, so every waiter is resolved.
+            let response = waiter.now_or_never().unwrap_or_else(|| {
+                Err(TransportErrorKind::custom_str("batch response not received"))
+            });
+            handler(response);
+        }
+
+        if poll_count >= self.limit {
+            self.state.stop();
+        }
+    }
+}
+
+/// A response type that can be deserialized borrowing from the raw JSON-RPC response, see
+/// [`IcpPollerBuilder::start_borrowed`].
+///
+/// Implement this on a marker type, naming the borrowed type for any lifetime.
+pub trait BorrowedResponse: 'static {
+    /// The response type, borrowing from the raw response.
+    type Borrowed<'a>: Deserialize<'a>;
+}
+
+/// The state of a started poller, shared by all its ticks so that a tick only clones the
+/// [`Rc`] around it.
+struct PollerShared<Conn, F, Resp> {
+    client: WeakClient<Conn>,
+    method: Cow<'static, str>,
+    params: Box<RawValue>,
+    /// The params of each poll, replacing `params` if set.
+    params_fn: RefCell<Option<ParamsFn>>,
+    limit: usize,
+    max_attempts: usize,
+    /// The number of polls made, successful or not.
+    attempts: Cell<usize>,
+    priority: RequestPriority,
+    backoff: Option<PollBackoff>,
+    /// Whether the poller polls once, see [`IcpPollerBuilder::with_one_shot`].
+    one_shot: bool,
+    jitter: Duration,
+    /// The state of the pseudo-random generator of the jitter.
+    rng: Cell<u64>,
+    cycles: CyclesMeter,
+    state: Rc<PollerState>,
+    ticks: Cell<u64>,
+    /// The number of consecutive failed polls.
+    failures: Cell<u32>,
+    /// The time, in nanoseconds, before which ticks are skipped to back off. `0` if not backing
+    /// off.
+    resume_at: Cell<u64>,
+    response_handler: RefCell<F>,
+    until: RefCell<Option<Until<Resp>>>,
+    on_complete: RefCell<Option<CompleteHandler>>,
+    error_handler: RefCell<Option<ErrorHandler>>,
+    budget: Option<PollerBudget>,
+}
+
+impl<Conn: Transport + Clone, F, Resp> PollerShared<Conn, F, Resp> {
+    /// Poll once and hand the response to the handler.
+    async fn poll<Fut>(self: Rc<Self>, client: Arc<RpcClientInner<Conn>>)
+    where
+        Resp: RpcReturn,
+        F: FnMut(Resp) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let trace_id = TraceId::new();
+        let context = RequestContext::new()
+            .with_priority(self.priority)
+            .with_trace_id(trace_id)
+            .with_cycles_meter(self.cycles.clone());
+        // The instructions of the timer message, the response is handled in another message.
+        let mut instructions = 0;
+        let started = ic_cdk::api::time();
+        let result = match self.next_params() {
+            Ok(params) => {
+                let params = params.as_deref().unwrap_or(&*self.params);
+                let call = client.request::<_, Resp>(self.method.clone(), params);
+                instructions = instruction_counter();
+                context.scope(call).await
+            }
+            Err(e) => Err(TransportError::ser_err(e)),
+        };
+        let now = ic_cdk::api::time();
+        let latency = now.saturating_sub(started);
+
+        let mut complete = false;
+        let handled = match result {
+            Ok(response) => {
+                self.failures.set(0);
+                self.resume_at.set(0);
+                self.state.metrics.lock().unwrap().record_success(now, latency);
+                let poll_count = self.state.poll_count.get() + 1;
+                self.state.poll_count.set(poll_count);
+                client.pollers.record_poll(self.state.id);
+
+                let done =
+                    self.until.borrow_mut().as_mut().is_some_and(|Until(until)| until(&response));
+                let handled = (self.response_handler.borrow_mut())(response);
+
+                if poll_count >= self.limit || done {
+                    // Clear the timer if limit is reached or the stop condition is met, before
+                    // awaiting the handler so no further poll is made meanwhile.
+                    complete = self.state.is_active();
+                    self.state.stop();
+                }
+
+                Some(handled)
+            }
+            Err(e) => {
+                let failures = self.failures.get().saturating_add(1);
+                self.failures.set(failures);
+                if let Some(delay) = self.backoff.and_then(|backoff| backoff.delay(failures)) {
+                    self.resume_at.set(now.saturating_add(delay.as_nanos() as u64));
+                    ic_cdk::println!(
+                        "[trace {trace_id}] {failures} failed polls in a row, backing off for {delay:?}."
+                    );
+                }
+                self.state.metrics.lock().unwrap().record_failure(e.to_string(), latency);
+                match self.error_handler.borrow_mut().as_mut() {
+                    Some(ErrorHandler(error_handler)) => {
+                        if error_handler(e).is_break() {
+                            ic_cdk::println!("[trace {trace_id}] Poll failed, stopping poller.");
+                            self.state.stop();
+                        }
+                    }
+                    None => ic_cdk::println!("[trace {trace_id}] Request failed: {:?}", e),
+                }
+                None
+            }
+        };
+
+        self.charge(instructions.saturating_add(instruction_counter()), trace_id);
+        let attempts = self.attempts.get() + 1;
+        self.attempts.set(attempts);
+        if self.one_shot || attempts >= self.max_attempts {
+            self.state.stop();
+        }
+
+        if let Some(handled) = handled {
+            handled.await;
+        }
+        if complete {
+            if let Some(CompleteHandler(on_complete)) = self.on_complete.take() {
+                on_complete();
+            }
+        }
+    }
+
+    /// Returns the params of the next poll if they are set by a function, see
+    /// [`IcpPollerBuilder::with_params_fn`].
+    fn next_params(&self) -> serde_json::Result<Option<Box<RawValue>>> {
+        self.params_fn.borrow_mut().as_mut().map(|ParamsFn(params)| params()).transpose()
+    }
+
+    /// Charge the instructions of a poll to the cycles budget, stopping the poller once the
+    /// budget is exhausted.
+    fn charge(&self, instructions: u64, trace_id: TraceId) {
+        let Some(budget) = &self.budget else { return };
+        let Some(spent) = budget.charge(instructions, self.cycles.get()) else { return };
+        if !self.state.is_active() {
+            return;
+        }
+        ic_cdk::println!(
+            "[trace {trace_id}] Cycles budget exhausted, spent {spent}, stopping poller."
+        );
+        self.state.stop();
+        if let Some(BudgetExhaustedHandler(handler)) = budget.exhausted_handler.take() {
+            handler(spent);
+        }
+    }
+}
+
+impl<Conn, Resp> IcpPollerBuilder<Conn, Box<RawValue>, Resp>
+where
+    Conn: Transport + Clone + 'static,
+    Resp: RpcReturn + Clone + 'static,
+{
+    /// Create a poller from a persisted [`PollerDefinition`], resuming its poll count and
+    /// cursor.
+    pub fn from_definition(
+        client: WeakClient<Conn>,
+        definition: PollerDefinition,
+    ) -> Result<Self, String> {
+        let params = RawValue::from_string(definition.params).map_err(|e| e.to_string())?;
+        let mut poller = Self::new(client, definition.method, params)
+            .with_name(definition.name)
+            .with_poll_interval(Duration::from_millis(definition.poll_interval_ms))
+            .with_limit(definition.limit.map(|limit| limit as usize));
+        poller.polls = definition.polls as usize;
+        poller.cursor = definition.cursor;
+        Ok(poller)
+    }
+}
+
+/// The persistable definition of a named poller, see [`IcpPollerBuilder::with_name`].
+///
+/// Timers and poller state live on the heap and are wiped when a canister is upgraded.
+/// Store the definitions returned by
+/// [`RpcClientInner::poller_definitions`](crate::RpcClientInner) in stable memory in
+/// `pre_upgrade`, and restore them in `post_upgrade` with [`PollerRestorer`].
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct PollerDefinition {
+    /// The name of the poller.
+    pub name: String,
+    /// The polled method.
+    pub method: String,
+    /// The JSON encoded params of the polled method.
+    pub params: String,
+    /// The duration between polls, in milliseconds.
+    pub poll_interval_ms: u64,
+    /// The limit on the number of successful polls, if any.
+    pub limit: Option<u64>,
+    /// The number of successful polls so far.
+    pub polls: u64,
+    /// An application defined cursor, such as the last processed block, see
+    /// [`RpcClientInner::set_poller_cursor`](crate::RpcClientInner).
+    pub cursor: Option<String>,
+}
+
+impl PollerDefinition {
+    /// Returns the number of successful polls left before the limit is reached, or `None` if
+    /// the poller has no limit.
+    pub fn remaining(&self) -> Option<u64> {
+        self.limit.map(|limit| limit.saturating_sub(self.polls))
+    }
+}
+
+/// The cycles spent by a poller running on a client, see
+/// [`RpcClientInner::poller_cycles`](crate::RpcClientInner::poller_cycles).
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct PollerCycles {
+    /// The name of the poller, if set.
+    pub name: Option<String>,
+    /// The polled method.
+    pub method: String,
+    /// The cycles spent on the polls since the poller was started.
+    pub cycles: CallCycles,
+}
+
+/// How the pollers of a client are scheduled when the cycles budget of the transport runs low,
+/// see [`RpcClientInner::set_cycles_scheduling`](crate::RpcClientInner::set_cycles_scheduling).
+///
+/// The remaining budget is the percentage of the spend per period of the
+/// [`CyclesBudget`](alloy_transport_icp::CyclesBudget) that remains in the current period.
+/// Pollers are throttled by priority class, keeping the most important pollers alive longest:
+///
+/// - Above the `low_percent` threshold, all pollers run normally.
+/// - Below it, [`RequestPriority::Background`] pollers are paused, and [`RequestPriority::Polling`]
+///   pollers only poll on every `stretch_factor`-th tick.
+/// - Below the `critical_percent` threshold, only [`RequestPriority::Interactive`] pollers keep
+///   running, on every `stretch_factor`-th tick.
+///
+/// Paused pollers keep their timers, and resume on their own once the budget recovers, e.g.
+/// when a new period starts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct CyclesScheduling {
+    /// The remaining budget, in percent, below which pollers are throttled.
+    pub low_percent: u8,
+    /// The remaining budget, in percent, below which only interactive pollers keep running.
+    pub critical_percent: u8,
+    /// The factor by which the intervals of throttled pollers are stretched.
+    pub stretch_factor: u32,
+}
+
+impl Default for CyclesScheduling {
+    fn default() -> Self {
+        Self { low_percent: 25, critical_percent: 10, stretch_factor: 4 }
+    }
+}
+
+impl CyclesScheduling {
+    /// Returns `true` if a poller of the given priority polls on the given tick, with the given
+    /// percentage of the budget remaining.
+    pub const fn should_poll(&self, priority: RequestPriority, tick: u64, remaining: u8) -> bool {
+        let stretched = self.stretch_factor <= 1 || tick % self.stretch_factor as u64 == 0;
+        if remaining >= self.low_percent {
+            return true;
+        }
+        match priority {
+            RequestPriority::Interactive if remaining >= self.critical_percent => true,
+            RequestPriority::Interactive => stretched,
+            RequestPriority::Polling => remaining >= self.critical_percent && stretched,
+            RequestPriority::Background => false,
+        }
+    }
+}
+
+type RemainingBudgetFn = Box<dyn Fn() -> Option<u8> + Send + Sync>;
+
+/// A [`CyclesScheduling`] with the probe reading the remaining budget of the transport.
+struct Scheduler {
+    scheduling: CyclesScheduling,
+    remaining: RemainingBudgetFn,
+}
+
+impl fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scheduler").field("scheduling", &self.scheduling).finish_non_exhaustive()
+    }
+}
+
+/// A poller started on a client.
+#[derive(Debug)]
+pub(crate) struct RegisteredPoller {
+    id: u64,
+    timer_id: Option<TimerId>,
+    name: Option<String>,
+    method: String,
+    definition: Option<PollerDefinition>,
+    cycles: CyclesMeter,
+    metrics: Arc<Mutex<PollerMetrics>>,
+    active: Arc<AtomicBool>,
+}
+
+impl RegisteredPoller {
+    /// Stop the poller through its state, so that its stream ends, or only clear its timer once
+    /// its state is gone. A poller driven by the heartbeat is dropped on the next tick.
+    ///
+    /// Stopping the poller unregisters it, so the registry must not be locked.
+    fn stop(&self) {
+        if let Some(state) = POLLER_STATES.with_borrow(|states| states.get(&self.id)?.upgrade()) {
+            state.stop();
+            return;
+        }
+        self.active.store(false, Ordering::Relaxed);
+        if let Some(timer_id) = self.timer_id {
+            ic_cdk_timers::clear_timer(timer_id);
+        }
+    }
+}
+
+/// A poller running on a client, see
+/// [`RpcClientInner::active_pollers`](crate::RpcClientInner::active_pollers).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ActivePoller {
+    /// The name of the poller, if set.
+    pub name: Option<String>,
+    /// The polled method.
+    pub method: String,
+    /// The timer of the poller, `None` if its polls are driven by the heartbeat, see
+    /// [`PollDriver`].
+    pub timer_id: Option<TimerId>,
+}
+
+/// The default minimum poll interval of the pollers of a client, in milliseconds.
+const DEFAULT_MIN_POLL_INTERVAL_MS: u64 = 1_000;
+
+/// The pollers started on a client, so they can be stopped together and persisted.
+#[derive(Debug)]
+pub(crate) struct PollerRegistry {
+    pollers: Mutex<Vec<RegisteredPoller>>,
+    scheduler: Mutex<Option<Scheduler>>,
+    /// The minimum poll interval of the pollers, in milliseconds.
+    min_poll_interval: AtomicU64,
+}
+
+impl Default for PollerRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PollerRegistry {
+    pub(crate) const fn new() -> Self {
+        Self {
+            pollers: Mutex::new(Vec::new()),
+            scheduler: Mutex::new(None),
+            min_poll_interval: AtomicU64::new(DEFAULT_MIN_POLL_INTERVAL_MS),
+        }
+    }
+
+    pub(crate) fn min_poll_interval(&self) -> Duration {
+        Duration::from_millis(self.min_poll_interval.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set_min_poll_interval(&self, min_poll_interval: Duration) {
+        let millis = min_poll_interval.as_millis().try_into().unwrap_or(u64::MAX);
+        self.min_poll_interval.store(millis, Ordering::Relaxed);
+    }
+
+    /// Returns the poll interval raised to the minimum poll interval, logging the change.
+    pub(crate) fn clamp_poll_interval(&self, poll_interval: Duration) -> Duration {
+        let min_poll_interval = self.min_poll_interval();
+        if poll_interval >= min_poll_interval {
+            return poll_interval;
+        }
+        ic_cdk::println!(
+            "Poll interval {poll_interval:?} is below the minimum of {min_poll_interval:?}, \
+             polling every {min_poll_interval:?} instead."
+        );
+        min_poll_interval
+    }
+
+    pub(crate) fn set_scheduling(
+        &self,
+        scheduling: CyclesScheduling,
+        remaining: impl Fn() -> Option<u8> + Send + Sync + 'static,
+    ) {
+        *self.scheduler.lock().unwrap() =
+            Some(Scheduler { scheduling, remaining: Box::new(remaining) });
+    }
+
+    pub(crate) fn clear_scheduling(&self) {
+        *self.scheduler.lock().unwrap() = None;
+    }
+
+    pub(crate) fn scheduling(&self) -> Option<CyclesScheduling> {
+        self.scheduler.lock().unwrap().as_ref().map(|scheduler| scheduler.scheduling)
+    }
+
+    /// Returns `true` if a poller of the given priority polls on the given tick.
+    pub(crate) fn should_poll(&self, priority: RequestPriority, tick: u64) -> bool {
+        let scheduler = self.scheduler.lock().unwrap();
+        let Some(scheduler) = scheduler.as_ref() else { return true };
+        (scheduler.remaining)()
+            .map_or(true, |remaining| scheduler.scheduling.should_poll(priority, tick, remaining))
+    }
+
+    pub(crate) fn register(&self, poller: RegisteredPoller) {
+        self.pollers.lock().unwrap().push(poller);
+    }
+
+    /// Replace the timer of a poller, once it is resumed.
+    pub(crate) fn replace_timer(&self, id: u64, timer_id: TimerId) {
+        let mut pollers = self.pollers.lock().unwrap();
+        if let Some(poller) = pollers.iter_mut().find(|poller| poller.id == id) {
+            poller.timer_id = Some(timer_id);
+        }
+    }
+
+    pub(crate) fn unregister(&self, id: u64) {
+        self.pollers.lock().unwrap().retain(|poller| poller.id != id);
+    }
+
+    pub(crate) fn record_poll(&self, id: u64) {
+        let mut pollers = self.pollers.lock().unwrap();
+        if let Some(definition) = pollers
+            .iter_mut()
+            .find(|poller| poller.id == id)
+            .and_then(|poller| poller.definition.as_mut())
+        {
+            definition.polls += 1;
+        }
+    }
+
+    pub(crate) fn definitions(&self) -> Vec<PollerDefinition> {
+        self.pollers.lock().unwrap().iter().filter_map(|poller| poller.definition.clone()).collect()
+    }
+
+    pub(crate) fn cycles(&self) -> Vec<PollerCycles> {
+        self.pollers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|poller| PollerCycles {
+                name: poller.name.clone(),
+                method: poller.method.clone(),
+                cycles: poller.cycles.get(),
+            })
+            .collect()
+    }
+
+    pub(crate) fn metrics(&self) -> Vec<PollerMetrics> {
+        self.pollers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|poller| poller.metrics.lock().unwrap().clone())
+            .collect()
+    }
+
+    pub(crate) fn active(&self) -> Vec<ActivePoller> {
+        self.pollers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|poller| ActivePoller {
+                name: poller.name.clone(),
+                method: poller.method.clone(),
+                timer_id: poller.timer_id,
+            })
+            .collect()
+    }
+
+    /// Stop the pollers with the given name, returning the number of pollers stopped.
+    pub(crate) fn stop(&self, name: &str) -> usize {
+        let stopped = {
+            let mut pollers = self.pollers.lock().unwrap();
+            let (stopped, running): (Vec<_>, Vec<_>) = std::mem::take(&mut *pollers)
+                .into_iter()
+                .partition(|poller| poller.name.as_deref() == Some(name));
+            *pollers = running;
+            stopped
+        };
+        for poller in &stopped {
+            poller.stop();
+        }
+        stopped.len()
+    }
+
+    pub(crate) fn cursor(&self, name: &str) -> Option<String> {
+        self.with_definition(name, |definition| definition.cursor.clone()).flatten()
+    }
+
+    pub(crate) fn set_cursor(&self, name: &str, cursor: String) -> bool {
+        self.with_definition(name, |definition| definition.cursor = Some(cursor)).is_some()
+    }
+
+    fn with_definition<R>(
+        &self,
+        name: &str,
+        f: impl FnOnce(&mut PollerDefinition) -> R,
+    ) -> Option<R> {
+        let mut pollers = self.pollers.lock().unwrap();
+        pollers
+            .iter_mut()
+            .filter_map(|poller| poller.definition.as_mut())
+            .find(|definition| definition.name == name)
+            .map(f)
+    }
+
+    /// Clear all timers, returning the number of timers cleared.
+    pub(crate) fn clear(&self) -> usize {
+        let pollers = std::mem::take(&mut *self.pollers.lock().unwrap());
+        for poller in &pollers {
+            poller.stop();
+        }
+        pollers.len()
+    }
+}
+
+type RestoreFn<Conn> =
+    Box<dyn FnOnce(WeakClient<Conn>, PollerDefinition) -> Result<IcpPollerHandle, String>>;
+
+/// Maps poller names to response handlers, to restore persisted pollers after an upgrade.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[ic_cdk::post_upgrade]
+/// fn post_upgrade() {
+///     let (definitions,): (Vec<PollerDefinition>,) = ic_cdk::storage::stable_restore().unwrap();
+///     let restorer =
+///         PollerRestorer::new().with_handler("blocks", |blocks: Vec<B256>| handle_blocks(blocks));
+///     for (name, result) in restorer.restore(provider().weak_client(), definitions) {
+///         if let Err(err) = result {
+///             ic_cdk::println!("Failed to restore poller {name}: {err}");
+///         }
+///     }
+/// }
+/// ```
+pub struct PollerRestorer<Conn> {
+    handlers: HashMap<String, RestoreFn<Conn>>,
+}
+
+impl<Conn> fmt::Debug for PollerRestorer<Conn> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PollerRestorer").field("handlers", &self.handlers.keys()).finish()
+    }
+}
+
+impl<Conn> Default for PollerRestorer<Conn> {
+    fn default() -> Self {
+        Self { handlers: HashMap::new() }
+    }
+}
+
+impl<Conn> PollerRestorer<Conn>
+where
+    Conn: Transport + Clone + 'static,
+{
+    /// Create a new, empty restorer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the response handler of the poller with the given name.
+    pub fn with_handler<Resp, F>(self, name: impl Into<String>, response_handler: F) -> Self
+    where
+        Resp: RpcReturn + Clone + 'static,
+        F: FnMut(Resp) + 'static,
+    {
+        self.with_restore(name, |poller: IcpPollerBuilder<Conn, Box<RawValue>, Resp>| {
+            poller.start(response_handler)
+        })
+    }
+
+    /// Register the async response handler of the poller with the given name, see
+    /// [`IcpPollerBuilder::start_async`].
+    pub fn with_async_handler<Resp, F, Fut>(
+        self,
+        name: impl Into<String>,
+        response_handler: F,
+    ) -> Self
+    where
+        Resp: RpcReturn + Clone + 'static,
+        F: FnMut(Resp) -> Fut + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        self.with_restore(name, |poller: IcpPollerBuilder<Conn, Box<RawValue>, Resp>| {
+            poller.start_async(response_handler)
+        })
+    }
+
+    fn with_restore<Resp>(
+        mut self,
+        name: impl Into<String>,
+        start: impl FnOnce(IcpPollerBuilder<Conn, Box<RawValue>, Resp>) -> Result<IcpPollerHandle, String>
+            + 'static,
+    ) -> Self
+    where
+        Resp: RpcReturn + Clone + 'static,
+    {
+        let restore: RestoreFn<Conn> = Box::new(move |client, definition| {
+            if definition.remaining() == Some(0) {
+                return Err("Poller has already reached its limit.".into());
+            }
+            start(IcpPollerBuilder::from_definition(client, definition)?)
+        });
+        self.handlers.insert(name.into(), restore);
+        self
+    }
+
+    /// Restore the given pollers on the client.
+    ///
+    /// Each definition is matched to a response handler by name. Returns the handle of each
+    /// restored poller, or the reason it could not be restored.
+    pub fn restore(
+        mut self,
+        client: WeakClient<Conn>,
+        definitions: impl IntoIterator<Item = PollerDefinition>,
+    ) -> Vec<(String, Result<IcpPollerHandle, String>)> {
+        definitions
+            .into_iter()
+            .map(|definition| {
+                let name = definition.name.clone();
+                let result = self.handlers.remove(&name).map_or_else(
+                    || Err("No handler registered for this poller.".into()),
+                    |restore| restore(client.clone(), definition),
+                );
+                (name, result)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scheduling_throttles_by_priority() {
+        let scheduling = CyclesScheduling::default();
+        let polls = |priority, remaining| {
+            (0..8).filter(|&tick| scheduling.should_poll(priority, tick, remaining)).count()
+        };
+
+        assert_eq!(polls(RequestPriority::Background, 50), 8);
+        assert_eq!(polls(RequestPriority::Background, 20), 0);
+        assert_eq!(polls(RequestPriority::Polling, 20), 2);
+        assert_eq!(polls(RequestPriority::Interactive, 20), 8);
+        assert_eq!(polls(RequestPriority::Polling, 5), 0);
+        assert_eq!(polls(RequestPriority::Interactive, 5), 2);
+    }
+
+    #[test]
+    fn definition_remaining_polls() {
+        let mut definition = PollerDefinition {
+            name: "blocks".into(),
+            method: "eth_getFilterChanges".into(),
+            params: "[\"0x1\"]".into(),
+            poll_interval_ms: 10_000,
+            limit: Some(10),
+            polls: 4,
+            cursor: None,
+        };
+        assert_eq!(definition.remaining(), Some(6));
+
+        definition.polls = 12;
+        assert_eq!(definition.remaining(), Some(0));
+
+        definition.limit = None;
+        assert_eq!(definition.remaining(), None);
+    }
+
+    #[test]
+    fn restored_definitions_keep_their_cursor() {
+        let register = |registry: &PollerRegistry, definition: Option<PollerDefinition>| {
+            registry.register(RegisteredPoller {
+                id: next_poller_id(),
+                timer_id: None,
+                name: Some("blocks".into()),
+                method: "eth_getFilterChanges".into(),
+                definition,
+                cycles: CyclesMeter::new(),
+                metrics: Arc::default(),
+                active: Arc::default(),
+            })
+        };
+        let client = WeakClient::<alloy_transport::BoxTransport>::new();
+        let poller =
+            IcpPollerBuilder::<_, _, Vec<u64>>::new(client, "eth_getFilterChanges", ("0x1",))
+                .with_name("blocks");
+        let params = serde_json::value::to_raw_value(&poller.params).unwrap();
+        let registry = PollerRegistry::new();
+        register(&registry, poller.definition(&params));
+        assert!(registry.set_cursor("blocks", "0x2a".into()));
+
+        // Save, restore after an upgrade, then save again.
+        let saved = registry.definitions();
+        let registry = PollerRegistry::new();
+        let restored = IcpPollerBuilder::<_, _, Vec<u64>>::from_definition(
+            WeakClient::<alloy_transport::BoxTransport>::new(),
+            saved[0].clone(),
+        )
+        .unwrap();
+        register(&registry, restored.definition(&restored.params));
+        assert_eq!(registry.cursor("blocks").as_deref(), Some("0x2a"));
+        assert_eq!(registry.definitions(), saved);
+    }
+
+    #[test]
+    fn dedup_skips_repeated_responses() {
+        let client = WeakClient::<alloy_transport::BoxTransport>::new();
+        let mut poller =
+            IcpPollerBuilder::<_, _, u64>::new(client, "eth_blockNumber", ()).with_dedup(true);
+        let mut is_new = poller.take_dedup();
+        let delivered: Vec<u64> = [1, 1, 2, 2, 2, 3, 1].into_iter().filter(|n| is_new(n)).collect();
+        assert_eq!(delivered, [1, 2, 3, 1]);
+
+        let mut poller = poller.with_dedup(false);
+        let mut is_new = poller.take_dedup();
+        assert!(is_new(&1) && is_new(&1));
+    }
+
+    #[test]
+    fn params_fn_serializes_each_poll() {
+        let client = WeakClient::<alloy_transport::BoxTransport>::new();
+        let mut block = 0u64;
+        let mut poller = IcpPollerBuilder::<_, _, u64>::new(client, "eth_getBlockByNumber", (0,))
+            .with_params_fn(move || {
+                block += 1;
+                (block,)
+            });
+        let ParamsFn(params) = poller.params_fn.as_mut().unwrap();
+
+        assert_eq!(params().unwrap().get(), "[1]");
+        assert_eq!(params().unwrap().get(), "[2]");
+    }
+
+    #[test]
+    fn until_checks_typed_responses() {
+        let client = WeakClient::<alloy_transport::BoxTransport>::new();
+        let mut poller = IcpPollerBuilder::<_, _, Option<u64>>::new(client, "eth_getReceipt", ())
+            .until(|receipt: &Option<u64>| receipt.is_some());
+        let Until(until) = poller.until.as_mut().unwrap();
+
+        assert!(!until(&None));
+        assert!(until(&Some(1)));
+    }
+
+    #[test]
+    fn metrics_record_polls() {
+        let mut metrics = PollerMetrics::default();
+        assert_eq!(metrics.average_latency(), None);
+
+        metrics.record_success(1_000, 300);
+        metrics.record_failure("timeout".into(), 500);
+        metrics.record_success(2_000, 400);
+
+        assert_eq!((metrics.polls, metrics.successful_polls, metrics.failed_polls), (3, 2, 1));
+        assert_eq!(metrics.last_success_ns, Some(2_000));
+        assert_eq!(metrics.last_error.as_deref(), Some("timeout"));
+        assert_eq!(metrics.average_latency(), Some(Duration::from_nanos(400)));
+    }
+
+    #[test]
+    fn batched_calls_dispatch_typed_responses() {
+        let client = WeakClient::<alloy_transport::BoxTransport>::new();
+        let numbers = Rc::new(RefCell::new(Vec::new()));
+        let mut poller = BatchedIcpPoller::new(client)
+            .with_call("eth_blockNumber", (), {
+                let numbers = numbers.clone();
+                move |number: u64| numbers.borrow_mut().push(number)
+            })
+            .with_call("eth_chainId", (), |_: u64| {});
+        assert_eq!(poller.len(), 2);
+
+        let handler = &mut poller.calls[0].handler;
+        handler(Ok(RawValue::from_string("12".into()).unwrap()));
+        handler(Ok(RawValue::from_string("\"latest\"".into()).unwrap()));
+        handler(Err(TransportErrorKind::custom_str("timeout")));
+        assert_eq!(*numbers.borrow(), [12]);
+    }
+
+    #[test]
+    fn paused_pollers_keep_their_state() {
+        let state = Rc::new(PollerState { poll_count: Cell::new(3), ..Default::default() });
+        state.timer_id.set(Some(TimerId::default()));
+        state.active.store(true, Ordering::Relaxed);
+        *state.resume.borrow_mut() = Some(Box::new(|_| TimerId::default()));
+        let handle = IcpPollerHandle { state };
+
+        assert!(!handle.resume());
+        assert!(handle.pause());
+        assert!(!handle.pause());
+        assert!(handle.is_paused() && handle.is_active());
+        assert!(handle.resume());
+        assert!(!handle.is_paused());
+        assert_eq!(handle.poll_count(), 3);
+
+        handle.pause();
+        handle.stop();
+        assert!(!handle.resume());
+        assert!(handle.state.resume.borrow().is_none());
+
+        // Pollers driven by the heartbeat have no timer to set.
+        let handle = IcpPollerHandle { state: Rc::default() };
+        handle.state.active.store(true, Ordering::Relaxed);
+        assert!(handle.pause() && handle.resume());
+        assert_eq!(handle.timer_id(), None);
+    }
+
+    #[test]
+    fn confirmations_delay_items() {
+        let mut confirming = Confirmations::new(2);
+        assert_eq!(confirming.push(vec![1, 2]), [] as [u64; 0]);
+        assert_eq!(confirming.push(vec![]), [] as [u64; 0]);
+        assert_eq!(confirming.push(vec![3]), [1]);
+        assert_eq!(confirming.push(vec![4, 5, 6]), [2, 3, 4]);
+        assert_eq!(confirming.pending, [5, 6]);
+
+        let mut immediate = Confirmations::new(0);
+        assert_eq!(immediate.push(vec![1, 2]), [1, 2]);
+    }
+
+    #[test]
+    fn adaptive_interval_follows_item_rate() {
+        let secs = |secs: u64| secs * 1_000_000_000;
+        let mut interval = AdaptiveInterval::new(Duration::from_secs(2), Duration::from_secs(30));
+        assert_eq!(interval.observe(0, 1, Duration::from_secs(3)), None);
+        assert_eq!(
+            interval.observe(secs(12), 1, Duration::from_secs(3)),
+            Some(Duration::from_secs(12))
+        );
+        assert_eq!(interval.observe(secs(24), 0, Duration::from_secs(12)), None);
+        assert_eq!(
+            interval.observe(secs(30), 3, Duration::from_secs(12)),
+            Some(Duration::from_millis(10_500))
+        );
+        // Within a tenth of the poll interval.
+        assert_eq!(interval.observe(secs(40), 1, Duration::from_millis(10_500)), None);
+        assert_eq!(
+            interval.observe(secs(340), 1, Duration::from_millis(10_500)),
+            Some(Duration::from_secs(30))
+        );
+
+        let handle = IcpPollerHandle { state: Rc::default() };
+        handle.state.timer_id.set(Some(TimerId::default()));
+        handle.state.active.store(true, Ordering::Relaxed);
+        let replaced = Rc::new(Cell::new(None));
+        *handle.state.resume.borrow_mut() = Some(Box::new({
+            let replaced = replaced.clone();
+            move |poll_interval| {
+                replaced.set(Some(poll_interval));
+                TimerId::default()
+            }
+        }));
+        handle.set_poll_interval(Duration::from_secs(12));
+        assert_eq!(handle.poll_interval(), Duration::from_secs(12));
+        assert_eq!(replaced.get(), Some(Duration::from_secs(12)));
+    }
+
+    #[test]
+    fn registry_clamps_poll_intervals() {
+        let registry = PollerRegistry::new();
+        assert_eq!(registry.min_poll_interval(), Duration::from_secs(1));
+        assert_eq!(registry.clamp_poll_interval(Duration::from_secs(12)), Duration::from_secs(12));
+
+        registry.set_min_poll_interval(Duration::from_secs(5));
+        assert_eq!(registry.clamp_poll_interval(Duration::from_secs(5)), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn budget_counts_calls_and_instructions() {
+        let budget = PollerBudget {
+            budget: 1_000,
+            instruction_cycles: Cell::new(0),
+            exhausted_handler: RefCell::new(None),
+        };
+        let calls = CallCycles { calls: 1, attached: 800, refunded: 200 };
+
+        assert_eq!(budget.charge(500, calls), None);
+        assert_eq!(budget.instruction_cycles.get(), 200);
+        assert_eq!(budget.charge(250, calls), None);
+        let calls = CallCycles { calls: 2, attached: 1_600, refunded: 400 };
+        assert_eq!(budget.charge(0, calls), Some(1_500));
+    }
+
+    #[test]
+    fn jitter_spreads_within_window() {
+        let jitter = Duration::from_secs(3);
+        let mut rng = 42;
+        let delays: Vec<_> =
+            (0..1000).map(|_| jitter_delay(jitter, splitmix64(&mut rng))).collect();
+
+        assert!(delays.iter().all(|delay| *delay <= jitter));
+        assert!(delays.iter().any(|delay| *delay < Duration::from_secs(1)));
+        assert!(delays.iter().any(|delay| *delay > Duration::from_secs(2)));
+        assert_eq!(jitter_delay(Duration::ZERO, 12345), Duration::ZERO);
+        assert_eq!(jitter_delay(Duration::MAX, u64::MAX), Duration::ZERO);
+    }
+
+    #[test]
+    fn registry_stops_pollers_by_name() {
+        let registry = PollerRegistry::new();
+        let register = |name: Option<&str>| {
+            let active = Arc::new(AtomicBool::new(true));
+            registry.register(RegisteredPoller {
+                id: next_poller_id(),
+                timer_id: None,
+                name: name.map(Into::into),
+                method: "eth_blockNumber".into(),
+                definition: None,
+                cycles: CyclesMeter::new(),
+                metrics: Arc::default(),
+                active: active.clone(),
+            });
+            active
+        };
+        let blocks = register(Some("blocks"));
+        let logs = register(Some("logs"));
+        let unnamed = register(None);
+
+        assert_eq!(registry.stop("blocks"), 1);
+        assert_eq!(registry.stop("blocks"), 0);
+        assert!(!blocks.load(Ordering::Relaxed));
+        assert!(logs.load(Ordering::Relaxed));
+        let names: Vec<_> = registry.active().into_iter().map(|poller| poller.name).collect();
+        assert_eq!(names, [Some("logs".to_string()), None]);
+
+        assert_eq!(registry.clear(), 2);
+        assert!(!logs.load(Ordering::Relaxed) && !unnamed.load(Ordering::Relaxed));
+        assert!(registry.active().is_empty());
+    }
+
+    #[test]
+    fn registry_stops_streamed_pollers() {
+        use futures::StreamExt;
+
+        let registry = PollerRegistry::new();
+        let state = Rc::new(PollerState { id: next_poller_id(), ..Default::default() });
+        state.active.store(true, Ordering::Relaxed);
+        *state.resume.borrow_mut() = Some(Box::new(|_| TimerId::default()));
+        track_state(&state);
+        registry.register(RegisteredPoller {
+            id: state.id,
+            timer_id: None,
+            name: Some("blocks".into()),
+            method: "eth_getFilterChanges".into(),
+            definition: None,
+            cycles: CyclesMeter::new(),
+            metrics: Arc::default(),
+            active: state.active.clone(),
+        });
+        let buffer = Rc::new(RefCell::new(StreamBuffer { items: VecDeque::new(), waker: None }));
+        let mut stream = IcpPollerStream::<u64>::new(buffer.clone(), IcpPollerHandle { state });
+
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(stream.poll_next_unpin(&mut cx), Poll::Pending);
+        assert_eq!(registry.stop("blocks"), 1);
+        // The stream is woken, and the state no longer holds the ticks of the poller.
+        assert!(buffer.borrow().waker.is_none());
+        let state = &stream.handle().unwrap().state;
+        assert!(state.resume.borrow().is_none());
+        assert_eq!(stream.poll_next_unpin(&mut cx), Poll::Ready(None));
+    }
+
+    #[test]
+    fn mapped_pollers_compose() {
+        let client = WeakClient::<alloy_transport::BoxTransport>::new();
+        let mut mapped =
+            IcpPollerBuilder::<_, _, Vec<u64>>::new(client, "eth_getFilterChanges", ())
+                .map(|blocks: Vec<u64>| blocks.last().copied())
+                .map(|newest: Option<u64>| newest.map(|block| block * 2));
+
+        assert_eq!((mapped.map)(vec![1, 2, 3]), Some(6));
+        assert_eq!((mapped.map)(vec![]), None);
+    }
+
+    #[test]
+    fn stream_drains_buffer_then_ends() {
+        use futures::StreamExt;
+
+        let buffer =
+            Rc::new(RefCell::new(StreamBuffer { items: VecDeque::from([1, 2]), waker: None }));
+        let handle = IcpPollerHandle { state: Rc::default() };
+        handle.state.active.store(true, Ordering::Relaxed);
+        let mut stream = IcpPollerStream { buffer: buffer.clone(), handle: Some(handle.clone()) };
+
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(stream.poll_next_unpin(&mut cx), Poll::Ready(Some(1)));
+        assert_eq!(stream.poll_next_unpin(&mut cx), Poll::Ready(Some(2)));
+        assert_eq!(stream.poll_next_unpin(&mut cx), Poll::Pending);
+        assert!(buffer.borrow().waker.is_some());
+
+        buffer.borrow_mut().items.push_back(3);
+        handle.state.active.store(false, Ordering::Relaxed);
+        assert_eq!(stream.poll_next_unpin(&mut cx), Poll::Ready(Some(3)));
+        assert_eq!(stream.poll_next_unpin(&mut cx), Poll::Ready(None));
+    }
+
+    #[test]
+    fn backoff_grows_after_threshold() {
+        let backoff = PollBackoff::new()
+            .with_threshold(2)
+            .with_initial_delay(Duration::from_secs(5))
+            .with_multiplier(3)
+            .with_max_interval(Duration::from_secs(60));
 
-    #[cold]
-    fn init(&mut self) -> serde_json::Result<&RawValue> {
-        let Self::Typed(p) = self else { unreachable!() };
-        let v = serde_json::value::to_raw_value(p)?;
-        *self = Self::Serialized(v);
-        let Self::Serialized(v) = self else { unreachable!() };
-        Ok(v)
+        assert_eq!(backoff.delay(0), None);
+        assert_eq!(backoff.delay(1), None);
+        assert_eq!(backoff.delay(2), Some(Duration::from_secs(5)));
+        assert_eq!(backoff.delay(3), Some(Duration::from_secs(15)));
+        assert_eq!(backoff.delay(4), Some(Duration::from_secs(45)));
+        assert_eq!(backoff.delay(5), Some(Duration::from_secs(60)));
+        assert_eq!(backoff.delay(u32::MAX), Some(Duration::from_secs(60)));
     }
 }
@@ -44,4 +44,9 @@ pub type ReqwestClient = RpcClient<alloy_transport_http::ReqwestTransport>;
 pub type IcpClient = RpcClient<alloy_transport_icp::IcpTransport>;
 
 mod icp_poller;
-pub use icp_poller::IcpPollerBuilder;
+pub use icp_poller::{
+    tick_pollers, ActivePoller, AdaptivePoller, BatchedIcpPoller, BorrowedResponse,
+    CyclesScheduling, IcpPollerBuilder, IcpPollerHandle, IcpPollerStream, MappedPoller,
+    PollBackoff, PollDriver, PollerCycles, PollerDefinition, PollerMetrics, PollerRestorer,
+    StatefulPoller,
+};
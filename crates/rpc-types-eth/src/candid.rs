@@ -0,0 +1,467 @@
+//! [`CandidType`] implementations for the RPC types, to use them in the Candid interface of an
+//! ICP canister.
+//!
+//! The encoding follows the EVM RPC canister: addresses, hashes and byte strings are hex `text`,
+//! and numbers are `nat`.
+//!
+//! The [`Deserialize`] implementations of the RPC types are their JSON representation, so Candid
+//! values are decoded with [`FromCandid`] instead, e.g. in the arguments of a canister method:
+//!
+//! ```ignore
+//! #[derive(CandidType, Deserialize)]
+//! struct CallArgs {
+//!     #[serde(deserialize_with = "alloy_rpc_types_eth::candid::deserialize")]
+//!     transaction: TransactionRequest,
+//! }
+//! ```
+
+use crate::{Log, TransactionInput, TransactionReceipt, TransactionRequest};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use alloy_consensus::{Eip658Value, Receipt, ReceiptEnvelope, ReceiptWithBloom, TxReceipt, TxType};
+use alloy_eips::eip2930::{AccessList, AccessListItem};
+use alloy_primitives::{hex, Address, Bloom, Bytes, LogData, TxKind, B256, U256};
+use candid::{
+    types::{Serializer, Type},
+    CandidType, Nat,
+};
+use core::{fmt, str::FromStr};
+use serde::{de::Error as _, Deserialize, Deserializer};
+
+/// RPC types decoded from their Candid encoding.
+pub trait FromCandid: CandidType + Sized {
+    /// Deserialize the value from its Candid encoding.
+    fn deserialize_candid<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>;
+}
+
+/// Deserialize an RPC type from its Candid encoding, for use with
+/// `#[serde(deserialize_with = "alloy_rpc_types_eth::candid::deserialize")]`.
+pub fn deserialize<'de, T: FromCandid, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    T::deserialize_candid(deserializer)
+}
+
+/// Implements [`CandidType`] and [`FromCandid`] for an RPC type by converting it from and into a
+/// record.
+macro_rules! candid_record {
+    ($name:ty, $record:ident) => {
+        impl CandidType for $name {
+            fn _ty() -> Type {
+                $record::ty()
+            }
+
+            fn idl_serialize<S: Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+                $record::from(self).idl_serialize(serializer)
+            }
+        }
+
+        impl FromCandid for $name {
+            fn deserialize_candid<'de, D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> Result<Self, D::Error> {
+                $record::deserialize(deserializer)?.try_into().map_err(D::Error::custom)
+            }
+        }
+    };
+}
+
+// Encoded like the `LogEntry` of the EVM RPC canister.
+candid_record!(Log, LogRecord);
+
+// The EIP-7702 authorization list is not encoded.
+candid_record!(TransactionReceipt, ReceiptRecord);
+
+// The blob sidecar and the EIP-7702 authorization list are not encoded, and an explicit
+// `TxKind::Create` is encoded as a missing `to`.
+candid_record!(TransactionRequest, RequestRecord);
+
+fn to_nat(value: impl fmt::Display) -> Nat {
+    Nat::parse(value.to_string().as_bytes()).expect("decimal digits are a valid nat")
+}
+
+fn from_nat<T: TryFrom<U256>>(nat: &Nat) -> Result<T, String> {
+    let bytes = nat.0.to_bytes_be();
+    if bytes.len() > 32 {
+        return Err(format!("{nat} does not fit in 256 bits"));
+    }
+    T::try_from(U256::from_be_slice(&bytes)).map_err(|_| format!("{nat} is out of range"))
+}
+
+fn from_opt_nat<T: TryFrom<U256>>(nat: &Option<Nat>) -> Result<Option<T>, String> {
+    nat.as_ref().map(from_nat).transpose()
+}
+
+fn from_hex<T: FromStr>(text: &str) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    text.parse().map_err(|e| format!("invalid hex {text:?}: {e}"))
+}
+
+fn from_opt_hex<T: FromStr>(text: &Option<String>) -> Result<Option<T>, String>
+where
+    T::Err: fmt::Display,
+{
+    text.as_deref().map(from_hex).transpose()
+}
+
+fn from_hex_vec<T: FromStr>(texts: &[String]) -> Result<Vec<T>, String>
+where
+    T::Err: fmt::Display,
+{
+    texts.iter().map(|text| from_hex(text)).collect()
+}
+
+fn to_hex_vec<T: AsRef<[u8]>>(values: &[T]) -> Vec<String> {
+    values.iter().map(hex::encode_prefixed).collect()
+}
+
+#[derive(CandidType, Deserialize)]
+struct LogRecord {
+    address: String,
+    topics: Vec<String>,
+    data: String,
+    #[serde(rename = "blockHash")]
+    block_hash: Option<String>,
+    #[serde(rename = "blockNumber")]
+    block_number: Option<Nat>,
+    #[serde(rename = "blockTimestamp")]
+    block_timestamp: Option<Nat>,
+    #[serde(rename = "transactionHash")]
+    transaction_hash: Option<String>,
+    #[serde(rename = "transactionIndex")]
+    transaction_index: Option<Nat>,
+    #[serde(rename = "logIndex")]
+    log_index: Option<Nat>,
+    removed: bool,
+}
+
+impl From<&Log> for LogRecord {
+    fn from(log: &Log) -> Self {
+        Self {
+            address: hex::encode_prefixed(log.address()),
+            topics: to_hex_vec(log.topics()),
+            data: hex::encode_prefixed(&log.data().data),
+            block_hash: log.block_hash.map(hex::encode_prefixed),
+            block_number: log.block_number.map(to_nat),
+            block_timestamp: log.block_timestamp.map(to_nat),
+            transaction_hash: log.transaction_hash.map(hex::encode_prefixed),
+            transaction_index: log.transaction_index.map(to_nat),
+            log_index: log.log_index.map(to_nat),
+            removed: log.removed,
+        }
+    }
+}
+
+impl TryFrom<LogRecord> for Log {
+    type Error = String;
+
+    fn try_from(record: LogRecord) -> Result<Self, Self::Error> {
+        let data = LogData::new(from_hex_vec(&record.topics)?, from_hex::<Bytes>(&record.data)?)
+            .ok_or("a log has at most 4 topics")?;
+        Ok(Self {
+            inner: alloy_primitives::Log { address: from_hex(&record.address)?, data },
+            block_hash: from_opt_hex(&record.block_hash)?,
+            block_number: from_opt_nat(&record.block_number)?,
+            block_timestamp: from_opt_nat(&record.block_timestamp)?,
+            transaction_hash: from_opt_hex(&record.transaction_hash)?,
+            transaction_index: from_opt_nat(&record.transaction_index)?,
+            log_index: from_opt_nat(&record.log_index)?,
+            removed: record.removed,
+        })
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+struct ReceiptRecord {
+    #[serde(rename = "type")]
+    transaction_type: u8,
+    /// Missing for pre-Byzantium receipts, which have a `root` instead.
+    status: Option<bool>,
+    root: Option<String>,
+    #[serde(rename = "cumulativeGasUsed")]
+    cumulative_gas_used: Nat,
+    logs: Vec<LogRecord>,
+    #[serde(rename = "logsBloom")]
+    logs_bloom: String,
+    #[serde(rename = "transactionHash")]
+    transaction_hash: String,
+    #[serde(rename = "transactionIndex")]
+    transaction_index: Option<Nat>,
+    #[serde(rename = "blockHash")]
+    block_hash: Option<String>,
+    #[serde(rename = "blockNumber")]
+    block_number: Option<Nat>,
+    #[serde(rename = "gasUsed")]
+    gas_used: Nat,
+    #[serde(rename = "effectiveGasPrice")]
+    effective_gas_price: Nat,
+    #[serde(rename = "blobGasUsed")]
+    blob_gas_used: Option<Nat>,
+    #[serde(rename = "blobGasPrice")]
+    blob_gas_price: Option<Nat>,
+    from: String,
+    to: Option<String>,
+    #[serde(rename = "contractAddress")]
+    contract_address: Option<String>,
+}
+
+impl From<&TransactionReceipt> for ReceiptRecord {
+    fn from(receipt: &TransactionReceipt) -> Self {
+        let inner = &receipt.inner;
+        let status = inner.status_or_post_state();
+        Self {
+            transaction_type: inner.tx_type() as u8,
+            status: status.as_eip658(),
+            root: status.as_post_state().or(receipt.state_root).map(hex::encode_prefixed),
+            cumulative_gas_used: to_nat(inner.cumulative_gas_used()),
+            logs: inner.logs().iter().map(Into::into).collect(),
+            logs_bloom: hex::encode_prefixed(inner.logs_bloom()),
+            transaction_hash: hex::encode_prefixed(receipt.transaction_hash),
+            transaction_index: receipt.transaction_index.map(to_nat),
+            block_hash: receipt.block_hash.map(hex::encode_prefixed),
+            block_number: receipt.block_number.map(to_nat),
+            gas_used: to_nat(receipt.gas_used),
+            effective_gas_price: to_nat(receipt.effective_gas_price),
+            blob_gas_used: receipt.blob_gas_used.map(to_nat),
+            blob_gas_price: receipt.blob_gas_price.map(to_nat),
+            from: hex::encode_prefixed(receipt.from),
+            to: receipt.to.map(hex::encode_prefixed),
+            contract_address: receipt.contract_address.map(hex::encode_prefixed),
+        }
+    }
+}
+
+impl TryFrom<ReceiptRecord> for TransactionReceipt {
+    type Error = String;
+
+    fn try_from(record: ReceiptRecord) -> Result<Self, Self::Error> {
+        let root = from_opt_hex::<B256>(&record.root)?;
+        let status = match record.status {
+            Some(status) => Eip658Value::Eip658(status),
+            None => Eip658Value::PostState(root.ok_or("a receipt needs a status or a root")?),
+        };
+        let receipt = ReceiptWithBloom {
+            receipt: Receipt {
+                status,
+                cumulative_gas_used: from_nat(&record.cumulative_gas_used)?,
+                logs: record.logs.into_iter().map(TryInto::try_into).collect::<Result<_, _>>()?,
+            },
+            logs_bloom: from_hex::<Bloom>(&record.logs_bloom)?,
+        };
+        let tx_type = TxType::try_from(record.transaction_type).map_err(|e| e.to_string())?;
+        let inner = match tx_type {
+            TxType::Legacy => ReceiptEnvelope::Legacy(receipt),
+            TxType::Eip2930 => ReceiptEnvelope::Eip2930(receipt),
+            TxType::Eip1559 => ReceiptEnvelope::Eip1559(receipt),
+            TxType::Eip4844 => ReceiptEnvelope::Eip4844(receipt),
+            TxType::Eip7702 => ReceiptEnvelope::Eip7702(receipt),
+        };
+        Ok(Self {
+            inner,
+            transaction_hash: from_hex(&record.transaction_hash)?,
+            transaction_index: from_opt_nat(&record.transaction_index)?,
+            block_hash: from_opt_hex(&record.block_hash)?,
+            block_number: from_opt_nat(&record.block_number)?,
+            gas_used: from_nat(&record.gas_used)?,
+            effective_gas_price: from_nat(&record.effective_gas_price)?,
+            blob_gas_used: from_opt_nat(&record.blob_gas_used)?,
+            blob_gas_price: from_opt_nat(&record.blob_gas_price)?,
+            from: from_hex(&record.from)?,
+            to: from_opt_hex(&record.to)?,
+            contract_address: from_opt_hex(&record.contract_address)?,
+            state_root: root.filter(|_| record.status.is_some()),
+            authorization_list: None,
+        })
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+struct AccessListItemRecord {
+    address: String,
+    #[serde(rename = "storageKeys")]
+    storage_keys: Vec<String>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct RequestRecord {
+    from: Option<String>,
+    to: Option<String>,
+    gas: Option<Nat>,
+    #[serde(rename = "gasPrice")]
+    gas_price: Option<Nat>,
+    #[serde(rename = "maxFeePerGas")]
+    max_fee_per_gas: Option<Nat>,
+    #[serde(rename = "maxPriorityFeePerGas")]
+    max_priority_fee_per_gas: Option<Nat>,
+    #[serde(rename = "maxFeePerBlobGas")]
+    max_fee_per_blob_gas: Option<Nat>,
+    value: Option<Nat>,
+    input: Option<String>,
+    nonce: Option<Nat>,
+    #[serde(rename = "chainId")]
+    chain_id: Option<Nat>,
+    #[serde(rename = "accessList")]
+    access_list: Option<Vec<AccessListItemRecord>>,
+    #[serde(rename = "type")]
+    transaction_type: Option<u8>,
+    #[serde(rename = "blobVersionedHashes")]
+    blob_versioned_hashes: Option<Vec<String>>,
+}
+
+impl From<&TransactionRequest> for RequestRecord {
+    fn from(request: &TransactionRequest) -> Self {
+        Self {
+            from: request.from.map(hex::encode_prefixed),
+            to: request.to.and_then(|to| to.to().copied()).map(hex::encode_prefixed),
+            gas: request.gas.map(to_nat),
+            gas_price: request.gas_price.map(to_nat),
+            max_fee_per_gas: request.max_fee_per_gas.map(to_nat),
+            max_priority_fee_per_gas: request.max_priority_fee_per_gas.map(to_nat),
+            max_fee_per_blob_gas: request.max_fee_per_blob_gas.map(to_nat),
+            value: request.value.map(to_nat),
+            input: request.input.input().map(hex::encode_prefixed),
+            nonce: request.nonce.map(to_nat),
+            chain_id: request.chain_id.map(to_nat),
+            access_list: request.access_list.as_ref().map(|list| {
+                list.iter()
+                    .map(|item| AccessListItemRecord {
+                        address: hex::encode_prefixed(item.address),
+                        storage_keys: to_hex_vec(&item.storage_keys),
+                    })
+                    .collect()
+            }),
+            transaction_type: request.transaction_type,
+            blob_versioned_hashes: request.blob_versioned_hashes.as_deref().map(to_hex_vec),
+        }
+    }
+}
+
+impl TryFrom<RequestRecord> for TransactionRequest {
+    type Error = String;
+
+    fn try_from(record: RequestRecord) -> Result<Self, Self::Error> {
+        let access_list = record
+            .access_list
+            .map(|list| {
+                list.into_iter()
+                    .map(|item| {
+                        Ok(AccessListItem {
+                            address: from_hex(&item.address)?,
+                            storage_keys: from_hex_vec(&item.storage_keys)?,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, String>>()
+                    .map(AccessList)
+            })
+            .transpose()?;
+        Ok(Self {
+            from: from_opt_hex(&record.from)?,
+            to: from_opt_hex::<Address>(&record.to)?.map(TxKind::Call),
+            gas: from_opt_nat(&record.gas)?,
+            gas_price: from_opt_nat(&record.gas_price)?,
+            max_fee_per_gas: from_opt_nat(&record.max_fee_per_gas)?,
+            max_priority_fee_per_gas: from_opt_nat(&record.max_priority_fee_per_gas)?,
+            max_fee_per_blob_gas: from_opt_nat(&record.max_fee_per_blob_gas)?,
+            value: from_opt_nat(&record.value)?,
+            input: from_opt_hex::<Bytes>(&record.input)?
+                .map(TransactionInput::new)
+                .unwrap_or_default(),
+            nonce: from_opt_nat(&record.nonce)?,
+            chain_id: from_opt_nat(&record.chain_id)?,
+            access_list,
+            transaction_type: record.transaction_type,
+            blob_versioned_hashes: record
+                .blob_versioned_hashes
+                .as_deref()
+                .map(from_hex_vec)
+                .transpose()?,
+            sidecar: None,
+            authorization_list: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{address, b256, bytes};
+
+    /// Decodes through [`FromCandid`], like a derived type using [`deserialize`].
+    #[derive(Debug, PartialEq, CandidType, Deserialize)]
+    struct Args<T: FromCandid> {
+        #[serde(deserialize_with = "deserialize")]
+        value: T,
+    }
+
+    fn roundtrip<T: FromCandid + PartialEq + fmt::Debug>(value: T) {
+        let args = Args { value };
+        let bytes = candid::encode_one(&args).unwrap();
+        assert_eq!(candid::decode_one::<Args<T>>(&bytes).unwrap(), args);
+    }
+
+    fn log() -> Log {
+        Log {
+            inner: alloy_primitives::Log::new_unchecked(
+                address!("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"),
+                vec![b256!("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef")],
+                bytes!("0000000000000000000000000000000000000000000000000000000000000001"),
+            ),
+            block_number: Some(20_000_000),
+            log_index: Some(3),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn log_roundtrip() {
+        roundtrip(log());
+    }
+
+    #[test]
+    fn receipt_roundtrip() {
+        roundtrip(TransactionReceipt {
+            inner: ReceiptEnvelope::Eip1559(ReceiptWithBloom {
+                receipt: Receipt {
+                    status: true.into(),
+                    cumulative_gas_used: 21_000,
+                    logs: vec![log()],
+                },
+                logs_bloom: Bloom::default(),
+            }),
+            transaction_hash: B256::repeat_byte(1),
+            transaction_index: Some(0),
+            block_hash: Some(B256::repeat_byte(2)),
+            block_number: Some(20_000_000),
+            gas_used: 21_000,
+            effective_gas_price: 30_000_000_000,
+            blob_gas_used: None,
+            blob_gas_price: None,
+            from: Address::repeat_byte(3),
+            to: Some(Address::repeat_byte(4)),
+            contract_address: None,
+            state_root: None,
+            authorization_list: None,
+        });
+    }
+
+    #[test]
+    fn request_roundtrip() {
+        roundtrip(
+            TransactionRequest::default()
+                .from(Address::repeat_byte(3))
+                .to(Address::repeat_byte(4))
+                .value(U256::from(10).pow(U256::from(18)))
+                .input(bytes!("a9059cbb").into())
+                .nonce(7)
+                .access_list(AccessList(vec![AccessListItem {
+                    address: Address::repeat_byte(5),
+                    storage_keys: vec![B256::repeat_byte(6)],
+                }])),
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{collections::HashMap, Log, TransactionReceipt};
-use alloy_primitives::{Address, BlockNumber, Bytes, B256, U256};
+use alloy_primitives::{keccak256, Address, BlockNumber, Bytes, ChainId, B256, U256};
+use alloy_sol_types::SolValue;
 
 use alloc::vec::Vec;
 
@@ -115,6 +116,84 @@ pub struct PackedUserOperation {
     pub signature: Bytes,
 }
 
+impl UserOperation {
+    /// Returns the hash of the user operation for the given entry point and chain, the hash
+    /// signed by the owner of the smart account.
+    ///
+    /// The signature is not part of the hash.
+    pub fn hash(&self, entry_point: Address, chain_id: ChainId) -> B256 {
+        let packed = (
+            self.sender,
+            self.nonce,
+            keccak256(&self.init_code),
+            keccak256(&self.call_data),
+            self.call_gas_limit,
+            self.verification_gas_limit,
+            self.pre_verification_gas,
+            self.max_fee_per_gas,
+            self.max_priority_fee_per_gas,
+            keccak256(&self.paymaster_and_data),
+        )
+            .abi_encode();
+        keccak256((keccak256(packed), entry_point, U256::from(chain_id)).abi_encode())
+    }
+}
+
+impl PackedUserOperation {
+    /// Returns the `initCode` of the operation packed by the entry point, the factory address
+    /// followed by the factory data, or empty if there is no factory.
+    pub fn init_code(&self) -> Bytes {
+        if self.factory.is_zero() {
+            return Bytes::new();
+        }
+        [self.factory.as_slice(), &self.factory_data].concat().into()
+    }
+
+    /// Returns the `paymasterAndData` of the operation packed by the entry point, the paymaster
+    /// address followed by its 16 bytes gas limits and its data, or empty if there is no
+    /// paymaster.
+    pub fn paymaster_and_data(&self) -> Bytes {
+        if self.paymaster.is_zero() {
+            return Bytes::new();
+        }
+        let verification_gas_limit = self.paymaster_verification_gas_limit.to_be_bytes::<32>();
+        let post_op_gas_limit = self.paymaster_post_op_gas_limit.to_be_bytes::<32>();
+        [
+            self.paymaster.as_slice(),
+            &verification_gas_limit[16..],
+            &post_op_gas_limit[16..],
+            &self.paymaster_data,
+        ]
+        .concat()
+        .into()
+    }
+
+    /// Returns the hash of the user operation for the given entry point and chain, the hash
+    /// signed by the owner of the smart account.
+    ///
+    /// The signature is not part of the hash.
+    pub fn hash(&self, entry_point: Address, chain_id: ChainId) -> B256 {
+        let packed = (
+            self.sender,
+            self.nonce,
+            keccak256(self.init_code()),
+            keccak256(&self.call_data),
+            pack_u128s(self.verification_gas_limit, self.call_gas_limit),
+            self.pre_verification_gas,
+            pack_u128s(self.max_priority_fee_per_gas, self.max_fee_per_gas),
+            keccak256(self.paymaster_and_data()),
+        )
+            .abi_encode();
+        keccak256((keccak256(packed), entry_point, U256::from(chain_id)).abi_encode())
+    }
+}
+
+/// Packs two values of at most 128 bits in a single word, the first in the high bits.
+fn pack_u128s(high: U256, low: U256) -> B256 {
+    let mask = U256::from(u128::MAX);
+    B256::from((high << 128) | (low & mask))
+}
+
 /// Send User Operation
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -125,6 +204,33 @@ pub enum SendUserOperation {
     EntryPointV07(PackedUserOperation),
 }
 
+impl SendUserOperation {
+    /// Returns the hash of the user operation for the given entry point and chain, the hash
+    /// signed by the owner of the smart account.
+    pub fn hash(&self, entry_point: Address, chain_id: ChainId) -> B256 {
+        match self {
+            Self::EntryPointV06(user_op) => user_op.hash(entry_point, chain_id),
+            Self::EntryPointV07(packed_user_op) => packed_user_op.hash(entry_point, chain_id),
+        }
+    }
+
+    /// Returns the signature of the user operation.
+    pub const fn signature(&self) -> &Bytes {
+        match self {
+            Self::EntryPointV06(user_op) => &user_op.signature,
+            Self::EntryPointV07(packed_user_op) => &packed_user_op.signature,
+        }
+    }
+
+    /// Sets the signature of the user operation.
+    pub fn set_signature(&mut self, signature: Bytes) {
+        match self {
+            Self::EntryPointV06(user_op) => user_op.signature = signature,
+            Self::EntryPointV07(packed_user_op) => packed_user_op.signature = signature,
+        }
+    }
+}
+
 /// Response to sending a user operation.
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -177,3 +283,60 @@ pub struct UserOperationGasEstimation {
     /// The gas limit for the call.
     pub call_gas_limit: U256,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packed_user_op() -> PackedUserOperation {
+        PackedUserOperation {
+            sender: Address::with_last_byte(1),
+            nonce: U256::from(1),
+            factory: Address::ZERO,
+            factory_data: Bytes::new(),
+            call_data: Bytes::from_static(&[0xb6, 0x1d, 0x27, 0xf6]),
+            call_gas_limit: U256::from(100_000),
+            verification_gas_limit: U256::from(200_000),
+            pre_verification_gas: U256::from(50_000),
+            max_fee_per_gas: U256::from(30_000_000_000u64),
+            max_priority_fee_per_gas: U256::from(1_000_000_000),
+            paymaster: Address::ZERO,
+            paymaster_verification_gas_limit: U256::ZERO,
+            paymaster_post_op_gas_limit: U256::ZERO,
+            paymaster_data: Bytes::new(),
+            signature: Bytes::new(),
+        }
+    }
+
+    #[test]
+    fn packs_user_operation() {
+        let mut user_op = packed_user_op();
+        assert!(user_op.init_code().is_empty());
+        assert!(user_op.paymaster_and_data().is_empty());
+
+        user_op.paymaster = Address::with_last_byte(2);
+        user_op.paymaster_verification_gas_limit = U256::from(3);
+        user_op.paymaster_post_op_gas_limit = U256::from(4);
+        user_op.paymaster_data = Bytes::from_static(&[5]);
+        let paymaster_and_data = user_op.paymaster_and_data();
+        assert_eq!(paymaster_and_data.len(), 20 + 16 + 16 + 1);
+        assert_eq!(paymaster_and_data[35], 3);
+        assert_eq!(paymaster_and_data[51], 4);
+        assert_eq!(paymaster_and_data[52], 5);
+
+        let gas_fees = pack_u128s(user_op.max_priority_fee_per_gas, user_op.max_fee_per_gas);
+        assert_eq!(U256::from_be_slice(&gas_fees[..16]), user_op.max_priority_fee_per_gas);
+        assert_eq!(U256::from_be_slice(&gas_fees[16..]), user_op.max_fee_per_gas);
+    }
+
+    #[test]
+    fn hash_excludes_signature() {
+        let entry_point = Address::with_last_byte(0x77);
+        let mut user_op = SendUserOperation::EntryPointV07(packed_user_op());
+        let hash = user_op.hash(entry_point, 1);
+        user_op.set_signature(Bytes::from_static(&[1; 65]));
+        assert_eq!(user_op.hash(entry_point, 1), hash);
+        assert_ne!(user_op.hash(entry_point, 2), hash);
+        assert_ne!(user_op.hash(Address::ZERO, 1), hash);
+    }
+}
@@ -38,6 +38,9 @@ pub use alloy_network_primitives::{
     BlockTransactionHashes, BlockTransactions, BlockTransactionsKind,
 };
 
+#[cfg(feature = "candid")]
+pub mod candid;
+
 mod call;
 pub use call::{Bundle, EthCallResponse, StateContext, TransactionIndex};
 
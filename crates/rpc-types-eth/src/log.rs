@@ -88,6 +88,101 @@ impl Log<LogData> {
     }
 }
 
+/// A [`Log`] decoded into the event `E` only when its typed fields are first accessed.
+///
+/// When watching high-volume events, most logs may be discarded by their address or topics
+/// before their fields are needed. A lazy log keeps the raw log, and only pays the ABI decoding
+/// cost of the logs actually processed. The decoded event is cached, so it is decoded at most
+/// once.
+///
+/// # Examples
+///
+/// ```ignore
+/// for log in logs.into_iter().map(LazyLog::<Transfer>::new) {
+///     if !log.is_event() || log.topics().get(2) != Some(&watched) {
+///         continue;
+///     }
+///     let transfer = log.decoded()?;
+///     handle_transfer(transfer.from, transfer.to, transfer.value);
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct LazyLog<E> {
+    log: Log,
+    decoded: core::cell::OnceCell<alloy_sol_types::Result<E>>,
+}
+
+impl<E: alloy_sol_types::SolEvent> LazyLog<E> {
+    /// Wrap a raw log, without decoding it.
+    pub const fn new(log: Log) -> Self {
+        Self { log, decoded: core::cell::OnceCell::new() }
+    }
+
+    /// Returns the raw log.
+    pub const fn log(&self) -> &Log {
+        &self.log
+    }
+
+    /// Consumes the lazy log and returns the raw log.
+    pub fn into_log(self) -> Log {
+        self.log
+    }
+
+    /// Returns `true` if the first topic of the log is the signature of the event `E`, without
+    /// decoding the log. Always `true` for anonymous events.
+    pub fn is_event(&self) -> bool {
+        E::ANONYMOUS || self.log.topic0() == Some(&E::SIGNATURE_HASH)
+    }
+
+    /// Returns `true` if the log has been decoded.
+    pub fn is_decoded(&self) -> bool {
+        self.decoded.get().is_some()
+    }
+
+    /// Returns the decoded event, decoding the log on first access.
+    pub fn decoded(&self) -> Result<&E, &alloy_sol_types::Error> {
+        self.decoded
+            .get_or_init(|| {
+                let data = &self.log.inner.data;
+                E::decode_raw_log(data.topics().iter().copied(), &data.data, false)
+            })
+            .as_ref()
+    }
+
+    /// Consumes the lazy log and returns the typed log, decoding it if not decoded yet.
+    pub fn into_decoded(self) -> alloy_sol_types::Result<Log<E>> {
+        let Self { log, decoded } = self;
+        let event = match decoded.into_inner() {
+            Some(decoded) => decoded?,
+            None => return log.log_decode(),
+        };
+        Ok(Log {
+            inner: alloy_primitives::Log { address: log.inner.address, data: event },
+            block_hash: log.block_hash,
+            block_number: log.block_number,
+            block_timestamp: log.block_timestamp,
+            transaction_hash: log.transaction_hash,
+            transaction_index: log.transaction_index,
+            log_index: log.log_index,
+            removed: log.removed,
+        })
+    }
+}
+
+impl<E> core::ops::Deref for LazyLog<E> {
+    type Target = Log;
+
+    fn deref(&self) -> &Self::Target {
+        &self.log
+    }
+}
+
+impl<E> From<Log> for LazyLog<E> {
+    fn from(log: Log) -> Self {
+        Self { log, decoded: core::cell::OnceCell::new() }
+    }
+}
+
 impl<T> alloy_rlp::Encodable for Log<T>
 where
     for<'a> &'a T: Into<LogData>,
@@ -167,6 +262,36 @@ mod tests {
         let _: Log = Log::arbitrary(&mut arbitrary::Unstructured::new(&bytes)).unwrap();
     }
 
+    #[test]
+    fn lazy_log_decodes_once() {
+        alloy_sol_types::sol! {
+            event Transfer(address indexed from, address indexed to, uint256 value);
+        }
+        use alloy_sol_types::SolEvent;
+
+        let event = Transfer {
+            from: Address::with_last_byte(1),
+            to: Address::with_last_byte(2),
+            value: alloy_primitives::U256::from(3),
+        };
+        let log = Log {
+            inner: alloy_primitives::Log { address: Address::ZERO, data: event.encode_log_data() },
+            ..Default::default()
+        };
+
+        let lazy = LazyLog::<Transfer>::new(log);
+        assert!(lazy.is_event());
+        assert!(!lazy.is_decoded());
+        assert_eq!(lazy.decoded().unwrap().value, event.value);
+        assert!(lazy.is_decoded());
+        assert_eq!(lazy.into_decoded().unwrap().inner.data.to, event.to);
+
+        let other = Log::<alloy_primitives::LogData>::default();
+        let lazy = LazyLog::<Transfer>::from(other);
+        assert!(!lazy.is_event());
+        assert!(lazy.decoded().is_err());
+    }
+
     #[test]
     #[cfg(feature = "serde")]
     fn serde_log() {
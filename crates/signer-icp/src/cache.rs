@@ -0,0 +1,66 @@
+//! Stable memory cache of signer public keys
+
+use std::{borrow::Cow, cell::RefCell, thread::LocalKey};
+
+use alloy_primitives::Address;
+use ic_cdk::api::management_canister::ecdsa::EcdsaKeyId;
+use ic_stable_structures::{storable::Bound, Memory, StableBTreeMap, Storable};
+
+/// The stable map caching the [`SignerMetadata`] of each key id and derivation path.
+pub type StableSignerCache<M> = StableBTreeMap<Vec<u8>, SignerMetadata, M>;
+
+/// The public key and address of a signer, cached per key id and derivation path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignerMetadata {
+    /// SEC1 encoded ECDSA public key.
+    pub public_key: Vec<u8>,
+    /// The Ethereum address of the public key.
+    pub address: Address,
+}
+
+impl Storable for SignerMetadata {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = Vec::with_capacity(Address::len_bytes() + self.public_key.len());
+        bytes.extend_from_slice(self.address.as_slice());
+        bytes.extend_from_slice(&self.public_key);
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        let (address, public_key) = bytes.split_at(Address::len_bytes());
+        Self { public_key: public_key.to_vec(), address: Address::from_slice(address) }
+    }
+
+    // An address and an uncompressed SEC1 public key.
+    const BOUND: Bound = Bound::Bounded { max_size: 20 + 65, is_fixed_size: false };
+}
+
+/// Returns the key of the cache entry for the given key id and derivation path.
+pub(crate) fn cache_key(key_id: &EcdsaKeyId, derivation_path: &[Vec<u8>]) -> Vec<u8> {
+    // Length-prefix every component so that different paths never share a key.
+    let mut key = Vec::new();
+    for component in
+        std::iter::once(key_id.name.as_bytes()).chain(derivation_path.iter().map(Vec::as_slice))
+    {
+        key.extend_from_slice(&(component.len() as u32).to_be_bytes());
+        key.extend_from_slice(component);
+    }
+    key
+}
+
+pub(crate) fn get<M: Memory>(
+    cache: &'static LocalKey<RefCell<StableSignerCache<M>>>,
+    key_id: &EcdsaKeyId,
+    derivation_path: &[Vec<u8>],
+) -> Option<SignerMetadata> {
+    cache.with_borrow(|cache| cache.get(&cache_key(key_id, derivation_path)))
+}
+
+pub(crate) fn insert<M: Memory>(
+    cache: &'static LocalKey<RefCell<StableSignerCache<M>>>,
+    key_id: &EcdsaKeyId,
+    derivation_path: &[Vec<u8>],
+    metadata: SignerMetadata,
+) {
+    cache.with_borrow_mut(|cache| cache.insert(cache_key(key_id, derivation_path), metadata));
+}
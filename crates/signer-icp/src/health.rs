@@ -0,0 +1,57 @@
+//! Signing health of an ICP signer
+
+use std::sync::{Arc, Mutex};
+
+use candid::{CandidType, Deserialize};
+
+/// The signing health of an [`IcpSigner`], shared by all its clones.
+///
+/// [`IcpSigner`]: crate::IcpSigner
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize)]
+pub struct SignerHealth {
+    /// The checksummed Ethereum address of the signer.
+    pub address: String,
+    /// The name of the ECDSA key of the signer.
+    pub key_name: String,
+    /// Number of hashes signed.
+    pub signatures: u64,
+    /// Number of signing calls that failed.
+    pub failures: u64,
+    /// Number of signing calls that failed since the last successful signature.
+    pub consecutive_failures: u64,
+    /// The time of the last successful signature, in nanoseconds since the epoch.
+    pub last_signature_at_ns: Option<u64>,
+    /// The error of the last failed signing call.
+    pub last_error: Option<String>,
+}
+
+impl SignerHealth {
+    /// Returns `true` if the last signing call did not fail.
+    pub const fn is_healthy(&self) -> bool {
+        self.consecutive_failures == 0
+    }
+}
+
+/// Records the outcome of the signing calls of a signer.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HealthRecorder(Arc<Mutex<SignerHealth>>);
+
+impl HealthRecorder {
+    pub(crate) fn record_signature(&self) {
+        let mut health = self.0.lock().unwrap();
+        health.signatures += 1;
+        health.consecutive_failures = 0;
+        health.last_signature_at_ns = Some(ic_cdk::api::time());
+    }
+
+    pub(crate) fn record_failure(&self, error: String) {
+        let mut health = self.0.lock().unwrap();
+        health.failures += 1;
+        health.consecutive_failures += 1;
+        health.last_error = Some(error);
+    }
+
+    pub(crate) fn snapshot(&self) -> SignerHealth {
+        self.0.lock().unwrap().clone()
+    }
+}
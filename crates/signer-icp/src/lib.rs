@@ -6,8 +6,14 @@
 #![cfg_attr(not(test), warn(unused_crate_dependencies))]
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
+#[cfg(feature = "stable-cache")]
+mod cache;
+mod health;
 mod signer;
 mod utils;
 
+#[cfg(feature = "stable-cache")]
+pub use cache::{SignerMetadata, StableSignerCache};
+pub use health::SignerHealth;
 pub use signer::*;
 pub use utils::*;
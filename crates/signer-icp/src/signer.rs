@@ -4,7 +4,9 @@ use std::fmt;
 
 use crate::{
     ecdsa_key_id,
+    health::HealthRecorder,
     utils::{address_for_public_key, get_public_key, y_parity},
+    SignerHealth,
 };
 use alloy_consensus::SignableTransaction;
 use alloy_primitives::{hex, Address, ChainId, B256};
@@ -24,6 +26,7 @@ pub struct IcpSigner {
     public_key: Vec<u8>,
     address: Address,
     chain_id: Option<ChainId>,
+    health: HealthRecorder,
 }
 
 impl fmt::Debug for IcpSigner {
@@ -103,17 +106,84 @@ impl IcpSigner {
         let key_id = ecdsa_key_id(ecdsa_key_name);
         let public_key = get_public_key(&derivation_path, &key_id).await?;
         let address = address_for_public_key(&public_key).await?;
-        Ok(Self { derivation_path, key_id, public_key, address, chain_id })
+        Ok(Self {
+            derivation_path,
+            key_id,
+            public_key,
+            address,
+            chain_id,
+            health: HealthRecorder::default(),
+        })
     }
 
-    async fn sign_hash_inner(&self, hash: &B256) -> Result<Signature> {
-        let (signature_response,) = sign_with_ecdsa(SignWithEcdsaArgument {
+    /// Instantiate a new signer instance, caching its public key and address in stable memory.
+    ///
+    /// The public key is only requested from the management canister if it is not cached yet
+    /// for the key and derivation path, e.g. on the first call after the canister is installed.
+    /// After an upgrade, the signer is available without any round trip.
+    ///
+    /// Takes the same arguments as [`IcpSigner::new`], and a [`StableSignerCache`] owned by a
+    /// thread local.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// thread_local! {
+    ///     static SIGNERS: RefCell<StableSignerCache<VirtualMemory<DefaultMemoryImpl>>> =
+    ///         RefCell::new(StableBTreeMap::init(
+    ///             MEMORY_MANAGER.with_borrow(|m| m.get(MemoryId::new(1))),
+    ///         ));
+    /// }
+    ///
+    /// let signer = IcpSigner::new_cached(derivation_path, "key_1", None, &SIGNERS).await?;
+    /// ```
+    ///
+    /// [`StableSignerCache`]: crate::StableSignerCache
+    #[cfg(feature = "stable-cache")]
+    pub async fn new_cached<M: ic_stable_structures::Memory>(
+        derivation_path: Vec<Vec<u8>>,
+        ecdsa_key_name: &str,
+        chain_id: Option<ChainId>,
+        cache: &'static std::thread::LocalKey<std::cell::RefCell<crate::StableSignerCache<M>>>,
+    ) -> Result<Self, IcpSignerError> {
+        let key_id = ecdsa_key_id(ecdsa_key_name);
+        let metadata = match crate::cache::get(cache, &key_id, &derivation_path) {
+            Some(metadata) => metadata,
+            None => {
+                let public_key = get_public_key(&derivation_path, &key_id).await?;
+                let address = address_for_public_key(&public_key).await?;
+                let metadata = crate::SignerMetadata { public_key, address };
+                crate::cache::insert(cache, &key_id, &derivation_path, metadata.clone());
+                metadata
+            }
+        };
+        let crate::SignerMetadata { public_key, address } = metadata;
+        Ok(Self {
+            derivation_path,
+            key_id,
+            public_key,
+            address,
+            chain_id,
+            health: HealthRecorder::default(),
+        })
+    }
+
+    async fn sign_hash_inner(&self, hash: &B256) -> Result<Signature, IcpSignerError> {
+        let signed = sign_with_ecdsa(SignWithEcdsaArgument {
             message_hash: hash.to_vec(),
             derivation_path: self.derivation_path.clone(),
             key_id: self.key_id.clone(),
         })
-        .await
-        .unwrap();
+        .await;
+        let (signature_response,) = match signed {
+            Ok(response) => response,
+            Err((code, message)) => {
+                let err = IcpSignerError::IcpCall(code, message);
+                self.health.record_failure(err.to_string());
+                return Err(err);
+            }
+        };
+        self.health.record_signature();
 
         Ok(Signature::from_bytes_and_parity(
             &signature_response.signature,
@@ -122,6 +192,15 @@ impl IcpSigner {
         .unwrap())
     }
 
+    /// Returns the signing health of the signer, shared by all its clones.
+    pub fn health(&self) -> SignerHealth {
+        SignerHealth {
+            address: self.address.to_checksum(None),
+            key_name: self.key_id.name.clone(),
+            ..self.health.snapshot()
+        }
+    }
+
     /// SEC1 encoded ECDSA public key for current canister using the given derivation path and key id.
     pub const fn public_key(&self) -> &Vec<u8> {
         &self.public_key
@@ -0,0 +1,84 @@
+use crate::{buffer::PooledBuffer, RequestResult, RpcService, CANISTER_ID};
+use ic_cdk::api::call::{call_raw128, CallResult, RejectionCode};
+use std::sync::Arc;
+
+/// The Candid encoded arguments of the EVM RPC canister `request` method, with the encoding of
+/// the [`RpcService`] computed once and reused for every request.
+///
+/// The arguments are encoded as a header with the argument types, followed by the argument
+/// values in order. Only the JSON-RPC payload and the max response size change between requests,
+/// as the payload holds a new request ID even for identical recurring calls, so the header and
+/// the service are encoded once and the last two values are appended for each request.
+#[derive(Clone, Debug)]
+pub(crate) struct RequestArgs {
+    prefix: Arc<[u8]>,
+}
+
+impl RequestArgs {
+    /// Encode the arguments up to the payload for the given service.
+    pub(crate) fn new(rpc_service: &RpcService) -> Self {
+        let mut encoded =
+            candid::encode_args((rpc_service, "", 0u64)).expect("Failed to encode arguments.");
+        // An empty text is encoded as its length, a single byte, and a nat64 as 8 bytes.
+        encoded.truncate(encoded.len() - 1 - 8);
+        Self { prefix: encoded.into() }
+    }
+
+    /// Encode the arguments of a request with the given JSON payload and max response size.
+    pub(crate) fn encode(&self, payload: &[u8], max_response_size: u64) -> PooledBuffer {
+        let mut encoded = PooledBuffer::take();
+        encoded.reserve(self.prefix.len() + 10 + payload.len() + 8);
+        encoded.extend_from_slice(&self.prefix);
+        let mut len = payload.len() as u64;
+        loop {
+            let byte = (len & 0x7f) as u8;
+            len >>= 7;
+            if len == 0 {
+                encoded.push(byte);
+                break;
+            }
+            encoded.push(byte | 0x80);
+        }
+        encoded.extend_from_slice(payload);
+        encoded.extend_from_slice(&max_response_size.to_le_bytes());
+        encoded
+    }
+
+    /// Call the `request` method of the EVM RPC canister with encoded arguments.
+    pub(crate) async fn call(
+        args: PooledBuffer,
+        call_cycles: u128,
+    ) -> CallResult<(RequestResult,)> {
+        let bytes = call_raw128(CANISTER_ID, "request", args, call_cycles).await?;
+        candid::decode_args(&bytes).map_err(|err| {
+            (RejectionCode::CanisterError, format!("failed to decode canister response: {err}"))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EthSepoliaService, HttpHeader, RpcApi};
+
+    #[test]
+    fn matches_full_encoding() {
+        let services = [
+            RpcService::EthSepolia(EthSepoliaService::Alchemy),
+            RpcService::Custom(RpcApi {
+                url: "https://example.com".into(),
+                headers: Some(vec![HttpHeader { name: "x".into(), value: "y".into() }]),
+            }),
+        ];
+        let long_payload = "x".repeat(300);
+        for service in services {
+            let args = RequestArgs::new(&service);
+            for payload in
+                ["", r#"{"jsonrpc":"2.0","id":1,"method":"eth_blockNumber"}"#, &long_payload]
+            {
+                let expected = candid::encode_args((&service, payload, 2_000u64)).unwrap();
+                assert_eq!(*args.encode(payload.as_bytes(), 2_000), expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use crate::{coalesce::SharedResponse, RequestContext};
+use alloy_json_rpc::{Id, RequestPacket, ResponsePacket, SerializedRequest};
+use alloy_transport::{TransportErrorKind, TransportResult};
+use futures::channel::oneshot;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Maximum number of requests sent in a single batch. Providers limit the size of batches,
+/// commonly to 100 requests or more.
+const MAX_BATCH_SIZE: usize = 100;
+
+/// Requests queued to be sent together, made in the same [`RequestContext`].
+#[derive(Debug)]
+pub(crate) struct Batch {
+    context: RequestContext,
+    requests: Vec<SerializedRequest>,
+    waiters: Waiters,
+}
+
+impl Batch {
+    /// Returns the context the requests were made in, the packet to send, a single request if
+    /// the batch holds only one, and the callers waiting for the response.
+    pub(crate) fn into_parts(self) -> (RequestContext, RequestPacket, Waiters) {
+        let Self { context, mut requests, waiters } = self;
+        let packet = if requests.len() == 1 {
+            RequestPacket::Single(requests.remove(0))
+        } else {
+            RequestPacket::Batch(requests)
+        };
+        (context, packet, waiters)
+    }
+}
+
+/// The callers waiting for the responses of a batch, with the IDs of their requests.
+#[derive(Debug, Default)]
+pub(crate) struct Waiters(Vec<(Id, oneshot::Sender<SharedResponse>)>);
+
+impl Waiters {
+    /// Hand the response of each request to its caller. Requests missing from a successful
+    /// response fail, and all requests share the error of a failed one.
+    pub(crate) fn complete(self, result: &TransportResult<ResponsePacket>) {
+        let mut payloads: HashMap<_, _> = match result {
+            Ok(ResponsePacket::Single(response)) => {
+                HashMap::from([(response.id.clone(), response.payload.clone())])
+            }
+            Ok(ResponsePacket::Batch(responses)) => responses
+                .iter()
+                .map(|response| (response.id.clone(), response.payload.clone()))
+                .collect(),
+            Err(_) => HashMap::new(),
+        };
+        for (id, waiter) in self.0 {
+            let response = match (result, payloads.remove(&id)) {
+                (Err(_), _) => SharedResponse::new(result),
+                (Ok(_), Some(payload)) => SharedResponse::Payload(payload),
+                (Ok(_), None) => {
+                    SharedResponse::Other(format!("missing response for request {id}"))
+                }
+            };
+            let _ = waiter.send(response);
+        }
+    }
+}
+
+/// Requests queued for the next batches, and whether they are scheduled to be sent.
+#[derive(Debug, Default)]
+struct Queue {
+    scheduled: bool,
+    batches: Vec<Batch>,
+}
+
+/// Queues single requests so that requests made together are sent as JSON-RPC batches.
+///
+/// Shared between all clones of a transport.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RequestBatcher(Arc<Mutex<Queue>>);
+
+impl RequestBatcher {
+    /// Queue a request for the next batches. Returns `true` if the batches must be scheduled to
+    /// be sent, i.e. if this is the first request queued since they were last taken.
+    ///
+    /// Requests are only batched with requests made in the same context, and with unique IDs,
+    /// as requests made by different clients may share IDs.
+    pub(crate) fn push(
+        &self,
+        context: RequestContext,
+        request: SerializedRequest,
+    ) -> (BatchedRequest, bool) {
+        let mut queue = self.0.lock().unwrap();
+        let id = request.id().clone();
+        let (tx, rx) = oneshot::channel();
+        let batch = queue.batches.iter_mut().find(|batch| {
+            batch.context == context
+                && batch.requests.len() < MAX_BATCH_SIZE
+                && batch.requests.iter().all(|req| *req.id() != id)
+        });
+        match batch {
+            Some(batch) => {
+                batch.requests.push(request);
+                batch.waiters.0.push((id.clone(), tx));
+            }
+            None => queue.batches.push(Batch {
+                context,
+                requests: vec![request],
+                waiters: Waiters(vec![(id.clone(), tx)]),
+            }),
+        }
+        let schedule = !std::mem::replace(&mut queue.scheduled, true);
+        (BatchedRequest { id, rx }, schedule)
+    }
+
+    /// Take the queued batches, to send them.
+    pub(crate) fn take(&self) -> Vec<Batch> {
+        let mut queue = self.0.lock().unwrap();
+        queue.scheduled = false;
+        std::mem::take(&mut queue.batches)
+    }
+}
+
+/// A request waiting for its response in a batch.
+#[derive(Debug)]
+pub(crate) struct BatchedRequest {
+    id: Id,
+    rx: oneshot::Receiver<SharedResponse>,
+}
+
+impl BatchedRequest {
+    /// Wait for the response of the batch, addressed to this request's ID.
+    pub(crate) async fn wait(self) -> TransportResult<ResponsePacket> {
+        match self.rx.await {
+            Ok(response) => response.into_result(self.id),
+            Err(_) => Err(TransportErrorKind::custom_str("batched request was cancelled")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_json_rpc::Request;
+    use futures::executor::block_on;
+
+    fn request(method: &'static str, id: u64) -> SerializedRequest {
+        Request::new(method, Id::Number(id), ()).serialize().unwrap()
+    }
+
+    #[test]
+    fn requests_made_together_share_a_batch() {
+        let batcher = RequestBatcher::default();
+        let (chain_id, schedule) = batcher.push(RequestContext::new(), request("eth_chainId", 1));
+        assert!(schedule);
+        let (gas_price, schedule) = batcher.push(RequestContext::new(), request("eth_gasPrice", 2));
+        assert!(!schedule);
+        // A duplicate ID or another context starts another batch.
+        let (_, _) = batcher.push(RequestContext::new(), request("eth_blockNumber", 1));
+        let context = RequestContext::new().with_retry(1);
+        let (_, _) = batcher.push(context, request("eth_blockNumber", 3));
+
+        let mut batches = batcher.take();
+        assert_eq!(batches.len(), 3);
+        assert!(batcher.take().is_empty());
+        let (_, packet, waiters) = batches.remove(0).into_parts();
+        let RequestPacket::Batch(requests) = &packet else { panic!("expected a batch") };
+        assert_eq!(requests.len(), 2);
+
+        let response: ResponsePacket = serde_json::from_str(
+            r#"[{"jsonrpc":"2.0","id":2,"result":"0x1"},{"jsonrpc":"2.0","id":1,"result":"0xaa36a7"}]"#,
+        )
+        .unwrap();
+        waiters.complete(&Ok(response));
+        let ResponsePacket::Single(response) = block_on(chain_id.wait()).unwrap() else {
+            panic!("expected a single response")
+        };
+        assert_eq!(response.payload.as_success().unwrap().get(), r#""0xaa36a7""#);
+        assert!(block_on(gas_price.wait()).is_ok());
+    }
+
+    #[test]
+    fn failed_batches_fail_every_request() {
+        let batcher = RequestBatcher::default();
+        let (first, _) = batcher.push(RequestContext::new(), request("eth_chainId", 1));
+        let (second, _) = batcher.push(RequestContext::new(), request("eth_gasPrice", 2));
+        let (_, _, waiters) = batcher.take().remove(0).into_parts();
+        waiters.complete(&Err(TransportErrorKind::backend_gone()));
+        assert!(block_on(first.wait()).is_err());
+        assert!(block_on(second.wait()).is_err());
+    }
+}
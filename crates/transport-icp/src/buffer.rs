@@ -0,0 +1,89 @@
+use std::{
+    cell::RefCell,
+    ops::{Deref, DerefMut},
+};
+
+/// The maximum number of buffers kept in the pool.
+const MAX_POOLED_BUFFERS: usize = 8;
+
+/// The maximum capacity of a buffer returned to the pool, so a single large request does not
+/// keep its memory for the lifetime of the canister.
+const MAX_POOLED_CAPACITY: usize = 64 * 1024;
+
+thread_local! {
+    static POOL: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A byte buffer taken from a thread-local pool and returned to it when dropped.
+///
+/// The JSON-RPC payload and the Candid encoded arguments of every outcall are written to pooled
+/// buffers, so frequent requests, such as those of pollers, reuse the same allocations instead of
+/// allocating and growing fresh buffers for every call.
+#[derive(Debug, Default)]
+pub(crate) struct PooledBuffer(Vec<u8>);
+
+impl PooledBuffer {
+    /// Take an empty buffer from the pool, or create one if the pool is empty.
+    pub(crate) fn take() -> Self {
+        Self(POOL.with_borrow_mut(Vec::pop).unwrap_or_default())
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        let mut buffer = std::mem::take(&mut self.0);
+        if buffer.capacity() == 0 || buffer.capacity() > MAX_POOLED_CAPACITY {
+            return;
+        }
+        buffer.clear();
+        POOL.with_borrow_mut(|pool| {
+            if pool.len() < MAX_POOLED_BUFFERS {
+                pool.push(buffer);
+            }
+        });
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl AsRef<[u8]> for PooledBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_are_reused() {
+        let mut buffer = PooledBuffer::take();
+        buffer.extend_from_slice(&[1; 100]);
+        let ptr = buffer.as_ptr();
+        drop(buffer);
+
+        let buffer = PooledBuffer::take();
+        assert!(buffer.is_empty());
+        assert!(buffer.capacity() >= 100);
+        assert_eq!(buffer.as_ptr(), ptr);
+
+        // Large buffers are not kept.
+        let mut large = PooledBuffer::take();
+        large.reserve(MAX_POOLED_CAPACITY + 1);
+        drop(large);
+        assert!(PooledBuffer::take().capacity() <= MAX_POOLED_CAPACITY);
+    }
+}
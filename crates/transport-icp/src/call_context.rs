@@ -0,0 +1,45 @@
+use alloy_json_rpc::RequestPacket;
+
+/// Error returned when a request is made from a call context that can't make outcalls.
+///
+/// Calls to the EVM RPC canister, like any inter-canister call, can only be made in replicated
+/// execution: from update methods and timers. Requests made from a query are rejected locally
+/// with this error, instead of failing with a system error of the call.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("`{method}` was requested from a query, outcalls can only be made from update methods and timers")]
+pub struct WrongCallContext {
+    method: String,
+}
+
+impl WrongCallContext {
+    /// Returns the method of the rejected request, the first method of a rejected batch.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+}
+
+/// Returns `true` if the current call context can make calls to other canisters.
+///
+/// Outside of WebAssembly, e.g. in unit tests, there is no call context and this always returns
+/// `true`.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn can_make_calls() -> bool {
+    ic_cdk::api::in_replicated_execution()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const fn can_make_calls() -> bool {
+    true
+}
+
+/// Check that the request is made from a call context that can make outcalls.
+pub(crate) fn check(request_packet: &RequestPacket) -> Result<(), WrongCallContext> {
+    if can_make_calls() {
+        return Ok(());
+    }
+    let method = match request_packet {
+        RequestPacket::Single(req) => req.method(),
+        RequestPacket::Batch(reqs) => reqs.first().map_or("", |req| req.method()),
+    };
+    Err(WrongCallContext { method: method.to_string() })
+}
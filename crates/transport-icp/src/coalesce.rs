@@ -0,0 +1,215 @@
+use crate::EvmRpcError;
+use alloy_json_rpc::{ErrorPayload, Id, RequestPacket, Response, ResponsePacket, ResponsePayload};
+use alloy_transport::{TransportError, TransportErrorKind, TransportResult};
+use futures::channel::oneshot;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Methods that must never share a response with another caller, because they have side
+/// effects or return state that is consumed by the call.
+const NON_COALESCABLE_METHODS: &[&str] = &[
+    "eth_sendRawTransaction",
+    "eth_sendTransaction",
+    "eth_newFilter",
+    "eth_newBlockFilter",
+    "eth_newPendingTransactionFilter",
+    "eth_getFilterChanges",
+    "eth_uninstallFilter",
+    "eth_subscribe",
+    "eth_unsubscribe",
+];
+
+/// Identifies requests that are interchangeable: same method and same params.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct RequestKey {
+    method: String,
+    params: Option<String>,
+}
+
+impl RequestKey {
+    /// Returns the key of a single, coalescable request.
+    fn for_packet(request_packet: &RequestPacket) -> Option<Self> {
+        let RequestPacket::Single(req) = request_packet else { return None };
+        if NON_COALESCABLE_METHODS.contains(&req.method()) {
+            return None;
+        }
+        Some(Self {
+            method: req.method().to_string(),
+            params: req.params().map(|p| p.get().into()),
+        })
+    }
+}
+
+/// A cloneable copy of the outcome of a request, handed out to every waiting caller.
+#[derive(Clone, Debug)]
+pub(crate) enum SharedResponse {
+    Payload(ResponsePayload),
+    ErrorResp(ErrorPayload),
+    EvmRpc(EvmRpcError),
+    Other(String),
+}
+
+impl SharedResponse {
+    pub(crate) fn new(result: &TransportResult<ResponsePacket>) -> Self {
+        match result {
+            Ok(ResponsePacket::Single(response)) => Self::Payload(response.payload.clone()),
+            Ok(ResponsePacket::Batch(_)) => Self::Other("unexpected batch response".into()),
+            Err(TransportError::ErrorResp(err)) => Self::ErrorResp(err.clone()),
+            Err(err) => EvmRpcError::from_transport_error(err)
+                .map_or_else(|| Self::Other(err.to_string()), |err| Self::EvmRpc(err.clone())),
+        }
+    }
+
+    pub(crate) fn into_result(self, id: Id) -> TransportResult<ResponsePacket> {
+        match self {
+            Self::Payload(payload) => Ok(ResponsePacket::Single(Response { id, payload })),
+            Self::ErrorResp(err) => Err(TransportError::ErrorResp(err)),
+            Self::EvmRpc(err) => Err(TransportErrorKind::custom(err)),
+            Self::Other(err) => Err(TransportErrorKind::custom_str(&err)),
+        }
+    }
+}
+
+type Waiters = HashMap<RequestKey, Vec<oneshot::Sender<SharedResponse>>>;
+
+/// Tracks in-flight requests so that identical concurrent requests share a single outcall.
+///
+/// Shared between all clones of a transport.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct InFlightRequests(Arc<Mutex<Waiters>>);
+
+/// The role of a request after joining the [`InFlightRequests`].
+#[derive(Debug)]
+pub(crate) enum Joined {
+    /// No identical request is in flight: this request must be sent, and its outcome shared.
+    Leader(Leader),
+    /// An identical request is in flight: this request waits for its outcome.
+    Follower(Follower),
+}
+
+impl InFlightRequests {
+    /// Join the in-flight requests, or return `None` if the request cannot be coalesced.
+    pub(crate) fn join(&self, request_packet: &RequestPacket) -> Option<Joined> {
+        let key = RequestKey::for_packet(request_packet)?;
+        let mut waiters = self.0.lock().unwrap();
+        if let Some(followers) = waiters.get_mut(&key) {
+            let (tx, rx) = oneshot::channel();
+            followers.push(tx);
+            let RequestPacket::Single(req) = request_packet else { unreachable!() };
+            return Some(Joined::Follower(Follower { id: req.id().clone(), rx }));
+        }
+        waiters.insert(key.clone(), Vec::new());
+        Some(Joined::Leader(Leader { in_flight: self.clone(), key: Some(key) }))
+    }
+}
+
+/// The request whose outcall is shared. Dropping it without calling [`Leader::complete`]
+/// releases all followers with an error.
+#[derive(Debug)]
+pub(crate) struct Leader {
+    in_flight: InFlightRequests,
+    key: Option<RequestKey>,
+}
+
+impl Leader {
+    /// Share the outcome of the outcall with all followers.
+    pub(crate) fn complete(mut self, result: &TransportResult<ResponsePacket>) {
+        let followers = self.take_followers();
+        if followers.is_empty() {
+            return;
+        }
+        let response = SharedResponse::new(result);
+        for follower in followers {
+            let _ = follower.send(response.clone());
+        }
+    }
+
+    fn take_followers(&mut self) -> Vec<oneshot::Sender<SharedResponse>> {
+        self.key
+            .take()
+            .and_then(|key| self.in_flight.0.lock().unwrap().remove(&key))
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        // Dropping the senders cancels the followers.
+        self.take_followers();
+    }
+}
+
+/// A request waiting for the outcome of an identical in-flight request.
+#[derive(Debug)]
+pub(crate) struct Follower {
+    id: Id,
+    rx: oneshot::Receiver<SharedResponse>,
+}
+
+impl Follower {
+    /// Wait for the shared outcome, addressed to this request's ID.
+    pub(crate) async fn wait(self) -> TransportResult<ResponsePacket> {
+        match self.rx.await {
+            Ok(response) => response.into_result(self.id),
+            Err(_) => Err(TransportErrorKind::custom_str("coalesced request was cancelled")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_json_rpc::Request;
+    use futures::executor::block_on;
+
+    fn request(method: &'static str, id: u64, params: u64) -> RequestPacket {
+        Request::new(method, Id::Number(id), (params,)).serialize().unwrap().into()
+    }
+
+    #[test]
+    fn followers_receive_the_leader_response() {
+        let in_flight = InFlightRequests::default();
+        let Some(Joined::Leader(leader)) = in_flight.join(&request("eth_getBalance", 1, 7)) else {
+            panic!("expected leader")
+        };
+        let Some(Joined::Follower(follower)) = in_flight.join(&request("eth_getBalance", 2, 7))
+        else {
+            panic!("expected follower")
+        };
+        // Different params are not coalesced.
+        assert!(matches!(
+            in_flight.join(&request("eth_getBalance", 3, 8)),
+            Some(Joined::Leader(_))
+        ));
+
+        let payload = ResponsePayload::Success(serde_json::value::to_raw_value("0x2a").unwrap());
+        leader.complete(&Ok(ResponsePacket::Single(Response { id: Id::Number(1), payload })));
+
+        let ResponsePacket::Single(response) = block_on(follower.wait()).unwrap() else {
+            panic!("expected single response")
+        };
+        assert_eq!(response.id, Id::Number(2));
+        assert_eq!(response.payload.as_success().unwrap().get(), "\"0x2a\"");
+    }
+
+    #[test]
+    fn dropped_leader_cancels_followers() {
+        let in_flight = InFlightRequests::default();
+        let leader = in_flight.join(&request("eth_chainId", 1, 0));
+        let Some(Joined::Follower(follower)) = in_flight.join(&request("eth_chainId", 2, 0)) else {
+            panic!("expected follower")
+        };
+        drop(leader);
+        assert!(block_on(follower.wait()).is_err());
+        assert!(matches!(in_flight.join(&request("eth_chainId", 3, 0)), Some(Joined::Leader(_))));
+    }
+
+    #[test]
+    fn stateful_methods_are_not_coalesced() {
+        let in_flight = InFlightRequests::default();
+        assert!(in_flight.join(&request("eth_getFilterChanges", 1, 0)).is_none());
+        assert!(in_flight.join(&request("eth_sendRawTransaction", 2, 0)).is_none());
+    }
+}
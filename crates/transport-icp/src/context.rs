@@ -0,0 +1,347 @@
+use crate::{CyclesMeter, MethodPolicy};
+use pin_project::pin_project;
+use std::{
+    cell::RefCell,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+thread_local! {
+    static CURRENT: RefCell<RequestContext> = const { RefCell::new(RequestContext::new()) };
+}
+
+/// The priority of a request, used to order requests whose dispatch is delayed.
+///
+/// Ordered from lowest to highest, so `Interactive > Polling > Background`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RequestPriority {
+    /// Background work that can wait, such as indexing historical logs.
+    Background,
+    /// Periodic polling, such as watching blocks or waiting for confirmations.
+    Polling,
+    /// Requests made on behalf of a user call. This is the default.
+    #[default]
+    Interactive,
+}
+
+/// Identifies a logical operation, such as sending a transaction and waiting for its receipt,
+/// across all the requests it makes.
+///
+/// Trace IDs are generated from a counter, so they are unique within a canister until it is
+/// upgraded or reinstalled. They are displayed as 16 hex digits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TraceId(u64);
+
+impl TraceId {
+    /// Generate a new trace ID.
+    pub fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Returns the trace ID as a number.
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for TraceId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<u64> for TraceId {
+    fn from(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Per-request information that is passed from the caller to the [`IcpTransport`] and the
+/// ICP transport layers.
+///
+/// JSON-RPC requests don't carry any out-of-band data, so the context is installed
+/// ambiently for the duration of a [`tower::Service::call`] using [`RequestContext::enter`],
+/// or for every poll of a future using [`RequestContext::scope`]. Canisters are single
+/// threaded and the transport reads the context synchronously when a request is dispatched,
+/// so the context a request sees is always the one of its caller.
+///
+/// # Examples
+///
+/// ```ignore
+/// let context = RequestContext::new().with_priority(RequestPriority::Background);
+/// let logs: Vec<Log> = context.scope(provider.get_logs(&filter)).await?;
+///
+/// // Tag all requests of a logical operation with the same trace ID.
+/// let receipt = RequestContext::traced()
+///     .scope(async { provider.send_raw_transaction(&tx).await?.get_receipt().await })
+///     .await?;
+/// ```
+///
+/// [`IcpTransport`]: crate::IcpTransport
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RequestContext {
+    retry: u32,
+    priority: RequestPriority,
+    method_policy: Option<Arc<MethodPolicy>>,
+    trace_id: Option<TraceId>,
+    cycles_meter: Option<CyclesMeter>,
+    call_cycles: Option<u128>,
+    timeout: Option<Duration>,
+    non_replicated: bool,
+}
+
+impl RequestContext {
+    /// Create a new, empty request context.
+    pub const fn new() -> Self {
+        Self {
+            retry: 0,
+            priority: RequestPriority::Interactive,
+            method_policy: None,
+            trace_id: None,
+            cycles_meter: None,
+            call_cycles: None,
+            timeout: None,
+            non_replicated: false,
+        }
+    }
+
+    /// Create a new request context with a newly generated [`TraceId`].
+    pub fn traced() -> Self {
+        Self::new().with_trace_id(TraceId::new())
+    }
+
+    /// Returns the context of the request currently being dispatched.
+    pub fn current() -> Self {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Run `f` with `self` installed as the current context, restoring the previous context
+    /// afterwards.
+    pub fn enter<R>(self, f: impl FnOnce() -> R) -> R {
+        struct Restore(RequestContext);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let previous = std::mem::take(&mut self.0);
+                CURRENT.with(|current| *current.borrow_mut() = previous);
+            }
+        }
+
+        let _restore = Restore(CURRENT.with(|current| current.replace(self)));
+        f()
+    }
+
+    /// Wrap a future so that `self` is installed as the current context whenever it is polled.
+    ///
+    /// Use this to set the context of requests made by an `async` operation, e.g. an
+    /// [`RpcCall`] or a provider method.
+    ///
+    /// [`RpcCall`]: https://docs.rs/alloy-rpc-client/latest/alloy_rpc_client/struct.RpcCall.html
+    pub const fn scope<F: Future>(self, future: F) -> Scoped<F> {
+        Scoped { context: self, future }
+    }
+
+    /// Returns the retry number of the request, `0` for the initial attempt.
+    pub const fn retry(&self) -> u32 {
+        self.retry
+    }
+
+    /// Returns `true` if the request is a retry of a previously failed request.
+    pub const fn is_retry(&self) -> bool {
+        self.retry > 0
+    }
+
+    /// Set the retry number of the request.
+    pub const fn with_retry(mut self, retry: u32) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Returns the priority of the request.
+    pub const fn priority(&self) -> RequestPriority {
+        self.priority
+    }
+
+    /// Set the priority of the request.
+    pub const fn with_priority(mut self, priority: RequestPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Returns the trace ID of the request, if any.
+    pub const fn trace_id(&self) -> Option<TraceId> {
+        self.trace_id
+    }
+
+    /// Set the trace ID of the request.
+    pub const fn with_trace_id(mut self, trace_id: TraceId) -> Self {
+        self.trace_id = Some(trace_id);
+        self
+    }
+
+    /// Returns the method policy of the request, if any.
+    pub fn method_policy(&self) -> Option<&MethodPolicy> {
+        self.method_policy.as_deref()
+    }
+
+    /// Set a method policy for the request, in addition to the policy of the transport.
+    pub fn with_method_policy(mut self, method_policy: MethodPolicy) -> Self {
+        self.method_policy = Some(Arc::new(method_policy));
+        self
+    }
+
+    /// Returns the cycles meter of the request, if any.
+    pub const fn cycles_meter(&self) -> Option<&CyclesMeter> {
+        self.cycles_meter.as_ref()
+    }
+
+    /// Set a [`CyclesMeter`] that records the cycles spent on the request.
+    pub fn with_cycles_meter(mut self, cycles_meter: CyclesMeter) -> Self {
+        self.cycles_meter = Some(cycles_meter);
+        self
+    }
+
+    /// Returns the cycles attached to the calls of the request, if set.
+    pub const fn call_cycles(&self) -> Option<u128> {
+        self.call_cycles
+    }
+
+    /// Attach fixed cycles to the calls of the request, overriding the call cycles of the
+    /// transport and its [`CyclesEstimator`](crate::CyclesEstimator), e.g. for an
+    /// `eth_getLogs` over a large range of blocks. Cycles not spent by a call are refunded.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let logs: Vec<Log> = RequestContext::new()
+    ///     .with_call_cycles(50_000_000_000)
+    ///     .scope(provider.get_logs(&filter))
+    ///     .await?;
+    /// ```
+    pub const fn with_call_cycles(mut self, call_cycles: u128) -> Self {
+        self.call_cycles = Some(call_cycles);
+        self
+    }
+
+    /// Returns how long to wait for the request, if set.
+    pub const fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Set how long to wait for the request, overriding the timeout of the transport, see
+    /// [`IcpConfig::set_request_timeout`](crate::IcpConfig::set_request_timeout).
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Returns `true` if the outcalls of the request are made by a single replica.
+    pub const fn is_non_replicated(&self) -> bool {
+        self.non_replicated
+    }
+
+    /// Make the outcalls of the request with a single replica instead of every replica of the
+    /// subnet, which is faster and cheaper, but returns a response no other replica agreed on.
+    /// Only use it for reads that need no consensus, e.g. to display a balance.
+    ///
+    /// Only the [`IcpHttpTransport`](crate::IcpHttpTransport) makes non-replicated outcalls.
+    /// The EVM RPC canister always makes replicated outcalls, so the [`IcpTransport`] ignores
+    /// it.
+    ///
+    /// [`IcpTransport`]: crate::IcpTransport
+    pub const fn with_non_replicated(mut self, non_replicated: bool) -> Self {
+        self.non_replicated = non_replicated;
+        self
+    }
+}
+
+/// A future with a [`RequestContext`] installed while it is polled, see
+/// [`RequestContext::scope`].
+#[pin_project]
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Scoped<F> {
+    context: RequestContext,
+    #[pin]
+    future: F,
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let future = this.future;
+        this.context.clone().enter(|| future.poll(cx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enter_restores_previous_context() {
+        assert_eq!(RequestContext::current(), RequestContext::new());
+        RequestContext::new().with_retry(1).enter(|| {
+            assert_eq!(RequestContext::current().retry(), 1);
+            RequestContext::new().with_retry(2).enter(|| {
+                assert_eq!(RequestContext::current().retry(), 2);
+            });
+            assert_eq!(RequestContext::current().retry(), 1);
+        });
+        assert!(!RequestContext::current().is_retry());
+    }
+
+    #[test]
+    fn trace_ids_are_unique() {
+        let (a, b) = (TraceId::new(), TraceId::new());
+        assert_ne!(a, b);
+        assert_eq!(TraceId::from(0x2a).to_string(), "000000000000002a");
+        assert!(RequestContext::traced().trace_id().is_some());
+    }
+
+    #[test]
+    fn scope_installs_context_while_polling() {
+        let context = RequestContext::new().with_priority(RequestPriority::Background);
+        let priority = futures::executor::block_on(
+            context.scope(async { RequestContext::current().priority() }),
+        );
+        assert_eq!(priority, RequestPriority::Background);
+        assert_eq!(RequestContext::current().priority(), RequestPriority::Interactive);
+    }
+
+    #[test]
+    fn call_cycles_override_is_scoped() {
+        let context = RequestContext::new().with_call_cycles(1_000);
+        let cycles = futures::executor::block_on(
+            context.scope(async { RequestContext::current().call_cycles() }),
+        );
+        assert_eq!(cycles, Some(1_000));
+        assert_eq!(RequestContext::current().call_cycles(), None);
+    }
+
+    #[test]
+    fn non_replicated_is_scoped() {
+        let context = RequestContext::new().with_non_replicated(true);
+        let non_replicated = futures::executor::block_on(
+            context.scope(async { RequestContext::current().is_non_replicated() }),
+        );
+        assert!(non_replicated);
+        assert!(!RequestContext::current().is_non_replicated());
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{estimate_max_response_size, CyclesEstimator, IcpTransport};
+use candid::CandidType;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// The size of a typical request, in bytes: the JSON-RPC envelope and a few params.
+const DEFAULT_REQUEST_BYTES: u64 = 256;
+
+/// The planned calls of a single JSON-RPC method, see [`Workload`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct MethodWorkload {
+    /// The number of calls per hour.
+    pub calls_per_hour: u64,
+    /// The size of a request, in bytes. 256 bytes if unset.
+    pub request_bytes: Option<u64>,
+}
+
+/// A planned workload, as the number of calls per JSON-RPC method per hour.
+///
+/// A poller polling every 12 seconds makes 300 calls per hour.
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize)]
+pub struct Workload {
+    /// The planned calls, by method.
+    pub methods: BTreeMap<String, MethodWorkload>,
+}
+
+impl Workload {
+    /// Create an empty workload.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add calls to the given method, with requests of a typical size.
+    pub fn with_calls(self, method: impl Into<String>, calls_per_hour: u64) -> Self {
+        self.with_method(method, MethodWorkload { calls_per_hour, request_bytes: None })
+    }
+
+    /// Add calls to the given method, replacing any calls previously added for it.
+    pub fn with_method(mut self, method: impl Into<String>, workload: MethodWorkload) -> Self {
+        self.methods.insert(method.into(), workload);
+        self
+    }
+}
+
+/// The projected cost of the calls of a single JSON-RPC method, see [`CostProjection`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, CandidType, Deserialize)]
+pub struct MethodCost {
+    /// The number of calls per day.
+    pub calls_per_day: u64,
+    /// The cycles spent per call.
+    pub cycles_per_call: u128,
+    /// The cycles attached per call, including the safety margin. Attached cycles that are not
+    /// spent are refunded, but the canister must hold them while the call is in flight.
+    pub attached_per_call: u128,
+    /// The cycles spent per day.
+    pub cycles_per_day: u128,
+}
+
+/// The projected cost of a [`Workload`], see [`CostModel::simulate`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize)]
+pub struct CostProjection {
+    /// The projected cost, by method.
+    pub methods: BTreeMap<String, MethodCost>,
+}
+
+impl CostProjection {
+    /// Returns the cycles spent per day on all methods combined.
+    pub fn cycles_per_day(&self) -> u128 {
+        self.methods.values().map(|cost| cost.cycles_per_day).sum()
+    }
+
+    /// Returns the cycles spent over the given number of days, e.g. to size the funding of a
+    /// canister.
+    pub fn cycles_for_days(&self, days: u64) -> u128 {
+        self.cycles_per_day().saturating_mul(days as u128)
+    }
+}
+
+/// Projects the cycles spent on a planned [`Workload`] without making any calls, so the funding
+/// of a canister can be sized before a polling-heavy feature is deployed.
+///
+/// The projection uses the pricing parameters of a [`CyclesEstimator`], and the max response
+/// size the transport would request for each method.
+///
+/// # Examples
+///
+/// ```ignore
+/// // Watch blocks every 12 seconds and fetch the logs of each new block.
+/// let workload = Workload::new().with_calls("eth_blockNumber", 300).with_calls("eth_getLogs", 300);
+/// let projection = CostModel::for_transport(&transport).simulate(&workload);
+/// ic_cdk::println!("{} cycles per month", projection.cycles_for_days(30));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CostModel {
+    estimator: CyclesEstimator,
+    max_response_size: Option<u64>,
+}
+
+impl CostModel {
+    /// Create a cost model with the given pricing parameters, estimating the max response size
+    /// per method.
+    pub const fn new(estimator: CyclesEstimator) -> Self {
+        Self { estimator, max_response_size: None }
+    }
+
+    /// Create a cost model with the pricing parameters and max response size of a transport.
+    pub const fn for_transport(transport: &IcpTransport) -> Self {
+        Self {
+            estimator: *transport.cycles_estimator(),
+            max_response_size: transport.max_response_size(),
+        }
+    }
+
+    /// Set a fixed max response size for every method, in bytes.
+    pub const fn with_max_response_size(mut self, max_response_size: u64) -> Self {
+        self.max_response_size = Some(max_response_size);
+        self
+    }
+
+    /// Returns the projected cost of a single call to the given method.
+    pub fn call_cost(&self, method: &str, request_bytes: u64) -> MethodCost {
+        let max_response_size =
+            self.max_response_size.unwrap_or_else(|| estimate_max_response_size(method));
+        let cycles_per_call = self.estimator.outcall_cost(request_bytes, max_response_size);
+        MethodCost {
+            calls_per_day: 1,
+            cycles_per_call,
+            attached_per_call: self.estimator.estimate(request_bytes, max_response_size),
+            cycles_per_day: cycles_per_call,
+        }
+    }
+
+    /// Project the cycles spent per day on the given workload.
+    pub fn simulate(&self, workload: &Workload) -> CostProjection {
+        let methods = workload
+            .methods
+            .iter()
+            .map(|(method, planned)| {
+                let request_bytes = planned.request_bytes.unwrap_or(DEFAULT_REQUEST_BYTES);
+                let calls_per_day = planned.calls_per_hour.saturating_mul(24);
+                let call = self.call_cost(method, request_bytes);
+                let cycles_per_day = call.cycles_per_call.saturating_mul(calls_per_day as u128);
+                (method.clone(), MethodCost { calls_per_day, cycles_per_day, ..call })
+            })
+            .collect();
+        CostProjection { methods }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn projects_cycles_per_day() {
+        let model = CostModel::new(CyclesEstimator::new().with_subnet_size(13));
+        let workload = Workload::new().with_calls("eth_blockNumber", 300).with_method(
+            "eth_getLogs",
+            MethodWorkload { calls_per_hour: 10, request_bytes: Some(512) },
+        );
+        let projection = model.simulate(&workload);
+
+        let block_number = projection.methods["eth_blockNumber"];
+        assert_eq!(block_number.calls_per_day, 7_200);
+        assert_eq!(
+            block_number.cycles_per_call,
+            model.call_cost("eth_blockNumber", 256).cycles_per_call
+        );
+        assert_eq!(block_number.cycles_per_day, block_number.cycles_per_call * 7_200);
+        assert!(block_number.attached_per_call > block_number.cycles_per_call);
+
+        // Logs are requested with a larger max response size.
+        let logs = projection.methods["eth_getLogs"];
+        assert!(logs.cycles_per_call > block_number.cycles_per_call);
+        assert_eq!(projection.cycles_per_day(), block_number.cycles_per_day + logs.cycles_per_day);
+        assert_eq!(projection.cycles_for_days(30), projection.cycles_per_day() * 30);
+    }
+}
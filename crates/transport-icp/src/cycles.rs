@@ -0,0 +1,605 @@
+use crate::RequestPriority;
+use candid::CandidType;
+use serde::Deserialize;
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+const DEFAULT_BUDGET_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The number of nodes of the fiduciary subnet, which hosts the EVM RPC canister.
+const DEFAULT_SUBNET_SIZE: u32 = 34;
+const DEFAULT_MARGIN_PERCENT: u32 = 20;
+
+/// The HTTPS outcall fees of the Internet Computer, see
+/// <https://internetcomputer.org/docs/current/developer-docs/gas-cost>.
+const HTTP_REQUEST_BASE_FEE: u128 = 3_000_000;
+const HTTP_REQUEST_PER_NODE_FEE: u128 = 60_000;
+const HTTP_REQUEST_PER_BYTE_FEE: u128 = 400;
+const HTTP_RESPONSE_PER_BYTE_FEE: u128 = 800;
+
+/// Estimates the cycles to attach to a call to the EVM RPC canister from the size of the
+/// request and the max response size, using the HTTPS outcall fees of the Internet Computer.
+///
+/// Outcalls are paid for per node of the subnet making them, and the cycles attached but not
+/// needed are refunded. The estimate is increased by a safety margin, to absorb fees of the
+/// EVM RPC canister itself and changes in the outcall fees. A call that is attached too few
+/// cycles fails with a `TooFewCycles` provider error, telling the expected cost: raise the
+/// margin if that happens.
+///
+/// An estimator is only used if no fixed call cycles are set, see
+/// [`IcpConfig::set_call_cycles`](crate::IcpConfig::set_call_cycles).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct CyclesEstimator {
+    /// The number of nodes of the subnet of the EVM RPC canister. 34 by default.
+    pub subnet_size: u32,
+    /// The safety margin added to the estimated cost, in percent. 20 by default.
+    pub margin_percent: u32,
+}
+
+impl Default for CyclesEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CyclesEstimator {
+    /// Create a new estimator for the subnet of the EVM RPC canister, with the default margin.
+    pub const fn new() -> Self {
+        Self { subnet_size: DEFAULT_SUBNET_SIZE, margin_percent: DEFAULT_MARGIN_PERCENT }
+    }
+
+    /// Set the number of nodes of the subnet of the EVM RPC canister.
+    pub const fn with_subnet_size(mut self, subnet_size: u32) -> Self {
+        self.subnet_size = subnet_size;
+        self
+    }
+
+    /// Set the safety margin added to the estimated cost, in percent.
+    pub const fn with_margin_percent(mut self, margin_percent: u32) -> Self {
+        self.margin_percent = margin_percent;
+        self
+    }
+
+    /// Returns the cost of an outcall with the given request and max response size, in bytes,
+    /// without margin.
+    pub const fn outcall_cost(&self, request_bytes: u64, max_response_bytes: u64) -> u128 {
+        let nodes = self.subnet_size as u128;
+        (HTTP_REQUEST_BASE_FEE + HTTP_REQUEST_PER_NODE_FEE * nodes) * nodes
+            + HTTP_REQUEST_PER_BYTE_FEE * nodes * request_bytes as u128
+            + HTTP_RESPONSE_PER_BYTE_FEE * nodes * max_response_bytes as u128
+    }
+
+    /// Returns the cycles to attach to a call with the given request and max response size, in
+    /// bytes, including the margin.
+    pub const fn estimate(&self, request_bytes: u64, max_response_bytes: u64) -> u128 {
+        let cost = self.outcall_cost(request_bytes, max_response_bytes);
+        cost + cost * self.margin_percent as u128 / 100
+    }
+}
+
+/// Cycles spent on calls to the EVM RPC canister.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, CandidType, Deserialize)]
+pub struct CallCycles {
+    /// Number of calls made. A batch is a single call.
+    pub calls: u64,
+    /// Cycles attached to the calls.
+    pub attached: u128,
+    /// Cycles refunded by the EVM RPC canister, i.e. the part of the attached cycles that was
+    /// not needed to pay for the outcalls.
+    pub refunded: u128,
+}
+
+impl CallCycles {
+    /// Returns the cycles actually spent, i.e. attached but not refunded.
+    pub const fn spent(&self) -> u128 {
+        self.attached.saturating_sub(self.refunded)
+    }
+}
+
+/// Accumulates the [`CallCycles`] of every request made with it in their [`RequestContext`].
+///
+/// Clones share the same counters, so a meter can be installed in the context of a user
+/// operation and read once the operation completes, to charge the exact RPC spend to the user.
+/// Requests that are coalesced with an identical in-flight request make no call of their own,
+/// and are not metered.
+///
+/// # Examples
+///
+/// ```ignore
+/// let meter = CyclesMeter::new();
+/// let balance = RequestContext::new()
+///     .with_cycles_meter(meter.clone())
+///     .scope(provider.get_balance(address))
+///     .await?;
+/// charge(caller, meter.get().spent());
+/// ```
+///
+/// [`RequestContext`]: crate::RequestContext
+#[derive(Clone, Debug, Default)]
+pub struct CyclesMeter(Arc<Mutex<CallCycles>>);
+
+impl CyclesMeter {
+    /// Create a new meter with all counters at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the cycles recorded so far.
+    pub fn get(&self) -> CallCycles {
+        *self.0.lock().unwrap()
+    }
+
+    /// Reset all counters to zero, returning the cycles recorded so far.
+    pub fn take(&self) -> CallCycles {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+
+    /// Add the cycles of one or more calls.
+    pub(crate) fn record(&self, call: CallCycles) {
+        let mut cycles = self.0.lock().unwrap();
+        cycles.calls += call.calls;
+        cycles.attached = cycles.attached.saturating_add(call.attached);
+        cycles.refunded = cycles.refunded.saturating_add(call.refunded);
+    }
+}
+
+impl PartialEq for CyclesMeter {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CyclesMeter {}
+
+/// Limits on the cycles an [`IcpTransport`] may spend, so that a misbehaving caller or a
+/// runaway poller cannot silently drain the canister.
+///
+/// Requests that would exceed the budget fail locally with a [`CyclesBudgetExceeded`] error,
+/// before any cycles are attached. The budget is checked when a request is dispatched, against
+/// the cycles attached to its call: refunds are only credited back once the call completes.
+///
+/// # Examples
+///
+/// ```ignore
+/// // Keep at least 1T cycles, and spend at most 500B cycles per day.
+/// let budget = CyclesBudget::new()
+///     .with_min_balance(1_000_000_000_000)
+///     .with_max_spend(500_000_000_000, Duration::from_secs(24 * 60 * 60));
+/// let config = IcpConfig::new(rpc_service).set_cycles_budget(budget);
+/// ```
+///
+/// [`IcpTransport`]: crate::IcpTransport
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct CyclesBudget {
+    /// The canister balance that calls may not bring the balance below. Unlimited if unset.
+    pub min_balance: Option<u128>,
+    /// The cycles that may be spent per period. Unlimited if unset.
+    pub max_spend: Option<u128>,
+    /// The length of a spend period, in seconds. A day by default.
+    pub period_secs: u64,
+    /// Whether requests with [`RequestPriority::Polling`] or [`RequestPriority::Background`]
+    /// are rejected too once the budget is exceeded, pausing pollers and watchers until the
+    /// next period or until the canister is topped up. Enabled by default.
+    ///
+    /// Disable this to keep pollers running, e.g. to watch for the confirmation of
+    /// transactions that have already been sent, and only reject interactive requests.
+    pub pause_pollers: bool,
+}
+
+impl Default for CyclesBudget {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CyclesBudget {
+    /// Create a new budget without any limits.
+    pub const fn new() -> Self {
+        Self {
+            min_balance: None,
+            max_spend: None,
+            period_secs: DEFAULT_BUDGET_PERIOD.as_secs(),
+            pause_pollers: true,
+        }
+    }
+
+    /// Set the canister balance that calls may not bring the balance below.
+    pub const fn with_min_balance(mut self, min_balance: u128) -> Self {
+        self.min_balance = Some(min_balance);
+        self
+    }
+
+    /// Set the cycles that may be spent per period of the given length.
+    pub const fn with_max_spend(mut self, max_spend: u128, period: Duration) -> Self {
+        self.max_spend = Some(max_spend);
+        self.period_secs = period.as_secs();
+        self
+    }
+
+    /// Set whether polling and background requests are rejected too once the budget is
+    /// exceeded. See [`CyclesBudget::pause_pollers`].
+    pub const fn with_pause_pollers(mut self, pause_pollers: bool) -> Self {
+        self.pause_pollers = pause_pollers;
+        self
+    }
+
+    /// Returns the length of a spend period.
+    pub const fn period(&self) -> Duration {
+        Duration::from_secs(self.period_secs)
+    }
+}
+
+/// Error returned when a request is rejected by a [`CyclesBudget`].
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum CyclesBudgetExceeded {
+    /// Attaching the cycles of the call would bring the canister balance below the minimum.
+    #[error("attaching {cycles} cycles would bring the balance of {balance} below the minimum of {min_balance}")]
+    MinBalance {
+        /// The cycles that would have been attached.
+        cycles: u128,
+        /// The canister balance.
+        balance: u128,
+        /// The minimum balance of the budget.
+        min_balance: u128,
+    },
+    /// Attaching the cycles of the call would exceed the spend of the current period.
+    #[error("attaching {cycles} cycles would exceed the budget of {max_spend} per period, {spent} already spent")]
+    MaxSpend {
+        /// The cycles that would have been attached.
+        cycles: u128,
+        /// The cycles spent in the current period, including calls still in flight.
+        spent: u128,
+        /// The maximum spend of the budget.
+        max_spend: u128,
+    },
+}
+
+/// A low cycles condition reported to a [`CyclesAlert`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LowCycles {
+    /// The canister balance dropped below the threshold.
+    Balance {
+        /// The canister balance.
+        balance: u128,
+        /// The balance threshold of the alert.
+        threshold: u128,
+    },
+    /// The cycles remaining in the current period of the [`CyclesBudget`] dropped below the
+    /// threshold.
+    RemainingBudget {
+        /// The cycles remaining in the current period.
+        remaining: u128,
+        /// The remaining budget threshold of the alert.
+        threshold: u128,
+    },
+}
+
+/// A hook called when the canister balance, or the cycles remaining in the current period of
+/// the [`CyclesBudget`], drop below a threshold, so the canister can be topped up before
+/// requests start failing.
+///
+/// The thresholds are checked whenever a call to the EVM RPC canister completes. The hook is
+/// called once when a threshold is crossed, and again only after the balance or the remaining
+/// budget has risen above the threshold, e.g. after a top-up or when a new period starts.
+///
+/// # Examples
+///
+/// ```ignore
+/// let alert = CyclesAlert::new(|low| {
+///     ic_cdk::println!("Low on cycles: {low:?}");
+///     // Notify a monitoring canister.
+///     ic_cdk::spawn(async move {
+///         let _: CallResult<()> = ic_cdk::call(MONITOR, "low_cycles", (format!("{low:?}"),)).await;
+///     });
+/// })
+/// .with_balance_threshold(2_000_000_000_000);
+/// let config = IcpConfig::new(rpc_service).set_cycles_alert(alert);
+/// ```
+#[derive(Clone)]
+pub struct CyclesAlert {
+    balance_threshold: Option<u128>,
+    remaining_budget_threshold: Option<u128>,
+    handler: Arc<AlertFn>,
+}
+
+type AlertFn = dyn Fn(LowCycles) + Send + Sync;
+
+impl fmt::Debug for CyclesAlert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CyclesAlert")
+            .field("balance_threshold", &self.balance_threshold)
+            .field("remaining_budget_threshold", &self.remaining_budget_threshold)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CyclesAlert {
+    /// Create an alert calling the given handler, without any thresholds.
+    pub fn new(handler: impl Fn(LowCycles) + Send + Sync + 'static) -> Self {
+        Self {
+            balance_threshold: None,
+            remaining_budget_threshold: None,
+            handler: Arc::new(handler),
+        }
+    }
+
+    /// Alert when the canister balance drops below the threshold.
+    pub const fn with_balance_threshold(mut self, threshold: u128) -> Self {
+        self.balance_threshold = Some(threshold);
+        self
+    }
+
+    /// Alert when the cycles remaining in the current period of the [`CyclesBudget`] drop below
+    /// the threshold. Only applies if the budget limits the spend per period.
+    pub const fn with_remaining_budget_threshold(mut self, threshold: u128) -> Self {
+        self.remaining_budget_threshold = Some(threshold);
+        self
+    }
+
+    /// Returns the balance threshold, if set.
+    pub const fn balance_threshold(&self) -> Option<u128> {
+        self.balance_threshold
+    }
+
+    /// Returns the remaining budget threshold, if set.
+    pub const fn remaining_budget_threshold(&self) -> Option<u128> {
+        self.remaining_budget_threshold
+    }
+}
+
+#[derive(Debug, Default)]
+struct BudgetState {
+    budget: Option<CyclesBudget>,
+    period_start: u64,
+    spent: u128,
+    alert: Option<CyclesAlert>,
+    balance_low: bool,
+    budget_low: bool,
+}
+
+impl BudgetState {
+    /// Check that a call with the given cycles fits the budget, and if so, count them as spent.
+    fn spend(
+        &mut self,
+        priority: RequestPriority,
+        cycles: u128,
+        now: u64,
+        balance: impl FnOnce() -> u128,
+    ) -> Result<(), CyclesBudgetExceeded> {
+        let Some(budget) = self.budget else { return Ok(()) };
+        if now.saturating_sub(self.period_start) >= budget.period().as_nanos() as u64 {
+            self.period_start = now;
+            self.spent = 0;
+        }
+        if priority < RequestPriority::Interactive && !budget.pause_pollers {
+            self.spent = self.spent.saturating_add(cycles);
+            return Ok(());
+        }
+        if let Some(min_balance) = budget.min_balance {
+            let balance = balance();
+            if balance.saturating_sub(cycles) < min_balance {
+                return Err(CyclesBudgetExceeded::MinBalance { cycles, balance, min_balance });
+            }
+        }
+        if let Some(max_spend) = budget.max_spend {
+            if self.spent.saturating_add(cycles) > max_spend {
+                return Err(CyclesBudgetExceeded::MaxSpend {
+                    cycles,
+                    spent: self.spent,
+                    max_spend,
+                });
+            }
+        }
+        self.spent = self.spent.saturating_add(cycles);
+        Ok(())
+    }
+
+    /// Returns the percentage of the spend per period that remains, if the spend is limited.
+    fn remaining_percent(&self, now: impl FnOnce() -> u64) -> Option<u8> {
+        let budget = self.budget?;
+        let max_spend = budget.max_spend?;
+        if max_spend == 0 {
+            return Some(0);
+        }
+        // A period that has elapsed is only reset by the next spend, which may never come if
+        // all pollers are paused.
+        let elapsed = now().saturating_sub(self.period_start) >= budget.period().as_nanos() as u64;
+        let spent = if elapsed { 0 } else { self.spent };
+        let remaining = max_spend.saturating_sub(spent);
+        Some((remaining.saturating_mul(100) / max_spend) as u8)
+    }
+
+    /// Returns the low cycles conditions that have newly arisen since the last check.
+    fn check_alert(&mut self, balance: impl FnOnce() -> u128) -> Vec<LowCycles> {
+        let Some(alert) = &self.alert else { return Vec::new() };
+        let mut alerts = Vec::new();
+        if let Some(threshold) = alert.balance_threshold {
+            let balance = balance();
+            let low = balance < threshold;
+            if low && !self.balance_low {
+                alerts.push(LowCycles::Balance { balance, threshold });
+            }
+            self.balance_low = low;
+        }
+        let max_spend = self.budget.and_then(|budget| budget.max_spend);
+        if let (Some(threshold), Some(max_spend)) = (alert.remaining_budget_threshold, max_spend) {
+            let remaining = max_spend.saturating_sub(self.spent);
+            let low = remaining < threshold;
+            if low && !self.budget_low {
+                alerts.push(LowCycles::RemainingBudget { remaining, threshold });
+            }
+            self.budget_low = low;
+        }
+        alerts
+    }
+}
+
+/// Enforces the [`CyclesBudget`] of a transport, and raises its [`CyclesAlert`].
+///
+/// Shared between all clones of a transport.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct BudgetTracker(Arc<Mutex<BudgetState>>);
+
+impl BudgetTracker {
+    pub(crate) fn new(budget: Option<CyclesBudget>, alert: Option<CyclesAlert>) -> Self {
+        Self(Arc::new(Mutex::new(BudgetState { budget, alert, ..Default::default() })))
+    }
+
+    pub(crate) fn alert(&self) -> Option<CyclesAlert> {
+        self.0.lock().unwrap().alert.clone()
+    }
+
+    /// Replace the alert. An alert is raised again if its thresholds are already crossed.
+    pub(crate) fn set_alert(&self, alert: Option<CyclesAlert>) {
+        let mut state = self.0.lock().unwrap();
+        state.alert = alert;
+        state.balance_low = false;
+        state.budget_low = false;
+    }
+
+    pub(crate) fn budget(&self) -> Option<CyclesBudget> {
+        self.0.lock().unwrap().budget
+    }
+
+    /// Replace the budget, keeping the spend of the current period.
+    pub(crate) fn set_budget(&self, budget: Option<CyclesBudget>) {
+        self.0.lock().unwrap().budget = budget;
+    }
+
+    pub(crate) fn spent(&self) -> u128 {
+        self.0.lock().unwrap().spent
+    }
+
+    pub(crate) fn remaining_percent(&self) -> Option<u8> {
+        self.0.lock().unwrap().remaining_percent(ic_cdk::api::time)
+    }
+
+    /// Check that a call attaching `cycles` fits the budget, and if so, count them as spent
+    /// until the call completes.
+    pub(crate) fn spend(
+        &self,
+        priority: RequestPriority,
+        cycles: u128,
+    ) -> Result<(), CyclesBudgetExceeded> {
+        let mut state = self.0.lock().unwrap();
+        if state.budget.is_none() {
+            return Ok(());
+        }
+        state.spend(priority, cycles, ic_cdk::api::time(), ic_cdk::api::canister_balance128)
+    }
+
+    /// Credit back the cycles refunded by a completed call, and raise the alert if a threshold
+    /// has been crossed.
+    pub(crate) fn complete(&self, refunded: u128) {
+        let (alert, alerts) = {
+            let mut state = self.0.lock().unwrap();
+            state.spent = state.spent.saturating_sub(refunded);
+            (state.alert.clone(), state.check_alert(ic_cdk::api::canister_balance128))
+        };
+        // Call the handler without holding the lock, so it can use the transport.
+        if let Some(alert) = alert {
+            alerts.into_iter().for_each(|low| (alert.handler)(low));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_counters() {
+        let meter = CyclesMeter::new();
+        let clone = meter.clone();
+        clone.record(CallCycles { calls: 1, attached: 1_000, refunded: 400 });
+        meter.record(CallCycles { calls: 1, attached: 1_000, refunded: 1_000 });
+        assert_eq!(clone, meter);
+
+        let cycles = meter.get();
+        assert_eq!(cycles, CallCycles { calls: 2, attached: 2_000, refunded: 1_400 });
+        assert_eq!(cycles.spent(), 600);
+        assert_eq!(meter.take(), cycles);
+        assert_eq!(meter.get(), CallCycles::default());
+        assert_ne!(meter, CyclesMeter::new());
+    }
+
+    #[test]
+    fn budget_limits_spend_per_period() {
+        let budget = CyclesBudget::new().with_max_spend(1_000, Duration::from_secs(60));
+        let mut state = BudgetState { budget: Some(budget), ..Default::default() };
+        let interactive = RequestPriority::Interactive;
+
+        assert!(state.spend(interactive, 600, 0, || 0).is_ok());
+        assert_eq!(
+            state.spend(interactive, 600, 1, || 0),
+            Err(CyclesBudgetExceeded::MaxSpend { cycles: 600, spent: 600, max_spend: 1_000 })
+        );
+        assert!(state.spend(RequestPriority::Polling, 600, 1, || 0).is_err());
+
+        // A new period starts with a clean slate.
+        assert_eq!(state.remaining_percent(|| 1), Some(40));
+        assert_eq!(state.remaining_percent(|| 60_000_000_000), Some(100));
+        assert!(state.spend(interactive, 600, 60_000_000_000, || 0).is_ok());
+        assert_eq!(state.spent, 600);
+    }
+
+    #[test]
+    fn budget_keeps_min_balance() {
+        let budget = CyclesBudget::new().with_min_balance(10_000).with_pause_pollers(false);
+        let mut state = BudgetState { budget: Some(budget), ..Default::default() };
+
+        assert!(state.spend(RequestPriority::Interactive, 1_000, 0, || 11_000).is_ok());
+        assert_eq!(
+            state.spend(RequestPriority::Interactive, 1_000, 0, || 10_500),
+            Err(CyclesBudgetExceeded::MinBalance {
+                cycles: 1_000,
+                balance: 10_500,
+                min_balance: 10_000
+            })
+        );
+        assert!(state.spend(RequestPriority::Background, 1_000, 0, || 10_500).is_ok());
+    }
+
+    #[test]
+    fn estimates_outcall_cost_with_margin() {
+        let estimator = CyclesEstimator::new().with_subnet_size(13).with_margin_percent(50);
+        // (3M + 60K * 13) * 13 + 400 * 13 * 100 + 800 * 13 * 1_000
+        assert_eq!(estimator.outcall_cost(100, 1_000), 49_140_000 + 520_000 + 10_400_000);
+        assert_eq!(estimator.estimate(100, 1_000), 60_060_000 * 3 / 2);
+        assert!(
+            CyclesEstimator::new().estimate(100, 2_000)
+                > CyclesEstimator::new().estimate(100, 1_000)
+        );
+    }
+
+    #[test]
+    fn alert_fires_once_per_crossing() {
+        let budget = CyclesBudget::new().with_max_spend(1_000, Duration::from_secs(60));
+        let alert = CyclesAlert::new(|_| {})
+            .with_balance_threshold(5_000)
+            .with_remaining_budget_threshold(500);
+        let mut state =
+            BudgetState { budget: Some(budget), alert: Some(alert), ..Default::default() };
+
+        assert!(state.check_alert(|| 6_000).is_empty());
+        state.spent = 600;
+        assert_eq!(
+            state.check_alert(|| 4_000),
+            [
+                LowCycles::Balance { balance: 4_000, threshold: 5_000 },
+                LowCycles::RemainingBudget { remaining: 400, threshold: 500 },
+            ]
+        );
+        assert!(state.check_alert(|| 3_000).is_empty());
+
+        // Topping up re-arms the balance alert.
+        assert!(state.check_alert(|| 10_000).is_empty());
+        assert_eq!(
+            state.check_alert(|| 4_500),
+            [LowCycles::Balance { balance: 4_500, threshold: 5_000 }]
+        );
+    }
+}
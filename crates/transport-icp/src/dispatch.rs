@@ -0,0 +1,361 @@
+use crate::context::RequestPriority;
+use futures::channel::oneshot;
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    sync::{Arc, Mutex},
+};
+
+/// A request waiting for an outcall slot.
+#[derive(Debug)]
+struct Waiter {
+    priority: RequestPriority,
+    seq: u64,
+    tx: oneshot::Sender<()>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    /// Higher priorities first, and requests of the same priority in the order they were queued.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.cmp(&other.priority).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+#[derive(Debug, Default)]
+struct DispatchState {
+    max_concurrent: Option<usize>,
+    in_flight: usize,
+    queue: BinaryHeap<Waiter>,
+    next_seq: u64,
+    closed: bool,
+    idle: Vec<oneshot::Sender<()>>,
+}
+
+impl DispatchState {
+    fn below_limit(&self) -> bool {
+        self.max_concurrent.map_or(true, |max| self.in_flight < max)
+    }
+
+    fn has_capacity(&self) -> bool {
+        self.queue.is_empty() && self.below_limit()
+    }
+}
+
+/// Limits the number of concurrent outcalls, dispatching delayed requests by
+/// [`RequestPriority`].
+///
+/// Shared between all clones of a transport.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Dispatcher(Arc<Mutex<DispatchState>>);
+
+impl Dispatcher {
+    /// Create a dispatcher allowing at most `max_concurrent` outcalls, or any number if `None`.
+    pub(crate) fn new(max_concurrent: Option<usize>) -> Self {
+        Self(Arc::new(Mutex::new(DispatchState { max_concurrent, ..Default::default() })))
+    }
+
+    /// Returns the maximum number of concurrent outcalls, if limited.
+    pub(crate) fn max_concurrent(&self) -> Option<usize> {
+        self.0.lock().unwrap().max_concurrent
+    }
+
+    /// Change the maximum number of concurrent outcalls. Raising the limit immediately
+    /// dispatches queued requests; lowering it lets in-flight outcalls complete.
+    pub(crate) fn set_max_concurrent(&self, max_concurrent: Option<usize>) {
+        let mut state = self.0.lock().unwrap();
+        state.max_concurrent = max_concurrent;
+        while state.below_limit() {
+            let Some(waiter) = state.queue.pop() else { break };
+            if waiter.tx.send(()).is_ok() {
+                state.in_flight += 1;
+            }
+        }
+    }
+
+    /// Returns the number of outcalls in flight.
+    pub(crate) fn in_flight(&self) -> usize {
+        self.0.lock().unwrap().in_flight
+    }
+
+    /// Returns the number of requests waiting for an outcall slot.
+    pub(crate) fn queued(&self) -> usize {
+        self.0.lock().unwrap().queue.len()
+    }
+
+    /// Stop dispatching requests: queued requests are cancelled, and new requests are rejected.
+    pub(crate) fn close(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.closed = true;
+        // Dropping the senders wakes the waiters, which then see that the dispatcher is closed.
+        state.queue.clear();
+    }
+
+    /// Returns `true` if the dispatcher has been closed.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.0.lock().unwrap().closed
+    }
+
+    /// Wait until no outcalls are in flight.
+    pub(crate) async fn idle(&self) {
+        let rx = {
+            let mut state = self.0.lock().unwrap();
+            if state.in_flight == 0 {
+                return;
+            }
+            let (tx, rx) = oneshot::channel();
+            state.idle.push(tx);
+            rx
+        };
+        let _ = rx.await;
+    }
+
+    /// Wait for an outcall slot. The slot is held until the returned [`Permit`] is dropped.
+    ///
+    /// Returns `None` if the dispatcher is closed before a slot is available.
+    pub(crate) async fn acquire(&self, priority: RequestPriority) -> Option<Permit> {
+        loop {
+            let rx = {
+                let mut state = self.0.lock().unwrap();
+                if state.closed {
+                    return None;
+                }
+                if state.has_capacity() {
+                    state.in_flight += 1;
+                    return Some(Permit(self.clone()));
+                }
+                let (tx, rx) = oneshot::channel();
+                let seq = state.next_seq;
+                state.next_seq += 1;
+                state.queue.push(Waiter { priority, seq, tx });
+                rx
+            };
+
+            let mut waiting = Waiting { dispatcher: self, rx: Some(rx) };
+            let handed_over = waiting.rx.as_mut().unwrap().await.is_ok();
+            waiting.rx = None;
+            if handed_over {
+                return Some(Permit(self.clone()));
+            }
+        }
+    }
+
+    /// Hand a released slot to the highest priority waiter, or free it if nobody is waiting.
+    fn release(&self) {
+        let mut state = self.0.lock().unwrap();
+        // The limit may have been lowered while the slot was held.
+        if state.max_concurrent.map_or(true, |max| state.in_flight <= max) {
+            while let Some(waiter) = state.queue.pop() {
+                // Waiters that were dropped while queued can't take the slot.
+                if waiter.tx.send(()).is_ok() {
+                    return;
+                }
+            }
+        }
+        state.in_flight -= 1;
+        if state.in_flight == 0 {
+            for tx in state.idle.drain(..) {
+                let _ = tx.send(());
+            }
+        }
+    }
+}
+
+/// An outcall slot, released when dropped.
+#[derive(Debug)]
+#[must_use = "the slot is released immediately if the permit is not held"]
+pub(crate) struct Permit(Dispatcher);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+/// Releases the slot of a waiter that was dropped after the slot was handed over to it.
+struct Waiting<'a> {
+    dispatcher: &'a Dispatcher,
+    rx: Option<oneshot::Receiver<()>>,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        if let Some(mut rx) = self.rx.take() {
+            rx.close();
+            if let Ok(Some(())) = rx.try_recv() {
+                self.dispatcher.release();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::LocalPool, task::LocalSpawnExt};
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn unlimited_dispatcher_never_waits() {
+        let dispatcher = Dispatcher::new(None);
+        let permits: Vec<_> = (0..10)
+            .map(|_| {
+                futures::executor::block_on(dispatcher.acquire(RequestPriority::Background))
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(permits.len(), 10);
+    }
+
+    #[test]
+    fn delayed_requests_are_dispatched_by_priority() {
+        let dispatcher = Dispatcher::new(Some(1));
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let mut pool = LocalPool::new();
+
+        let first =
+            futures::executor::block_on(dispatcher.acquire(RequestPriority::Interactive)).unwrap();
+        for (name, priority) in [
+            ("background", RequestPriority::Background),
+            ("polling-1", RequestPriority::Polling),
+            ("interactive", RequestPriority::Interactive),
+            ("polling-2", RequestPriority::Polling),
+        ] {
+            let dispatcher = dispatcher.clone();
+            let order = order.clone();
+            pool.spawner()
+                .spawn_local(async move {
+                    let _permit = dispatcher.acquire(priority).await.unwrap();
+                    order.borrow_mut().push(name);
+                })
+                .unwrap();
+        }
+
+        pool.run_until_stalled();
+        assert!(order.borrow().is_empty());
+
+        drop(first);
+        pool.run_until_stalled();
+        assert_eq!(*order.borrow(), ["interactive", "polling-1", "polling-2", "background"]);
+    }
+
+    #[test]
+    fn changing_the_limit_dispatches_and_drains() {
+        let dispatcher = Dispatcher::new(Some(2));
+        let first =
+            futures::executor::block_on(dispatcher.acquire(RequestPriority::Interactive)).unwrap();
+        let second =
+            futures::executor::block_on(dispatcher.acquire(RequestPriority::Interactive)).unwrap();
+
+        let dispatched = Rc::new(RefCell::new(0));
+        let mut pool = LocalPool::new();
+        for _ in 0..3 {
+            let dispatcher = dispatcher.clone();
+            let dispatched = dispatched.clone();
+            pool.spawner()
+                .spawn_local(async move {
+                    let permit = dispatcher.acquire(RequestPriority::Polling).await.unwrap();
+                    *dispatched.borrow_mut() += 1;
+                    std::mem::forget(permit);
+                })
+                .unwrap();
+        }
+        pool.run_until_stalled();
+        assert_eq!(dispatcher.queued(), 3);
+
+        // Raising the limit dispatches one queued request right away.
+        dispatcher.set_max_concurrent(Some(3));
+        pool.run_until_stalled();
+        assert_eq!(*dispatched.borrow(), 1);
+        assert_eq!(dispatcher.in_flight(), 3);
+
+        // Lowering the limit keeps queued requests waiting until enough slots are released.
+        dispatcher.set_max_concurrent(Some(1));
+        drop(first);
+        drop(second);
+        pool.run_until_stalled();
+        assert_eq!(*dispatched.borrow(), 1);
+        assert_eq!(dispatcher.in_flight(), 1);
+
+        dispatcher.set_max_concurrent(None);
+        pool.run_until_stalled();
+        assert_eq!(*dispatched.borrow(), 3);
+        assert_eq!(dispatcher.queued(), 0);
+    }
+
+    #[test]
+    fn dropped_waiters_do_not_hold_slots() {
+        let dispatcher = Dispatcher::new(Some(1));
+        let first =
+            futures::executor::block_on(dispatcher.acquire(RequestPriority::Interactive)).unwrap();
+
+        // Queue a waiter, then drop it along with the pool.
+        let mut pool = LocalPool::new();
+        pool.spawner()
+            .spawn_local({
+                let dispatcher = dispatcher.clone();
+                async move { drop(dispatcher.acquire(RequestPriority::Interactive).await) }
+            })
+            .unwrap();
+        pool.run_until_stalled();
+        drop(pool);
+
+        drop(first);
+        let second = futures::executor::block_on(dispatcher.acquire(RequestPriority::Polling));
+        assert!(second.is_some());
+    }
+
+    #[test]
+    fn close_cancels_queued_requests_and_idle_waits_for_in_flight() {
+        let dispatcher = Dispatcher::new(Some(1));
+        let first = futures::executor::block_on(dispatcher.acquire(RequestPriority::Interactive));
+
+        let outcome = Rc::new(RefCell::new(None));
+        let idle = Rc::new(RefCell::new(false));
+        let mut pool = LocalPool::new();
+        pool.spawner()
+            .spawn_local({
+                let (dispatcher, outcome) = (dispatcher.clone(), outcome.clone());
+                async move {
+                    let permit = dispatcher.acquire(RequestPriority::Interactive).await;
+                    *outcome.borrow_mut() = Some(permit.is_some());
+                }
+            })
+            .unwrap();
+        pool.spawner()
+            .spawn_local({
+                let (dispatcher, idle) = (dispatcher.clone(), idle.clone());
+                async move {
+                    dispatcher.idle().await;
+                    *idle.borrow_mut() = true;
+                }
+            })
+            .unwrap();
+        pool.run_until_stalled();
+
+        dispatcher.close();
+        pool.run_until_stalled();
+        assert_eq!(*outcome.borrow(), Some(false));
+        assert!(!*idle.borrow());
+        assert!(
+            futures::executor::block_on(dispatcher.acquire(RequestPriority::Interactive)).is_none()
+        );
+
+        drop(first);
+        pool.run_until_stalled();
+        assert!(*idle.borrow());
+    }
+}
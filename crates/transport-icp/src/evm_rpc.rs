@@ -1,7 +1,7 @@
 use candid::{self, CandidType, Deserialize, Principal};
 use ic_cdk::api::call::{call_with_payment128, CallResult as Result};
 
-#[derive(Debug, CandidType, Deserialize, Clone)]
+#[derive(Debug, CandidType, Deserialize, Clone, PartialEq, Eq)]
 pub enum EthSepoliaService {
     Alchemy,
     BlockPi,
@@ -9,7 +9,7 @@ pub enum EthSepoliaService {
     Ankr,
 }
 
-#[derive(Debug, CandidType, Deserialize, Clone)]
+#[derive(Debug, CandidType, Deserialize, Clone, PartialEq, Eq)]
 pub enum L2MainnetService {
     Alchemy,
     BlockPi,
@@ -17,19 +17,19 @@ pub enum L2MainnetService {
     Ankr,
 }
 
-#[derive(Debug, CandidType, Deserialize, Clone)]
+#[derive(Debug, CandidType, Deserialize, Clone, PartialEq, Eq)]
 pub struct HttpHeader {
     pub value: String,
     pub name: String,
 }
 
-#[derive(Debug, CandidType, Deserialize, Clone)]
+#[derive(Debug, CandidType, Deserialize, Clone, PartialEq, Eq)]
 pub struct RpcApi {
     pub url: String,
     pub headers: Option<Vec<HttpHeader>>,
 }
 
-#[derive(Debug, CandidType, Deserialize, Clone)]
+#[derive(Debug, CandidType, Deserialize, Clone, PartialEq, Eq)]
 pub enum EthMainnetService {
     Alchemy,
     BlockPi,
@@ -62,7 +62,7 @@ pub enum ValidationError {
     InvalidHex(String),
 }
 
-#[derive(Debug, CandidType, Deserialize, Clone, Copy)]
+#[derive(Debug, CandidType, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum RejectionCode {
     NoError,
     CanisterError,
@@ -99,7 +99,7 @@ pub enum RpcError {
     HttpOutcallError(HttpOutcallError),
 }
 
-#[derive(Debug, CandidType, Deserialize, Clone)]
+#[derive(Debug, CandidType, Deserialize, Clone, PartialEq, Eq)]
 pub enum RpcService {
     EthSepolia(EthSepoliaService),
     BaseMainnet(L2MainnetService),
@@ -0,0 +1,392 @@
+use crate::{
+    args::RequestArgs, headers::ServiceHeaders, sizing, HttpOutcallError, JsonRpcError,
+    RequestResult, RpcError, RpcService,
+};
+use alloy_json_rpc::{RequestPacket, ResponsePacket, ResponsePayload, SerializedRequest};
+use candid::CandidType;
+use ic_cdk::api::call::{CallResult, RejectionCode};
+use serde::Deserialize;
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Default time a failed service is skipped for.
+pub(crate) const DEFAULT_FAILOVER_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// JSON-RPC error codes of providers limiting the rate of requests.
+const RATE_LIMITED_CODES: [i64; 2] = [429, -32005];
+
+/// Methods creating a filter on the provider, which answer with the ID of the filter.
+const NEW_FILTER_METHODS: [&str; 3] =
+    ["eth_newFilter", "eth_newBlockFilter", "eth_newPendingTransactionFilter"];
+
+/// Methods using a filter, whose first param is the ID of the filter.
+const FILTER_METHODS: [&str; 3] =
+    ["eth_getFilterChanges", "eth_getFilterLogs", "eth_uninstallFilter"];
+
+/// Methods sending a transaction, which are never rotated.
+const WRITE_METHODS: [&str; 2] = ["eth_sendRawTransaction", "eth_sendTransaction"];
+
+/// How long a filter is routed to the service it was created on after it was last used.
+/// Providers remove filters that are not polled for 5 minutes.
+const FILTER_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// How reads are spread over the services of an [`IcpTransport`](crate::IcpTransport) with
+/// fallback services, see
+/// [`IcpConfig::set_service_rotation`](crate::IcpConfig::set_service_rotation).
+///
+/// Requests sending a transaction are always sent to the primary service first. Requests
+/// using a filter are sent to the service the filter was created on, as filters only exist on
+/// that provider. Services that failed are skipped by the rotation during the failover
+/// cooldown, and every request still fails over to the other services.
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize)]
+pub enum ServiceRotation {
+    /// Send every request to the primary service first. The default.
+    #[default]
+    Primary,
+    /// Send each read to the next service in turn.
+    RoundRobin,
+    /// Send reads to the services in turn, in proportion to their weights, given in order for
+    /// the primary service and then the fallback services. Services without a weight have a
+    /// weight of 1, and services with a weight of 0 are only failed over to.
+    Weighted(Vec<u32>),
+}
+
+impl ServiceRotation {
+    /// Returns the weights of the given number of services, or `None` if reads are not rotated.
+    fn weights(&self, services: usize) -> Option<Vec<u32>> {
+        match self {
+            Self::Primary => None,
+            Self::RoundRobin => Some(vec![1; services]),
+            Self::Weighted(weights) => {
+                Some((0..services).map(|i| weights.get(i).copied().unwrap_or(1)).collect())
+            }
+        }
+    }
+}
+
+/// The services of a transport, in the order they are tried, and their health.
+#[derive(Clone, Debug)]
+pub(crate) struct Failover {
+    services: Arc<[(RpcService, RequestArgs)]>,
+    cooldown: Duration,
+    rotation: ServiceRotation,
+    weights: Option<Arc<[u32]>>,
+    state: Arc<Mutex<FailoverState>>,
+}
+
+impl Failover {
+    /// Returns `None` if there are no fallback services. The arguments of the services are
+    /// encoded with their headers.
+    pub(crate) fn new(
+        primary: &RpcService,
+        fallbacks: &[RpcService],
+        headers: &ServiceHeaders,
+        cooldown: Duration,
+        rotation: ServiceRotation,
+    ) -> Option<Self> {
+        if fallbacks.is_empty() {
+            return None;
+        }
+        let services: Arc<[_]> = std::iter::once(primary)
+            .chain(fallbacks)
+            .map(|service| (service.clone(), RequestArgs::new(&headers.apply(service))))
+            .collect();
+        let weights = rotation.weights(services.len()).map(Into::into);
+        let state = FailoverState::new(services.len());
+        Some(Self { services, cooldown, rotation, weights, state: Arc::new(Mutex::new(state)) })
+    }
+
+    /// Returns the fallback services, without the primary service.
+    pub(crate) fn fallbacks(&self) -> Vec<RpcService> {
+        self.services[1..].iter().map(|(service, _)| service.clone()).collect()
+    }
+
+    pub(crate) const fn cooldown(&self) -> Duration {
+        self.cooldown
+    }
+
+    pub(crate) fn rotation(&self) -> ServiceRotation {
+        self.rotation.clone()
+    }
+
+    /// Returns the indices of the services in the order the request should be tried at `now`.
+    pub(crate) fn order(&self, request_packet: &RequestPacket, now: u64) -> Vec<usize> {
+        let mut state = self.state.lock().unwrap();
+        let requests = match request_packet {
+            RequestPacket::Single(req) => std::slice::from_ref(req),
+            RequestPacket::Batch(reqs) => reqs.as_slice(),
+        };
+        let first = if let Some(id) = requests.iter().find_map(filter_id) {
+            state.filter_service(&id, now)
+        } else if requests.iter().any(|req| WRITE_METHODS.contains(&req.method())) {
+            None
+        } else {
+            self.weights.as_ref().and_then(|weights| state.rotate(weights, now))
+        };
+        state.order(now, first)
+    }
+
+    /// Route the filters created or removed by the request to the service at the given index.
+    pub(crate) fn track_filters(
+        &self,
+        index: usize,
+        request_packet: &RequestPacket,
+        response_packet: &ResponsePacket,
+        now: u64,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let mut track = |req: &SerializedRequest, payload: &ResponsePayload| {
+            if req.method() == "eth_uninstallFilter" {
+                if let Some(id) = filter_id(req) {
+                    state.filters.remove(&id);
+                }
+            } else if NEW_FILTER_METHODS.contains(&req.method()) {
+                let id = payload
+                    .as_success()
+                    .and_then(|id| serde_json::from_str::<String>(id.get()).ok());
+                if let Some(id) = id.as_deref().map(normalize_filter_id) {
+                    state.insert_filter(id, index, now);
+                }
+            }
+        };
+        match (request_packet, response_packet) {
+            (RequestPacket::Single(req), ResponsePacket::Single(res)) => track(req, &res.payload),
+            (RequestPacket::Batch(reqs), ResponsePacket::Batch(responses)) => {
+                for res in responses {
+                    if let Some(req) = reqs.iter().find(|req| *req.id() == res.id) {
+                        track(req, &res.payload);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the service at the given index and its encoded arguments.
+    pub(crate) fn service(&self, index: usize) -> &(RpcService, RequestArgs) {
+        &self.services[index]
+    }
+
+    /// Record the outcome of a request sent to the service at the given index.
+    pub(crate) fn record(&self, index: usize, failed: bool, now: u64) {
+        let mut state = self.state.lock().unwrap();
+        if failed {
+            let cooldown = self.cooldown.as_nanos().try_into().unwrap_or(u64::MAX);
+            state.unhealthy_until[index] = now.saturating_add(cooldown);
+        } else {
+            state.unhealthy_until[index] = 0;
+        }
+    }
+
+    /// Returns the services skipped at `now` after failing.
+    pub(crate) fn unhealthy(&self, now: u64) -> Vec<RpcService> {
+        let state = self.state.lock().unwrap();
+        self.services
+            .iter()
+            .zip(&state.unhealthy_until)
+            .filter(|(_, until)| **until > now)
+            .map(|((service, _), _)| service.clone())
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+struct FailoverState {
+    /// The time until which each service is skipped, in nanoseconds, `0` if it is healthy.
+    unhealthy_until: Vec<u64>,
+    /// The current weights of the services in the smooth weighted round-robin of the rotation.
+    current_weights: Vec<i64>,
+    /// The service each filter was created on, and when the filter was last used, by ID.
+    filters: BTreeMap<String, (usize, u64)>,
+}
+
+impl FailoverState {
+    fn new(services: usize) -> Self {
+        Self {
+            unhealthy_until: vec![0; services],
+            current_weights: vec![0; services],
+            filters: BTreeMap::new(),
+        }
+    }
+
+    /// The given service first, then the healthy services in order, followed by the others,
+    /// those recovering first first.
+    fn order(&self, now: u64, first: Option<usize>) -> Vec<usize> {
+        let (mut healthy, mut unhealthy): (Vec<_>, Vec<_>) =
+            (0..self.unhealthy_until.len()).partition(|&i| self.unhealthy_until[i] <= now);
+        unhealthy.sort_by_key(|&i| self.unhealthy_until[i]);
+        healthy.append(&mut unhealthy);
+        if let Some(first) = first {
+            healthy.retain(|&i| i != first);
+            healthy.insert(0, first);
+        }
+        healthy
+    }
+
+    /// Returns the next healthy service of the rotation, if any has a weight.
+    fn rotate(&mut self, weights: &[u32], now: u64) -> Option<usize> {
+        let candidates: Vec<_> = (0..weights.len())
+            .filter(|&i| weights[i] > 0 && self.unhealthy_until[i] <= now)
+            .collect();
+        let total: i64 = candidates.iter().map(|&i| i64::from(weights[i])).sum();
+        for &i in &candidates {
+            self.current_weights[i] += i64::from(weights[i]);
+        }
+        let next = candidates.into_iter().max_by_key(|&i| (self.current_weights[i], Reverse(i)))?;
+        self.current_weights[next] -= total;
+        Some(next)
+    }
+
+    /// Returns the service the filter was created on, if it was not dropped.
+    fn filter_service(&mut self, id: &str, now: u64) -> Option<usize> {
+        let (index, last_used) = self.filters.get_mut(id)?;
+        *last_used = now;
+        Some(*index)
+    }
+
+    fn insert_filter(&mut self, id: String, index: usize, now: u64) {
+        let ttl: u64 = FILTER_TTL.as_nanos().try_into().unwrap_or(u64::MAX);
+        self.filters.retain(|_, (_, last_used)| last_used.saturating_add(ttl) > now);
+        self.filters.insert(id, (index, now));
+    }
+}
+
+/// Returns the normalized ID of the filter used by a request, if it uses one.
+fn filter_id(request: &SerializedRequest) -> Option<String> {
+    if !FILTER_METHODS.contains(&request.method()) {
+        return None;
+    }
+    let (id,): (String,) = serde_json::from_str(request.params()?.get()).ok()?;
+    Some(normalize_filter_id(&id))
+}
+
+/// Filter IDs are quantities, which may be echoed without their leading zeros.
+fn normalize_filter_id(id: &str) -> String {
+    let digits = id.strip_prefix("0x").unwrap_or(id).trim_start_matches('0');
+    digits.to_ascii_lowercase()
+}
+
+/// Returns `true` if the call failed because of the provider, so the next service should be
+/// tried: the outcall failed, the provider answered with a 5xx or 429 HTTP status, or the
+/// call to the EVM RPC canister was rejected with a transient error.
+pub(crate) fn is_provider_failure(call_result: &CallResult<(RequestResult,)>) -> bool {
+    match call_result {
+        Err((code, _)) => *code == RejectionCode::SysTransient,
+        Ok((RequestResult::Ok(_),)) => false,
+        Ok((RequestResult::Err(err),)) => match err {
+            // A response exceeding its max response size is retried with a larger one instead.
+            RpcError::HttpOutcallError(HttpOutcallError::IcError { .. }) => {
+                !sizing::is_response_too_large(call_result)
+            }
+            RpcError::HttpOutcallError(HttpOutcallError::InvalidHttpJsonRpcResponse {
+                status,
+                ..
+            }) => *status == 429 || *status >= 500,
+            RpcError::JsonRpcError(JsonRpcError { code, .. }) => RATE_LIMITED_CODES.contains(code),
+            RpcError::ProviderError(_) | RpcError::ValidationError(_) => false,
+        },
+    }
+}
+
+/// Returns `true` if the provider answered any request of the packet with a rate limit error.
+pub(crate) fn is_rate_limited(response_packet: &ResponsePacket) -> bool {
+    response_packet.iter_errors().any(|err| RATE_LIMITED_CODES.contains(&err.code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EthSepoliaService;
+    use alloy_json_rpc::{Id, Request};
+
+    fn failover(rotation: ServiceRotation) -> Failover {
+        Failover::new(
+            &RpcService::EthSepolia(EthSepoliaService::Alchemy),
+            &[
+                RpcService::EthSepolia(EthSepoliaService::Ankr),
+                RpcService::EthSepolia(EthSepoliaService::PublicNode),
+            ],
+            &ServiceHeaders::new(),
+            Duration::from_nanos(100),
+            rotation,
+        )
+        .unwrap()
+    }
+
+    fn request(method: &'static str, params: &[&str]) -> RequestPacket {
+        Request::new(method, Id::Number(1), params.to_vec()).serialize().unwrap().into()
+    }
+
+    #[test]
+    fn failed_services_are_tried_last() {
+        let failover = failover(ServiceRotation::Primary);
+        let read = request("eth_blockNumber", &[]);
+        assert_eq!(failover.order(&read, 0), [0, 1, 2]);
+
+        failover.record(1, true, 10);
+        failover.record(0, true, 20);
+        assert_eq!(failover.order(&read, 30), [2, 1, 0]);
+        assert_eq!(failover.unhealthy(30).len(), 2);
+        // Service 1 recovers after its cooldown, service 0 after a success.
+        assert_eq!(failover.order(&read, 110), [1, 2, 0]);
+        failover.record(0, false, 110);
+        assert_eq!(failover.order(&read, 110), [0, 1, 2]);
+
+        let (service, headers) = (&failover.service(0).0, ServiceHeaders::new());
+        let rotation = ServiceRotation::RoundRobin;
+        assert!(Failover::new(service, &[], &headers, Duration::ZERO, rotation).is_none());
+    }
+
+    #[test]
+    fn rotates_reads_and_routes_filters_to_their_service() {
+        let failover = failover(ServiceRotation::Weighted(vec![2, 0]));
+        let read = request("eth_blockNumber", &[]);
+        let first = |now| failover.order(&read, now)[0];
+        assert_eq!([first(0), first(0), first(0)], [0, 2, 0]);
+        let write = request("eth_sendRawTransaction", &["0x02"]);
+        assert_eq!(failover.order(&write, 0), [0, 1, 2]);
+        // A failed service is skipped by the rotation.
+        failover.record(0, true, 0);
+        assert_eq!([first(10), first(10)], [2, 2]);
+
+        let new_filter = request("eth_newBlockFilter", &[]);
+        let created: ResponsePacket =
+            serde_json::from_str(r#"{"jsonrpc":"2.0","id":1,"result":"0x0a"}"#).unwrap();
+        failover.track_filters(2, &new_filter, &created, 10);
+        let changes = request("eth_getFilterChanges", &["0xA"]);
+        assert_eq!(failover.order(&changes, 20), [2, 1, 0]);
+        let uninstall = request("eth_uninstallFilter", &["0xa"]);
+        let removed: ResponsePacket =
+            serde_json::from_str(r#"{"jsonrpc":"2.0","id":1,"result":true}"#).unwrap();
+        failover.track_filters(2, &uninstall, &removed, 30);
+        assert_eq!(failover.order(&changes, 200), [0, 1, 2]);
+    }
+
+    #[test]
+    fn classifies_provider_failures() {
+        let http = |status| {
+            Ok((RequestResult::Err(RpcError::HttpOutcallError(
+                HttpOutcallError::InvalidHttpJsonRpcResponse {
+                    status,
+                    body: String::new(),
+                    parsingError: None,
+                },
+            )),))
+        };
+        assert!(is_provider_failure(&http(503)));
+        assert!(is_provider_failure(&http(429)));
+        assert!(!is_provider_failure(&http(400)));
+        assert!(is_provider_failure(&Err((RejectionCode::SysTransient, String::new()))));
+        assert!(!is_provider_failure(&Ok((RequestResult::Ok("{}".into()),))));
+
+        let limited: ResponsePacket = serde_json::from_str(
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32005,"message":"limit exceeded"}}"#,
+        )
+        .unwrap();
+        assert!(is_rate_limited(&limited));
+    }
+}
@@ -0,0 +1,136 @@
+//! Static headers of custom RPC services, such as API keys, and the redaction of their values
+//! from responses and errors.
+
+use crate::{HttpHeader, RpcService};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// Replaces the values of the headers in responses and errors.
+const REDACTED: &str = "[redacted]";
+
+thread_local! {
+    static SECRETS: RefCell<BTreeSet<String>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+/// Register the value of a header to redact from responses and errors.
+pub(crate) fn register_secret(value: &str) {
+    if value.is_empty() {
+        return;
+    }
+    SECRETS.with_borrow_mut(|secrets| {
+        if !secrets.contains(value) {
+            secrets.insert(value.to_string());
+        }
+    });
+}
+
+/// Replace the registered header values in a text.
+pub(crate) fn redact(mut text: String) -> String {
+    SECRETS.with_borrow(|secrets| {
+        for secret in secrets {
+            if text.contains(secret.as_str()) {
+                text = text.replace(secret.as_str(), REDACTED);
+            }
+        }
+    });
+    text
+}
+
+/// Replace the registered header values in a body, which may not be UTF-8.
+#[cfg(feature = "http")]
+pub(crate) fn redact_bytes(mut body: Vec<u8>) -> Vec<u8> {
+    SECRETS.with_borrow(|secrets| {
+        for secret in secrets.iter().map(String::as_bytes) {
+            let find = |body: &[u8]| body.windows(secret.len()).position(|window| window == secret);
+            let Some(mut start) = find(&body) else { continue };
+            let mut redacted = Vec::with_capacity(body.len());
+            let mut rest = body.as_slice();
+            loop {
+                redacted.extend_from_slice(&rest[..start]);
+                redacted.extend_from_slice(REDACTED.as_bytes());
+                rest = &rest[start + secret.len()..];
+                match find(rest) {
+                    Some(next) => start = next,
+                    None => break,
+                }
+            }
+            redacted.extend_from_slice(rest);
+            body = redacted;
+        }
+    });
+    body
+}
+
+/// The static headers of the [`RpcService::Custom`] services, by URL.
+///
+/// The headers are only added to the encoded arguments of the calls, so the services returned
+/// by the transport and its `Debug` output never hold their values.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct ServiceHeaders(BTreeMap<String, Vec<HttpHeader>>);
+
+impl ServiceHeaders {
+    pub(crate) const fn new() -> Self {
+        Self(BTreeMap::new())
+    }
+
+    /// Add a header to the requests sent to the given URL, registering its value as a secret.
+    pub(crate) fn insert(&mut self, url: String, header: HttpHeader) {
+        register_secret(&header.value);
+        self.0.entry(url).or_default().push(header);
+    }
+
+    /// Returns the headers of each URL.
+    pub(crate) fn entries(&self) -> Vec<(String, Vec<HttpHeader>)> {
+        self.0.iter().map(|(url, headers)| (url.clone(), headers.clone())).collect()
+    }
+
+    /// Returns the service with the headers of its URL, if it is a custom service.
+    pub(crate) fn apply<'a>(&self, rpc_service: &'a RpcService) -> Cow<'a, RpcService> {
+        let RpcService::Custom(api) = rpc_service else { return Cow::Borrowed(rpc_service) };
+        let Some(headers) = self.0.get(&api.url) else { return Cow::Borrowed(rpc_service) };
+        let mut api = api.clone();
+        api.headers.get_or_insert_with(Vec::new).extend(headers.iter().cloned());
+        Cow::Owned(RpcService::Custom(api))
+    }
+}
+
+impl fmt::Debug for ServiceHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.0.iter().map(|(url, headers)| {
+                (url, headers.iter().map(|header| &header.name).collect::<Vec<_>>())
+            }))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RpcApi;
+
+    #[test]
+    fn adds_headers_and_redacts_their_values() {
+        let mut headers = ServiceHeaders::new();
+        let header = HttpHeader { name: "x-api-key".into(), value: "s3cr3t".into() };
+        headers.insert("https://rpc.example.com".into(), header.clone());
+
+        let service = |url: &str| RpcService::Custom(RpcApi { url: url.into(), headers: None });
+        let RpcService::Custom(api) =
+            headers.apply(&service("https://rpc.example.com")).into_owned()
+        else {
+            unreachable!()
+        };
+        assert_eq!(api.headers, Some(vec![header]));
+        assert!(matches!(headers.apply(&service("https://other.example.com")), Cow::Borrowed(_)));
+        assert!(!format!("{headers:?}").contains("s3cr3t"));
+
+        assert_eq!(redact("invalid key s3cr3t".into()), "invalid key [redacted]");
+        #[cfg(feature = "http")]
+        assert_eq!(redact_bytes(b"s3cr3t, s3cr3t!".to_vec()), b"[redacted], [redacted]!");
+    }
+}
@@ -0,0 +1,555 @@
+//! A transport making HTTPS outcalls to a JSON-RPC endpoint directly, without the EVM RPC
+//! canister.
+
+use crate::{
+    estimate_max_response_size, headers, serializer, transform, CyclesEstimator, InFlightRequests,
+    Joined, RequestContext,
+};
+use alloy_json_rpc::{RequestPacket, ResponsePacket, SerializedRequest};
+use alloy_transport::{TransportError, TransportErrorKind, TransportFut, TransportResult};
+use candid::{CandidType, Principal};
+use ic_cdk::api::{
+    call::CallResult,
+    management_canister::http_request::{
+        http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod, HttpResponse,
+        TransformArgs, TransformContext,
+    },
+};
+use std::task;
+use tower::Service;
+
+/// The name of the query method stripping the headers of outcall responses, exported by this
+/// crate and used as the transform of an [`IcpHttpTransport`] unless another one is set. It
+/// also applies the transforms registered with
+/// [`register_http_transform`](crate::register_http_transform).
+pub const HTTP_TRANSFORM_METHOD: &str = "__alloy_transport_icp_http_transform";
+
+/// Room left in the max response size for the headers of the response, which count towards it
+/// before they are stripped by the transform.
+const MAX_RESPONSE_HEADERS_SIZE: u64 = 1_000;
+
+/// Maximum size of a decompressed response body, the maximum size of an outcall response.
+#[cfg(feature = "gzip")]
+const MAX_DECODED_BODY_SIZE: u64 = 2_000_000;
+
+/// Returns the `Content-Encoding` of a response, unless it is not encoded.
+#[cfg(feature = "gzip")]
+fn content_encoding(headers: &[HttpHeader]) -> Option<String> {
+    let header =
+        headers.iter().find(|header| header.name.eq_ignore_ascii_case("content-encoding"))?;
+    let encoding = header.value.trim().to_ascii_lowercase();
+    (encoding != "identity").then_some(encoding)
+}
+
+/// Decompress a response body with the given `Content-Encoding`.
+#[cfg(feature = "gzip")]
+fn decode_body(encoding: &str, body: &[u8]) -> Result<Vec<u8>, String> {
+    use flate2::read::{GzDecoder, ZlibDecoder};
+    use std::io::Read;
+
+    let decoder: Box<dyn Read + '_> = match encoding {
+        "gzip" | "x-gzip" => Box::new(GzDecoder::new(body)),
+        "deflate" => Box::new(ZlibDecoder::new(body)),
+        _ => return Err(format!("unsupported response encoding `{encoding}`")),
+    };
+    let mut decoded = Vec::new();
+    decoder
+        .take(MAX_DECODED_BODY_SIZE + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|err| format!("invalid {encoding} response: {err}"))?;
+    if decoded.len() as u64 > MAX_DECODED_BODY_SIZE {
+        return Err(format!("{encoding} response exceeds {MAX_DECODED_BODY_SIZE} bytes"));
+    }
+    Ok(decoded)
+}
+
+/// Strip the headers of an outcall response, which differ between the replicas making the
+/// outcall, e.g. dates and request IDs, so the replicas can reach consensus on the response,
+/// and redact the values of secret headers from its body. Then apply the registered transform
+/// named by the context, if any.
+///
+/// With the `gzip` feature, compressed bodies are decompressed first.
+#[ic_cdk::query(name = "__alloy_transport_icp_http_transform", hidden = true)]
+fn strip_headers(args: TransformArgs) -> HttpResponse {
+    #[cfg(feature = "gzip")]
+    let body = match content_encoding(&args.response.headers) {
+        Some(encoding) => {
+            decode_body(&encoding, &args.response.body).unwrap_or_else(|err| ic_cdk::trap(&err))
+        }
+        None => args.response.body,
+    };
+    #[cfg(not(feature = "gzip"))]
+    let body = args.response.body;
+    let response = HttpResponse {
+        status: args.response.status,
+        headers: Vec::new(),
+        body: headers::redact_bytes(body),
+    };
+    if args.context.is_empty() {
+        return response;
+    }
+    let name = String::from_utf8_lossy(&args.context);
+    transform::apply(&name, response)
+        .unwrap_or_else(|| ic_cdk::trap(&format!("no HTTP transform registered as `{name}`")))
+}
+
+/// The argument of the `http_request` method of the management canister, with the
+/// `is_replicated` field missing from [`CanisterHttpRequestArgument`].
+#[derive(CandidType)]
+struct NonReplicatedHttpRequest {
+    url: String,
+    max_response_bytes: Option<u64>,
+    method: HttpMethod,
+    headers: Vec<HttpHeader>,
+    body: Option<Vec<u8>>,
+    transform: Option<TransformContext>,
+    is_replicated: Option<bool>,
+}
+
+/// Make an outcall with a single replica of the subnet, see
+/// [`RequestContext::with_non_replicated`].
+async fn non_replicated_http_request(
+    request: CanisterHttpRequestArgument,
+    cycles: u128,
+) -> CallResult<(HttpResponse,)> {
+    let request = NonReplicatedHttpRequest {
+        url: request.url,
+        max_response_bytes: request.max_response_bytes,
+        method: request.method,
+        headers: request.headers,
+        body: request.body,
+        transform: request.transform,
+        is_replicated: Some(false),
+    };
+    ic_cdk::api::call::call_with_payment128(
+        Principal::management_canister(),
+        "http_request",
+        (request,),
+        cycles,
+    )
+    .await
+}
+
+/// An ICP transport sending JSON-RPC requests to an URL with HTTPS outcalls made by the
+/// canister itself, instead of through the EVM RPC canister like the
+/// [`IcpTransport`](crate::IcpTransport).
+///
+/// Each request is a `POST` of the JSON-RPC payload, made by every replica of the subnet. The
+/// responses of the replicas must be identical to reach consensus, so their headers are
+/// stripped by a transform, see [`with_transform`](Self::with_transform). The endpoint itself
+/// must answer identical requests identically, e.g. `eth_blockNumber` may fail to reach
+/// consensus when a block is produced while the replicas make their outcalls.
+///
+/// The cycles attached to each outcall are estimated with a [`CyclesEstimator`] unless fixed
+/// call cycles are set. Requests with a non-replicated [`RequestContext`] are made by a
+/// single replica, see [`RequestContext::with_non_replicated`], and their unspent cycles are
+/// refunded. Identical concurrent requests share one outcall, see
+/// [`with_request_coalescing`](Self::with_request_coalescing). Method policies, budgets and
+/// metrics of the [`IcpTransport`](crate::IcpTransport) do not apply to this transport.
+///
+/// # Examples
+///
+/// ```ignore
+/// let transport = IcpHttpTransport::new("https://ethereum-rpc.publicnode.com")
+///     .with_secret_header("Authorization", format!("Bearer {API_KEY}"))
+///     .with_max_response_size(10_000);
+/// ```
+#[derive(Clone, Debug)]
+pub struct IcpHttpTransport {
+    url: String,
+    headers: Vec<HttpHeader>,
+    max_response_size: Option<u64>,
+    call_cycles: Option<u128>,
+    cycles_estimator: CyclesEstimator,
+    transform: Option<TransformContext>,
+    in_flight: Option<InFlightRequests>,
+    #[cfg(feature = "gzip")]
+    compression: bool,
+}
+
+impl IcpHttpTransport {
+    /// Create a transport sending requests to the given URL.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            headers: Vec::new(),
+            max_response_size: None,
+            call_cycles: None,
+            cycles_estimator: CyclesEstimator::new(),
+            transform: None,
+            in_flight: Some(InFlightRequests::default()),
+            #[cfg(feature = "gzip")]
+            compression: false,
+        }
+    }
+
+    /// Returns the URL requests are sent to.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Add a header to every request, e.g. an API key. The `Content-Type` header is always set.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push(HttpHeader { name: name.into(), value: value.into() });
+        self
+    }
+
+    /// Add a header holding a secret to every request, e.g. `Authorization` or `x-api-key`.
+    ///
+    /// The value of the header is redacted from the body of the responses by the default
+    /// transform, before the responses of the replicas reach consensus, in case the endpoint
+    /// echoes it, e.g. in an error message. Custom transforms set with
+    /// [`with_transform`](Self::with_transform) must redact it themselves.
+    pub fn with_secret_header(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let value = value.into();
+        headers::register_secret(&value);
+        self.with_header(name, value)
+    }
+
+    /// Set the max response size, in bytes, including the headers of the response.
+    ///
+    /// Defaults to an estimate from the methods of each request, with room for the headers.
+    pub const fn with_max_response_size(mut self, max_response_size: u64) -> Self {
+        self.max_response_size = Some(max_response_size);
+        self
+    }
+
+    /// Set fixed call cycles, attached to every outcall instead of the cycles estimated by the
+    /// [`CyclesEstimator`]. A request can attach other cycles with
+    /// [`RequestContext::with_call_cycles`].
+    pub const fn with_call_cycles(mut self, call_cycles: u128) -> Self {
+        self.call_cycles = Some(call_cycles);
+        self
+    }
+
+    /// Set the [`CyclesEstimator`], used unless fixed call cycles are set. The subnet size
+    /// should be the size of the subnet of the canister.
+    pub const fn with_cycles_estimator(mut self, cycles_estimator: CyclesEstimator) -> Self {
+        self.cycles_estimator = cycles_estimator;
+        self
+    }
+
+    /// Set the transform of the outcall responses, a query method of the canister, replacing
+    /// the default transform stripping the headers, see [`HTTP_TRANSFORM_METHOD`].
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// #[ic_cdk::query(hidden = true)]
+    /// fn transform(args: TransformArgs) -> HttpResponse {
+    ///     HttpResponse { status: args.response.status, headers: vec![], body: args.response.body }
+    /// }
+    ///
+    /// let transport = IcpHttpTransport::new(url)
+    ///     .with_transform(TransformContext::from_name("transform".into(), vec![]));
+    /// ```
+    pub fn with_transform(mut self, transform: TransformContext) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+
+    /// Apply the transform registered under the given name with
+    /// [`register_http_transform`](crate::register_http_transform) to the outcall responses,
+    /// after their headers are stripped, e.g. to normalize or round values that differ between
+    /// the replicas. Outcalls fail if no transform is registered under the name.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// register_http_transform("normalize", normalize_json);
+    /// let transport = IcpHttpTransport::new(url).with_registered_transform("normalize");
+    /// ```
+    pub fn with_registered_transform(self, name: impl Into<String>) -> Self {
+        let context = name.into().into_bytes();
+        self.with_transform(TransformContext::from_name(HTTP_TRANSFORM_METHOD.into(), context))
+    }
+
+    /// Ask the endpoint for compressed responses with an `Accept-Encoding: gzip, deflate`
+    /// header, cutting the bytes received by the outcalls.
+    ///
+    /// Responses are decompressed by the default transform, before the registered transform,
+    /// or when parsed if a custom transform keeps their `Content-Encoding` header. The max
+    /// response size applies to the compressed response, so it can be lowered with
+    /// [`with_max_response_size`](Self::with_max_response_size) to pay for fewer bytes.
+    #[cfg(feature = "gzip")]
+    pub const fn with_compression(mut self) -> Self {
+        self.compression = true;
+        self
+    }
+
+    /// Enable or disable request coalescing: identical requests made while one is in flight
+    /// wait for its response instead of making their own outcall. Enabled by default.
+    ///
+    /// Clones of the transport share their in-flight requests. Batches, non-replicated
+    /// requests and requests with side effects, e.g. `eth_sendRawTransaction`, are never
+    /// coalesced.
+    pub fn with_request_coalescing(mut self, enabled: bool) -> Self {
+        self.in_flight = enabled.then(InFlightRequests::default);
+        self
+    }
+
+    /// Returns `true` if request coalescing is enabled.
+    pub const fn request_coalescing(&self) -> bool {
+        self.in_flight.is_some()
+    }
+
+    /// Check if the transport is local. Always `false`.
+    pub const fn is_local(&self) -> bool {
+        false
+    }
+
+    /// Estimate the cycles attached to the outcall of a request, see
+    /// [`IcpTransport::estimate_request_cycles`](crate::IcpTransport::estimate_request_cycles).
+    pub fn estimate_request_cycles(
+        &self,
+        request_packet: impl Into<RequestPacket>,
+        expected_response_size: Option<u64>,
+    ) -> TransportResult<u128> {
+        let request_packet = request_packet.into();
+        let mut body = Vec::new();
+        serializer::serialize_packet(&request_packet, None, &mut body)
+            .map_err(TransportError::ser_err)?;
+        let max_response_size = expected_response_size
+            .or(self.max_response_size)
+            .unwrap_or_else(|| Self::estimate_max_response_size(&request_packet));
+        Ok(self.call_cycles_for(&body, &self.headers(), max_response_size))
+    }
+
+    /// Returns the headers of every request.
+    fn headers(&self) -> Vec<HttpHeader> {
+        let mut headers = self.headers.clone();
+        headers.push(HttpHeader { name: "Content-Type".into(), value: "application/json".into() });
+        #[cfg(feature = "gzip")]
+        if self.compression {
+            headers
+                .push(HttpHeader { name: "Accept-Encoding".into(), value: "gzip, deflate".into() });
+        }
+        headers
+    }
+
+    /// Returns the cycles to attach to the outcall of a request: the cycles of its
+    /// [`RequestContext`], the fixed call cycles of this transport, or an estimate.
+    fn call_cycles_for(&self, body: &[u8], headers: &[HttpHeader], max_response_size: u64) -> u128 {
+        let request_size = self.url.len()
+            + body.len()
+            + headers.iter().map(|header| header.name.len() + header.value.len()).sum::<usize>();
+        RequestContext::current().call_cycles().or(self.call_cycles).unwrap_or_else(|| {
+            self.cycles_estimator.estimate(request_size as u64, max_response_size)
+        })
+    }
+
+    fn estimate_max_response_size(request_packet: &RequestPacket) -> u64 {
+        let max_response_size = |req: &SerializedRequest| estimate_max_response_size(req.method());
+        let body = match request_packet {
+            RequestPacket::Single(req) => max_response_size(req),
+            RequestPacket::Batch(reqs) => reqs.iter().map(max_response_size).sum(),
+        };
+        body + MAX_RESPONSE_HEADERS_SIZE
+    }
+
+    /// Returns the in-flight requests a request joins, if it is coalesced. The response of a
+    /// single replica must not be shared with replicated requests.
+    fn in_flight_for(&self, non_replicated: bool) -> Option<InFlightRequests> {
+        self.in_flight.clone().filter(|_| !non_replicated)
+    }
+
+    /// Make a JSON-RPC request with an HTTPS outcall to the URL of this transport.
+    fn request(&self, request_packet: RequestPacket) -> TransportFut<'static> {
+        let mut body = Vec::new();
+        if let Err(err) = serializer::serialize_packet(&request_packet, None, &mut body) {
+            return Box::pin(async move { Err(TransportError::ser_err(err)) });
+        }
+        let max_response_size = self
+            .max_response_size
+            .unwrap_or_else(|| Self::estimate_max_response_size(&request_packet));
+        let headers = self.headers();
+        let call_cycles = self.call_cycles_for(&body, &headers, max_response_size);
+        let transform = self.transform.clone();
+        let url = self.url.clone();
+        let non_replicated = RequestContext::current().is_non_replicated();
+        let in_flight = self.in_flight_for(non_replicated);
+
+        Box::pin(async move {
+            let leader = match in_flight.and_then(|in_flight| in_flight.join(&request_packet)) {
+                Some(Joined::Follower(follower)) => return follower.wait().await,
+                Some(Joined::Leader(leader)) => Some(leader),
+                None => None,
+            };
+            let request = CanisterHttpRequestArgument {
+                url,
+                max_response_bytes: Some(max_response_size),
+                method: HttpMethod::POST,
+                headers,
+                body: Some(body),
+                transform: Some(transform.unwrap_or_else(|| {
+                    TransformContext::from_name(HTTP_TRANSFORM_METHOD.into(), Vec::new())
+                })),
+            };
+            let result = if non_replicated {
+                non_replicated_http_request(request, call_cycles).await
+            } else {
+                http_request(request, call_cycles).await
+            };
+            let result = match result {
+                Ok((response,)) => parse_response(response),
+                Err(err) => Err(TransportError::ErrorResp(alloy_json_rpc::ErrorPayload {
+                    code: err.0 as i64,
+                    message: err.1,
+                    data: None,
+                })),
+            };
+            if let Some(leader) = leader {
+                leader.complete(&result);
+            }
+            result
+        })
+    }
+}
+
+/// Parse the JSON-RPC response of an outcall, failing on non-success statuses.
+fn parse_response(response: HttpResponse) -> TransportResult<ResponsePacket> {
+    #[cfg(feature = "gzip")]
+    let response = match content_encoding(&response.headers) {
+        Some(encoding) => HttpResponse {
+            body: decode_body(&encoding, &response.body)
+                .map_err(|err| TransportErrorKind::custom_str(&err))?,
+            ..response
+        },
+        None => response,
+    };
+    let status = u16::try_from(&response.status.0).unwrap_or(u16::MAX);
+    if !(200..300).contains(&status) {
+        return Err(TransportErrorKind::http_error(
+            status,
+            String::from_utf8_lossy(&response.body).into_owned(),
+        ));
+    }
+    serde_json::from_slice(&response.body)
+        .map_err(|err| TransportError::deser_err(err, String::from_utf8_lossy(&response.body)))
+}
+
+impl Service<RequestPacket> for IcpHttpTransport {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    #[inline]
+    fn poll_ready(&mut self, _cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        // The IcpHttpTransport is always ready to make requests.
+        task::Poll::Ready(Ok(()))
+    }
+
+    #[inline]
+    fn call(&mut self, req: RequestPacket) -> Self::Future {
+        self.request(req)
+    }
+}
+
+impl Service<RequestPacket> for &IcpHttpTransport {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    #[inline]
+    fn poll_ready(&mut self, _cx: &mut task::Context<'_>) -> task::Poll<Result<(), Self::Error>> {
+        // The IcpHttpTransport is always ready to make requests.
+        task::Poll::Ready(Ok(()))
+    }
+
+    #[inline]
+    fn call(&mut self, req: RequestPacket) -> Self::Future {
+        self.request(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u64, body: &str) -> HttpResponse {
+        HttpResponse { status: status.into(), headers: Vec::new(), body: body.into() }
+    }
+
+    #[test]
+    fn parses_outcall_responses() {
+        let packet =
+            parse_response(response(200, r#"{"jsonrpc":"2.0","id":1,"result":"0x10"}"#)).unwrap();
+        let ResponsePacket::Single(single) = packet else { panic!("expected a single response") };
+        assert_eq!(single.payload.as_success().unwrap().get(), r#""0x10""#);
+
+        let err = parse_response(response(429, "rate limited")).unwrap_err();
+        assert!(err.to_string().contains("429"), "{err}");
+        assert!(parse_response(response(200, "not json")).is_err());
+    }
+
+    #[test]
+    fn coalesces_requests_by_default() {
+        let transport = IcpHttpTransport::new("https://rpc.example.com");
+        assert!(transport.request_coalescing());
+        let request: RequestPacket = alloy_json_rpc::Request::new("eth_blockNumber", 1.into(), ())
+            .serialize()
+            .unwrap()
+            .into();
+
+        let in_flight = transport.in_flight_for(false).unwrap();
+        let Some(Joined::Leader(leader)) = in_flight.join(&request) else { panic!("no leader") };
+        let Some(Joined::Follower(follower)) =
+            transport.in_flight_for(false).unwrap().join(&request)
+        else {
+            panic!("the identical request was not coalesced")
+        };
+        leader.complete(&parse_response(response(
+            200,
+            r#"{"jsonrpc":"2.0","id":1,"result":"0x10"}"#,
+        )));
+        let ResponsePacket::Single(shared) = futures::executor::block_on(follower.wait()).unwrap()
+        else {
+            panic!("expected a single response")
+        };
+        assert_eq!(shared.payload.as_success().unwrap().get(), r#""0x10""#);
+
+        assert!(transport.in_flight_for(true).is_none());
+        assert!(transport.with_request_coalescing(false).in_flight_for(false).is_none());
+    }
+
+    #[test]
+    fn strips_response_headers() {
+        let mut raw = response(200, "{}");
+        raw.headers.push(HttpHeader { name: "Date".into(), value: "today".into() });
+        let stripped = strip_headers(TransformArgs { response: raw, context: Vec::new() });
+        assert!(stripped.headers.is_empty());
+        assert_eq!(stripped.body, b"{}");
+
+        headers::register_secret("k3y");
+        let raw = response(401, "invalid api key k3y");
+        let redacted = strip_headers(TransformArgs { response: raw, context: Vec::new() });
+        assert_eq!(redacted.body, b"invalid api key [redacted]");
+
+        crate::register_http_transform("normalize", crate::normalize_json);
+        let raw = response(200, "{ \"id\": 1 }");
+        let transformed =
+            strip_headers(TransformArgs { response: raw, context: b"normalize".into() });
+        assert_eq!(transformed.body, br#"{"id":1}"#);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn decompresses_encoded_responses() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(br#"{"jsonrpc":"2.0","id":1,"result":"0x10"}"#).unwrap();
+        let mut raw = response(200, "");
+        raw.body = encoder.finish().unwrap();
+        raw.headers.push(HttpHeader { name: "Content-Encoding".into(), value: "gzip".into() });
+
+        let packet = parse_response(raw.clone()).unwrap();
+        assert!(packet.as_error().is_none());
+        let stripped = strip_headers(TransformArgs { response: raw, context: Vec::new() });
+        assert_eq!(stripped.body, br#"{"jsonrpc":"2.0","id":1,"result":"0x10"}"#);
+
+        assert!(decode_body("br", b"").is_err());
+        assert!(decode_body("gzip", b"not gzip").is_err());
+    }
+}
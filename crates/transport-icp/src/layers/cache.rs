@@ -0,0 +1,321 @@
+use alloy_json_rpc::{RequestPacket, Response, ResponsePacket, ResponsePayload, SerializedRequest};
+use alloy_transport::{Transport, TransportError, TransportFut};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::{Layer, Service};
+
+/// Default maximum number of responses kept by a [`ResponseCache`].
+const DEFAULT_MAX_ENTRIES: usize = 256;
+
+/// The methods whose responses are cached, and for how long.
+///
+/// Only successful responses to single requests are cached, keyed by method and params, so
+/// requests with block tags such as `latest` are cached as they were first answered for the
+/// whole TTL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachePolicy {
+    ttls: BTreeMap<String, Duration>,
+    max_entries: usize,
+}
+
+impl Default for CachePolicy {
+    /// Responses that rarely or never change:
+    ///
+    /// - `eth_chainId` and `net_version`, never expiring.
+    /// - `eth_getCode`, for one hour.
+    fn default() -> Self {
+        Self::empty()
+            .with_ttl("eth_chainId", Duration::MAX)
+            .with_ttl("net_version", Duration::MAX)
+            .with_ttl("eth_getCode", Duration::from_secs(60 * 60))
+    }
+}
+
+impl CachePolicy {
+    /// Create a policy without any methods, i.e. one that never caches.
+    pub const fn empty() -> Self {
+        Self { ttls: BTreeMap::new(), max_entries: DEFAULT_MAX_ENTRIES }
+    }
+
+    /// Cache the responses of the given method for `ttl`. Use [`Duration::MAX`] for responses
+    /// that never expire, e.g. the `eth_call`s reading the metadata of a token.
+    pub fn with_ttl(mut self, method: impl Into<String>, ttl: Duration) -> Self {
+        self.ttls.insert(method.into(), ttl);
+        self
+    }
+
+    /// Set the maximum number of cached responses. Defaults to 256.
+    ///
+    /// When the cache is full, expired responses are evicted first, then the responses closest
+    /// to expiring.
+    pub const fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Returns how long responses of the given method are cached, if they are.
+    pub fn ttl(&self, method: &str) -> Option<Duration> {
+        self.ttls.get(method).copied()
+    }
+
+    /// Returns the maximum number of cached responses.
+    pub const fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+}
+
+/// Identifies requests answered by the same response: same method and same params.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct CacheKey {
+    method: String,
+    params: Option<String>,
+}
+
+impl CacheKey {
+    fn new(request: &SerializedRequest) -> Self {
+        Self {
+            method: request.method().to_string(),
+            params: request.params().map(|params| params.get().to_string()),
+        }
+    }
+}
+
+/// A cached response, and the time it expires at in nanoseconds since the epoch.
+#[derive(Debug)]
+struct CacheEntry {
+    payload: ResponsePayload,
+    expires_at: u64,
+}
+
+#[derive(Debug)]
+struct CacheState {
+    policy: CachePolicy,
+    entries: HashMap<CacheKey, CacheEntry>,
+}
+
+/// The responses cached by a [`CacheLayer`], shared between all its services.
+///
+/// Clones share the same responses, so a clone kept by the canister can invalidate responses
+/// that are known to have changed, e.g. after upgrading a contract.
+#[derive(Clone, Debug)]
+pub struct ResponseCache(Arc<Mutex<CacheState>>);
+
+impl ResponseCache {
+    /// Create an empty cache with the given policy.
+    pub fn new(policy: CachePolicy) -> Self {
+        Self(Arc::new(Mutex::new(CacheState { policy, entries: HashMap::new() })))
+    }
+
+    /// Returns the policy of this cache.
+    pub fn policy(&self) -> CachePolicy {
+        self.0.lock().unwrap().policy.clone()
+    }
+
+    /// Returns the number of cached responses, including expired responses not yet evicted.
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().entries.len()
+    }
+
+    /// Returns `true` if no responses are cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove the cached responses of the given method.
+    pub fn invalidate(&self, method: &str) {
+        self.0.lock().unwrap().entries.retain(|key, _| key.method != method);
+    }
+
+    /// Remove all cached responses.
+    pub fn clear(&self) {
+        self.0.lock().unwrap().entries.clear();
+    }
+
+    /// Returns `true` if the responses of the given method are cached.
+    fn is_cached(&self, method: &str) -> bool {
+        self.0.lock().unwrap().policy.ttl(method).is_some()
+    }
+
+    /// Returns the cached response to a request at `now`, if any and not expired.
+    fn get(&self, request: &SerializedRequest, now: u64) -> Option<ResponsePayload> {
+        let state = self.0.lock().unwrap();
+        state.policy.ttl(request.method())?;
+        let entry = state.entries.get(&CacheKey::new(request))?;
+        (entry.expires_at > now).then(|| entry.payload.clone())
+    }
+
+    /// Cache the response to a request received at `now`, if its method is cached.
+    fn insert(&self, request: &SerializedRequest, payload: ResponsePayload, now: u64) {
+        let mut state = self.0.lock().unwrap();
+        let Some(ttl) = state.policy.ttl(request.method()) else { return };
+        if state.policy.max_entries == 0 {
+            return;
+        }
+        let ttl = ttl.as_nanos().try_into().unwrap_or(u64::MAX);
+        let key = CacheKey::new(request);
+        if !state.entries.contains_key(&key) && state.entries.len() >= state.policy.max_entries {
+            state.entries.retain(|_, entry| entry.expires_at > now);
+            if state.entries.len() >= state.policy.max_entries {
+                let closest = state
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires_at)
+                    .map(|(key, _)| key.clone());
+                if let Some(closest) = closest {
+                    state.entries.remove(&closest);
+                }
+            }
+        }
+        state.entries.insert(key, CacheEntry { payload, expires_at: now.saturating_add(ttl) });
+    }
+}
+
+/// A transport layer answering repeated reads from a cache, without making an outcall, see
+/// [`CachePolicy`].
+///
+/// # Examples
+///
+/// ```ignore
+/// let layer = CacheLayer::new(
+///     CachePolicy::default().with_ttl("eth_call", Duration::from_secs(24 * 60 * 60)),
+/// );
+/// let cache = layer.cache().clone();
+/// let client = ClientBuilder::default().layer(layer).icp(config);
+///
+/// // After upgrading a contract:
+/// cache.invalidate("eth_getCode");
+/// ```
+#[derive(Clone, Debug)]
+pub struct CacheLayer {
+    cache: ResponseCache,
+}
+
+impl Default for CacheLayer {
+    fn default() -> Self {
+        Self::new(CachePolicy::default())
+    }
+}
+
+impl CacheLayer {
+    /// Create a new cache layer with the given policy.
+    pub fn new(policy: CachePolicy) -> Self {
+        Self { cache: ResponseCache::new(policy) }
+    }
+
+    /// Returns the cache of this layer, shared with the services it creates.
+    pub const fn cache(&self) -> &ResponseCache {
+        &self.cache
+    }
+}
+
+impl<S> Layer<S> for CacheLayer {
+    type Service = CacheService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CacheService { inner, cache: self.cache.clone() }
+    }
+}
+
+/// A Tower Service used by the [`CacheLayer`] that answers requests from its
+/// [`ResponseCache`], or caches the response of the inner service.
+#[derive(Clone, Debug)]
+pub struct CacheService<S> {
+    inner: S,
+    cache: ResponseCache,
+}
+
+impl<S> CacheService<S> {
+    /// Returns the cache of this service.
+    pub const fn cache(&self) -> &ResponseCache {
+        &self.cache
+    }
+}
+
+impl<S> Service<RequestPacket> for CacheService<S>
+where
+    S: Transport + Clone,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let RequestPacket::Single(req) = &request else { return self.inner.call(request) };
+        if !self.cache.is_cached(req.method()) {
+            return self.inner.call(request);
+        }
+        if let Some(payload) = self.cache.get(req, ic_cdk::api::time()) {
+            let response = Response { id: req.id().clone(), payload };
+            return Box::pin(async move { Ok(ResponsePacket::Single(response)) });
+        }
+        let req = req.clone();
+        let fut = self.inner.call(request);
+        let cache = self.cache.clone();
+        Box::pin(async move {
+            let response = fut.await?;
+            if let ResponsePacket::Single(res) = &response {
+                if res.payload.is_success() {
+                    cache.insert(&req, res.payload.clone(), ic_cdk::api::time());
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_json_rpc::{Id, Request};
+
+    fn request(method: &'static str, params: u64) -> SerializedRequest {
+        Request::new(method, Id::Number(1), (params,)).serialize().unwrap()
+    }
+
+    fn payload(value: &str) -> ResponsePayload {
+        ResponsePayload::Success(serde_json::value::to_raw_value(value).unwrap())
+    }
+
+    #[test]
+    fn caches_responses_until_they_expire() {
+        let cache = ResponseCache::new(
+            CachePolicy::empty().with_ttl("eth_getCode", Duration::from_nanos(100)),
+        );
+        cache.insert(&request("eth_getCode", 1), payload("0x60"), 0);
+        cache.insert(&request("eth_blockNumber", 1), payload("0x1"), 0);
+        assert_eq!(cache.len(), 1);
+
+        assert!(cache.get(&request("eth_getCode", 1), 99).is_some());
+        assert!(cache.get(&request("eth_getCode", 2), 99).is_none());
+        assert!(cache.get(&request("eth_getCode", 1), 100).is_none());
+
+        cache.insert(&request("eth_getCode", 1), payload("0x60"), 100);
+        cache.invalidate("eth_getCode");
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn evicts_responses_closest_to_expiring() {
+        let cache = ResponseCache::new(
+            CachePolicy::empty()
+                .with_ttl("eth_getCode", Duration::from_nanos(100))
+                .with_ttl("eth_chainId", Duration::MAX)
+                .with_max_entries(2),
+        );
+        cache.insert(&request("eth_chainId", 0), payload("0x1"), 0);
+        cache.insert(&request("eth_getCode", 1), payload("0x60"), 0);
+        cache.insert(&request("eth_getCode", 2), payload("0x61"), 10);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&request("eth_getCode", 1), 20).is_none());
+        assert!(cache.get(&request("eth_getCode", 2), 20).is_some());
+        assert!(cache.get(&request("eth_chainId", 0), u64::MAX - 1).is_some());
+    }
+}
@@ -0,0 +1,164 @@
+use alloy_json_rpc::{RequestPacket, Response, ResponsePacket, SerializedRequest};
+use alloy_transport::{Transport, TransportError, TransportFut};
+use std::{
+    fmt,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+/// A response as received by the transport, with its result or error still serialized.
+pub type RawResponse = Response;
+
+type RequestHook = dyn Fn(&SerializedRequest) + Send + Sync;
+type ResponseHook = dyn Fn(&RawResponse) + Send + Sync;
+
+/// The hooks of a [`HookLayer`], shared with its services.
+#[derive(Clone, Default)]
+struct Hooks {
+    on_request: Vec<Arc<RequestHook>>,
+    on_response: Vec<Arc<ResponseHook>>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("on_request", &self.on_request.len())
+            .field("on_response", &self.on_response.len())
+            .finish()
+    }
+}
+
+impl Hooks {
+    fn before_send(&self, request: &RequestPacket) {
+        let requests = match request {
+            RequestPacket::Single(req) => std::slice::from_ref(req),
+            RequestPacket::Batch(reqs) => reqs.as_slice(),
+        };
+        for req in requests {
+            self.on_request.iter().for_each(|hook| hook(req));
+        }
+    }
+
+    fn after_receive(&self, response: &ResponsePacket) {
+        let responses = match response {
+            ResponsePacket::Single(res) => std::slice::from_ref(res),
+            ResponsePacket::Batch(responses) => responses.as_slice(),
+        };
+        for res in responses {
+            self.on_response.iter().for_each(|hook| hook(res));
+        }
+    }
+}
+
+/// A transport layer calling hooks with each serialized request before it is sent and each
+/// raw response once it is received, e.g. to audit the requests of a canister.
+///
+/// Each request and response of a batch is passed to the hooks separately. Hooks are called in
+/// the order they are added, and only see what reaches the layer: layers added after this one,
+/// e.g. a [`CacheLayer`](super::CacheLayer), may answer requests without them. Failed requests
+/// have no response to pass to the response hooks.
+///
+/// # Examples
+///
+/// ```ignore
+/// let hooks = HookLayer::new()
+///     .on_request(|req| ic_cdk::println!("sending {}", req.method()))
+///     .on_response(|res| ic_cdk::println!("received {}", res.id));
+/// let client = ClientBuilder::default().layer(hooks).icp(config);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct HookLayer {
+    hooks: Hooks,
+}
+
+impl HookLayer {
+    /// Create a new hook layer without hooks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a hook called with each serialized request before it is sent.
+    pub fn on_request(mut self, hook: impl Fn(&SerializedRequest) + Send + Sync + 'static) -> Self {
+        self.hooks.on_request.push(Arc::new(hook));
+        self
+    }
+
+    /// Add a hook called with each raw response once it is received.
+    pub fn on_response(mut self, hook: impl Fn(&RawResponse) + Send + Sync + 'static) -> Self {
+        self.hooks.on_response.push(Arc::new(hook));
+        self
+    }
+}
+
+impl<S> Layer<S> for HookLayer {
+    type Service = HookService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HookService { inner, hooks: Arc::new(self.hooks.clone()) }
+    }
+}
+
+/// A Tower Service used by the [`HookLayer`] that calls its hooks with the requests and
+/// responses passing through it.
+#[derive(Clone, Debug)]
+pub struct HookService<S> {
+    inner: S,
+    hooks: Arc<Hooks>,
+}
+
+impl<S> Service<RequestPacket> for HookService<S>
+where
+    S: Transport + Clone,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        self.hooks.before_send(&request);
+        if self.hooks.on_response.is_empty() {
+            return self.inner.call(request);
+        }
+        let hooks = self.hooks.clone();
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let response = response.await?;
+            hooks.after_receive(&response);
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_json_rpc::{Id, Request};
+    use std::sync::Mutex;
+
+    #[test]
+    fn calls_hooks_with_each_request_and_response() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let (requests, responses) = (seen.clone(), seen.clone());
+        let layer = HookLayer::new()
+            .on_request(move |req| requests.lock().unwrap().push(req.method().to_string()))
+            .on_response(move |res| responses.lock().unwrap().push(res.id.to_string()));
+
+        let request = |method, id| Request::new(method, Id::Number(id), ()).serialize().unwrap();
+        layer.hooks.before_send(&RequestPacket::Batch(vec![
+            request("eth_chainId", 1),
+            request("eth_blockNumber", 2),
+        ]));
+        let response: ResponsePacket = serde_json::from_str(
+            r#"[{"jsonrpc":"2.0","id":1,"result":"0x1"},{"jsonrpc":"2.0","id":2,"result":"0x2"}]"#,
+        )
+        .unwrap();
+        layer.hooks.after_receive(&response);
+
+        assert_eq!(*seen.lock().unwrap(), ["eth_chainId", "eth_blockNumber", "1", "2"]);
+    }
+}
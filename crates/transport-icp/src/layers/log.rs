@@ -0,0 +1,315 @@
+use crate::headers;
+use alloy_json_rpc::{RequestPacket, ResponsePacket, SerializedRequest};
+use alloy_transport::{Transport, TransportError, TransportFut, TransportResult};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+/// Default maximum length of the params and results logged at [`Level::Debug`].
+const DEFAULT_MAX_PAYLOAD_LEN: usize = 256;
+
+/// The verbosity of an [`IcpLogLayer`], from the least to the most verbose. Each level also
+/// logs everything logged by the levels before it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    /// Log the requests that failed, e.g. because of a rejected call.
+    Error,
+    /// Log the requests answered with a JSON-RPC error.
+    Warn,
+    /// Log the method, outcome, and latency of every request.
+    #[default]
+    Info,
+    /// Log the params and results of every request, truncated.
+    Debug,
+    /// Log the params and results of every request in full.
+    Trace,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Error => "ERROR",
+            Self::Warn => "WARN",
+            Self::Info => "INFO",
+            Self::Debug => "DEBUG",
+            Self::Trace => "TRACE",
+        })
+    }
+}
+
+/// A function rewriting the logged text, e.g. to hide private data.
+#[derive(Clone)]
+struct Redaction(Arc<dyn Fn(String) -> String + Send + Sync>);
+
+impl fmt::Debug for Redaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Redaction").finish_non_exhaustive()
+    }
+}
+
+/// The settings of an [`IcpLogLayer`], shared with its services.
+#[derive(Clone, Debug)]
+struct LogConfig {
+    level: Level,
+    max_payload_len: usize,
+    redaction: Option<Redaction>,
+}
+
+impl LogConfig {
+    /// Redact the registered header values, then apply the redaction of the layer.
+    fn redact(&self, text: String) -> String {
+        let text = headers::redact(text);
+        match &self.redaction {
+            Some(redaction) => (redaction.0)(text),
+            None => text,
+        }
+    }
+
+    /// Redact a params or result, truncating it below [`Level::Trace`].
+    fn payload(&self, payload: &str) -> String {
+        let mut payload = self.redact(payload.to_string());
+        if self.level < Level::Trace && payload.len() > self.max_payload_len {
+            let mut end = self.max_payload_len;
+            while !payload.is_char_boundary(end) {
+                end -= 1;
+            }
+            payload.truncate(end);
+            payload.push_str("...");
+        }
+        payload
+    }
+
+    /// The line logged when a request is sent, from [`Level::Debug`].
+    fn request_line(&self, id: u64, request: &RequestPacket) -> Option<String> {
+        if self.level < Level::Debug {
+            return None;
+        }
+        let requests = match request {
+            RequestPacket::Single(req) => std::slice::from_ref(req),
+            RequestPacket::Batch(reqs) => reqs.as_slice(),
+        };
+        let requests: Vec<_> = requests
+            .iter()
+            .map(|req| {
+                let params = req.params().map_or("", |params| params.get());
+                format!("{}({})", req.method(), self.payload(params))
+            })
+            .collect();
+        Some(format!("#{id} > {}", requests.join(", ")))
+    }
+
+    /// The line logged when a request completes, with its level, if it is logged.
+    fn response_line(
+        &self,
+        id: u64,
+        methods: &str,
+        result: &TransportResult<ResponsePacket>,
+        latency_ms: u64,
+    ) -> Option<(Level, String)> {
+        let (level, line) = match result {
+            Err(err) => (
+                Level::Error,
+                format!(
+                    "#{id} {methods}: failed in {latency_ms}ms: {}",
+                    self.redact(err.to_string())
+                ),
+            ),
+            Ok(response) if response.is_error() => {
+                let errors: Vec<_> = response.iter_errors().map(ToString::to_string).collect();
+                let errors = self.redact(errors.join("; "));
+                (Level::Warn, format!("#{id} {methods}: {errors} in {latency_ms}ms"))
+            }
+            Ok(response) => {
+                let mut line = format!("#{id} {methods}: ok in {latency_ms}ms");
+                if self.level >= Level::Debug {
+                    let results: Vec<_> = response_results(response)
+                        .into_iter()
+                        .map(|result| self.payload(result))
+                        .collect();
+                    line.push_str(&format!(" < {}", results.join(", ")));
+                }
+                (Level::Info, line)
+            }
+        };
+        (level <= self.level).then_some((level, line))
+    }
+}
+
+/// Returns the results of the successful responses of a packet.
+fn response_results(response: &ResponsePacket) -> Vec<&str> {
+    let responses = match response {
+        ResponsePacket::Single(res) => std::slice::from_ref(res),
+        ResponsePacket::Batch(responses) => responses.as_slice(),
+    };
+    responses.iter().filter_map(|res| res.payload.as_success()).map(|raw| raw.get()).collect()
+}
+
+/// Returns the methods of a request, e.g. `batch(eth_chainId, eth_blockNumber)`.
+fn methods(request: &RequestPacket) -> String {
+    match request {
+        RequestPacket::Single(req) => req.method().to_string(),
+        RequestPacket::Batch(reqs) => {
+            let methods: Vec<_> = reqs.iter().map(SerializedRequest::method).collect();
+            format!("batch({})", methods.join(", "))
+        }
+    }
+}
+
+/// A transport layer logging requests and their responses through [`ic_cdk::println!`], since
+/// `tracing` subscribers don't run in canisters.
+///
+/// Each request is numbered, so that its response can be matched with it in the logs of the
+/// canister. The values of the headers set with [`IcpConfig::set_service_header`] are always
+/// redacted, and [`IcpLogLayer::with_redaction`] can hide more, e.g. addresses.
+///
+/// # Examples
+///
+/// ```ignore
+/// let client = ClientBuilder::default().layer(IcpLogLayer::new(Level::Debug)).icp(config);
+/// ```
+///
+/// [`IcpConfig::set_service_header`]: crate::IcpConfig::set_service_header
+#[derive(Clone, Debug)]
+pub struct IcpLogLayer {
+    config: LogConfig,
+    next_id: Arc<AtomicU64>,
+}
+
+impl IcpLogLayer {
+    /// Create a new log layer logging at the given level.
+    pub fn new(level: Level) -> Self {
+        Self {
+            config: LogConfig { level, max_payload_len: DEFAULT_MAX_PAYLOAD_LEN, redaction: None },
+            next_id: Default::default(),
+        }
+    }
+
+    /// Set the maximum length of the params and results logged at [`Level::Debug`]. Longer
+    /// ones are truncated. Defaults to 256 bytes.
+    pub const fn with_max_payload_len(mut self, max_payload_len: usize) -> Self {
+        self.config.max_payload_len = max_payload_len;
+        self
+    }
+
+    /// Rewrite every logged text with the given function, e.g. to hide private data.
+    pub fn with_redaction(
+        mut self,
+        redaction: impl Fn(String) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.config.redaction = Some(Redaction(Arc::new(redaction)));
+        self
+    }
+
+    /// Returns the level of the layer.
+    pub const fn level(&self) -> Level {
+        self.config.level
+    }
+}
+
+impl<S> Layer<S> for IcpLogLayer {
+    type Service = IcpLogService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        IcpLogService {
+            inner,
+            config: Arc::new(self.config.clone()),
+            next_id: self.next_id.clone(),
+        }
+    }
+}
+
+/// A Tower Service used by the [`IcpLogLayer`] that logs requests and their responses.
+#[derive(Clone, Debug)]
+pub struct IcpLogService<S> {
+    inner: S,
+    config: Arc<LogConfig>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl<S> Service<RequestPacket> for IcpLogService<S>
+where
+    S: Transport + Clone,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        if let Some(line) = self.config.request_line(id, &request) {
+            ic_cdk::println!("[{}] {line}", Level::Debug);
+        }
+        let methods = methods(&request);
+        let config = self.config.clone();
+        let started_at = ic_cdk::api::time();
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let result = response.await;
+            let latency_ms = ic_cdk::api::time().saturating_sub(started_at) / 1_000_000;
+            if let Some((level, line)) = config.response_line(id, &methods, &result, latency_ms) {
+                ic_cdk::println!("[{level}] {line}");
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_json_rpc::{Id, Request};
+    use alloy_transport::TransportErrorKind;
+
+    fn request(method: &'static str, params: &str) -> RequestPacket {
+        Request::new(method, Id::Number(1), (params,)).serialize().unwrap().into()
+    }
+
+    fn response(json: &str) -> TransportResult<ResponsePacket> {
+        Ok(serde_json::from_str(json).unwrap())
+    }
+
+    #[test]
+    fn logs_requests_at_or_above_the_level() {
+        let config = IcpLogLayer::new(Level::Warn).config;
+        let ok = response(r#"{"jsonrpc":"2.0","id":1,"result":"0x1"}"#);
+        let reverted = response(
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":3,"message":"execution reverted"}}"#,
+        );
+        assert_eq!(config.request_line(0, &request("eth_call", "0x1")), None);
+        assert_eq!(config.response_line(0, "eth_call", &ok, 5), None);
+        assert_eq!(
+            config.response_line(0, "eth_call", &reverted, 5),
+            Some((Level::Warn, "#0 eth_call: error code 3: execution reverted in 5ms".into()))
+        );
+        let failed = Err(TransportErrorKind::backend_gone());
+        assert_eq!(config.response_line(1, "eth_call", &failed, 5).unwrap().0, Level::Error);
+
+        let config = IcpLogLayer::new(Level::Debug).config;
+        assert_eq!(
+            config.response_line(2, "eth_chainId", &ok, 5),
+            Some((Level::Info, r#"#2 eth_chainId: ok in 5ms < "0x1""#.into()))
+        );
+    }
+
+    #[test]
+    fn redacts_and_truncates_payloads() {
+        let layer = IcpLogLayer::new(Level::Debug)
+            .with_max_payload_len(12)
+            .with_redaction(|text| text.replace("0xabc", "0x..."));
+        let line = layer.config.request_line(0, &request("eth_getBalance", "0xabc"));
+        assert_eq!(line.as_deref(), Some(r#"#0 > eth_getBalance(["0x..."])"#));
+
+        let line = layer.config.request_line(1, &request("eth_call", "0x0123456789"));
+        assert_eq!(line.as_deref(), Some(r#"#1 > eth_call(["0x01234567...)"#));
+    }
+}
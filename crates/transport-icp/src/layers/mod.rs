@@ -0,0 +1,16 @@
+//! Module for housing ICP transport layers.
+
+mod cache;
+pub use cache::{CacheLayer, CachePolicy, CacheService, ResponseCache};
+
+mod hooks;
+pub use hooks::{HookLayer, HookService, RawResponse};
+
+mod log;
+pub use log::{IcpLogLayer, IcpLogService, Level};
+
+mod rate_limit;
+pub use rate_limit::{RateLimitLayer, RateLimitService, RateLimited};
+
+mod retry;
+pub use retry::{IcpRetryPolicy, RetryBackoffLayer, RetryBackoffService, RetryRule};
@@ -0,0 +1,234 @@
+use crate::sleep;
+use alloy_json_rpc::{RequestPacket, ResponsePacket};
+use alloy_transport::{Transport, TransportError, TransportErrorKind, TransportFut};
+use std::{
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::{Layer, Service};
+
+/// Default maximum number of requests waiting for the rate limit.
+const DEFAULT_MAX_QUEUED: usize = 64;
+
+/// Error returned when a request exceeds the rate limit and the queue of waiting requests is
+/// full, see [`RateLimitLayer::with_max_queued`].
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("rate limit exceeded with {queued} requests queued, retry after {retry_after:?}")]
+pub struct RateLimited {
+    queued: usize,
+    retry_after: Duration,
+}
+
+impl RateLimited {
+    /// Returns the number of requests that were waiting when the request was rejected.
+    pub const fn queued(&self) -> usize {
+        self.queued
+    }
+
+    /// Returns how long the request would have waited for the rate limit.
+    pub const fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
+}
+
+/// The quota of a [`RateLimitLayer`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Quota {
+    /// The time between two requests at the sustained rate, in nanoseconds.
+    interval: u64,
+    burst: u32,
+    max_queued: usize,
+}
+
+/// A token bucket, tracked as the time at which it is full again, in nanoseconds since the
+/// epoch, and the requests waiting for tokens.
+#[derive(Debug, Default)]
+struct Bucket {
+    full_at: u64,
+    queued: usize,
+}
+
+impl Bucket {
+    /// Take `tokens` at `now`, returning how long to wait for them, or an error if the request
+    /// would have to wait and the queue is full.
+    fn take(&mut self, quota: Quota, tokens: u32, now: u64) -> Result<u64, RateLimited> {
+        let tolerance = quota.interval.saturating_mul(u64::from(quota.burst));
+        let full_at = self.full_at.max(now);
+        let cost = quota.interval.saturating_mul(u64::from(tokens));
+        let wait = (full_at.saturating_add(cost)).saturating_sub(now).saturating_sub(tolerance);
+        if wait > 0 && self.queued >= quota.max_queued {
+            return Err(RateLimited {
+                queued: self.queued,
+                retry_after: Duration::from_nanos(wait),
+            });
+        }
+        self.full_at = full_at.saturating_add(cost);
+        Ok(wait)
+    }
+}
+
+/// A transport layer limiting the rate of requests with a token bucket, so that bursts of
+/// requests stay within the quota of the RPC provider.
+///
+/// Each request takes a token, and each request of a batch takes one. Requests that exceed the
+/// rate wait for their tokens on a timer, see [`sleep`](crate::sleep()), up to a bounded number
+/// of waiting requests, beyond which requests fail fast with a [`RateLimited`] error.
+///
+/// The bucket is shared between all the services created by the layer, so the layer must be
+/// created once per provider quota.
+///
+/// # Examples
+///
+/// ```ignore
+/// let client = ClientBuilder::default()
+///     .layer(RateLimitLayer::per_second(10).with_burst(20).with_max_queued(100))
+///     .icp(config);
+/// ```
+#[derive(Clone, Debug)]
+pub struct RateLimitLayer {
+    quota: Quota,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl RateLimitLayer {
+    /// Create a new rate limit layer allowing the given number of requests per second, with a
+    /// burst of as many requests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `requests_per_second` is zero.
+    pub fn per_second(requests_per_second: u32) -> Self {
+        assert!(requests_per_second > 0, "the rate limit must allow at least one request");
+        Self {
+            quota: Quota {
+                interval: 1_000_000_000 / u64::from(requests_per_second),
+                burst: requests_per_second,
+                max_queued: DEFAULT_MAX_QUEUED,
+            },
+            bucket: Default::default(),
+        }
+    }
+
+    /// Set how many requests can be made at once when no requests were made for a while.
+    /// Defaults to the number of requests per second.
+    pub const fn with_burst(mut self, burst: u32) -> Self {
+        self.quota.burst = burst;
+        self
+    }
+
+    /// Set the maximum number of requests waiting for the rate limit. Requests exceeding the
+    /// rate when as many requests are waiting fail with a [`RateLimited`] error. Set to `0` to
+    /// fail fast instead of queuing. Defaults to 64.
+    pub const fn with_max_queued(mut self, max_queued: usize) -> Self {
+        self.quota.max_queued = max_queued;
+        self
+    }
+
+    /// Returns the number of requests waiting for the rate limit.
+    pub fn queued(&self) -> usize {
+        self.bucket.lock().unwrap().queued
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService { inner, quota: self.quota, bucket: self.bucket.clone() }
+    }
+}
+
+/// A Tower Service used by the [`RateLimitLayer`] that delays or rejects requests exceeding
+/// the rate limit.
+#[derive(Clone, Debug)]
+pub struct RateLimitService<S> {
+    inner: S,
+    quota: Quota,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+/// Counts a request as queued until it is dropped.
+struct Queued(Arc<Mutex<Bucket>>);
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().queued -= 1;
+    }
+}
+
+impl<S> Service<RequestPacket> for RateLimitService<S>
+where
+    S: Transport + Clone,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let tokens = match &request {
+            RequestPacket::Single(_) => 1,
+            RequestPacket::Batch(reqs) => reqs.len().try_into().unwrap_or(u32::MAX),
+        };
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            match bucket.take(self.quota, tokens, ic_cdk::api::time()) {
+                Ok(0) => None,
+                Ok(wait) => {
+                    bucket.queued += 1;
+                    Some((Duration::from_nanos(wait), Queued(self.bucket.clone())))
+                }
+                Err(err) => {
+                    return Box::pin(async move { Err(TransportErrorKind::custom(err)) });
+                }
+            }
+        };
+        let Some((wait, queued)) = wait else { return self.inner.call(request) };
+
+        let inner = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, inner);
+        Box::pin(async move {
+            sleep(wait).await;
+            drop(queued);
+            inner.call(request).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    fn quota(max_queued: usize) -> Quota {
+        RateLimitLayer::per_second(2).with_max_queued(max_queued).quota
+    }
+
+    #[test]
+    fn bursts_then_waits_for_tokens() {
+        let mut bucket = Bucket::default();
+        let now = 10 * SECOND;
+        assert_eq!(bucket.take(quota(8), 1, now), Ok(0));
+        assert_eq!(bucket.take(quota(8), 1, now), Ok(0));
+        assert_eq!(bucket.take(quota(8), 1, now), Ok(SECOND / 2));
+        assert_eq!(bucket.take(quota(8), 2, now), Ok(3 * SECOND / 2));
+        // The bucket refills at the sustained rate.
+        assert_eq!(bucket.take(quota(8), 1, now + 5 * SECOND), Ok(0));
+    }
+
+    #[test]
+    fn fails_fast_when_the_queue_is_full() {
+        let mut bucket = Bucket { full_at: 0, queued: 1 };
+        let now = 10 * SECOND;
+        assert_eq!(bucket.take(quota(1), 2, now), Ok(0));
+        let err = bucket.take(quota(1), 1, now).unwrap_err();
+        assert_eq!(err.queued(), 1);
+        assert_eq!(err.retry_after(), Duration::from_millis(500));
+        assert_eq!(bucket.take(quota(2), 1, now), Ok(SECOND / 2));
+    }
+}
@@ -0,0 +1,369 @@
+use crate::{sleep, RequestContext};
+use alloy_json_rpc::{ErrorPayload, RequestPacket, ResponsePacket};
+use alloy_transport::{Transport, TransportError, TransportErrorKind, TransportFut};
+use std::{
+    borrow::Cow,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::{Layer, Service};
+
+/// Default number of retries for a matching [`RetryRule`].
+const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Default delay before the first retry of a matching [`RetryRule`].
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Default upper bound on the delay between two retries.
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// The class of errors a [`RetryRule`] matches.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum RetryClass {
+    /// JSON-RPC error responses, by code and message.
+    ErrorResp { code: Option<i64>, message: Option<Cow<'static, str>> },
+    /// HTTP errors with the given status, as returned by the
+    /// [`IcpHttpTransport`](crate::IcpHttpTransport).
+    HttpStatus(u16),
+    /// Requests that timed out.
+    Timeout,
+}
+
+/// A rule describing a retriable error and how to back off from it.
+///
+/// A rule matches an error response when the error code equals [`RetryRule::code`] (if set)
+/// and the error message contains [`RetryRule::message`] (if set, case-insensitive), or an
+/// HTTP error with the status of [`RetryRule::http_status`], or a timeout for
+/// [`RetryRule::timeout`]. The delay before retry `n`
+/// (starting at `0`) is `initial_backoff * 2^n`, capped at `max_backoff`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryRule {
+    class: RetryClass,
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryRule {
+    const fn new(code: Option<i64>, message: Option<Cow<'static, str>>) -> Self {
+        Self::with_class(RetryClass::ErrorResp { code, message })
+    }
+
+    const fn with_class(class: RetryClass) -> Self {
+        Self {
+            class,
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        }
+    }
+
+    /// Create a rule matching every error with the given code.
+    pub const fn code(code: i64) -> Self {
+        Self::new(Some(code), None)
+    }
+
+    /// Create a rule matching errors with the given code whose message contains `message`.
+    pub fn code_with_message(code: i64, message: impl Into<Cow<'static, str>>) -> Self {
+        Self::new(Some(code), Some(message.into()))
+    }
+
+    /// Create a rule matching errors with any code whose message contains `message`.
+    pub fn message(message: impl Into<Cow<'static, str>>) -> Self {
+        Self::new(None, Some(message.into()))
+    }
+
+    /// Create a rule matching HTTP errors with the given status, e.g. `503`.
+    pub const fn http_status(status: u16) -> Self {
+        Self::with_class(RetryClass::HttpStatus(status))
+    }
+
+    /// Create a rule matching requests that timed out, see
+    /// [`IcpConfig::set_request_timeout`](crate::IcpConfig::set_request_timeout).
+    pub const fn timeout() -> Self {
+        Self::with_class(RetryClass::Timeout)
+    }
+
+    /// Set the maximum number of retries for errors matching this rule.
+    pub const fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the delay before the first retry.
+    pub const fn with_initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// Set the upper bound on the delay between two retries.
+    pub const fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Returns the maximum number of retries for errors matching this rule.
+    pub const fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Returns `true` if the rule matches the given error payload.
+    pub fn matches(&self, error: &ErrorPayload) -> bool {
+        let RetryClass::ErrorResp { code, message } = &self.class else { return false };
+        code.map_or(true, |code| code == error.code)
+            && message.as_ref().map_or(true, |message| {
+                error.message.to_lowercase().contains(&message.to_lowercase())
+            })
+    }
+
+    /// Returns `true` if the rule matches the given error.
+    pub fn matches_error(&self, error: &TransportError) -> bool {
+        match (&self.class, error) {
+            (RetryClass::ErrorResp { .. }, TransportError::ErrorResp(payload)) => {
+                self.matches(payload)
+            }
+            (
+                RetryClass::HttpStatus(status),
+                TransportError::Transport(TransportErrorKind::HttpError(err)),
+            ) => err.status == *status,
+            (RetryClass::Timeout, TransportError::Transport(TransportErrorKind::Timeout(_))) => {
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the delay to wait before retry number `retry` (starting at `0`).
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry);
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// The set of [`RetryRule`]s used by the [`RetryBackoffLayer`].
+///
+/// Rules are checked in order, and the first matching rule decides how the
+/// request is retried. Errors not matched by any rule are returned immediately.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IcpRetryPolicy {
+    rules: Vec<RetryRule>,
+}
+
+impl Default for IcpRetryPolicy {
+    /// Rate limit and transient errors commonly returned by public RPC providers:
+    ///
+    /// - `-32005`: request limit exceeded.
+    /// - `429`: too many requests, returned as a JSON-RPC code by some providers.
+    /// - `-32603` with a timeout or temporary unavailability message.
+    /// - Any code with a "capacity exceeded" message, as returned by metered providers.
+    /// - HTTP statuses `429`, `502`, `503` and `504`.
+    /// - Timeouts.
+    fn default() -> Self {
+        Self::empty()
+            .with_rule(RetryRule::code(-32005))
+            .with_rule(RetryRule::code(429))
+            .with_rule(RetryRule::code_with_message(-32603, "timeout"))
+            .with_rule(RetryRule::code_with_message(-32603, "timed out"))
+            .with_rule(RetryRule::code_with_message(-32603, "temporarily unavailable"))
+            .with_rule(RetryRule::code_with_message(-32603, "header not found"))
+            .with_rule(RetryRule::message("capacity exceeded"))
+            .with_rule(RetryRule::message("exceeded its compute units per second capacity"))
+            .with_rule(RetryRule::http_status(429))
+            .with_rule(RetryRule::http_status(502))
+            .with_rule(RetryRule::http_status(503))
+            .with_rule(RetryRule::http_status(504))
+            .with_rule(RetryRule::timeout())
+    }
+}
+
+impl IcpRetryPolicy {
+    /// Create a policy without any rules, i.e. one that never retries.
+    pub const fn empty() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Add a rule to the policy. Rules added first take precedence.
+    pub fn with_rule(mut self, rule: RetryRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Returns the rules of this policy.
+    pub fn rules(&self) -> &[RetryRule] {
+        &self.rules
+    }
+
+    /// Returns the first rule matching the given error payload, if any.
+    pub fn rule_for(&self, error: &ErrorPayload) -> Option<&RetryRule> {
+        self.rules.iter().find(|rule| rule.matches(error))
+    }
+
+    /// Returns the first rule matching the given error, if any.
+    pub fn rule_for_error(&self, error: &TransportError) -> Option<&RetryRule> {
+        self.rules.iter().find(|rule| rule.matches_error(error))
+    }
+}
+
+/// A transport layer that retries requests failing with retriable errors, see [`RetryRule`].
+///
+/// This is the ICP counterpart of [`alloy_transport::layers::RetryBackoffLayer`]. Since
+/// canisters cannot use `tokio` sleeps, the backoff between retries is awaited on a one-shot
+/// [`ic_cdk_timers`] timer, see [`sleep`](crate::sleep()).
+///
+/// # Examples
+///
+/// ```ignore
+/// let policy = IcpRetryPolicy::default()
+///     .with_rule(RetryRule::code(-32000).with_max_retries(1));
+/// let client = ClientBuilder::default()
+///     .layer(RetryBackoffLayer::new(policy))
+///     .icp(config);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RetryBackoffLayer {
+    policy: Arc<IcpRetryPolicy>,
+}
+
+impl RetryBackoffLayer {
+    /// Create a new retry layer with the given policy.
+    pub fn new(policy: IcpRetryPolicy) -> Self {
+        Self { policy: Arc::new(policy) }
+    }
+}
+
+impl<S> Layer<S> for RetryBackoffLayer {
+    type Service = RetryBackoffService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RetryBackoffService { inner, policy: self.policy.clone() }
+    }
+}
+
+/// A Tower Service used by the [`RetryBackoffLayer`] that is responsible for retrying requests
+/// based on the error they fail with. See [`IcpRetryPolicy`].
+#[derive(Clone, Debug)]
+pub struct RetryBackoffService<S> {
+    inner: S,
+    policy: Arc<IcpRetryPolicy>,
+}
+
+impl<S> RetryBackoffService<S> {
+    /// Returns the retry policy of this service.
+    pub fn policy(&self) -> &IcpRetryPolicy {
+        &self.policy
+    }
+}
+
+impl<S> Service<RequestPacket> for RetryBackoffService<S>
+where
+    S: Transport + Clone,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let inner = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, inner);
+        let policy = self.policy.clone();
+        let context = RequestContext::current();
+        Box::pin(async move {
+            let mut retry = 0;
+            loop {
+                let fut = context.clone().with_retry(retry).enter(|| inner.call(request.clone()));
+                let err = match fut.await {
+                    Ok(res) => match res.as_error() {
+                        Some(e) => TransportError::ErrorResp(e.clone()),
+                        None => return Ok(res),
+                    },
+                    Err(e) => e,
+                };
+
+                let Some(rule) = policy.rule_for_error(&err) else {
+                    return Err(err);
+                };
+                // Keep the error once the retries are exhausted, so that callers can still
+                // match on it, e.g. on a timeout or the JSON-RPC error of the provider.
+                if retry >= rule.max_retries() {
+                    return Err(err);
+                }
+
+                sleep(rule.backoff(retry)).await;
+                retry += 1;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(code: i64, message: &str) -> ErrorPayload {
+        ErrorPayload { code, message: message.to_string(), data: None }
+    }
+
+    #[test]
+    fn default_policy_matches_transient_errors() {
+        let policy = IcpRetryPolicy::default();
+        assert!(policy.rule_for(&payload(-32005, "limit exceeded")).is_some());
+        assert!(policy.rule_for(&payload(-32603, "Request Timed Out")).is_some());
+        assert!(policy.rule_for(&payload(-32000, "Capacity exceeded, try again")).is_some());
+        assert!(policy.rule_for(&payload(-32603, "execution reverted")).is_none());
+        assert!(policy.rule_for(&payload(3, "execution reverted")).is_none());
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let policy = IcpRetryPolicy::empty()
+            .with_rule(RetryRule::code(-32005).with_max_retries(7))
+            .with_rule(RetryRule::message("limit").with_max_retries(1));
+        assert_eq!(policy.rule_for(&payload(-32005, "limit exceeded")).unwrap().max_retries(), 7);
+        assert_eq!(policy.rule_for(&payload(-32000, "limit exceeded")).unwrap().max_retries(), 1);
+    }
+
+    #[test]
+    fn http_rules_match_http_errors() {
+        let policy = IcpRetryPolicy::default();
+        let unavailable = TransportErrorKind::http_error(503, String::new());
+        assert!(policy.rule_for_error(&unavailable).is_some());
+        assert!(policy
+            .rule_for_error(&TransportErrorKind::http_error(404, String::new()))
+            .is_none());
+        assert!(policy.rule_for_error(&TransportError::ErrorResp(payload(-32005, ""))).is_some());
+        assert!(!RetryRule::http_status(503).matches(&payload(503, "")));
+        let timeout = TransportErrorKind::timeout(Duration::from_secs(30));
+        assert!(policy.rule_for_error(&timeout).is_some());
+        assert!(matches!(&timeout, TransportError::Transport(kind) if kind.recoverable()));
+    }
+
+    #[test]
+    fn backoff_is_exponential_and_capped() {
+        let rule = RetryRule::code(-32005)
+            .with_initial_backoff(Duration::from_millis(500))
+            .with_max_backoff(Duration::from_secs(3));
+        assert_eq!(rule.backoff(0), Duration::from_millis(500));
+        assert_eq!(rule.backoff(1), Duration::from_secs(1));
+        assert_eq!(rule.backoff(2), Duration::from_secs(2));
+        assert_eq!(rule.backoff(3), Duration::from_secs(3));
+        assert_eq!(rule.backoff(40), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn exhausted_retries_keep_the_error() {
+        let timeout = tower::service_fn(|_: RequestPacket| -> TransportFut<'static> {
+            Box::pin(async { Err(TransportErrorKind::timeout(Duration::from_secs(30))) })
+        });
+        let policy = IcpRetryPolicy::empty().with_rule(RetryRule::timeout().with_max_retries(0));
+        let mut service = RetryBackoffLayer::new(policy).layer(timeout);
+        let request =
+            alloy_json_rpc::Request::new("eth_blockNumber", 1.into(), ()).serialize().unwrap();
+        let err = futures::executor::block_on(service.call(request.into())).unwrap_err();
+        assert!(matches!(err, TransportError::Transport(TransportErrorKind::Timeout(_))));
+    }
+}
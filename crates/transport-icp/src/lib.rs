@@ -15,16 +15,105 @@
 )]
 mod evm_rpc;
 
+mod args;
+use args::RequestArgs;
+
+mod buffer;
+use buffer::PooledBuffer;
+
+pub mod layers;
+
+mod batch;
+use batch::RequestBatcher;
+
+mod call_context;
+pub use call_context::WrongCallContext;
+
+mod coalesce;
+use coalesce::{InFlightRequests, Joined};
+
+mod context;
+pub use context::{RequestContext, RequestPriority, Scoped, TraceId};
+
+mod cost;
+pub use cost::{CostModel, CostProjection, MethodCost, MethodWorkload, Workload};
+
+mod cycles;
+use cycles::BudgetTracker;
+pub use cycles::{
+    CallCycles, CyclesAlert, CyclesBudget, CyclesBudgetExceeded, CyclesEstimator, CyclesMeter,
+    LowCycles,
+};
+
+mod dispatch;
+use dispatch::Dispatcher;
+
+mod failover;
+pub use failover::ServiceRotation;
+use failover::{Failover, DEFAULT_FAILOVER_COOLDOWN};
+
+mod headers;
+use headers::ServiceHeaders;
+
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "http")]
+pub use http::{IcpHttpTransport, HTTP_TRANSFORM_METHOD};
+
+#[cfg(feature = "http")]
+mod transform;
+#[cfg(feature = "http")]
+pub use transform::{normalize_json, register_http_transform, round_quantities, HttpTransform};
+
+mod metrics;
+use metrics::MetricsRecorder;
+pub use metrics::{FailureClass, IcpMetrics, MethodMetrics};
+
+mod policy;
+pub use policy::{MethodNotAllowed, MethodPolicy};
+
+mod presets;
+
+mod provider_config;
+pub use provider_config::IcpProviderConfig;
+
+mod quorum;
+pub use quorum::{ConsensusMismatch, QuorumTransport, ResponseComparison};
+
+mod rpc_error;
+pub use rpc_error::EvmRpcError;
+
+mod serializer;
+pub use serializer::ParamsSerializer;
+
+mod sizing;
+use sizing::DEFAULT_RESPONSE_SIZE_RETRIES;
+
+mod timeout;
+
+mod sleep;
+pub use sleep::{sleep, Sleep};
+
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+
+#[cfg(feature = "test-utils-icp")]
+pub mod test_utils_icp;
+
 use alloy_json_rpc::{RequestPacket, ResponsePacket, SerializedRequest};
-use alloy_transport::{TransportError, TransportFut};
-use ic_cdk::api::call::CallResult;
-use std::task;
+use alloy_transport::{Pbf, TransportError, TransportErrorKind, TransportFut, TransportResult};
+use futures::future::Either;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+    task,
+    time::Duration,
+};
 use tower::Service;
 
 pub use evm_rpc::*;
 
-const DEFAULT_CALL_CYCLES: u128 = 60_000_000_000;
-
 const MAX_RESPONSE_SIZE_SMALL: u64 = 1_000;
 const MAX_RESPONSE_SIZE_MEDIUM: u64 = 2_000;
 const MAX_RESPONSE_SIZE_UNKNOWN: u64 = 5_000;
@@ -36,26 +125,278 @@ pub struct IcpConfig {
     rpc_service: RpcService,
     call_cycles: Option<u128>,
     max_response_size: Option<u64>,
+    method_max_response_sizes: BTreeMap<String, u64>,
+    response_size_retries: u32,
+    request_coalescing: bool,
+    request_batching: bool,
+    max_concurrent_requests: Option<usize>,
+    request_timeout: Option<Duration>,
+    poll_interval: Option<Duration>,
+    params_serializer: Option<ParamsSerializer>,
+    method_policy: MethodPolicy,
+    request_logging: bool,
+    cycles_budget: Option<CyclesBudget>,
+    cycles_estimator: CyclesEstimator,
+    cycles_alert: Option<CyclesAlert>,
+    shared_cycles_budget: Option<BudgetTracker>,
+    call_context_assertion: bool,
+    fallback_services: Vec<RpcService>,
+    failover_cooldown: Duration,
+    service_rotation: ServiceRotation,
+    service_headers: ServiceHeaders,
 }
 
 impl IcpConfig {
     /// Create a new [`IcpConfig`] with the given [`RpcService`] and default values for call cycles
     /// and max response size.
     pub const fn new(rpc_service: RpcService) -> Self {
-        Self { rpc_service, call_cycles: None, max_response_size: None }
+        Self {
+            rpc_service,
+            call_cycles: None,
+            max_response_size: None,
+            method_max_response_sizes: BTreeMap::new(),
+            response_size_retries: DEFAULT_RESPONSE_SIZE_RETRIES,
+            request_coalescing: true,
+            request_batching: false,
+            max_concurrent_requests: None,
+            request_timeout: None,
+            poll_interval: None,
+            params_serializer: None,
+            method_policy: MethodPolicy::AllowAll,
+            request_logging: false,
+            cycles_budget: None,
+            cycles_estimator: CyclesEstimator::new(),
+            cycles_alert: None,
+            shared_cycles_budget: None,
+            call_context_assertion: false,
+            fallback_services: Vec::new(),
+            failover_cooldown: DEFAULT_FAILOVER_COOLDOWN,
+            service_rotation: ServiceRotation::Primary,
+            service_headers: ServiceHeaders::new(),
+        }
     }
 
-    /// Set the call cycles for this config.
+    /// Set fixed call cycles for this config, attached to every call instead of the cycles
+    /// estimated by the [`CyclesEstimator`]. A request can attach other cycles with
+    /// [`RequestContext::with_call_cycles`].
     pub const fn set_call_cycles(mut self, call_cycles: u128) -> Self {
         self.call_cycles = Some(call_cycles);
         self
     }
 
-    /// Set the max response size for this config.
+    /// Set the max response size for this config, used for every request instead of the max
+    /// response size of its method. Outcalls are charged for the max response size, whatever
+    /// the size of the response.
     pub const fn set_max_response_size(mut self, max_response_size: u64) -> Self {
         self.max_response_size = Some(max_response_size);
         self
     }
+
+    /// Set the max response size of requests for the given method, replacing the estimate of
+    /// the method, e.g. a larger size for `eth_getLogs` over wide block ranges. The max
+    /// response size of a batch is the sum of the sizes of its requests.
+    ///
+    /// Unlike [`set_max_response_size`](Self::set_max_response_size), this does not apply to
+    /// the other methods, which keep their estimates: from 1 KB for methods returning a single
+    /// value, such as `eth_blockNumber`, to 5 KB for methods returning blocks or logs.
+    pub fn set_method_max_response_size(
+        mut self,
+        method: impl Into<String>,
+        max_response_size: u64,
+    ) -> Self {
+        self.method_max_response_sizes.insert(method.into(), max_response_size);
+        self
+    }
+
+    /// Set how many times a request whose response exceeds its max response size is retried,
+    /// doubling the max response size each time, up to the 2 MB limit of HTTPS outcalls.
+    /// Defaults to 3, so a response may be up to 8 times the size it was estimated at. Set to
+    /// `0` to fail such requests instead.
+    ///
+    /// Each retry is a call of its own, charged for its larger max response size.
+    pub const fn set_response_size_retries(mut self, retries: u32) -> Self {
+        self.response_size_retries = retries;
+        self
+    }
+
+    /// Enable or disable request coalescing for this config. Enabled by default.
+    ///
+    /// When enabled, a request that is identical (same method and params) to a request that is
+    /// already in flight does not make its own outcall, but waits for the in-flight request and
+    /// receives a copy of its response. Methods with side effects, such as
+    /// `eth_sendRawTransaction` and filter methods, are never coalesced.
+    pub const fn set_request_coalescing(mut self, enabled: bool) -> Self {
+        self.request_coalescing = enabled;
+        self
+    }
+
+    /// Enable or disable request batching for this config. Disabled by default.
+    ///
+    /// When enabled, requests made in the same message execution, such as the concurrent
+    /// `eth_chainId`, `eth_getTransactionCount` and `eth_estimateGas` requests of the fillers of
+    /// a provider, are sent as a single JSON-RPC batch with one call to the EVM RPC canister.
+    /// The batch is sent from a timer set when the first request is queued, which delays
+    /// requests until the next round. Batches made with [`BatchRequest`] are always sent with a
+    /// single call.
+    ///
+    /// [`BatchRequest`]: https://docs.rs/alloy-rpc-client/latest/alloy_rpc_client/struct.BatchRequest.html
+    pub const fn set_request_batching(mut self, enabled: bool) -> Self {
+        self.request_batching = enabled;
+        self
+    }
+
+    /// Set the maximum number of concurrent outcalls for this config. Unlimited by default.
+    ///
+    /// Canisters have a hard limit on the number of outstanding calls, and calls made beyond it
+    /// fail with a system error. Requests made while this limit is reached are queued instead,
+    /// and dispatched by [`RequestPriority`] as outcalls complete. See
+    /// [`RequestContext::with_priority`].
+    pub const fn set_max_concurrent_requests(mut self, max_concurrent_requests: usize) -> Self {
+        self.max_concurrent_requests = Some(max_concurrent_requests);
+        self
+    }
+
+    /// Set how long to wait for each request before failing with a
+    /// [`Timeout`](TransportErrorKind::Timeout) error. None by default, as the system gives up
+    /// on outcalls that hang eventually, which may take minutes. A request can set another
+    /// timeout with [`RequestContext::with_timeout`].
+    ///
+    /// The management canister cannot cancel outcalls, so a request that times out still
+    /// completes in the background: its outcalls are paid for and recorded in the metrics,
+    /// only their response is dropped. Timeouts are recoverable errors, retried by pollers and
+    /// by the default [`IcpRetryPolicy`](layers::IcpRetryPolicy).
+    pub const fn set_request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = Some(request_timeout);
+        self
+    }
+
+    /// Set the interval between polls of the pollers and watchers of the client, e.g. the
+    /// block time of the chain. Defaults to the poll interval of the client, 7 seconds.
+    pub const fn set_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = Some(poll_interval);
+        self
+    }
+
+    /// Returns the interval between polls of the client, if set.
+    pub const fn poll_interval(&self) -> Option<Duration> {
+        self.poll_interval
+    }
+
+    /// Set the [`ParamsSerializer`] applied to the params of every request for this config.
+    pub fn set_params_serializer(mut self, params_serializer: ParamsSerializer) -> Self {
+        self.params_serializer = Some(params_serializer);
+        self
+    }
+
+    /// Set the [`MethodPolicy`] for this config. Every method is allowed by default.
+    pub fn set_method_policy(mut self, method_policy: MethodPolicy) -> Self {
+        self.method_policy = method_policy;
+        self
+    }
+
+    /// Enable or disable request logging for this config. Disabled by default.
+    ///
+    /// When enabled, the outcome of every request is written to the canister log, tagged with
+    /// the [`TraceId`] of its [`RequestContext`], e.g.
+    /// `[trace 000000000000002a] eth_getTransactionReceipt: ok in 1532ms`.
+    pub const fn set_request_logging(mut self, enabled: bool) -> Self {
+        self.request_logging = enabled;
+        self
+    }
+
+    /// Set the [`CyclesBudget`] for this config. Unlimited by default.
+    pub const fn set_cycles_budget(mut self, cycles_budget: CyclesBudget) -> Self {
+        self.cycles_budget = Some(cycles_budget);
+        self
+    }
+
+    /// Set the [`CyclesEstimator`] for this config, used unless fixed call cycles are set.
+    pub const fn set_cycles_estimator(mut self, cycles_estimator: CyclesEstimator) -> Self {
+        self.cycles_estimator = cycles_estimator;
+        self
+    }
+
+    /// Set the [`CyclesAlert`] for this config, raised when the canister runs low on cycles.
+    pub fn set_cycles_alert(mut self, cycles_alert: CyclesAlert) -> Self {
+        self.cycles_alert = Some(cycles_alert);
+        self
+    }
+
+    /// Share the [`CyclesBudget`] and [`CyclesAlert`] of the given transport with the transport
+    /// of this config, so the requests of both are accounted against a single budget, e.g. the
+    /// transports of several chains. The budget and alert of this config are ignored.
+    pub fn share_cycles_budget(mut self, transport: &IcpTransport) -> Self {
+        self.shared_cycles_budget = Some(transport.cycles_budget.clone());
+        self
+    }
+
+    /// Enable or disable the call context assertion for this config. Disabled by default.
+    ///
+    /// When enabled, debug builds panic if the transport is created from a call context that
+    /// can't make outcalls, such as a query, to catch a provider set up in the wrong method
+    /// early. Requests made from such a context always fail with a [`WrongCallContext`] error.
+    pub const fn set_call_context_assertion(mut self, enabled: bool) -> Self {
+        self.call_context_assertion = enabled;
+        self
+    }
+
+    /// Set the services to fail over to, in order, when the [`RpcService`] of this config
+    /// fails. None by default.
+    ///
+    /// A request is sent to the next service when the outcall to the provider fails, the
+    /// provider answers with a 5xx or 429 HTTP status or a rate limit error, or the call to the
+    /// EVM RPC canister is rejected with a transient error. A service that failed is then
+    /// tried last for the failover cooldown, see
+    /// [`set_failover_cooldown`](Self::set_failover_cooldown). Requests are only sent to one
+    /// service at a time, and each attempt is charged against the [`CyclesBudget`].
+    pub fn set_fallback_services<I>(mut self, fallback_services: I) -> Self
+    where
+        I: IntoIterator<Item = RpcService>,
+    {
+        self.fallback_services = fallback_services.into_iter().collect();
+        self
+    }
+
+    /// Set how long a service that failed is tried last. Defaults to 60 seconds.
+    pub const fn set_failover_cooldown(mut self, failover_cooldown: Duration) -> Self {
+        self.failover_cooldown = failover_cooldown;
+        self
+    }
+
+    /// Set how reads are spread over the primary and fallback services, e.g. round-robin to
+    /// stay within the rate limits of each provider. Defaults to [`ServiceRotation::Primary`],
+    /// sending every request to the primary service first.
+    pub fn set_service_rotation(mut self, service_rotation: ServiceRotation) -> Self {
+        self.service_rotation = service_rotation;
+        self
+    }
+
+    /// Add a static header, e.g. `Authorization` or `x-api-key`, to the requests sent to the
+    /// [`RpcService::Custom`] services with the given URL, primary or fallback.
+    ///
+    /// The header is only added to the arguments of the calls to the EVM RPC canister, so it is
+    /// never returned by [`IcpTransport::rpc_service`] nor printed with the transport. Its value
+    /// is redacted from the errors of the transport, and from the responses of the
+    /// `IcpHttpTransport`s of the canister before they reach consensus. Responses of the EVM
+    /// RPC canister reach consensus in that canister, which strips their headers but not their
+    /// bodies.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let config = IcpConfig::new(RpcService::Custom(RpcApi { url: URL.into(), headers: None }))
+    ///     .set_service_header(URL, "x-api-key", API_KEY);
+    /// ```
+    pub fn set_service_header(
+        mut self,
+        url: impl Into<String>,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        let header = HttpHeader { name: name.into(), value: value.into() };
+        self.service_headers.insert(url.into(), header);
+        self
+    }
 }
 
 /// An ICP transport.
@@ -65,40 +406,126 @@ impl IcpConfig {
 #[derive(Clone, Debug)]
 pub struct IcpTransport {
     rpc_service: RpcService,
+    request_args: RequestArgs,
     call_cycles: Option<u128>,
     max_response_size: Option<u64>,
+    method_max_response_sizes: Arc<BTreeMap<String, u64>>,
+    response_size_retries: u32,
+    metrics: MetricsRecorder,
+    in_flight: Option<InFlightRequests>,
+    batcher: Option<RequestBatcher>,
+    dispatcher: Dispatcher,
+    request_timeout: Option<Duration>,
+    params_serializer: Arc<Mutex<Option<ParamsSerializer>>>,
+    method_policy: Arc<Mutex<MethodPolicy>>,
+    request_logging: bool,
+    cycles_budget: BudgetTracker,
+    cycles_estimator: CyclesEstimator,
+    failover: Option<Failover>,
+    service_headers: Arc<ServiceHeaders>,
 }
 
 impl IcpTransport {
     /// Create a new [`IcpTransport`] using the given [`IcpConfig`] details.
     pub fn with_config(config: IcpConfig) -> Self {
+        debug_assert!(
+            !config.call_context_assertion || call_context::can_make_calls(),
+            "IcpTransport created from a query, outcalls can only be made from update methods \
+             and timers"
+        );
+        let failover = Failover::new(
+            &config.rpc_service,
+            &config.fallback_services,
+            &config.service_headers,
+            config.failover_cooldown,
+            config.service_rotation,
+        );
         Self {
+            request_args: RequestArgs::new(&config.service_headers.apply(&config.rpc_service)),
             rpc_service: config.rpc_service,
             call_cycles: config.call_cycles,
             max_response_size: config.max_response_size,
+            method_max_response_sizes: Arc::new(config.method_max_response_sizes),
+            response_size_retries: config.response_size_retries,
+            metrics: MetricsRecorder::default(),
+            in_flight: config.request_coalescing.then(InFlightRequests::default),
+            batcher: config.request_batching.then(RequestBatcher::default),
+            dispatcher: Dispatcher::new(config.max_concurrent_requests),
+            request_timeout: config.request_timeout,
+            params_serializer: Arc::new(Mutex::new(config.params_serializer)),
+            method_policy: Arc::new(Mutex::new(config.method_policy)),
+            request_logging: config.request_logging,
+            cycles_budget: config
+                .shared_cycles_budget
+                .unwrap_or_else(|| BudgetTracker::new(config.cycles_budget, config.cycles_alert)),
+            cycles_estimator: config.cycles_estimator,
+            failover,
+            service_headers: Arc::new(config.service_headers),
         }
     }
 
-    /// Set the [`RpcService`] for this transport.
+    /// Set the [`RpcService`] for this transport. The fallback services are kept, and their
+    /// health, the rotation and the routing of filters are reset.
     pub fn set_rpc_service(&mut self, rpc_service: RpcService) {
+        if let Some(failover) = &self.failover {
+            self.failover = Failover::new(
+                &rpc_service,
+                &failover.fallbacks(),
+                &self.service_headers,
+                failover.cooldown(),
+                failover.rotation(),
+            );
+        }
+        self.request_args = RequestArgs::new(&self.service_headers.apply(&rpc_service));
         self.rpc_service = rpc_service;
     }
 
+    /// Returns the services this transport fails over to, see
+    /// [`IcpConfig::set_fallback_services`].
+    pub fn fallback_services(&self) -> Vec<RpcService> {
+        self.failover.as_ref().map(Failover::fallbacks).unwrap_or_default()
+    }
+
+    /// Returns the services, including the primary service, that failed within the failover
+    /// cooldown and are tried last.
+    pub fn unhealthy_services(&self) -> Vec<RpcService> {
+        self.failover
+            .as_ref()
+            .map(|failover| failover.unhealthy(ic_cdk::api::time()))
+            .unwrap_or_default()
+    }
+
     /// Get a reference to the rpc service.
     pub const fn rpc_service(&self) -> &RpcService {
         &self.rpc_service
     }
 
-    /// Set the call cycles for this transport.
+    /// Set fixed call cycles for this transport, see [`IcpConfig::set_call_cycles`].
     pub fn set_call_cycles(&mut self, call_cycles: u128) {
         self.call_cycles = Some(call_cycles);
     }
 
-    /// Get the call cycles for this transport.
+    /// Remove the fixed call cycles of this transport, estimating the cycles of every call with
+    /// the [`CyclesEstimator`] instead.
+    pub fn clear_call_cycles(&mut self) {
+        self.call_cycles = None;
+    }
+
+    /// Get the fixed call cycles for this transport, if set.
     pub const fn call_cycles(&self) -> Option<u128> {
         self.call_cycles
     }
 
+    /// Set the [`CyclesEstimator`] for this transport.
+    pub fn set_cycles_estimator(&mut self, cycles_estimator: CyclesEstimator) {
+        self.cycles_estimator = cycles_estimator;
+    }
+
+    /// Get the [`CyclesEstimator`] for this transport.
+    pub const fn cycles_estimator(&self) -> &CyclesEstimator {
+        &self.cycles_estimator
+    }
+
     /// Set the max response size for this transport.
     pub fn set_max_response_size(&mut self, max_response_size: u64) {
         self.max_response_size = Some(max_response_size);
@@ -109,6 +536,220 @@ impl IcpTransport {
         self.max_response_size
     }
 
+    /// Enable or disable request coalescing for this transport. See
+    /// [`IcpConfig::set_request_coalescing`].
+    pub fn set_request_coalescing(&mut self, enabled: bool) {
+        if enabled != self.in_flight.is_some() {
+            self.in_flight = enabled.then(InFlightRequests::default);
+        }
+    }
+
+    /// Returns `true` if identical concurrent requests share a single outcall.
+    pub const fn request_coalescing(&self) -> bool {
+        self.in_flight.is_some()
+    }
+
+    /// Enable or disable request batching for this transport. See
+    /// [`IcpConfig::set_request_batching`].
+    pub fn set_request_batching(&mut self, enabled: bool) {
+        if enabled != self.batcher.is_some() {
+            self.batcher = enabled.then(RequestBatcher::default);
+        }
+    }
+
+    /// Returns `true` if requests made together are sent as a single batch.
+    pub const fn request_batching(&self) -> bool {
+        self.batcher.is_some()
+    }
+
+    /// Set the maximum number of concurrent outcalls for this transport. See
+    /// [`IcpConfig::set_max_concurrent_requests`].
+    ///
+    /// The limit is shared between all clones of the transport, so it applies to every request
+    /// made through the client, including pollers and batches. A batch counts as a single
+    /// outcall. Raising the limit dispatches queued requests right away, while lowering it lets
+    /// the outcalls in flight complete before queued requests are dispatched.
+    pub fn set_max_concurrent_requests(&self, max_concurrent_requests: usize) {
+        self.dispatcher.set_max_concurrent(Some(max_concurrent_requests));
+    }
+
+    /// Remove the limit on the number of concurrent outcalls, dispatching all queued requests.
+    pub fn clear_max_concurrent_requests(&self) {
+        self.dispatcher.set_max_concurrent(None);
+    }
+
+    /// Returns the maximum number of concurrent outcalls, if limited.
+    pub fn max_concurrent_requests(&self) -> Option<usize> {
+        self.dispatcher.max_concurrent()
+    }
+
+    /// Returns the number of outcalls currently in flight.
+    pub fn in_flight_requests(&self) -> usize {
+        self.dispatcher.in_flight()
+    }
+
+    /// Returns the number of requests waiting for an outcall slot.
+    pub fn queued_requests(&self) -> usize {
+        self.dispatcher.queued()
+    }
+
+    /// Set the [`ParamsSerializer`] applied to the params of every request, replacing any
+    /// previously set serializer.
+    ///
+    /// The serializer is shared between all clones of the transport, so this applies to every
+    /// request made through the client, including pollers and batches.
+    pub fn set_params_serializer(&self, params_serializer: ParamsSerializer) {
+        *self.params_serializer.lock().unwrap() = Some(params_serializer);
+    }
+
+    /// Remove the [`ParamsSerializer`], sending params as they are serialized by the caller.
+    pub fn clear_params_serializer(&self) {
+        *self.params_serializer.lock().unwrap() = None;
+    }
+
+    /// Shut the transport down: requests waiting for an outcall slot are cancelled, and new
+    /// requests are rejected. Outcalls in flight are not affected, see
+    /// [`IcpTransport::wait_idle`].
+    ///
+    /// Cancelled and rejected requests fail with [`TransportErrorKind::BackendGone`]. The shut
+    /// down is shared between all clones of the transport, and cannot be undone.
+    pub fn shutdown(&self) {
+        self.dispatcher.close();
+    }
+
+    /// Returns `true` if the transport has been shut down.
+    pub fn is_shut_down(&self) -> bool {
+        self.dispatcher.is_closed()
+    }
+
+    /// Wait until no outcalls are in flight, or until `timeout` has elapsed.
+    ///
+    /// Returns `true` if all outcalls completed in time.
+    pub async fn wait_idle(&self, timeout: Duration) -> bool {
+        let idle = std::pin::pin!(self.dispatcher.idle());
+        matches!(futures::future::select(idle, sleep(timeout)).await, Either::Left(_))
+    }
+
+    /// Set the [`MethodPolicy`] of this transport, replacing the previous policy.
+    ///
+    /// The policy is shared between all clones of the transport, so it applies to every request
+    /// made through the client.
+    pub fn set_method_policy(&self, method_policy: MethodPolicy) {
+        *self.method_policy.lock().unwrap() = method_policy;
+    }
+
+    /// Returns the [`MethodPolicy`] of this transport.
+    pub fn method_policy(&self) -> MethodPolicy {
+        self.method_policy.lock().unwrap().clone()
+    }
+
+    /// Enable or disable request logging for this transport. See
+    /// [`IcpConfig::set_request_logging`].
+    pub fn set_request_logging(&mut self, enabled: bool) {
+        self.request_logging = enabled;
+    }
+
+    /// Returns `true` if the outcome of every request is written to the canister log.
+    pub const fn request_logging(&self) -> bool {
+        self.request_logging
+    }
+
+    /// Set how long to wait for each request, or `None` to wait until the system gives up.
+    /// See [`IcpConfig::set_request_timeout`].
+    pub fn set_request_timeout(&mut self, request_timeout: Option<Duration>) {
+        self.request_timeout = request_timeout;
+    }
+
+    /// Returns how long to wait for each request, if set.
+    pub const fn request_timeout(&self) -> Option<Duration> {
+        self.request_timeout
+    }
+
+    /// Set the [`CyclesBudget`] of this transport, replacing the previous budget. The cycles
+    /// spent in the current period are kept.
+    ///
+    /// The budget is shared between all clones of the transport, so it applies to every request
+    /// made through the client, including pollers and batches.
+    pub fn set_cycles_budget(&self, cycles_budget: CyclesBudget) {
+        self.cycles_budget.set_budget(Some(cycles_budget));
+    }
+
+    /// Remove the [`CyclesBudget`] of this transport.
+    pub fn clear_cycles_budget(&self) {
+        self.cycles_budget.set_budget(None);
+    }
+
+    /// Returns the [`CyclesBudget`] of this transport, if any.
+    pub fn cycles_budget(&self) -> Option<CyclesBudget> {
+        self.cycles_budget.budget()
+    }
+
+    /// Set the [`CyclesAlert`] of this transport, replacing the previous alert.
+    ///
+    /// The alert is shared between all clones of the transport, so it is raised by every
+    /// request made through the client.
+    pub fn set_cycles_alert(&self, cycles_alert: CyclesAlert) {
+        self.cycles_budget.set_alert(Some(cycles_alert));
+    }
+
+    /// Remove the [`CyclesAlert`] of this transport.
+    pub fn clear_cycles_alert(&self) {
+        self.cycles_budget.set_alert(None);
+    }
+
+    /// Returns the [`CyclesAlert`] of this transport, if any.
+    pub fn cycles_alert(&self) -> Option<CyclesAlert> {
+        self.cycles_budget.alert()
+    }
+
+    /// Returns the cycles spent in the current period of the [`CyclesBudget`], including the
+    /// cycles attached to calls still in flight.
+    pub fn cycles_budget_spent(&self) -> u128 {
+        self.cycles_budget.spent()
+    }
+
+    /// Returns the percentage of the spend per period of the [`CyclesBudget`] that remains in
+    /// the current period, or `None` if the spend is not limited.
+    pub fn cycles_budget_remaining_percent(&self) -> Option<u8> {
+        self.cycles_budget.remaining_percent()
+    }
+
+    /// Returns a snapshot of the request metrics of this transport.
+    ///
+    /// Metrics are shared between all clones of the transport, so this covers every request
+    /// made through the client, including pollers and batches.
+    pub fn metrics(&self) -> IcpMetrics {
+        self.metrics.snapshot()
+    }
+
+    /// Reset the request metrics of this transport.
+    pub fn reset_metrics(&self) {
+        self.metrics.reset();
+    }
+
+    /// Estimate the cycles attached to the call of a request, e.g. to check that the canister
+    /// holds enough cycles before starting a long polling job.
+    ///
+    /// The max response size is `expected_response_size` if set, or the max response size the
+    /// request would be sent with. The cycles are the fixed call cycles of the current
+    /// [`RequestContext`] or of this transport if set, or else the estimate of the
+    /// [`CyclesEstimator`] at its subnet size and margin. The cycles not needed by the outcall
+    /// are refunded, so less is actually spent, but each attempt on a fallback service and each
+    /// retry with a larger max response size attaches cycles again.
+    pub fn estimate_request_cycles(
+        &self,
+        request_packet: impl Into<RequestPacket>,
+        expected_response_size: Option<u64>,
+    ) -> TransportResult<u128> {
+        let request_packet = request_packet.into();
+        let mut payload = Vec::new();
+        self.serialize(&request_packet, &mut payload)?;
+        let max_response_size = expected_response_size
+            .or(self.max_response_size)
+            .unwrap_or_else(|| self.estimate_max_response_size(&request_packet));
+        Ok(self.call_cycles_for(&RequestContext::current(), &payload, max_response_size))
+    }
+
     /// Check if the transport is local. Always `false` for now.
     pub const fn is_local(&self) -> bool {
         // Currently always returns false. We could add a check here to see
@@ -118,77 +759,465 @@ impl IcpTransport {
     }
 
     fn estimate_max_response_size(&self, request_packet: &RequestPacket) -> u64 {
-        let max_response_size = |serialized_request: &SerializedRequest| -> u64 {
-            match serialized_request.meta().method.as_ref() {
-                "eth_blockNumber" => MAX_RESPONSE_SIZE_SMALL,
-                "eth_getBalance" => MAX_RESPONSE_SIZE_SMALL,
-                "eth_chainId" => MAX_RESPONSE_SIZE_SMALL,
-                "eth_feeHistory" => MAX_RESPONSE_SIZE_MEDIUM,
-                "eth_estimateGas" => MAX_RESPONSE_SIZE_SMALL,
-                "eth_gasPrice" => MAX_RESPONSE_SIZE_SMALL,
-                "eth_getBlockTransactionCountByHash" => MAX_RESPONSE_SIZE_SMALL,
-                "eth_getBlockTransactionCountByNumber" => MAX_RESPONSE_SIZE_SMALL,
-                "eth_getCode" => MAX_RESPONSE_SIZE_SMALL,
-                "eth_getProof" => MAX_RESPONSE_SIZE_SMALL,
-                "eth_getStorageAt" => MAX_RESPONSE_SIZE_SMALL,
-                "eth_getTransactionByBlockHashAndIndex" => MAX_RESPONSE_SIZE_MEDIUM,
-                "eth_getTransactionByHash" => MAX_RESPONSE_SIZE_MEDIUM,
-                "eth_getTransactionCount" => MAX_RESPONSE_SIZE_SMALL,
-                "eth_getUncleCountByBlockHash" => MAX_RESPONSE_SIZE_SMALL,
-                "eth_getUncleCountByBlockNumber" => MAX_RESPONSE_SIZE_SMALL,
-                "eth_maxPriorityFeePerGas" => MAX_RESPONSE_SIZE_SMALL,
-                "eth_protocolVersion" => MAX_RESPONSE_SIZE_SMALL,
-                _ => MAX_RESPONSE_SIZE_UNKNOWN,
-            }
+        let max_response_size = |req: &SerializedRequest| {
+            self.method_max_response_sizes
+                .get(req.method())
+                .copied()
+                .unwrap_or_else(|| estimate_max_response_size(req.method()))
         };
-
         match request_packet {
             RequestPacket::Single(req) => max_response_size(req),
             RequestPacket::Batch(reqs) => reqs.iter().map(max_response_size).sum(),
         }
     }
 
+    /// Queue a single request for the next batch if request batching is enabled, or make it
+    /// now, waiting for it until its timeout if any. Requests from queries are made now, to be
+    /// rejected.
+    fn batch_or_request(&self, request_packet: RequestPacket) -> TransportFut<'static> {
+        let can_make_calls = call_context::can_make_calls();
+        let timeout = RequestContext::current().timeout().or(self.request_timeout);
+        let request = match (&self.batcher, request_packet) {
+            (Some(batcher), RequestPacket::Single(request)) if can_make_calls => {
+                let (batched, schedule) = batcher.push(RequestContext::current(), request);
+                if schedule {
+                    let transport = self.clone();
+                    ic_cdk_timers::set_timer(Duration::ZERO, move || transport.send_batches());
+                }
+                Box::pin(batched.wait())
+            }
+            (_, request_packet) => self.request(request_packet),
+        };
+        match timeout {
+            Some(timeout) if can_make_calls => timeout::with_timeout(request, timeout),
+            _ => request,
+        }
+    }
+
+    /// Send the queued batches, each with its own call, in the context they were made in.
+    fn send_batches(&self) {
+        let Some(batcher) = &self.batcher else { return };
+        for batch in batcher.take() {
+            let (context, request_packet, waiters) = batch.into_parts();
+            let response = context.enter(|| self.request(request_packet));
+            ic_cdk::spawn(async move { waiters.complete(&response.await) });
+        }
+    }
+
     /// Make an EVM RPC request by calling the `request` method on the EVM RPC canister.
     fn request(&self, request_packet: RequestPacket) -> TransportFut<'static> {
-        let rpc_service = self.rpc_service.clone();
         let max_response_size =
             self.max_response_size.unwrap_or(self.estimate_max_response_size(&request_packet));
-        let call_cycles = self.call_cycles.unwrap_or(DEFAULT_CALL_CYCLES);
+
+        let outcall = match self.outcall(&request_packet) {
+            Ok(outcall) => outcall,
+            Err(err) => return Box::pin(async move { Err(err) }),
+        };
+        let in_flight = self.in_flight.clone();
+        let mut payload = PooledBuffer::take();
+        if let Err(err) = self.serialize(&request_packet, &mut payload) {
+            return Box::pin(async move { Err(err) });
+        }
+        let payload = Payload {
+            body: payload,
+            call_cycles: outcall.context.call_cycles().or(self.call_cycles),
+            cycles_estimator: self.cycles_estimator,
+        };
+        let request_args = self.request_args.clone();
+        let failover = self.failover.clone();
+        let mut response_size_retries = self.response_size_retries;
 
         Box::pin(async move {
-            let serialized_request = request_packet.serialize().map_err(TransportError::ser_err)?;
-
-            let call_result: CallResult<(RequestResult,)> = evm_rpc
-                .request(
-                    rpc_service,
-                    serialized_request.to_string(),
-                    max_response_size,
-                    call_cycles,
-                )
-                .await;
-
-            match call_result {
-                Ok((request_result,)) => match request_result {
-                    RequestResult::Ok(ok_result) => serde_json::from_str(&ok_result)
-                        .map_err(|err| TransportError::deser_err(err, &ok_result)),
-                    RequestResult::Err(rpc_error) => {
-                        Err(TransportError::ErrorResp(alloy_json_rpc::ErrorPayload {
-                            code: 6, // RPC error
-                            message: format!("{:?}", rpc_error),
-                            data: None,
-                        }))
+            let leader = match in_flight.and_then(|in_flight| in_flight.join(&request_packet)) {
+                Some(Joined::Follower(follower)) => {
+                    outcall.metrics.record_coalesced(&request_packet);
+                    if outcall.logging {
+                        log_request(&outcall.context, &request_packet, format_args!("coalesced"));
                     }
-                },
-                Err(err) => Err(TransportError::ErrorResp(alloy_json_rpc::ErrorPayload {
-                    code: err.0 as i64,
-                    message: err.1,
-                    data: None,
-                })),
+                    return follower.wait().await;
+                }
+                Some(Joined::Leader(leader)) => Some(leader),
+                None => None,
+            };
+            let mut max_response_size = max_response_size;
+            let result = loop {
+                let mut call = CallOutcome::default();
+                let result = match &failover {
+                    Some(failover) => {
+                        outcall
+                            .clone()
+                            .dispatch_with_failover(
+                                &request_packet,
+                                failover,
+                                (&payload, max_response_size),
+                                &mut call,
+                            )
+                            .await
+                    }
+                    None => {
+                        let args = request_args.encode(&payload.body, max_response_size);
+                        let call_cycles = payload.call_cycles(max_response_size);
+                        outcall
+                            .clone()
+                            .dispatch_to(&request_packet, args, call_cycles, &mut call)
+                            .await
+                    }
+                };
+                let larger = sizing::grow(max_response_size)
+                    .filter(|_| call.response_too_large && response_size_retries > 0);
+                let Some(larger) = larger else { break result };
+                if outcall.logging {
+                    log_request(
+                        &outcall.context,
+                        &request_packet,
+                        format_args!("response too large, retrying with {larger} bytes"),
+                    );
+                }
+                response_size_retries -= 1;
+                max_response_size = larger;
+            };
+            if let Some(leader) = leader {
+                leader.complete(&result);
+            }
+            result
+        })
+    }
+
+    /// Make an EVM RPC request with additional HTTP headers computed from its serialized
+    /// payload, such as a signature of the request body authenticating the canister.
+    ///
+    /// The headers are added to the headers of the [`RpcService::Custom`] of this transport and
+    /// its static headers, see [`IcpConfig::set_service_header`], for this request only.
+    /// Requests made with headers are never coalesced.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let response = transport
+    ///     .request_with_headers(request, |body| {
+    ///         Box::pin(async move {
+    ///             let signature = sign_body(&body).await?;
+    ///             Ok(vec![HttpHeader { name: "X-Signature".into(), value: signature }])
+    ///         })
+    ///     })
+    ///     .await?;
+    /// ```
+    pub fn request_with_headers<F>(
+        &self,
+        request_packet: impl Into<RequestPacket>,
+        headers: F,
+    ) -> TransportFut<'static>
+    where
+        F: FnOnce(Vec<u8>) -> Pbf<'static, Vec<HttpHeader>, String> + Send + 'static,
+    {
+        let request_packet = request_packet.into();
+        let rpc_service = self.service_headers.apply(&self.rpc_service);
+        let RpcService::Custom(api) = rpc_service.as_ref() else {
+            return Box::pin(async move {
+                Err(TransportErrorKind::custom_str("request headers require a custom RPC service"))
+            });
+        };
+        let max_response_size =
+            self.max_response_size.unwrap_or(self.estimate_max_response_size(&request_packet));
+
+        let outcall = match self.outcall(&request_packet) {
+            Ok(outcall) => outcall,
+            Err(err) => return Box::pin(async move { Err(err) }),
+        };
+        let mut payload = Vec::new();
+        if let Err(err) = self.serialize(&request_packet, &mut payload) {
+            return Box::pin(async move { Err(err) });
+        }
+        let call_cycles = self.call_cycles_for(&outcall.context, &payload, max_response_size);
+        let mut api = api.clone();
+
+        Box::pin(async move {
+            let headers = headers(payload.clone())
+                .await
+                .map_err(|err| TransportErrorKind::custom_str(&err))?;
+            api.headers.get_or_insert_with(Vec::new).extend(headers);
+            let args =
+                RequestArgs::new(&RpcService::Custom(api)).encode(&payload, max_response_size);
+            outcall.dispatch(&request_packet, args, call_cycles).await
+        })
+    }
+
+    /// Check that the request is allowed, and capture what its outcall is dispatched with.
+    fn outcall(&self, request_packet: &RequestPacket) -> TransportResult<Outcall> {
+        let context = RequestContext::current();
+        if let Err(err) = call_context::check(request_packet) {
+            if self.request_logging {
+                log_request(&context, request_packet, format_args!("rejected: {err}"));
+            }
+            return Err(TransportErrorKind::custom(err));
+        }
+        let allowed = self
+            .method_policy
+            .lock()
+            .unwrap()
+            .check(request_packet)
+            .and_then(|_| context.method_policy().map_or(Ok(()), |p| p.check(request_packet)));
+        if let Err(err) = allowed {
+            if self.request_logging {
+                log_request(&context, request_packet, format_args!("rejected: {err}"));
             }
+            return Err(TransportErrorKind::custom(err));
+        }
+        Ok(Outcall {
+            metrics: self.metrics.clone(),
+            dispatcher: self.dispatcher.clone(),
+            cycles_budget: self.cycles_budget.clone(),
+            logging: self.request_logging,
+            context,
+        })
+    }
+
+    /// Serialize the request to the buffer, applying the [`ParamsSerializer`] if set.
+    fn serialize(
+        &self,
+        request_packet: &RequestPacket,
+        buffer: &mut Vec<u8>,
+    ) -> TransportResult<()> {
+        let params_serializer = self.params_serializer.lock().unwrap().clone();
+        serializer::serialize_packet(request_packet, params_serializer.as_ref(), buffer)
+            .map_err(TransportError::ser_err)
+    }
+
+    /// Returns the cycles to attach to the call of a request: the cycles of its
+    /// [`RequestContext`], the fixed call cycles of this transport, or an estimate.
+    fn call_cycles_for(
+        &self,
+        context: &RequestContext,
+        payload: &[u8],
+        max_response_size: u64,
+    ) -> u128 {
+        context.call_cycles().or(self.call_cycles).unwrap_or_else(|| {
+            self.cycles_estimator.estimate(payload.len() as u64, max_response_size)
+        })
+    }
+
+    async fn send(
+        args: PooledBuffer,
+        call_cycles: u128,
+        cycles: &mut CallCycles,
+        call: &mut CallOutcome,
+    ) -> TransportResult<ResponsePacket> {
+        let call_result = RequestArgs::call(args, call_cycles).await;
+        // Only valid until the next call, so read it before anything else is awaited.
+        let refunded = ic_cdk::api::call::msg_cycles_refunded128();
+        *cycles = CallCycles { calls: 1, attached: call_cycles, refunded };
+        *call = CallOutcome {
+            provider_failed: failover::is_provider_failure(&call_result),
+            response_too_large: sizing::is_response_too_large(&call_result),
+            failure: FailureClass::of_call(&call_result),
+            response_bytes: match &call_result {
+                Ok((RequestResult::Ok(body),)) => body.len() as u64,
+                _ => 0,
+            },
+        };
+
+        let result = match call_result {
+            Ok((request_result,)) => match request_result {
+                RequestResult::Ok(ok_result) => serde_json::from_str(&ok_result).map_err(|err| {
+                    call.failure = Some(FailureClass::Deserialization);
+                    TransportError::deser_err(err, headers::redact(ok_result.clone()))
+                }),
+                RequestResult::Err(rpc_error) => Err(rpc_error::transport_error(rpc_error)),
+            },
+            Err(err) => Err(TransportError::ErrorResp(alloy_json_rpc::ErrorPayload {
+                code: err.0 as i64,
+                message: headers::redact(err.1),
+                data: None,
+            })),
+        };
+        if result.as_ref().is_ok_and(failover::is_rate_limited) {
+            call.provider_failed = true;
+        }
+        result
+    }
+}
+
+/// The serialized JSON-RPC payload of a request, kept to encode the arguments of its call again
+/// for another service or a larger max response size.
+struct Payload {
+    body: PooledBuffer,
+    call_cycles: Option<u128>,
+    cycles_estimator: CyclesEstimator,
+}
+
+impl Payload {
+    /// Returns the cycles to attach to a call with the given max response size: the cycles of
+    /// the request context or the transport, or an estimate.
+    fn call_cycles(&self, max_response_size: u64) -> u128 {
+        self.call_cycles.unwrap_or_else(|| {
+            self.cycles_estimator.estimate(self.body.len() as u64, max_response_size)
         })
     }
 }
 
+/// What is known about a call besides its result, to record it and decide how the request is
+/// retried.
+#[derive(Clone, Copy, Debug, Default)]
+struct CallOutcome {
+    /// The provider failed, so the request should be sent to another service.
+    provider_failed: bool,
+    /// The response exceeded the max response size, so the request should be sent again with
+    /// a larger one.
+    response_too_large: bool,
+    /// Why the call failed, if it did.
+    failure: Option<FailureClass>,
+    /// The size of the JSON-RPC response, in bytes.
+    response_bytes: u64,
+}
+
+/// What the outcall of a request is dispatched with, captured when the request is made.
+#[derive(Clone)]
+struct Outcall {
+    metrics: MetricsRecorder,
+    dispatcher: Dispatcher,
+    cycles_budget: BudgetTracker,
+    logging: bool,
+    context: RequestContext,
+}
+
+impl Outcall {
+    /// Wait for an outcall slot, then call the EVM RPC canister within the cycles budget,
+    /// recording the metrics of the request.
+    async fn dispatch(
+        self,
+        request_packet: &RequestPacket,
+        args: PooledBuffer,
+        call_cycles: u128,
+    ) -> TransportResult<ResponsePacket> {
+        self.dispatch_to(request_packet, args, call_cycles, &mut CallOutcome::default()).await
+    }
+
+    /// Dispatch the request to the services of the failover in order, until one does not fail,
+    /// see [`IcpConfig::set_fallback_services`] and [`IcpConfig::set_service_rotation`].
+    async fn dispatch_with_failover(
+        self,
+        request_packet: &RequestPacket,
+        failover: &Failover,
+        (payload, max_response_size): (&Payload, u64),
+        call: &mut CallOutcome,
+    ) -> TransportResult<ResponsePacket> {
+        let mut result = Err(TransportErrorKind::backend_gone());
+        for index in failover.order(request_packet, ic_cdk::api::time()) {
+            let args = failover.service(index).1.encode(&payload.body, max_response_size);
+            let call_cycles = payload.call_cycles(max_response_size);
+            result = self.clone().dispatch_to(request_packet, args, call_cycles, call).await;
+            failover.record(index, call.provider_failed, ic_cdk::api::time());
+            if !call.provider_failed {
+                if let Ok(response_packet) = &result {
+                    failover.track_filters(
+                        index,
+                        request_packet,
+                        response_packet,
+                        ic_cdk::api::time(),
+                    );
+                }
+                break;
+            }
+            if self.logging {
+                let service = &failover.service(index).0;
+                log_request(
+                    &self.context,
+                    request_packet,
+                    format_args!("failing over from {service:?}"),
+                );
+            }
+        }
+        result
+    }
+
+    /// Dispatch the request, setting the outcome of its call.
+    async fn dispatch_to(
+        self,
+        request_packet: &RequestPacket,
+        args: PooledBuffer,
+        call_cycles: u128,
+        call: &mut CallOutcome,
+    ) -> TransportResult<ResponsePacket> {
+        let Self { metrics, dispatcher, cycles_budget, logging, context } = self;
+        let Some(_permit) = dispatcher.acquire(context.priority()).await else {
+            return Err(TransportErrorKind::backend_gone());
+        };
+        if let Err(err) = cycles_budget.spend(context.priority(), call_cycles) {
+            if logging {
+                log_request(&context, request_packet, format_args!("rejected: {err}"));
+            }
+            return Err(TransportErrorKind::custom(err));
+        }
+        metrics.record_request(request_packet, context.is_retry());
+        let mut cycles = CallCycles::default();
+        let request_bytes = args.len() as u64;
+        let started_at = ic_cdk::api::time();
+        let result = IcpTransport::send(args, call_cycles, &mut cycles, call).await;
+        cycles_budget.complete(cycles.refunded);
+        metrics.record_call(request_packet, cycles, (request_bytes, call.response_bytes));
+        if let Some(cycles_meter) = context.cycles_meter() {
+            cycles_meter.record(cycles);
+        }
+        let latency = ic_cdk::api::time().saturating_sub(started_at);
+        metrics.record_response(request_packet, &result, call.failure, latency);
+        if logging {
+            let outcome = match &result {
+                Ok(ResponsePacket::Single(res)) => res.payload.as_error().map_or_else(
+                    || "ok".into(),
+                    |err| format!("error {}: {}", err.code, err.message),
+                ),
+                Ok(ResponsePacket::Batch(_)) => "ok".into(),
+                Err(err) => format!("failed: {err}"),
+            };
+            let latency_ms = latency / 1_000_000;
+            log_request(&context, request_packet, format_args!("{outcome} in {latency_ms}ms"));
+        }
+        result
+    }
+}
+
+/// Estimate the max response size of a request for the given method, in bytes.
+pub(crate) fn estimate_max_response_size(method: &str) -> u64 {
+    match method {
+        "eth_blockNumber" => MAX_RESPONSE_SIZE_SMALL,
+        "eth_getBalance" => MAX_RESPONSE_SIZE_SMALL,
+        "eth_chainId" => MAX_RESPONSE_SIZE_SMALL,
+        "eth_feeHistory" => MAX_RESPONSE_SIZE_MEDIUM,
+        "eth_estimateGas" => MAX_RESPONSE_SIZE_SMALL,
+        "eth_gasPrice" => MAX_RESPONSE_SIZE_SMALL,
+        "eth_getBlockTransactionCountByHash" => MAX_RESPONSE_SIZE_SMALL,
+        "eth_getBlockTransactionCountByNumber" => MAX_RESPONSE_SIZE_SMALL,
+        "eth_getCode" => MAX_RESPONSE_SIZE_SMALL,
+        "eth_getProof" => MAX_RESPONSE_SIZE_SMALL,
+        "eth_getStorageAt" => MAX_RESPONSE_SIZE_SMALL,
+        "eth_getTransactionByBlockHashAndIndex" => MAX_RESPONSE_SIZE_MEDIUM,
+        "eth_getTransactionByHash" => MAX_RESPONSE_SIZE_MEDIUM,
+        "eth_getTransactionCount" => MAX_RESPONSE_SIZE_SMALL,
+        "eth_getUncleCountByBlockHash" => MAX_RESPONSE_SIZE_SMALL,
+        "eth_getUncleCountByBlockNumber" => MAX_RESPONSE_SIZE_SMALL,
+        "eth_maxPriorityFeePerGas" => MAX_RESPONSE_SIZE_SMALL,
+        "eth_protocolVersion" => MAX_RESPONSE_SIZE_SMALL,
+        _ => MAX_RESPONSE_SIZE_UNKNOWN,
+    }
+}
+
+/// Write a request to the canister log, tagged with its trace ID.
+fn log_request(
+    context: &RequestContext,
+    request_packet: &RequestPacket,
+    outcome: fmt::Arguments<'_>,
+) {
+    let trace_id = context.trace_id().map_or_else(|| "-".into(), |id| id.to_string());
+    let methods = match request_packet {
+        RequestPacket::Single(req) => req.method().to_string(),
+        RequestPacket::Batch(reqs) => {
+            let methods: Vec<_> = reqs.iter().map(SerializedRequest::method).collect();
+            format!("batch({})", methods.join(", "))
+        }
+    };
+    ic_cdk::println!("[trace {trace_id}] {methods}: {outcome}");
+}
+
 impl Service<RequestPacket> for IcpTransport {
     type Response = ResponsePacket;
     type Error = TransportError;
@@ -202,7 +1231,7 @@ impl Service<RequestPacket> for IcpTransport {
 
     #[inline]
     fn call(&mut self, req: RequestPacket) -> Self::Future {
-        self.request(req)
+        self.batch_or_request(req)
     }
 }
 
@@ -219,6 +1248,6 @@ impl Service<RequestPacket> for &IcpTransport {
 
     #[inline]
     fn call(&mut self, req: RequestPacket) -> Self::Future {
-        self.request(req)
+        self.batch_or_request(req)
     }
 }
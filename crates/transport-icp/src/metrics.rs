@@ -0,0 +1,326 @@
+use crate::{CallCycles, RequestResult, RpcError};
+use alloy_json_rpc::{ErrorPayload, RequestPacket, ResponsePacket, SerializedRequest};
+use alloy_transport::TransportError;
+use candid::CandidType;
+use ic_cdk::api::call::CallResult;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Why a request failed, see [`MethodMetrics::failures_by_class`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, CandidType, Deserialize)]
+pub enum FailureClass {
+    /// The provider answered with a JSON-RPC error, e.g. a reverted call or a rate limit.
+    JsonRpc,
+    /// The outcall to the provider failed, or the provider answered with an HTTP error status.
+    Http,
+    /// The EVM RPC canister refused the request, e.g. for too few cycles or an invalid service.
+    Canister,
+    /// The call to the EVM RPC canister was rejected by the system.
+    CallRejected,
+    /// The response could not be deserialized.
+    Deserialization,
+}
+
+impl FailureClass {
+    /// Returns the class of the failure of a call to the EVM RPC canister, if it failed.
+    pub(crate) const fn of_call(call_result: &CallResult<(RequestResult,)>) -> Option<Self> {
+        match call_result {
+            Ok((RequestResult::Ok(_),)) => None,
+            Ok((RequestResult::Err(err),)) => Some(match err {
+                RpcError::JsonRpcError(_) => Self::JsonRpc,
+                RpcError::HttpOutcallError(_) => Self::Http,
+                RpcError::ProviderError(_) | RpcError::ValidationError(_) => Self::Canister,
+            }),
+            Err(_) => Some(Self::CallRejected),
+        }
+    }
+}
+
+/// Request counters of a single JSON-RPC method.
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize)]
+pub struct MethodMetrics {
+    /// Number of requests sent, including retries.
+    pub requests: u64,
+    /// Number of requests that returned a successful response.
+    pub successes: u64,
+    /// Number of requests that failed, for any reason.
+    pub failures: u64,
+    /// Number of failed requests, by JSON-RPC error code. Failures without an error code, such
+    /// as deserialization errors, are only counted in [`MethodMetrics::failures`].
+    pub failures_by_code: BTreeMap<i64, u64>,
+    /// Number of failed requests, by [`FailureClass`]. Failures of requests missing from the
+    /// response to a batch are only counted in [`MethodMetrics::failures`].
+    pub failures_by_class: BTreeMap<FailureClass, u64>,
+    /// Number of requests that were retries of a previously failed request.
+    pub retries: u64,
+    /// Number of requests that did not make their own outcall, but shared the response of an
+    /// identical in-flight request. These are not included in [`MethodMetrics::requests`].
+    pub coalesced: u64,
+    /// Accumulated latency of all completed requests, in nanoseconds.
+    pub total_latency_nanos: u64,
+    /// Cycles spent on the calls of all completed requests, i.e. attached but not refunded.
+    /// The cycles of a batch are split evenly between its requests, as are its bytes.
+    pub cycles_spent: u128,
+    /// Cycles attached to the calls of all completed requests.
+    pub cycles_attached: u128,
+    /// Cycles refunded by the EVM RPC canister for all completed requests.
+    pub cycles_refunded: u128,
+    /// Bytes of the Candid encoded arguments of the calls, including the JSON-RPC requests.
+    pub request_bytes: u64,
+    /// Bytes of the JSON-RPC responses.
+    pub response_bytes: u64,
+}
+
+impl MethodMetrics {
+    /// Returns the number of requests that completed, successfully or not.
+    pub const fn completed(&self) -> u64 {
+        self.successes + self.failures
+    }
+
+    /// Returns the average latency of completed requests.
+    pub fn average_latency(&self) -> Duration {
+        Duration::from_nanos(self.total_latency_nanos.checked_div(self.completed()).unwrap_or(0))
+    }
+
+    fn record_outcome(&mut self, outcome: Outcome, latency_nanos: u64) {
+        self.total_latency_nanos = self.total_latency_nanos.saturating_add(latency_nanos);
+        match outcome {
+            Ok(()) => self.successes += 1,
+            Err((code, class)) => {
+                self.failures += 1;
+                if let Some(code) = code {
+                    *self.failures_by_code.entry(code).or_default() += 1;
+                }
+                if let Some(class) = class {
+                    *self.failures_by_class.entry(class).or_default() += 1;
+                }
+            }
+        }
+    }
+}
+
+/// A snapshot of the request metrics of an [`IcpTransport`], by JSON-RPC method.
+///
+/// [`IcpTransport`]: crate::IcpTransport
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize)]
+pub struct IcpMetrics {
+    /// Metrics of each method that has been requested at least once.
+    pub methods: BTreeMap<String, MethodMetrics>,
+}
+
+impl IcpMetrics {
+    /// Returns the metrics of the given method, if it has been requested.
+    pub fn method(&self, method: &str) -> Option<&MethodMetrics> {
+        self.methods.get(method)
+    }
+
+    /// Returns the metrics of all methods combined.
+    pub fn total(&self) -> MethodMetrics {
+        self.methods.values().fold(MethodMetrics::default(), |mut total, method| {
+            total.requests += method.requests;
+            total.successes += method.successes;
+            total.failures += method.failures;
+            total.retries += method.retries;
+            total.coalesced += method.coalesced;
+            total.total_latency_nanos =
+                total.total_latency_nanos.saturating_add(method.total_latency_nanos);
+            total.cycles_spent = total.cycles_spent.saturating_add(method.cycles_spent);
+            total.cycles_attached = total.cycles_attached.saturating_add(method.cycles_attached);
+            total.cycles_refunded = total.cycles_refunded.saturating_add(method.cycles_refunded);
+            total.request_bytes = total.request_bytes.saturating_add(method.request_bytes);
+            total.response_bytes = total.response_bytes.saturating_add(method.response_bytes);
+            for (code, count) in &method.failures_by_code {
+                *total.failures_by_code.entry(*code).or_default() += count;
+            }
+            for (class, count) in &method.failures_by_class {
+                *total.failures_by_class.entry(*class).or_default() += count;
+            }
+            total
+        })
+    }
+}
+
+/// Records [`IcpMetrics`], shared between all clones of a transport.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MetricsRecorder(Arc<Mutex<IcpMetrics>>);
+
+impl MetricsRecorder {
+    pub(crate) fn snapshot(&self) -> IcpMetrics {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub(crate) fn reset(&self) {
+        *self.0.lock().unwrap() = IcpMetrics::default();
+    }
+
+    /// Record the dispatch of all requests in the packet.
+    pub(crate) fn record_request(&self, request_packet: &RequestPacket, is_retry: bool) {
+        let mut metrics = self.0.lock().unwrap();
+        for req in requests(request_packet) {
+            let method = metrics.methods.entry(req.method().to_string()).or_default();
+            method.requests += 1;
+            if is_retry {
+                method.retries += 1;
+            }
+        }
+    }
+
+    /// Record a request that shares the outcall of an identical in-flight request.
+    pub(crate) fn record_coalesced(&self, request_packet: &RequestPacket) {
+        let mut metrics = self.0.lock().unwrap();
+        for req in requests(request_packet) {
+            metrics.methods.entry(req.method().to_string()).or_default().coalesced += 1;
+        }
+    }
+
+    /// Record the outcome of all requests in the packet.
+    pub(crate) fn record_response(
+        &self,
+        request_packet: &RequestPacket,
+        result: &Result<ResponsePacket, TransportError>,
+        failure: Option<FailureClass>,
+        latency_nanos: u64,
+    ) {
+        let mut metrics = self.0.lock().unwrap();
+        let outcomes: Vec<Outcome> = match result {
+            Ok(ResponsePacket::Single(response)) => vec![outcome(response.payload.as_error())],
+            Ok(ResponsePacket::Batch(responses)) => {
+                // Batch responses may be in any order, so match them up by ID.
+                let by_id: HashMap<_, _> =
+                    responses.iter().map(|r| (&r.id, outcome(r.payload.as_error()))).collect();
+                requests(request_packet)
+                    .iter()
+                    .map(|req| by_id.get(req.id()).copied().unwrap_or(Err((None, None))))
+                    .collect()
+            }
+            Err(err) => {
+                let code = err.as_error_resp().map(|e| e.code);
+                requests(request_packet).iter().map(|_| Err((code, failure))).collect()
+            }
+        };
+        for (req, outcome) in requests(request_packet).iter().zip(outcomes) {
+            metrics
+                .methods
+                .entry(req.method().to_string())
+                .or_default()
+                .record_outcome(outcome, latency_nanos);
+        }
+    }
+
+    /// Record the cycles and bytes of the call of the packet, split evenly between its
+    /// requests.
+    pub(crate) fn record_call(
+        &self,
+        request_packet: &RequestPacket,
+        cycles: CallCycles,
+        (request_bytes, response_bytes): (u64, u64),
+    ) {
+        let requests = requests(request_packet);
+        if requests.is_empty() {
+            return;
+        }
+        let count = requests.len() as u128;
+        let mut spent = split(cycles.spent(), count);
+        let mut attached = split(cycles.attached, count);
+        let mut refunded = split(cycles.refunded, count);
+        let mut sent = split(request_bytes.into(), count);
+        let mut received = split(response_bytes.into(), count);
+        let mut metrics = self.0.lock().unwrap();
+        for req in requests {
+            let method = metrics.methods.entry(req.method().to_string()).or_default();
+            method.cycles_spent = method.cycles_spent.saturating_add(spent.next().unwrap_or(0));
+            method.cycles_attached =
+                method.cycles_attached.saturating_add(attached.next().unwrap_or(0));
+            method.cycles_refunded =
+                method.cycles_refunded.saturating_add(refunded.next().unwrap_or(0));
+            let bytes = |share: Option<u128>| share.unwrap_or(0) as u64;
+            method.request_bytes = method.request_bytes.saturating_add(bytes(sent.next()));
+            method.response_bytes = method.response_bytes.saturating_add(bytes(received.next()));
+        }
+    }
+}
+
+/// The outcome of a request: success, or the error code and class of its failure.
+type Outcome = Result<(), (Option<i64>, Option<FailureClass>)>;
+
+fn outcome<E>(error: Option<&ErrorPayload<E>>) -> Outcome {
+    error.map_or(Ok(()), |e| Err((Some(e.code), Some(FailureClass::JsonRpc))))
+}
+
+/// Split `total` evenly into `count` shares, the first share taking the remainder.
+fn split(total: u128, count: u128) -> impl Iterator<Item = u128> {
+    let share = total / count;
+    let remainder = total % count;
+    (0..count).map(move |i| if i == 0 { share + remainder } else { share })
+}
+
+fn requests(request_packet: &RequestPacket) -> &[SerializedRequest] {
+    match request_packet {
+        RequestPacket::Single(req) => std::slice::from_ref(req),
+        RequestPacket::Batch(reqs) => reqs.as_slice(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_json_rpc::{Id, Request, Response, ResponsePayload};
+
+    fn request(method: &'static str, id: u64) -> RequestPacket {
+        Request::new(method, Id::Number(id), ()).serialize().unwrap().into()
+    }
+
+    #[test]
+    fn records_successes_failures_and_retries() {
+        let recorder = MetricsRecorder::default();
+        let req = request("eth_blockNumber", 1);
+
+        recorder.record_request(&req, false);
+        let err = TransportError::ErrorResp(ErrorPayload {
+            code: -32005,
+            message: "limit exceeded".into(),
+            data: None,
+        });
+        recorder.record_response(&req, &Err(err), Some(FailureClass::JsonRpc), 10);
+
+        recorder.record_request(&req, true);
+        let ok = ResponsePacket::Single(Response {
+            id: Id::Number(1),
+            payload: ResponsePayload::Success(serde_json::value::to_raw_value("0x1").unwrap()),
+        });
+        recorder.record_response(&req, &Ok(ok), None, 30);
+
+        let metrics = recorder.snapshot();
+        let method = metrics.method("eth_blockNumber").unwrap();
+        assert_eq!(method.requests, 2);
+        assert_eq!(method.retries, 1);
+        assert_eq!(method.successes, 1);
+        assert_eq!(method.failures, 1);
+        assert_eq!(method.failures_by_code.get(&-32005), Some(&1));
+        assert_eq!(method.failures_by_class.get(&FailureClass::JsonRpc), Some(&1));
+        assert_eq!(method.average_latency(), Duration::from_nanos(20));
+        assert_eq!(metrics.total(), *method);
+
+        let batch = RequestPacket::Batch(vec![
+            Request::new("eth_blockNumber", Id::Number(2), ()).serialize().unwrap(),
+            Request::new("eth_chainId", Id::Number(3), ()).serialize().unwrap(),
+        ]);
+        let cycles = CallCycles { calls: 1, attached: 301, refunded: 200 };
+        recorder.record_call(&batch, cycles, (40, 7));
+        let metrics = recorder.snapshot();
+        assert_eq!(metrics.method("eth_blockNumber").unwrap().cycles_spent, 51);
+        assert_eq!(metrics.method("eth_chainId").unwrap().cycles_spent, 50);
+        assert_eq!(metrics.method("eth_chainId").unwrap().response_bytes, 3);
+        let total = metrics.total();
+        assert_eq!(total.cycles_spent, 101);
+        assert_eq!((total.cycles_attached, total.cycles_refunded), (301, 200));
+        assert_eq!((total.request_bytes, total.response_bytes), (40, 7));
+
+        recorder.reset();
+        assert_eq!(recorder.snapshot(), IcpMetrics::default());
+    }
+}
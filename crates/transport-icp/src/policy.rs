@@ -0,0 +1,126 @@
+use alloy_json_rpc::{RequestPacket, SerializedRequest};
+use candid::CandidType;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+
+/// A policy deciding which JSON-RPC methods may be requested.
+///
+/// Requests for methods that are not allowed are rejected locally with a
+/// [`MethodNotAllowed`] error, before any outcall is made. A batch is rejected as a whole if
+/// it contains any method that is not allowed.
+///
+/// A policy can be set on the [`IcpTransport`], applying to every request, or on a
+/// [`RequestContext`], applying only to the requests made from a specific code path. Both
+/// policies must allow a method for it to be requested.
+///
+/// # Examples
+///
+/// ```ignore
+/// // This code path must never send transactions.
+/// let policy = MethodPolicy::deny(["eth_sendRawTransaction"]);
+/// let context = RequestContext::new().with_method_policy(policy);
+/// let balance = context.scope(provider.get_balance(address)).await?;
+/// ```
+///
+/// [`IcpTransport`]: crate::IcpTransport
+/// [`RequestContext`]: crate::RequestContext
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize)]
+pub enum MethodPolicy {
+    /// Allow every method. This is the default.
+    #[default]
+    AllowAll,
+    /// Allow only the given methods.
+    Allow(BTreeSet<String>),
+    /// Allow every method except the given methods.
+    Deny(BTreeSet<String>),
+}
+
+impl MethodPolicy {
+    /// Create a policy allowing only the given methods.
+    pub fn allow<I, S>(methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self::Allow(methods.into_iter().map(Into::into).collect())
+    }
+
+    /// Create a policy allowing every method except the given methods.
+    pub fn deny<I, S>(methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self::Deny(methods.into_iter().map(Into::into).collect())
+    }
+
+    /// Returns `true` if the policy allows requesting the given method.
+    pub fn is_allowed(&self, method: &str) -> bool {
+        match self {
+            Self::AllowAll => true,
+            Self::Allow(methods) => methods.contains(method),
+            Self::Deny(methods) => !methods.contains(method),
+        }
+    }
+
+    /// Check that the policy allows every request in the packet.
+    pub(crate) fn check(&self, request_packet: &RequestPacket) -> Result<(), MethodNotAllowed> {
+        let requests = match request_packet {
+            RequestPacket::Single(req) => std::slice::from_ref(req),
+            RequestPacket::Batch(reqs) => reqs.as_slice(),
+        };
+        requests
+            .iter()
+            .map(SerializedRequest::method)
+            .find(|method| !self.is_allowed(method))
+            .map_or(Ok(()), |method| Err(MethodNotAllowed { method: method.to_string() }))
+    }
+}
+
+/// Error returned when a request is rejected by a [`MethodPolicy`].
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("method `{method}` is not allowed by the method policy")]
+pub struct MethodNotAllowed {
+    method: String,
+}
+
+impl MethodNotAllowed {
+    /// Returns the method that was rejected.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_json_rpc::{Id, Request};
+
+    fn request(method: &'static str) -> SerializedRequest {
+        Request::new(method, Id::Number(1), ()).serialize().unwrap()
+    }
+
+    #[test]
+    fn allow_and_deny_lists() {
+        let allow = MethodPolicy::allow(["eth_call", "eth_getBalance"]);
+        assert!(allow.is_allowed("eth_call"));
+        assert!(!allow.is_allowed("eth_sendRawTransaction"));
+
+        let deny = MethodPolicy::deny(["eth_sendRawTransaction"]);
+        assert!(deny.is_allowed("eth_call"));
+        assert!(!deny.is_allowed("eth_sendRawTransaction"));
+
+        assert!(MethodPolicy::default().is_allowed("debug_traceTransaction"));
+    }
+
+    #[test]
+    fn batches_are_rejected_as_a_whole() {
+        let policy = MethodPolicy::deny(["debug_traceTransaction"]);
+        let batch = RequestPacket::Batch(vec![
+            request("eth_blockNumber"),
+            request("debug_traceTransaction"),
+        ]);
+        assert_eq!(policy.check(&batch).unwrap_err().method(), "debug_traceTransaction");
+        assert!(policy.check(&request("eth_blockNumber").into()).is_ok());
+    }
+}
@@ -0,0 +1,82 @@
+//! [`IcpConfig`] presets for the chains supported by the EVM RPC canister.
+
+use crate::{EthMainnetService, EthSepoliaService, IcpConfig, L2MainnetService, RpcService};
+use std::time::Duration;
+
+/// Methods answered with a block, whose size depends on the number of transactions per block.
+const BLOCK_METHODS: [&str; 2] = ["eth_getBlockByNumber", "eth_getBlockByHash"];
+
+impl IcpConfig {
+    /// Create a config for a chain, polling at `poll_interval` and with room in the max response
+    /// size of blocks for their transaction hashes.
+    fn preset(rpc_service: RpcService, poll_interval: Duration, block_size: u64) -> Self {
+        BLOCK_METHODS
+            .into_iter()
+            .fold(Self::new(rpc_service), |config, method| {
+                config.set_method_max_response_size(method, block_size)
+            })
+            .set_poll_interval(poll_interval)
+    }
+
+    /// Create a config for Ethereum mainnet, through PublicNode, polling every 12 seconds.
+    pub fn ethereum_mainnet() -> Self {
+        Self::preset(
+            RpcService::EthMainnet(EthMainnetService::PublicNode),
+            Duration::from_secs(12),
+            40_000,
+        )
+    }
+
+    /// Create a config for the Sepolia testnet, through PublicNode, polling every 12 seconds.
+    pub fn sepolia() -> Self {
+        Self::preset(
+            RpcService::EthSepolia(EthSepoliaService::PublicNode),
+            Duration::from_secs(12),
+            20_000,
+        )
+    }
+
+    /// Create a config for Base mainnet, through PublicNode, polling every 2 seconds.
+    pub fn base() -> Self {
+        Self::preset(
+            RpcService::BaseMainnet(L2MainnetService::PublicNode),
+            Duration::from_secs(2),
+            40_000,
+        )
+    }
+
+    /// Create a config for Optimism mainnet, through PublicNode, polling every 2 seconds.
+    pub fn optimism() -> Self {
+        Self::preset(
+            RpcService::OptimismMainnet(L2MainnetService::PublicNode),
+            Duration::from_secs(2),
+            20_000,
+        )
+    }
+
+    /// Create a config for Arbitrum One, through PublicNode, polling every 2 seconds.
+    ///
+    /// Arbitrum produces a block every 250 milliseconds, but an outcall takes a few seconds, so
+    /// polling faster only pays for more outcalls.
+    pub fn arbitrum_one() -> Self {
+        Self::preset(
+            RpcService::ArbitrumOne(L2MainnetService::PublicNode),
+            Duration::from_secs(2),
+            10_000,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_select_the_service_of_their_chain() {
+        let config = IcpConfig::base();
+        assert_eq!(config.rpc_service, RpcService::BaseMainnet(L2MainnetService::PublicNode));
+        assert_eq!(config.poll_interval(), Some(Duration::from_secs(2)));
+        assert_eq!(config.method_max_response_sizes.get("eth_getBlockByNumber"), Some(&40_000));
+        assert_eq!(IcpConfig::sepolia().poll_interval(), Some(Duration::from_secs(12)));
+    }
+}
@@ -0,0 +1,376 @@
+use crate::{
+    headers::ServiceHeaders, CyclesBudget, CyclesEstimator, HttpHeader, IcpConfig, MethodPolicy,
+    RpcService, ServiceRotation,
+};
+use candid::{
+    types::{Serializer, Type},
+    CandidType,
+};
+use serde::{Deserialize, Deserializer};
+use std::time::Duration;
+
+/// The full RPC configuration of a canister, as accepted in an init or upgrade argument.
+///
+/// All fields except the [`RpcService`] are optional and fall back to the defaults of
+/// [`IcpConfig`] and the RPC client.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[ic_cdk::init]
+/// fn init(config: IcpProviderConfig) {
+///     CONFIG.with_borrow_mut(|c| *c = Some(config));
+/// }
+///
+/// async fn provider() -> impl Provider<IcpTransport> {
+///     let config = CONFIG.with_borrow(|c| c.clone()).unwrap();
+///     ProviderBuilder::new().on_icp_config(config)
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct IcpProviderConfig {
+    /// The chain and provider to send requests to.
+    pub rpc_service: RpcService,
+    /// The chain ID. If unset, it is requested from the provider when first needed.
+    pub chain_id: Option<u64>,
+    /// The interval between polls of pollers and watchers, in milliseconds.
+    pub poll_interval_ms: Option<u64>,
+    /// The cycles attached to each call to the EVM RPC canister. Estimated per call if unset.
+    pub call_cycles: Option<u128>,
+    /// The max response size of each request, in bytes. If unset, it is estimated per method.
+    pub max_response_size: Option<u64>,
+    /// The max response size of requests for each method, replacing the estimate of the method.
+    pub method_max_response_sizes: Option<Vec<(String, u64)>>,
+    /// How many times a request whose response is too large is retried with a doubled max
+    /// response size. 3 if unset.
+    pub response_size_retries: Option<u32>,
+    /// The maximum number of concurrent outcalls. Unlimited if unset.
+    pub max_concurrent_requests: Option<u32>,
+    /// How long to wait for each request, in milliseconds. Until the system gives up if unset.
+    pub request_timeout_ms: Option<u64>,
+    /// Whether identical concurrent requests share a single outcall. Enabled if unset.
+    pub request_coalescing: Option<bool>,
+    /// Whether requests made together are sent as a single batch. Disabled if unset.
+    pub request_batching: Option<bool>,
+    /// Whether the outcome of every request is written to the canister log. Disabled if unset.
+    pub request_logging: Option<bool>,
+    /// The methods that may be requested. Every method is allowed if unset.
+    pub method_policy: Option<MethodPolicy>,
+    /// Whether debug builds panic if the transport is created from a query. Disabled if unset.
+    pub call_context_assertion: Option<bool>,
+    /// The limits on the cycles spent on requests. Unlimited if unset.
+    pub cycles_budget: Option<CyclesBudget>,
+    /// How the cycles of each call are estimated, if no call cycles are set. The
+    /// [`CyclesEstimator`] defaults if unset.
+    pub cycles_estimator: Option<CyclesEstimator>,
+    /// The services requests fail over to, in order, when the RPC service fails. None if unset.
+    pub fallback_services: Option<Vec<RpcService>>,
+    /// The time a failed service is skipped for, in milliseconds. One minute if unset.
+    pub failover_cooldown_ms: Option<u64>,
+    /// How reads are spread over the services. Sent to the RPC service first if unset.
+    pub service_rotation: Option<ServiceRotation>,
+    /// Static headers of the custom services, by URL, e.g. API keys. None if unset.
+    pub service_headers: Option<Vec<(String, Vec<HttpHeader>)>>,
+}
+
+impl IcpProviderConfig {
+    /// Create a new [`IcpProviderConfig`] for the given [`RpcService`], with all other settings
+    /// unset.
+    pub const fn new(rpc_service: RpcService) -> Self {
+        Self {
+            rpc_service,
+            chain_id: None,
+            poll_interval_ms: None,
+            call_cycles: None,
+            max_response_size: None,
+            method_max_response_sizes: None,
+            response_size_retries: None,
+            max_concurrent_requests: None,
+            request_timeout_ms: None,
+            request_coalescing: None,
+            request_batching: None,
+            request_logging: None,
+            method_policy: None,
+            call_context_assertion: None,
+            cycles_budget: None,
+            cycles_estimator: None,
+            fallback_services: None,
+            failover_cooldown_ms: None,
+            service_rotation: None,
+            service_headers: None,
+        }
+    }
+
+    /// Returns the poll interval, if set.
+    pub const fn poll_interval(&self) -> Option<Duration> {
+        match self.poll_interval_ms {
+            Some(ms) => Some(Duration::from_millis(ms)),
+            None => None,
+        }
+    }
+
+    /// Returns the [`IcpConfig`] of the transport described by this config.
+    pub fn icp_config(&self) -> IcpConfig {
+        let mut config = IcpConfig::new(self.rpc_service.clone());
+        if let Some(call_cycles) = self.call_cycles {
+            config = config.set_call_cycles(call_cycles);
+        }
+        if let Some(max_response_size) = self.max_response_size {
+            config = config.set_max_response_size(max_response_size);
+        }
+        for (method, max_response_size) in self.method_max_response_sizes.iter().flatten() {
+            config = config.set_method_max_response_size(method.clone(), *max_response_size);
+        }
+        if let Some(retries) = self.response_size_retries {
+            config = config.set_response_size_retries(retries);
+        }
+        if let Some(max_concurrent_requests) = self.max_concurrent_requests {
+            config = config.set_max_concurrent_requests(max_concurrent_requests as usize);
+        }
+        if let Some(ms) = self.request_timeout_ms {
+            config = config.set_request_timeout(Duration::from_millis(ms));
+        }
+        if let Some(poll_interval) = self.poll_interval() {
+            config = config.set_poll_interval(poll_interval);
+        }
+        if let Some(enabled) = self.request_coalescing {
+            config = config.set_request_coalescing(enabled);
+        }
+        if let Some(enabled) = self.request_batching {
+            config = config.set_request_batching(enabled);
+        }
+        if let Some(enabled) = self.request_logging {
+            config = config.set_request_logging(enabled);
+        }
+        if let Some(method_policy) = &self.method_policy {
+            config = config.set_method_policy(method_policy.clone());
+        }
+        if let Some(enabled) = self.call_context_assertion {
+            config = config.set_call_context_assertion(enabled);
+        }
+        if let Some(cycles_budget) = self.cycles_budget {
+            config = config.set_cycles_budget(cycles_budget);
+        }
+        if let Some(cycles_estimator) = self.cycles_estimator {
+            config = config.set_cycles_estimator(cycles_estimator);
+        }
+        if let Some(fallback_services) = &self.fallback_services {
+            config = config.set_fallback_services(fallback_services.iter().cloned());
+        }
+        if let Some(ms) = self.failover_cooldown_ms {
+            config = config.set_failover_cooldown(Duration::from_millis(ms));
+        }
+        if let Some(service_rotation) = &self.service_rotation {
+            config = config.set_service_rotation(service_rotation.clone());
+        }
+        for (url, headers) in self.service_headers.iter().flatten() {
+            for header in headers {
+                config = config.set_service_header(url, &header.name, &header.value);
+            }
+        }
+        config
+    }
+}
+
+impl From<IcpProviderConfig> for IcpConfig {
+    fn from(config: IcpProviderConfig) -> Self {
+        config.icp_config()
+    }
+}
+
+/// The Candid representation of an [`IcpConfig`].
+///
+/// The [`ParamsSerializer`](crate::ParamsSerializer) and the
+/// [`CyclesAlert`](crate::CyclesAlert) are code, not data, so they are not part of the
+/// representation: a decoded config has none, and they can be set afterwards.
+#[derive(CandidType, Deserialize)]
+struct IcpConfigRecord {
+    rpc_service: RpcService,
+    call_cycles: Option<u128>,
+    max_response_size: Option<u64>,
+    method_max_response_sizes: Option<Vec<(String, u64)>>,
+    response_size_retries: Option<u32>,
+    request_coalescing: Option<bool>,
+    request_batching: Option<bool>,
+    max_concurrent_requests: Option<u64>,
+    request_timeout_ms: Option<u64>,
+    poll_interval_ms: Option<u64>,
+    method_policy: Option<MethodPolicy>,
+    request_logging: Option<bool>,
+    call_context_assertion: Option<bool>,
+    cycles_budget: Option<CyclesBudget>,
+    cycles_estimator: Option<CyclesEstimator>,
+    fallback_services: Option<Vec<RpcService>>,
+    failover_cooldown_ms: Option<u64>,
+    service_rotation: Option<ServiceRotation>,
+    service_headers: Option<Vec<(String, Vec<HttpHeader>)>>,
+}
+
+impl From<&IcpConfig> for IcpConfigRecord {
+    fn from(config: &IcpConfig) -> Self {
+        Self {
+            rpc_service: config.rpc_service.clone(),
+            call_cycles: config.call_cycles,
+            max_response_size: config.max_response_size,
+            method_max_response_sizes: Some(
+                config.method_max_response_sizes.iter().map(|(m, s)| (m.clone(), *s)).collect(),
+            ),
+            response_size_retries: Some(config.response_size_retries),
+            request_coalescing: Some(config.request_coalescing),
+            request_batching: Some(config.request_batching),
+            max_concurrent_requests: config.max_concurrent_requests.map(|max| max as u64),
+            request_timeout_ms: config.request_timeout.map(|timeout| timeout.as_millis() as u64),
+            poll_interval_ms: config.poll_interval.map(|interval| interval.as_millis() as u64),
+            method_policy: Some(config.method_policy.clone()),
+            request_logging: Some(config.request_logging),
+            call_context_assertion: Some(config.call_context_assertion),
+            cycles_budget: config.cycles_budget,
+            cycles_estimator: Some(config.cycles_estimator),
+            fallback_services: Some(config.fallback_services.clone()),
+            failover_cooldown_ms: Some(config.failover_cooldown.as_millis() as u64),
+            service_rotation: Some(config.service_rotation.clone()),
+            service_headers: Some(config.service_headers.entries()),
+        }
+    }
+}
+
+impl From<IcpConfigRecord> for IcpConfig {
+    fn from(record: IcpConfigRecord) -> Self {
+        let defaults = Self::new(record.rpc_service);
+        Self {
+            call_cycles: record.call_cycles,
+            max_response_size: record.max_response_size,
+            method_max_response_sizes: record
+                .method_max_response_sizes
+                .map(|sizes| sizes.into_iter().collect())
+                .unwrap_or_default(),
+            response_size_retries: record
+                .response_size_retries
+                .unwrap_or(defaults.response_size_retries),
+            request_coalescing: record.request_coalescing.unwrap_or(defaults.request_coalescing),
+            request_batching: record.request_batching.unwrap_or(defaults.request_batching),
+            max_concurrent_requests: record.max_concurrent_requests.map(|max| max as usize),
+            request_timeout: record.request_timeout_ms.map(Duration::from_millis),
+            poll_interval: record.poll_interval_ms.map(Duration::from_millis),
+            method_policy: record.method_policy.unwrap_or_default(),
+            request_logging: record.request_logging.unwrap_or(defaults.request_logging),
+            call_context_assertion: record
+                .call_context_assertion
+                .unwrap_or(defaults.call_context_assertion),
+            cycles_budget: record.cycles_budget,
+            cycles_estimator: record.cycles_estimator.unwrap_or(defaults.cycles_estimator),
+            fallback_services: record.fallback_services.unwrap_or_default(),
+            failover_cooldown: record
+                .failover_cooldown_ms
+                .map_or(defaults.failover_cooldown, Duration::from_millis),
+            service_rotation: record.service_rotation.unwrap_or_default(),
+            service_headers: record.service_headers.into_iter().flatten().fold(
+                ServiceHeaders::new(),
+                |mut service_headers, (url, headers)| {
+                    for header in headers {
+                        service_headers.insert(url.clone(), header);
+                    }
+                    service_headers
+                },
+            ),
+            ..defaults
+        }
+    }
+}
+
+/// An [`IcpConfig`] is encoded as a record with optional fields, so that an operator only
+/// needs to pass the settings that differ from the defaults, e.g. in an upgrade argument.
+impl CandidType for IcpConfig {
+    fn _ty() -> Type {
+        IcpConfigRecord::ty()
+    }
+
+    fn idl_serialize<S: Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+        IcpConfigRecord::from(self).idl_serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for IcpConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        IcpConfigRecord::deserialize(deserializer).map(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EthSepoliaService;
+
+    #[test]
+    fn candid_roundtrip() {
+        let config = IcpProviderConfig {
+            chain_id: Some(11155111),
+            poll_interval_ms: Some(12_000),
+            max_concurrent_requests: Some(4),
+            ..IcpProviderConfig::new(RpcService::EthSepolia(EthSepoliaService::Alchemy))
+        };
+        let bytes = candid::encode_one(&config).unwrap();
+        assert_eq!(candid::decode_one::<IcpProviderConfig>(&bytes).unwrap(), config);
+        assert_eq!(config.poll_interval(), Some(Duration::from_secs(12)));
+        assert_eq!(config.icp_config().max_concurrent_requests, Some(4));
+        assert_eq!(config.icp_config().poll_interval, Some(Duration::from_secs(12)));
+    }
+
+    #[test]
+    fn icp_config_candid_roundtrip() {
+        let config = IcpConfig::new(RpcService::EthSepolia(EthSepoliaService::Alchemy))
+            .set_call_cycles(10_000_000_000)
+            .set_max_concurrent_requests(8)
+            .set_request_timeout(Duration::from_secs(30))
+            .set_method_max_response_size("eth_getLogs", 100_000)
+            .set_method_policy(MethodPolicy::deny(["eth_sendRawTransaction"]))
+            .set_request_coalescing(false)
+            .set_request_batching(true)
+            .set_call_context_assertion(true)
+            .set_fallback_services([RpcService::EthSepolia(EthSepoliaService::Ankr)])
+            .set_failover_cooldown(Duration::from_millis(500))
+            .set_service_rotation(ServiceRotation::RoundRobin)
+            .set_service_header("https://rpc.example.com", "x-api-key", "key")
+            .set_cycles_budget(CyclesBudget::new().with_min_balance(1_000_000_000_000));
+        let bytes = candid::encode_one(&config).unwrap();
+        let decoded = candid::decode_one::<IcpConfig>(&bytes).unwrap();
+        assert_eq!(decoded.call_cycles, Some(10_000_000_000));
+        assert_eq!(decoded.max_concurrent_requests, Some(8));
+        assert_eq!(decoded.request_timeout, Some(Duration::from_secs(30)));
+        assert_eq!(decoded.method_max_response_sizes, config.method_max_response_sizes);
+        assert_eq!(decoded.response_size_retries, 3);
+        assert_eq!(decoded.method_policy, config.method_policy);
+        assert!(!decoded.request_coalescing);
+        assert!(decoded.request_batching);
+        assert!(!decoded.request_logging);
+        assert!(decoded.call_context_assertion);
+        assert_eq!(decoded.cycles_budget, config.cycles_budget);
+        assert_eq!(decoded.fallback_services, config.fallback_services);
+        assert_eq!(decoded.failover_cooldown, Duration::from_millis(500));
+        assert_eq!(decoded.service_rotation, ServiceRotation::RoundRobin);
+        assert_eq!(decoded.service_headers, config.service_headers);
+    }
+
+    #[test]
+    fn icp_config_fields_default_when_missing() {
+        // An operator only passes the settings that differ from the defaults.
+        #[derive(CandidType)]
+        struct UpgradeArg {
+            rpc_service: RpcService,
+            request_batching: Option<bool>,
+        }
+        let arg = UpgradeArg {
+            rpc_service: RpcService::EthSepolia(EthSepoliaService::Ankr),
+            request_batching: Some(true),
+        };
+        let bytes = candid::encode_one(arg).unwrap();
+        let decoded = candid::decode_one::<IcpConfig>(&bytes).unwrap();
+        let defaults = IcpConfig::new(RpcService::EthSepolia(EthSepoliaService::Ankr));
+        assert_eq!(decoded.rpc_service, defaults.rpc_service);
+        assert!(decoded.request_batching);
+        assert_eq!(decoded.request_coalescing, defaults.request_coalescing);
+        assert_eq!(decoded.cycles_estimator, defaults.cycles_estimator);
+        assert_eq!(decoded.failover_cooldown, defaults.failover_cooldown);
+        assert_eq!(decoded.service_rotation, defaults.service_rotation);
+    }
+}
@@ -0,0 +1,444 @@
+use crate::{IcpConfig, IcpTransport, RpcService};
+use alloy_json_rpc::{RequestPacket, Response, ResponsePacket, ResponsePayload};
+use alloy_transport::{Transport, TransportError, TransportErrorKind, TransportFut};
+use futures::{stream::FuturesUnordered, StreamExt};
+use serde_json::Value;
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::Service;
+
+/// How the responses of the transports of a [`QuorumTransport`] are compared.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ResponseComparison {
+    /// The results must be byte for byte identical.
+    Exact,
+    /// The results must be the same JSON, ignoring formatting and the order of object fields,
+    /// with hex strings compared ignoring case, and quantities compared as numbers, i.e. also
+    /// ignoring leading zeros. Data keeps its length, so `0x` and `0x00` differ. This is the
+    /// default.
+    #[default]
+    Json,
+    /// Only the given fields of object results are compared, as with
+    /// [`Json`](Self::Json), e.g. the `hash` and `number` of a block, whose other fields some
+    /// providers omit or format differently. Results that are not objects are compared whole.
+    Fields(Vec<String>),
+}
+
+impl ResponseComparison {
+    /// Create a comparison of the given fields of object results.
+    pub fn fields<I, S>(fields: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self::Fields(fields.into_iter().map(Into::into).collect())
+    }
+
+    /// Returns the value compared for a response packet. Error responses are compared by code.
+    fn key(&self, response_packet: &ResponsePacket) -> Value {
+        match response_packet {
+            ResponsePacket::Single(response) => self.response_key(response),
+            ResponsePacket::Batch(responses) => {
+                // Providers may answer the requests of a batch in any order.
+                let mut responses: Vec<_> = responses.iter().collect();
+                responses.sort_by_key(|response| response.id.to_string());
+                Value::Array(responses.into_iter().map(|r| self.response_key(r)).collect())
+            }
+        }
+    }
+
+    fn response_key(&self, response: &Response) -> Value {
+        let result = match &response.payload {
+            ResponsePayload::Success(result) => result,
+            ResponsePayload::Failure(error) => return serde_json::json!({ "error": error.code }),
+        };
+        if *self == Self::Exact {
+            return Value::String(result.get().to_owned());
+        }
+        let Ok(mut value) = serde_json::from_str::<Value>(result.get()) else {
+            return Value::String(result.get().to_owned());
+        };
+        if let (Self::Fields(fields), Value::Object(object)) = (self, &mut value) {
+            object.retain(|field, _| fields.contains(field));
+        }
+        normalize_hex(&mut value, false);
+        value
+    }
+}
+
+/// Fields of blocks, transactions, and receipts whose hex strings are quantities. The `nonce`
+/// of a block is data, unlike that of a transaction, so it is left out.
+const QUANTITY_FIELDS: &[&str] = &[
+    "baseFeePerBlobGas",
+    "baseFeePerGas",
+    "blobGasPrice",
+    "blobGasUsed",
+    "blockNumber",
+    "chainId",
+    "cumulativeGasUsed",
+    "difficulty",
+    "effectiveGasPrice",
+    "excessBlobGas",
+    "gas",
+    "gasLimit",
+    "gasPrice",
+    "gasUsed",
+    "logIndex",
+    "maxFeePerBlobGas",
+    "maxFeePerGas",
+    "maxPriorityFeePerGas",
+    "number",
+    "oldestBlock",
+    "size",
+    "status",
+    "timestamp",
+    "totalDifficulty",
+    "transactionIndex",
+    "type",
+    "v",
+    "value",
+    "yParity",
+];
+
+/// Lowercase the hex strings of a value, and strip the leading zeros of its quantities.
+///
+/// Data always has an even number of digits, so that its leading zeros are part of its value:
+/// only hex strings with an odd number of digits, or in [`QUANTITY_FIELDS`], are quantities.
+fn normalize_hex(value: &mut Value, quantity: bool) {
+    match value {
+        Value::String(string) => {
+            if let Some(digits) = string.strip_prefix("0x") {
+                let digits = digits.to_ascii_lowercase();
+                *string = if quantity || digits.len() % 2 == 1 {
+                    let digits = digits.trim_start_matches('0');
+                    format!("0x{}", if digits.is_empty() { "0" } else { digits })
+                } else {
+                    format!("0x{digits}")
+                };
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(|value| normalize_hex(value, quantity)),
+        Value::Object(object) => object.iter_mut().for_each(|(field, value)| {
+            normalize_hex(value, QUANTITY_FIELDS.contains(&field.as_str()))
+        }),
+        _ => {}
+    }
+}
+
+/// Error returned by a [`QuorumTransport`] when not enough of its transports agree on the
+/// response to a request.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "consensus mismatch: {agreeing} of {transports} providers agree, {threshold} required \
+     ({failed} failed)"
+)]
+pub struct ConsensusMismatch {
+    threshold: usize,
+    agreeing: usize,
+    failed: usize,
+    transports: usize,
+}
+
+impl ConsensusMismatch {
+    /// Returns the number of transports required to agree.
+    pub const fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Returns the number of transports agreeing on the most common response.
+    pub const fn agreeing(&self) -> usize {
+        self.agreeing
+    }
+
+    /// Returns the number of transports whose request failed.
+    pub const fn failed(&self) -> usize {
+        self.failed
+    }
+
+    /// Returns the number of transports the request was sent to.
+    pub const fn transports(&self) -> usize {
+        self.transports
+    }
+}
+
+/// The responses of the transports of a [`QuorumTransport`] to a request, grouped by
+/// [`ResponseComparison::key`].
+#[derive(Debug)]
+struct Tally {
+    threshold: usize,
+    transports: usize,
+    groups: Vec<(Value, usize, ResponsePacket)>,
+    failed: usize,
+    last_error: Option<TransportError>,
+}
+
+impl Tally {
+    const fn new(threshold: usize, transports: usize) -> Self {
+        Self { threshold, transports, groups: Vec::new(), failed: 0, last_error: None }
+    }
+
+    /// Record a response, returning the response once enough transports agree on it.
+    fn record(
+        &mut self,
+        comparison: &ResponseComparison,
+        result: Result<ResponsePacket, TransportError>,
+    ) -> Option<ResponsePacket> {
+        let response = match result {
+            Ok(response) => response,
+            Err(err) => {
+                self.failed += 1;
+                self.last_error = Some(err);
+                return None;
+            }
+        };
+        let key = comparison.key(&response);
+        let index = match self.groups.iter().position(|(group, ..)| *group == key) {
+            Some(index) => index,
+            None => {
+                self.groups.push((key, 0, response));
+                self.groups.len() - 1
+            }
+        };
+        let (_, count, response) = &mut self.groups[index];
+        *count += 1;
+        (*count >= self.threshold).then(|| response.clone())
+    }
+
+    /// Returns the error once every transport has responded without agreement. When every
+    /// request failed, the error of the last one is returned.
+    fn into_error(self) -> TransportError {
+        match self.last_error {
+            Some(err) if self.failed == self.transports => err,
+            _ => TransportErrorKind::custom(ConsensusMismatch {
+                threshold: self.threshold,
+                agreeing: self.groups.iter().map(|(_, count, _)| *count).max().unwrap_or(0),
+                failed: self.failed,
+                transports: self.transports,
+            }),
+        }
+    }
+}
+
+/// A transport sending every request to several transports, e.g. [`IcpTransport`]s of
+/// different [`RpcService`]s, and returning a response only once a threshold of them agree on
+/// it, see [`ResponseComparison`].
+///
+/// A request fails with a [`ConsensusMismatch`] error when too few transports agree, or with
+/// the error of the last transport when all of them fail. The response is returned as soon as
+/// the threshold is reached, without waiting for the other transports, whose outcalls still
+/// complete and are paid for.
+///
+/// Responses that legitimately differ between providers, such as `eth_blockNumber` while a
+/// block is being produced, may not reach agreement. Send such requests through a single
+/// transport, or compare only stable fields with [`ResponseComparison::Fields`].
+///
+/// # Examples
+///
+/// ```ignore
+/// let transport = QuorumTransport::icp(
+///     IcpConfig::new(RpcService::EthMainnet(EthMainnetService::Alchemy)),
+///     [
+///         RpcService::EthMainnet(EthMainnetService::Alchemy),
+///         RpcService::EthMainnet(EthMainnetService::Ankr),
+///         RpcService::EthMainnet(EthMainnetService::PublicNode),
+///     ],
+/// )
+/// .with_threshold(2);
+/// let client = ClientBuilder::default().transport(transport, false);
+/// ```
+#[derive(Clone, Debug)]
+pub struct QuorumTransport<T> {
+    transports: Arc<[T]>,
+    threshold: usize,
+    comparison: ResponseComparison,
+}
+
+impl<T> QuorumTransport<T> {
+    /// Create a quorum of the given transports, requiring all of them to agree by default.
+    pub fn new(transports: impl IntoIterator<Item = T>) -> Self {
+        let transports: Arc<[T]> = transports.into_iter().collect();
+        Self { threshold: transports.len(), transports, comparison: ResponseComparison::default() }
+    }
+
+    /// Set the number of transports that must agree on a response, at least 1 and at most the
+    /// number of transports.
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold.clamp(1, self.transports.len().max(1));
+        self
+    }
+
+    /// Set how the responses of the transports are compared. Defaults to
+    /// [`ResponseComparison::Json`].
+    pub fn with_comparison(mut self, comparison: ResponseComparison) -> Self {
+        self.comparison = comparison;
+        self
+    }
+
+    /// Returns the transports of the quorum.
+    pub fn transports(&self) -> &[T] {
+        &self.transports
+    }
+
+    /// Returns the number of transports that must agree on a response.
+    pub const fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Returns how the responses of the transports are compared.
+    pub const fn comparison(&self) -> &ResponseComparison {
+        &self.comparison
+    }
+
+    /// Check if the transport is local. Always `false`.
+    pub const fn is_local(&self) -> bool {
+        false
+    }
+}
+
+impl QuorumTransport<IcpTransport> {
+    /// Create a quorum of [`IcpTransport`]s with the given config, one for each of the given
+    /// services. The transports share the [`CyclesBudget`](crate::CyclesBudget) of the config.
+    pub fn icp(config: IcpConfig, rpc_services: impl IntoIterator<Item = RpcService>) -> Self {
+        let mut shared: Option<IcpTransport> = None;
+        Self::new(rpc_services.into_iter().map(|rpc_service| {
+            let config = shared
+                .as_ref()
+                .map_or_else(|| config.clone(), |first| config.clone().share_cycles_budget(first));
+            let mut transport = IcpTransport::with_config(config);
+            transport.set_rpc_service(rpc_service);
+            shared.get_or_insert_with(|| transport.clone());
+            transport
+        }))
+    }
+}
+
+impl<T> QuorumTransport<T>
+where
+    T: Transport + Clone,
+{
+    fn request(&self, request_packet: RequestPacket) -> TransportFut<'static> {
+        let mut pending: FuturesUnordered<_> = self
+            .transports
+            .iter()
+            .map(|transport| transport.clone().call(request_packet.clone()))
+            .collect();
+        let mut tally = Tally::new(self.threshold, self.transports.len());
+        let comparison = self.comparison.clone();
+        Box::pin(async move {
+            while let Some(result) = pending.next().await {
+                if let Some(response) = tally.record(&comparison, result) {
+                    return Ok(response);
+                }
+            }
+            Err(tally.into_error())
+        })
+    }
+}
+
+impl<T> Service<RequestPacket> for QuorumTransport<T>
+where
+    T: Transport + Clone,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    #[inline]
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Each request is sent with clones of the transports, which are ready when called.
+        Poll::Ready(Ok(()))
+    }
+
+    #[inline]
+    fn call(&mut self, req: RequestPacket) -> Self::Future {
+        self.request(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_json_rpc::{Id, Request};
+
+    /// A transport answering every request with a fixed result, or failing.
+    #[derive(Clone)]
+    struct Fixed(Option<&'static str>);
+
+    impl Service<RequestPacket> for Fixed {
+        type Response = ResponsePacket;
+        type Error = TransportError;
+        type Future = TransportFut<'static>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: RequestPacket) -> Self::Future {
+            let result = self.0;
+            Box::pin(async move {
+                let result = result.ok_or_else(TransportErrorKind::backend_gone)?;
+                let body = format!(r#"{{"jsonrpc":"2.0","id":1,"result":{result}}}"#);
+                Ok(serde_json::from_str(&body).unwrap())
+            })
+        }
+    }
+
+    fn request(quorum: &mut QuorumTransport<Fixed>) -> Result<ResponsePacket, TransportError> {
+        let request = Request::new("eth_getBalance", Id::Number(1), ()).serialize().unwrap();
+        futures::executor::block_on(quorum.call(request.into()))
+    }
+
+    fn result(response: ResponsePacket) -> String {
+        let ResponsePacket::Single(response) = response else { panic!("expected one response") };
+        response.payload.as_success().unwrap().get().to_owned()
+    }
+
+    #[test]
+    fn returns_responses_reaching_the_threshold() {
+        let transports =
+            [Fixed(Some(r#""0x00A""#)), Fixed(Some(r#""0xb""#)), Fixed(Some(r#""0xa""#))];
+        let mut quorum = QuorumTransport::new(transports).with_threshold(2);
+        assert_eq!(result(request(&mut quorum).unwrap()), r#""0x00A""#);
+
+        let mut exact = quorum.clone().with_comparison(ResponseComparison::Exact);
+        let err = request(&mut exact).unwrap_err().to_string();
+        assert!(err.contains("1 of 3 providers agree, 2 required"), "{err}");
+
+        let mut all = QuorumTransport::new([Fixed(None), Fixed(None)]);
+        assert!(matches!(
+            request(&mut all).unwrap_err(),
+            TransportError::Transport(TransportErrorKind::BackendGone)
+        ));
+    }
+
+    #[test]
+    fn compares_selected_fields() {
+        let transports = [
+            Fixed(Some(r#"{"hash":"0x0A","number":"0x2","size":"0x10"}"#)),
+            Fixed(Some(r#"{"number":"0x02","hash":"0x0a"}"#)),
+        ];
+        let mut quorum = QuorumTransport::new(transports);
+        assert!(request(&mut quorum).is_err());
+
+        let mut fields = quorum.with_comparison(ResponseComparison::fields(["hash", "number"]));
+        assert!(request(&mut fields).is_ok());
+    }
+
+    #[test]
+    fn compares_data_by_length() {
+        let mut quorum = QuorumTransport::new([Fixed(Some(r#""0x""#)), Fixed(Some(r#""0x00""#))]);
+        assert!(request(&mut quorum).is_err());
+
+        let transports = [
+            Fixed(Some(r#"{"nonce":"0x0000000000000000","gasUsed":"0x00"}"#)),
+            Fixed(Some(r#"{"nonce":"0x0","gasUsed":"0x0"}"#)),
+        ];
+        let mut fields = QuorumTransport::new(transports)
+            .with_comparison(ResponseComparison::fields(["gasUsed"]));
+        assert!(request(&mut fields).is_ok());
+        let mut nonces = fields.with_comparison(ResponseComparison::fields(["nonce"]));
+        assert!(request(&mut nonces).is_err());
+    }
+}
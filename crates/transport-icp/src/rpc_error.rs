@@ -0,0 +1,169 @@
+use crate::{headers, HttpOutcallError, ProviderError, RejectionCode, RpcError};
+use alloy_transport::{TransportError, TransportErrorKind};
+
+/// An error returned by the EVM RPC canister instead of a response from the provider.
+///
+/// Requests fail with a [`Custom`](TransportErrorKind::Custom) transport error holding it,
+/// see [`EvmRpcError::from_transport_error`]. JSON-RPC errors of the provider are returned as
+/// [`ErrorResp`](TransportError::ErrorResp) errors instead, and HTTP errors of the provider,
+/// e.g. `429` when it rate limits the canister, as [`HttpError`](TransportErrorKind::HttpError)
+/// transport errors, like those of the [`IcpHttpTransport`](crate::IcpHttpTransport).
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum EvmRpcError {
+    /// The call attached fewer cycles than the EVM RPC canister charges for the request.
+    #[error("too few cycles attached to the EVM RPC canister: {received} of {expected}")]
+    TooFewCycles { expected: u128, received: u128 },
+    /// The provider of the service is not supported by the EVM RPC canister.
+    #[error("the provider is not supported by the EVM RPC canister")]
+    ProviderNotFound,
+    /// The EVM RPC canister requires a provider for the request.
+    #[error("the EVM RPC canister requires a provider")]
+    MissingRequiredProvider,
+    /// The canister is not allowed to use the provider.
+    #[error("no permission to use the provider")]
+    NoPermission,
+    /// The EVM RPC canister rejected the request, e.g. because of a disallowed host.
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+    /// The replicas did not reach consensus on the response of the outcall, e.g. because the
+    /// provider answered them differently.
+    #[error("no consensus on the outcall response: {0}")]
+    NoConsensus(String),
+    /// The outcall failed, e.g. because the provider could not be reached or its response was
+    /// too large.
+    #[error("outcall failed with {code:?}: {message}")]
+    OutcallFailed { code: RejectionCode, message: String },
+    /// The provider answered with a success status but not with a JSON-RPC response.
+    #[error("invalid JSON-RPC response with status {status}: {body}")]
+    InvalidResponse { status: u16, body: String, parsing_error: Option<String> },
+}
+
+impl EvmRpcError {
+    /// Returns the EVM RPC canister error of a failed request, if it failed with one.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// if let Err(err) = provider.get_block_number().await {
+    ///     if let Some(EvmRpcError::NoConsensus(_)) = EvmRpcError::from_transport_error(&err) {
+    ///         // Try again with a request the provider answers consistently.
+    ///     }
+    /// }
+    /// ```
+    pub fn from_transport_error(err: &TransportError) -> Option<&Self> {
+        match err {
+            TransportError::Transport(TransportErrorKind::Custom(err)) => err.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Convert an error of the EVM RPC canister into a transport error, redacting the values of
+/// secret headers.
+pub(crate) fn transport_error(err: RpcError) -> TransportError {
+    let err = match err {
+        RpcError::JsonRpcError(err) => {
+            return TransportError::ErrorResp(alloy_json_rpc::ErrorPayload {
+                code: err.code,
+                message: headers::redact(err.message),
+                data: None,
+            });
+        }
+        RpcError::HttpOutcallError(HttpOutcallError::InvalidHttpJsonRpcResponse {
+            status,
+            body,
+            parsingError,
+        }) => {
+            if !(200..300).contains(&status) {
+                return TransportErrorKind::http_error(status, headers::redact(body));
+            }
+            EvmRpcError::InvalidResponse {
+                status,
+                body: headers::redact(body),
+                parsing_error: parsingError.map(headers::redact),
+            }
+        }
+        RpcError::HttpOutcallError(HttpOutcallError::IcError { code, message }) => {
+            let message = headers::redact(message);
+            if message.to_lowercase().contains("consensus") {
+                EvmRpcError::NoConsensus(message)
+            } else {
+                EvmRpcError::OutcallFailed { code, message }
+            }
+        }
+        RpcError::ProviderError(ProviderError::TooFewCycles { expected, received }) => {
+            let cycles = |nat: candid::Nat| u128::try_from(nat.0).unwrap_or(u128::MAX);
+            EvmRpcError::TooFewCycles { expected: cycles(expected), received: cycles(received) }
+        }
+        RpcError::ProviderError(ProviderError::ProviderNotFound) => EvmRpcError::ProviderNotFound,
+        RpcError::ProviderError(ProviderError::MissingRequiredProvider) => {
+            EvmRpcError::MissingRequiredProvider
+        }
+        RpcError::ProviderError(ProviderError::NoPermission) => EvmRpcError::NoPermission,
+        RpcError::ValidationError(err) => {
+            EvmRpcError::InvalidRequest(headers::redact(format!("{err:?}")))
+        }
+    };
+    TransportErrorKind::custom(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcall_error(code: RejectionCode, message: &str) -> RpcError {
+        RpcError::HttpOutcallError(HttpOutcallError::IcError { code, message: message.into() })
+    }
+
+    #[test]
+    fn maps_canister_errors_to_typed_errors() {
+        let err = transport_error(outcall_error(
+            RejectionCode::SysTransient,
+            "No consensus could be reached. Replicas had different responses.",
+        ));
+        assert!(matches!(
+            EvmRpcError::from_transport_error(&err),
+            Some(EvmRpcError::NoConsensus(_))
+        ));
+
+        let err = transport_error(outcall_error(RejectionCode::SysFatal, "connection refused"));
+        assert_eq!(
+            EvmRpcError::from_transport_error(&err),
+            Some(&EvmRpcError::OutcallFailed {
+                code: RejectionCode::SysFatal,
+                message: "connection refused".into()
+            })
+        );
+
+        let err = transport_error(RpcError::ProviderError(ProviderError::TooFewCycles {
+            expected: 10u32.into(),
+            received: 5u32.into(),
+        }));
+        assert_eq!(
+            EvmRpcError::from_transport_error(&err),
+            Some(&EvmRpcError::TooFewCycles { expected: 10, received: 5 })
+        );
+    }
+
+    #[test]
+    fn maps_provider_errors_to_json_rpc_and_http_errors() {
+        let err = transport_error(RpcError::HttpOutcallError(
+            HttpOutcallError::InvalidHttpJsonRpcResponse {
+                status: 429,
+                body: "too many requests".into(),
+                parsingError: None,
+            },
+        ));
+        assert!(matches!(
+            &err,
+            TransportError::Transport(TransportErrorKind::HttpError(err)) if err.is_rate_limit_err()
+        ));
+        assert_eq!(EvmRpcError::from_transport_error(&err), None);
+
+        let err = transport_error(RpcError::JsonRpcError(crate::JsonRpcError {
+            code: -32000,
+            message: "execution reverted".into(),
+        }));
+        assert!(err.as_error_resp().is_some_and(|payload| payload.code == -32000));
+    }
+}
@@ -0,0 +1,163 @@
+use alloy_json_rpc::RequestPacket;
+use serde_json::Value;
+use std::{fmt, sync::Arc};
+
+/// A hook that rewrites the params of every request before it leaves the [`IcpTransport`].
+///
+/// Some providers are picky about how params are encoded, e.g. they reject mixed-case hex
+/// strings or `null` fields. A serializer receives the method name and the params of each
+/// request, including each request of a batch, and may modify the params in place.
+///
+/// # Examples
+///
+/// ```ignore
+/// let serializer = ParamsSerializer::lowercase_hex().then(ParamsSerializer::strip_nulls());
+/// let config = IcpConfig::new(rpc_service).set_params_serializer(serializer);
+/// ```
+///
+/// [`IcpTransport`]: crate::IcpTransport
+#[derive(Clone)]
+pub struct ParamsSerializer(Arc<SerializeFn>);
+
+type SerializeFn = dyn Fn(&str, &mut Value) + Send + Sync;
+
+impl fmt::Debug for ParamsSerializer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParamsSerializer").finish_non_exhaustive()
+    }
+}
+
+impl ParamsSerializer {
+    /// Create a serializer from a function receiving the method name and params of a request.
+    pub fn new(f: impl Fn(&str, &mut Value) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// A serializer that lowercases all hex strings, e.g. addresses and hashes.
+    pub fn lowercase_hex() -> Self {
+        Self::new(|_, params| visit_strings(params, &mut make_hex_lowercase))
+    }
+
+    /// A serializer that removes all `null` fields from objects, e.g. unset transaction fields.
+    pub fn strip_nulls() -> Self {
+        Self::new(|_, params| strip_nulls(params))
+    }
+
+    /// A serializer that encodes all non-negative integers as hex quantities, e.g. `"0x1a"`.
+    pub fn quantity_numbers() -> Self {
+        Self::new(|_, params| encode_quantities(params))
+    }
+
+    /// Chain another serializer, applied after this one.
+    pub fn then(self, next: Self) -> Self {
+        Self::new(move |method, params| {
+            self.apply(method, params);
+            next.apply(method, params);
+        })
+    }
+
+    /// Apply the serializer to the params of a request.
+    pub fn apply(&self, method: &str, params: &mut Value) {
+        (self.0)(method, params)
+    }
+}
+
+/// Serialize a request packet to the buffer, rewriting the params of each request with the
+/// serializer.
+pub(crate) fn serialize_packet(
+    request_packet: &RequestPacket,
+    serializer: Option<&ParamsSerializer>,
+    buffer: &mut Vec<u8>,
+) -> serde_json::Result<()> {
+    let Some(serializer) = serializer else {
+        return serde_json::to_writer(buffer, request_packet);
+    };
+
+    let mut value = serde_json::to_value(request_packet)?;
+    let requests = match &mut value {
+        Value::Array(requests) => requests.as_mut_slice(),
+        request => std::slice::from_mut(request),
+    };
+    for request in requests {
+        let Value::Object(request) = request else { continue };
+        let method = request.get("method").and_then(Value::as_str).unwrap_or_default().to_string();
+        if let Some(params) = request.get_mut("params") {
+            serializer.apply(&method, params);
+        }
+    }
+    serde_json::to_writer(buffer, &value)
+}
+
+fn visit_strings(value: &mut Value, f: &mut impl FnMut(&mut str)) {
+    match value {
+        Value::String(s) => f(s),
+        Value::Array(values) => values.iter_mut().for_each(|v| visit_strings(v, f)),
+        Value::Object(map) => map.values_mut().for_each(|v| visit_strings(v, f)),
+        _ => {}
+    }
+}
+
+fn make_hex_lowercase(s: &mut str) {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        s.make_ascii_lowercase();
+    }
+}
+
+fn strip_nulls(value: &mut Value) {
+    match value {
+        Value::Array(values) => values.iter_mut().for_each(strip_nulls),
+        Value::Object(map) => {
+            map.retain(|_, v| !v.is_null());
+            map.values_mut().for_each(strip_nulls);
+        }
+        _ => {}
+    }
+}
+
+fn encode_quantities(value: &mut Value) {
+    match value {
+        Value::Number(n) => {
+            if let Some(n) = n.as_u64() {
+                *value = Value::String(format!("{n:#x}"));
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(encode_quantities),
+        Value::Object(map) => map.values_mut().for_each(encode_quantities),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_json_rpc::{Id, Request};
+    use serde_json::json;
+
+    #[test]
+    fn builtin_serializers() {
+        let mut params = json!([{ "to": "0xABcd", "value": null, "gas": 26 }, "Latest", 3]);
+        ParamsSerializer::lowercase_hex()
+            .then(ParamsSerializer::strip_nulls())
+            .then(ParamsSerializer::quantity_numbers())
+            .apply("eth_call", &mut params);
+        assert_eq!(params, json!([{ "to": "0xabcd", "gas": "0x1a" }, "Latest", "0x3"]));
+    }
+
+    #[test]
+    fn serializer_is_applied_to_each_request_of_a_batch() {
+        let serializer = ParamsSerializer::new(|method, params| {
+            if method == "eth_getBalance" {
+                *params = json!(["0x0"]);
+            }
+        });
+        let batch = RequestPacket::Batch(vec![
+            Request::new("eth_getBalance", Id::Number(1), (7,)).serialize().unwrap(),
+            Request::new("eth_getCode", Id::Number(2), (7,)).serialize().unwrap(),
+        ]);
+        let mut buffer = Vec::new();
+        serialize_packet(&batch, Some(&serializer), &mut buffer).unwrap();
+        let serialized: Value = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(serialized[0]["params"], json!(["0x0"]));
+        assert_eq!(serialized[1]["params"], json!([7]));
+    }
+}
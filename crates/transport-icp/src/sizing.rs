@@ -0,0 +1,49 @@
+use crate::{HttpOutcallError, RejectionCode, RequestResult, RpcError};
+use ic_cdk::api::call::CallResult;
+
+/// The largest max response size of an HTTPS outcall, in bytes.
+const MAX_RESPONSE_SIZE_LIMIT: u64 = 2_000_000;
+
+/// Default number of times a request is retried with a doubled max response size.
+pub(crate) const DEFAULT_RESPONSE_SIZE_RETRIES: u32 = 3;
+
+/// Returns the max response size to retry a request with after its response exceeded
+/// `max_response_size`, or `None` if it is already the largest.
+pub(crate) fn grow(max_response_size: u64) -> Option<u64> {
+    (max_response_size < MAX_RESPONSE_SIZE_LIMIT)
+        .then(|| max_response_size.saturating_mul(2).min(MAX_RESPONSE_SIZE_LIMIT))
+}
+
+/// Returns `true` if the outcall failed because the response exceeded its max response size.
+pub(crate) fn is_response_too_large(call_result: &CallResult<(RequestResult,)>) -> bool {
+    matches!(
+        call_result,
+        Ok((RequestResult::Err(RpcError::HttpOutcallError(HttpOutcallError::IcError {
+            code: RejectionCode::SysFatal,
+            message,
+        })),)) if message.contains("size limit")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_truncated_responses() {
+        let ic_error = |code, message: &str| {
+            Ok((RequestResult::Err(RpcError::HttpOutcallError(HttpOutcallError::IcError {
+                code,
+                message: message.into(),
+            })),))
+        };
+        let message = "Http body exceeds size limit of 1000 bytes: 1412 bytes";
+        assert!(is_response_too_large(&ic_error(RejectionCode::SysFatal, message)));
+        assert!(!is_response_too_large(&ic_error(RejectionCode::SysTransient, "timeout")));
+        assert!(!is_response_too_large(&Ok((RequestResult::Ok("{}".into()),))));
+
+        assert_eq!(grow(5_000), Some(10_000));
+        assert_eq!(grow(1_500_000), Some(MAX_RESPONSE_SIZE_LIMIT));
+        assert_eq!(grow(MAX_RESPONSE_SIZE_LIMIT), None);
+    }
+}
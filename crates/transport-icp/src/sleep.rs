@@ -0,0 +1,77 @@
+use ic_cdk_timers::{clear_timer, set_timer, TimerId};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+/// Shared state between a [`Sleep`] future and the timer that completes it.
+#[derive(Debug, Default)]
+struct SleepState {
+    elapsed: bool,
+    waker: Option<Waker>,
+}
+
+/// A future that completes after a duration has elapsed.
+///
+/// Canisters have no access to `tokio` or any other runtime timer, so this is
+/// backed by a one-shot [`ic_cdk_timers::set_timer`]. The timer is only
+/// registered once the future is first polled, and is cleared again if the
+/// future is dropped before it fires.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Sleep {
+    duration: Duration,
+    state: Arc<Mutex<SleepState>>,
+    timer_id: Option<TimerId>,
+}
+
+/// Returns a [`Sleep`] future that completes after `duration`.
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep { duration, state: Default::default(), timer_id: None }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        {
+            let mut state = this.state.lock().unwrap();
+            if state.elapsed {
+                return Poll::Ready(());
+            }
+            state.waker = Some(cx.waker().clone());
+        }
+
+        if this.timer_id.is_none() {
+            let state = this.state.clone();
+            let timer_id = set_timer(this.duration, move || {
+                let waker = {
+                    let mut state = state.lock().unwrap();
+                    state.elapsed = true;
+                    state.waker.take()
+                };
+                // Woken without the lock held, as the task polls the future right away.
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            });
+            this.timer_id = Some(timer_id);
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(timer_id) = self.timer_id.take() {
+            if !self.state.lock().unwrap().elapsed {
+                clear_timer(timer_id);
+            }
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! Utilities to build a stub EVM RPC canister, to test canisters using the ICP transport.
+//!
+//! The [`StubEvmRpc`] answers JSON-RPC requests with canned responses and records every request
+//! it receives, so tests can assert on the requests made by pollers, watchers and pending
+//! transactions without making HTTPS outcalls.
+//!
+//! # Stub canister
+//!
+//! A stub EVM RPC canister exposes the `request` method of the EVM RPC canister, answered by the
+//! thread-local stub, and methods to configure it and inspect the requests it received:
+//!
+//! ```ignore
+//! use alloy_transport_icp::{
+//!     test_utils::{self, StubEvmRpc, StubRequest, StubResponse},
+//!     RequestResult, RpcService,
+//! };
+//!
+//! #[ic_cdk::update]
+//! fn request(service: RpcService, payload: String, max_response_size: u64) -> RequestResult {
+//!     test_utils::handle_request(service, payload, max_response_size)
+//! }
+//!
+//! #[ic_cdk::update]
+//! fn respond(method: String, response: StubResponse) {
+//!     test_utils::with_stub(|stub| stub.respond(method, response));
+//! }
+//!
+//! #[ic_cdk::update]
+//! fn respond_once(method: String, response: StubResponse) {
+//!     test_utils::with_stub(|stub| stub.respond_once(method, response));
+//! }
+//!
+//! #[ic_cdk::query]
+//! fn requests() -> Vec<StubRequest> {
+//!     test_utils::with_stub(|stub| stub.requests().to_vec())
+//! }
+//!
+//! #[ic_cdk::update]
+//! fn clear_requests() {
+//!     test_utils::with_stub(StubEvmRpc::clear_requests);
+//! }
+//! ```
+//!
+//! # Host-side tests
+//!
+//! The [`test_utils_icp`](crate::test_utils_icp) module, behind the `test-utils-icp` feature,
+//! starts [PocketIC](https://github.com/dfinity/ic/tree/master/packages/pocket-ic), installs the
+//! stub canister at [`CANISTER_ID`] so the transport calls it, and advances time to fire the
+//! `ic_cdk_timers` of pollers and pending transactions:
+//!
+//! ```ignore
+//! let env = IcpTestEnv::new();
+//! env.install_stub(stub_wasm);
+//! env.respond("eth_blockNumber", StubResponse::result(&"0x2a"));
+//!
+//! // Start a poller in the canister under test, then fire its timers.
+//! env.advance_time(Duration::from_secs(10));
+//! env.assert_request_count("eth_blockNumber", 1);
+//! ```
+//!
+//! [`CANISTER_ID`]: crate::CANISTER_ID
+
+use crate::{
+    HttpOutcallError, JsonRpcError, RejectionCode, RequestResult, RpcError, RpcService,
+    ValidationError,
+};
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+};
+
+/// The JSON-RPC error code of a method the stub has no response for.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+thread_local! {
+    static STUB: RefCell<StubEvmRpc> = RefCell::new(StubEvmRpc::new());
+}
+
+/// A response of the [`StubEvmRpc`] to a JSON-RPC method.
+#[derive(Debug, CandidType, Deserialize, Clone)]
+pub enum StubResponse {
+    /// The JSON encoded result of the method.
+    Result(String),
+    /// A JSON-RPC error returned for the method.
+    JsonRpcError(JsonRpcError),
+    /// An error of the EVM RPC canister, failing the whole request.
+    RpcError(RpcError),
+}
+
+impl StubResponse {
+    /// Create a response with the given result.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the result fails to serialize.
+    pub fn result<T: Serialize>(result: &T) -> Self {
+        Self::Result(serde_json::to_string(result).expect("Failed to serialize result."))
+    }
+
+    /// Create a response with a JSON-RPC error.
+    pub fn error(code: i64, message: impl Into<String>) -> Self {
+        Self::JsonRpcError(JsonRpcError { code, message: message.into() })
+    }
+}
+
+/// A JSON-RPC request received by the [`StubEvmRpc`].
+#[derive(Debug, CandidType, Deserialize, Clone, PartialEq, Eq)]
+pub struct StubRequest {
+    /// The method of the request.
+    pub method: String,
+    /// The JSON encoded params of the request.
+    pub params: String,
+}
+
+/// A stub of the EVM RPC canister, answering JSON-RPC requests with canned responses.
+///
+/// Responses queued with [`respond_once`](Self::respond_once) are returned first, in order, then
+/// the response set with [`respond`](Self::respond) is returned for every request of the method.
+/// Requests to a method without a response fail with a "method not found" JSON-RPC error.
+#[derive(Debug, Default)]
+pub struct StubEvmRpc {
+    responses: HashMap<String, StubResponse>,
+    queued: HashMap<String, VecDeque<StubResponse>>,
+    requests: Vec<StubRequest>,
+}
+
+impl StubEvmRpc {
+    /// Create a stub without any response.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the response returned for every request of the method.
+    pub fn respond(&mut self, method: impl Into<String>, response: StubResponse) {
+        self.responses.insert(method.into(), response);
+    }
+
+    /// Queue a response returned for the next request of the method only.
+    pub fn respond_once(&mut self, method: impl Into<String>, response: StubResponse) {
+        self.queued.entry(method.into()).or_default().push_back(response);
+    }
+
+    /// Returns the requests received, in order.
+    pub fn requests(&self) -> &[StubRequest] {
+        &self.requests
+    }
+
+    /// Returns the number of requests received for the method.
+    pub fn request_count(&self, method: &str) -> usize {
+        self.requests.iter().filter(|request| request.method == method).count()
+    }
+
+    /// Forget the requests received.
+    pub fn clear_requests(&mut self) {
+        self.requests.clear();
+    }
+
+    /// Remove every response and forget the requests received.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Answer a JSON-RPC payload, holding a single request or a batch, as the EVM RPC canister.
+    ///
+    /// The request fails as an HTTPS outcall would if the response exceeds the max response
+    /// size.
+    pub fn handle(&mut self, payload: &str, max_response_size: u64) -> RequestResult {
+        let response = match serde_json::from_str(payload) {
+            Ok(Value::Array(requests)) => {
+                let mut responses = Vec::with_capacity(requests.len());
+                for request in requests {
+                    match self.answer(request) {
+                        Ok(response) => responses.push(response),
+                        Err(err) => return RequestResult::Err(err),
+                    }
+                }
+                Value::Array(responses)
+            }
+            Ok(request) => match self.answer(request) {
+                Ok(response) => response,
+                Err(err) => return RequestResult::Err(err),
+            },
+            Err(err) => return invalid_request(err.to_string()),
+        };
+
+        let body = response.to_string();
+        if body.len() as u64 > max_response_size {
+            return RequestResult::Err(RpcError::HttpOutcallError(HttpOutcallError::IcError {
+                code: RejectionCode::SysFatal,
+                message: format!(
+                    "Http body exceeds size limit of {max_response_size} bytes: {} bytes",
+                    body.len()
+                ),
+            }));
+        }
+        RequestResult::Ok(body)
+    }
+
+    fn answer(&mut self, request: Value) -> Result<Value, RpcError> {
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = request.get("method").and_then(Value::as_str).unwrap_or_default().to_owned();
+        let params = request.get("params").map(Value::to_string).unwrap_or_default();
+
+        let response = self
+            .queued
+            .get_mut(&method)
+            .and_then(VecDeque::pop_front)
+            .or_else(|| self.responses.get(&method).cloned());
+        self.requests.push(StubRequest { method, params });
+
+        match response {
+            Some(StubResponse::Result(result)) => {
+                let result: Value = serde_json::from_str(&result).map_err(|err| {
+                    RpcError::ValidationError(ValidationError::Custom(err.to_string()))
+                })?;
+                Ok(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+            }
+            Some(StubResponse::JsonRpcError(error)) => Ok(json_rpc_error(id, error)),
+            Some(StubResponse::RpcError(err)) => Err(err),
+            None => Ok(json_rpc_error(
+                id,
+                JsonRpcError { code: METHOD_NOT_FOUND, message: "method not found".into() },
+            )),
+        }
+    }
+}
+
+fn json_rpc_error(id: Value, error: JsonRpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": error.code, "message": error.message },
+    })
+}
+
+const fn invalid_request(message: String) -> RequestResult {
+    RequestResult::Err(RpcError::HttpOutcallError(HttpOutcallError::InvalidHttpJsonRpcResponse {
+        status: 400,
+        body: String::new(),
+        parsingError: Some(message),
+    }))
+}
+
+/// Calls the function with the thread-local [`StubEvmRpc`] of the canister.
+pub fn with_stub<R>(f: impl FnOnce(&mut StubEvmRpc) -> R) -> R {
+    STUB.with_borrow_mut(f)
+}
+
+/// Answer a request to the `request` method of the EVM RPC canister with the thread-local
+/// [`StubEvmRpc`]. The [`RpcService`] is ignored.
+pub fn handle_request(
+    _rpc_service: RpcService,
+    payload: String,
+    max_response_size: u64,
+) -> RequestResult {
+    with_stub(|stub| stub.handle(&payload, max_response_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ok(result: RequestResult) -> Value {
+        match result {
+            RequestResult::Ok(body) => serde_json::from_str(&body).unwrap(),
+            RequestResult::Err(err) => panic!("unexpected error: {err:?}"),
+        }
+    }
+
+    #[test]
+    fn answers_requests_and_batches() {
+        let mut stub = StubEvmRpc::new();
+        stub.respond("eth_blockNumber", StubResponse::result(&"0x2a"));
+        stub.respond_once("eth_blockNumber", StubResponse::result(&"0x29"));
+        stub.respond("eth_chainId", StubResponse::error(-32000, "unavailable"));
+
+        let request = r#"{"jsonrpc":"2.0","id":1,"method":"eth_blockNumber","params":[]}"#;
+        assert_eq!(ok(stub.handle(request, 2_000))["result"], "0x29");
+        assert_eq!(ok(stub.handle(request, 2_000))["result"], "0x2a");
+
+        let batch = r#"[
+            {"jsonrpc":"2.0","id":2,"method":"eth_chainId","params":[]},
+            {"jsonrpc":"2.0","id":3,"method":"eth_gasPrice","params":[]}
+        ]"#;
+        let responses = ok(stub.handle(batch, 2_000));
+        assert_eq!(responses[0]["id"], 2);
+        assert_eq!(responses[0]["error"]["code"], -32000);
+        assert_eq!(responses[1]["error"]["code"], METHOD_NOT_FOUND);
+
+        assert_eq!(stub.request_count("eth_blockNumber"), 2);
+        assert_eq!(stub.requests()[2].method, "eth_chainId");
+        assert_eq!(stub.requests()[2].params, "[]");
+    }
+
+    #[test]
+    fn fails_responses_over_max_size() {
+        let mut stub = StubEvmRpc::new();
+        stub.respond("eth_getBlockByNumber", StubResponse::result(&"x".repeat(100)));
+        let request = r#"{"jsonrpc":"2.0","id":1,"method":"eth_getBlockByNumber","params":[]}"#;
+        assert!(matches!(
+            stub.handle(request, 50),
+            RequestResult::Err(RpcError::HttpOutcallError(HttpOutcallError::IcError { .. }))
+        ));
+    }
+}
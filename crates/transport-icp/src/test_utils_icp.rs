@@ -0,0 +1,231 @@
+//! Host-side helpers to test canisters using the ICP transport with
+//! [PocketIC](https://github.com/dfinity/ic/tree/master/packages/pocket-ic).
+//!
+//! An [`IcpTestEnv`] runs a PocketIC instance with a fiduciary subnet, where the EVM RPC
+//! canister lives. Install a stub EVM RPC canister at [`CANISTER_ID`], built with
+//! [`test_utils`](crate::test_utils), or the real EVM RPC canister, then install the canister
+//! under test, advance time to fire the `ic_cdk_timers` of its pollers and pending transactions,
+//! and assert on the requests the stub received.
+//!
+//! The stub canister must expose the methods of the
+//! [stub canister example](crate::test_utils#stub-canister): `respond`, `respond_once`,
+//! `requests` and `clear_requests`.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let env = IcpTestEnv::new();
+//! env.install_stub(stub_wasm);
+//! env.respond("eth_blockNumber", StubResponse::result(&"0x2a"));
+//!
+//! let canister = env.install_canister(canister_wasm, encode_args(())?);
+//! env.update::<()>(canister, "start_polling", encode_args(())?)?;
+//! env.advance_time(Duration::from_secs(10));
+//! env.assert_request_count("eth_blockNumber", 1);
+//! ```
+//!
+//! [`CANISTER_ID`]: crate::CANISTER_ID
+
+use crate::{
+    test_utils::{StubRequest, StubResponse},
+    CANISTER_ID,
+};
+use candid::{decode_one, encode_args, CandidType, Deserialize, Principal};
+use pocket_ic::{PocketIc, PocketIcBuilder};
+use std::{fmt, time::Duration};
+
+/// The cycles added to each canister installed by an [`IcpTestEnv`].
+const INITIAL_CYCLES: u128 = 2_000_000_000_000;
+
+/// The rounds executed after advancing time, enough for a timer to call the EVM RPC canister
+/// and handle its response.
+const ROUNDS_PER_ADVANCE: usize = 10;
+
+/// A PocketIC instance to test canisters using the ICP transport, see the
+/// [module documentation](self).
+pub struct IcpTestEnv {
+    pic: PocketIc,
+}
+
+impl fmt::Debug for IcpTestEnv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IcpTestEnv").finish_non_exhaustive()
+    }
+}
+
+impl Default for IcpTestEnv {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IcpTestEnv {
+    /// Start a PocketIC instance with an application subnet and the fiduciary subnet of the
+    /// EVM RPC canister.
+    pub fn new() -> Self {
+        Self::with_pocket_ic(
+            PocketIcBuilder::new().with_application_subnet().with_fiduciary_subnet().build(),
+        )
+    }
+
+    /// Use the given PocketIC instance, which must have a fiduciary subnet.
+    pub const fn with_pocket_ic(pic: PocketIc) -> Self {
+        Self { pic }
+    }
+
+    /// Returns the PocketIC instance.
+    pub const fn pic(&self) -> &PocketIc {
+        &self.pic
+    }
+
+    /// Install the stub EVM RPC canister at [`CANISTER_ID`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a canister is already installed at [`CANISTER_ID`].
+    pub fn install_stub(&self, wasm: Vec<u8>) {
+        self.install_evm_rpc(wasm, encode_args(()).unwrap());
+    }
+
+    /// Install the EVM RPC canister at [`CANISTER_ID`] with the given init argument, e.g. the
+    /// real EVM RPC canister.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a canister is already installed at [`CANISTER_ID`].
+    pub fn install_evm_rpc(&self, wasm: Vec<u8>, arg: Vec<u8>) {
+        let canister = self
+            .pic
+            .create_canister_with_id(None, None, CANISTER_ID)
+            .expect("Failed to create the EVM RPC canister.");
+        self.pic.add_cycles(canister, INITIAL_CYCLES);
+        self.pic.install_canister(canister, wasm, arg, None);
+    }
+
+    /// Install a canister, e.g. the canister under test, returning its id.
+    pub fn install_canister(&self, wasm: Vec<u8>, arg: Vec<u8>) -> Principal {
+        let canister = self.pic.create_canister();
+        self.pic.add_cycles(canister, INITIAL_CYCLES);
+        self.pic.install_canister(canister, wasm, arg, None);
+        canister
+    }
+
+    /// Make an update call to a canister as the anonymous principal, decoding its reply.
+    pub fn update<R>(&self, canister: Principal, method: &str, arg: Vec<u8>) -> Result<R, String>
+    where
+        R: CandidType + for<'de> Deserialize<'de>,
+    {
+        let reply = self
+            .pic
+            .update_call(canister, Principal::anonymous(), method, arg)
+            .map_err(|err| err.to_string())?;
+        decode_one(&reply).map_err(|err| err.to_string())
+    }
+
+    /// Make a query call to a canister as the anonymous principal, decoding its reply.
+    pub fn query<R>(&self, canister: Principal, method: &str, arg: Vec<u8>) -> Result<R, String>
+    where
+        R: CandidType + for<'de> Deserialize<'de>,
+    {
+        let reply = self
+            .pic
+            .query_call(canister, Principal::anonymous(), method, arg)
+            .map_err(|err| err.to_string())?;
+        decode_one(&reply).map_err(|err| err.to_string())
+    }
+
+    /// Advance time, then execute enough rounds for the timers due by then to fire and for
+    /// their requests to the EVM RPC canister to complete.
+    pub fn advance_time(&self, duration: Duration) {
+        self.pic.advance_time(duration);
+        self.tick(ROUNDS_PER_ADVANCE);
+    }
+
+    /// Execute the given number of rounds.
+    pub fn tick(&self, rounds: usize) {
+        for _ in 0..rounds {
+            self.pic.tick();
+        }
+    }
+
+    /// Advance time by `step` until the condition holds, at most `max_steps` times. Returns
+    /// `true` if the condition holds.
+    pub fn advance_until(
+        &self,
+        step: Duration,
+        max_steps: usize,
+        mut condition: impl FnMut(&Self) -> bool,
+    ) -> bool {
+        for _ in 0..max_steps {
+            if condition(self) {
+                return true;
+            }
+            self.advance_time(step);
+        }
+        condition(self)
+    }
+
+    /// Set the response of the stub for every request of the method.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the stub canister rejects the call.
+    pub fn respond(&self, method: &str, response: StubResponse) {
+        self.call_stub("respond", encode_args((method, response)).unwrap());
+    }
+
+    /// Queue a response of the stub for the next request of the method only.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the stub canister rejects the call.
+    pub fn respond_once(&self, method: &str, response: StubResponse) {
+        self.call_stub("respond_once", encode_args((method, response)).unwrap());
+    }
+
+    /// Returns the requests received by the stub, in order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the stub canister rejects the call.
+    pub fn requests(&self) -> Vec<StubRequest> {
+        self.query(CANISTER_ID, "requests", encode_args(()).unwrap())
+            .unwrap_or_else(|err| panic!("Failed to read the requests of the stub: {err}"))
+    }
+
+    /// Returns the number of requests received by the stub for the method.
+    pub fn request_count(&self, method: &str) -> usize {
+        self.requests().iter().filter(|request| request.method == method).count()
+    }
+
+    /// Forget the requests received by the stub.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the stub canister rejects the call.
+    pub fn clear_requests(&self) {
+        self.call_stub("clear_requests", encode_args(()).unwrap());
+    }
+
+    /// Assert that the stub received the given number of requests for the method, e.g. one
+    /// `eth_getTransactionReceipt` per poll of a pending transaction.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the requests received if the count differs.
+    #[track_caller]
+    pub fn assert_request_count(&self, method: &str, expected: usize) {
+        let requests = self.requests();
+        let count = requests.iter().filter(|request| request.method == method).count();
+        assert_eq!(
+            count, expected,
+            "expected {expected} {method} requests, received {count}: {requests:?}"
+        );
+    }
+
+    fn call_stub(&self, method: &str, arg: Vec<u8>) {
+        if let Err(err) = self.pic.update_call(CANISTER_ID, Principal::anonymous(), method, arg) {
+            panic!("Failed to call {method} on the stub: {err}");
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use crate::sleep;
+use alloy_json_rpc::ResponsePacket;
+use alloy_transport::{TransportErrorKind, TransportFut, TransportResult};
+use futures::future::{select, Either};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+/// The result of a request made in its own task, and the waker of the caller waiting for it.
+#[derive(Default)]
+struct Slot {
+    result: Option<TransportResult<ResponsePacket>>,
+    waker: Option<Waker>,
+}
+
+/// Waits for the result of a request made in its own task. Dropping it stops waiting, and the
+/// request completes in its task.
+struct Detached(Arc<Mutex<Slot>>);
+
+impl Future for Detached {
+    type Output = TransportResult<ResponsePacket>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.0.lock().unwrap();
+        if let Some(result) = slot.result.take() {
+            return Poll::Ready(result);
+        }
+        slot.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Detached {
+    fn drop(&mut self) {
+        // The task of the caller may be gone once the request completes.
+        self.0.lock().unwrap().waker = None;
+    }
+}
+
+/// Wait for a request until `timeout` elapses, failing with a
+/// [`Timeout`](TransportErrorKind::Timeout) error after that.
+///
+/// Calls cannot be cancelled, so the request is made in its own task, where it completes
+/// after the deadline: its outcalls are still paid for and recorded, only their response is
+/// dropped.
+pub(crate) fn with_timeout(
+    request: TransportFut<'static>,
+    timeout: Duration,
+) -> TransportFut<'static> {
+    Box::pin(async move {
+        let slot = Arc::new(Mutex::new(Slot::default()));
+        let completed = slot.clone();
+        ic_cdk::spawn(async move {
+            let result = request.await;
+            let waker = {
+                let mut slot = completed.lock().unwrap();
+                slot.result = Some(result);
+                slot.waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        });
+        match select(Detached(slot), sleep(timeout)).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => Err(TransportErrorKind::timeout(timeout)),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker;
+
+    #[test]
+    fn detached_requests_forget_their_caller() {
+        let slot = Arc::new(Mutex::new(Slot::default()));
+        let mut detached = Detached(slot.clone());
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(Pin::new(&mut detached).poll(&mut cx).is_pending());
+        assert!(slot.lock().unwrap().waker.is_some());
+
+        slot.lock().unwrap().result = Some(Err(TransportErrorKind::backend_gone()));
+        assert!(matches!(Pin::new(&mut detached).poll(&mut cx), Poll::Ready(Err(_))));
+        drop(detached);
+        assert!(slot.lock().unwrap().waker.is_none());
+    }
+}
@@ -0,0 +1,140 @@
+//! Transforms of HTTPS outcall responses, making the responses of the replicas identical so
+//! they reach consensus.
+
+use ic_cdk::api::management_canister::http_request::HttpResponse;
+use serde_json::Value;
+use std::{cell::RefCell, collections::BTreeMap};
+
+/// A transform of an outcall response, applied by every replica before consensus.
+pub type HttpTransform = fn(HttpResponse) -> HttpResponse;
+
+thread_local! {
+    static TRANSFORMS: RefCell<BTreeMap<String, HttpTransform>> =
+        const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Register a transform under the given name, to be applied to the responses of the
+/// [`IcpHttpTransport`]s created with
+/// [`with_registered_transform`](crate::IcpHttpTransport::with_registered_transform).
+///
+/// Transforms are applied by the query method exported by this crate, see
+/// [`HTTP_TRANSFORM_METHOD`](crate::HTTP_TRANSFORM_METHOD), after the headers of the response
+/// are stripped. Registrations are not persisted, so they must be made again in
+/// `post_upgrade` as well as in `init`.
+///
+/// # Examples
+///
+/// ```ignore
+/// fn gas_price(response: HttpResponse) -> HttpResponse {
+///     round_quantities(normalize_json(response), &["result"], 1_000_000_000)
+/// }
+///
+/// #[ic_cdk::init]
+/// fn init() {
+///     register_http_transform("gas_price", gas_price);
+/// }
+/// ```
+///
+/// [`IcpHttpTransport`]: crate::IcpHttpTransport
+pub fn register_http_transform(name: impl Into<String>, transform: HttpTransform) {
+    TRANSFORMS.with_borrow_mut(|transforms| transforms.insert(name.into(), transform));
+}
+
+/// Apply the transform registered under the given name, or return `None` if there is none.
+pub(crate) fn apply(name: &str, response: HttpResponse) -> Option<HttpResponse> {
+    let transform = TRANSFORMS.with_borrow(|transforms| transforms.get(name).copied())?;
+    Some(transform(response))
+}
+
+/// Serialize the JSON body of a response again, without whitespace and with the keys of objects
+/// sorted, so providers formatting identical responses differently agree. Bodies that are not
+/// JSON are left as is.
+pub fn normalize_json(mut response: HttpResponse) -> HttpResponse {
+    if let Ok(body) = serde_json::from_slice::<Value>(&response.body) {
+        response.body = body.to_string().into_bytes();
+    }
+    response
+}
+
+/// Round down the hex quantities of the given fields of a JSON body to a multiple of `step`,
+/// e.g. the `result` of `eth_gasPrice` to a multiple of 1 gwei, so values sampled by the
+/// replicas a moment apart agree. The fields are found at any depth, including in arrays and in
+/// the responses of a batch, and may hold a quantity or an array of quantities.
+///
+/// Rounding changes the values returned to the caller, so only round fields that tolerate it.
+pub fn round_quantities(mut response: HttpResponse, fields: &[&str], step: u128) -> HttpResponse {
+    if step <= 1 {
+        return response;
+    }
+    if let Ok(mut body) = serde_json::from_slice::<Value>(&response.body) {
+        round_fields(&mut body, fields, step);
+        response.body = body.to_string().into_bytes();
+    }
+    response
+}
+
+fn round_fields(value: &mut Value, fields: &[&str], step: u128) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                if fields.contains(&key.as_str()) {
+                    round_quantity(value, step);
+                }
+                round_fields(value, fields, step);
+            }
+        }
+        Value::Array(values) => {
+            values.iter_mut().for_each(|value| round_fields(value, fields, step))
+        }
+        _ => {}
+    }
+}
+
+fn round_quantity(value: &mut Value, step: u128) {
+    match value {
+        Value::String(hex) => {
+            let Some(quantity) =
+                hex.strip_prefix("0x").and_then(|digits| u128::from_str_radix(digits, 16).ok())
+            else {
+                return;
+            };
+            *hex = format!("{:#x}", quantity - quantity % step);
+        }
+        Value::Array(values) => values.iter_mut().for_each(|value| round_quantity(value, step)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(body: &str) -> HttpResponse {
+        HttpResponse { status: 200u64.into(), headers: Vec::new(), body: body.into() }
+    }
+
+    #[test]
+    fn normalizes_json_bodies() {
+        let normalized = normalize_json(response("{ \"result\": \"0x1\",\n \"id\": 1 }"));
+        assert_eq!(normalized.body, br#"{"id":1,"result":"0x1"}"#);
+        assert_eq!(normalize_json(response("not json")).body, b"not json");
+    }
+
+    #[test]
+    fn rounds_quantities_of_fields() {
+        let body = r#"[{"id":1,"result":"0x3b9aca07"},{"id":2,"result":{"baseFeePerGas":["0x10","0x25"],"oldestBlock":"0x2f"}}]"#;
+        let rounded = round_quantities(response(body), &["result", "baseFeePerGas"], 16);
+        let rounded: Value = serde_json::from_slice(&rounded.body).unwrap();
+        assert_eq!(rounded[0]["result"], "0x3b9aca00");
+        assert_eq!(rounded[1]["result"]["baseFeePerGas"], serde_json::json!(["0x10", "0x20"]));
+        assert_eq!(rounded[1]["result"]["oldestBlock"], "0x2f");
+    }
+
+    #[test]
+    fn registered_transforms_are_applied_by_name() {
+        register_http_transform("normalize", normalize_json);
+        let transformed = apply("normalize", response("{ \"id\": 1 }")).unwrap();
+        assert_eq!(transformed.body, br#"{"id":1}"#);
+        assert!(apply("missing", response("{}")).is_none());
+    }
+}
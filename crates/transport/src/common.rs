@@ -1,5 +1,5 @@
 use base64::{engine::general_purpose, Engine};
-use std::{fmt, net::SocketAddr};
+use std::fmt;
 
 /// Basic, bearer or raw authentication in http or websocket transport.
 ///
@@ -16,12 +16,13 @@ pub enum Authorization {
 
 impl Authorization {
     /// Extract the auth info from a URL.
+    #[cfg(feature = "url")]
     pub fn extract_from_url(url: &url::Url) -> Option<Self> {
         let username = url.username();
         let password = url.password().unwrap_or_default();
 
         // eliminates false positives on the authority
-        if username.contains("localhost") || username.parse::<SocketAddr>().is_ok() {
+        if username.contains("localhost") || username.parse::<std::net::SocketAddr>().is_ok() {
             return None;
         }
 
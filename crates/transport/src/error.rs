@@ -1,7 +1,7 @@
 use alloy_json_rpc::{ErrorPayload, Id, RpcError, RpcResult};
 use serde::Deserialize;
 use serde_json::value::RawValue;
-use std::{error::Error as StdError, fmt::Debug};
+use std::{error::Error as StdError, fmt::Debug, time::Duration};
 use thiserror::Error;
 
 /// A transport error is an [`RpcError`] containing a [`TransportErrorKind`].
@@ -36,6 +36,10 @@ pub enum TransportErrorKind {
     #[error("{0}")]
     HttpError(#[from] HttpError),
 
+    /// The request did not complete before its deadline.
+    #[error("request timed out after {0:?}")]
+    Timeout(Duration),
+
     /// Custom error.
     #[error("{0}")]
     Custom(#[source] Box<dyn StdError + Send + Sync + 'static>),
@@ -45,7 +49,7 @@ impl TransportErrorKind {
     /// Returns `true` if the error is potentially recoverable.
     /// This is a naive heuristic and should be used with caution.
     pub const fn recoverable(&self) -> bool {
-        matches!(self, Self::MissingBatchResponse(_))
+        matches!(self, Self::MissingBatchResponse(_) | Self::Timeout(_))
     }
 
     /// Instantiate a new `TransportError` from a custom error.
@@ -73,6 +77,11 @@ impl TransportErrorKind {
         RpcError::Transport(Self::PubsubUnavailable)
     }
 
+    /// Instantiate a new `TransportError::Timeout`.
+    pub const fn timeout(timeout: Duration) -> TransportError {
+        RpcError::Transport(Self::Timeout(timeout))
+    }
+
     /// Instantiate a new `TransportError::HttpError`.
     pub const fn http_error(status: u16, body: String) -> TransportError {
         RpcError::Transport(Self::HttpError(HttpError { status, body }))
@@ -82,8 +91,8 @@ impl TransportErrorKind {
     /// variant.
     pub fn is_retry_err(&self) -> bool {
         match self {
-            // Missing batch response errors can be retried.
-            Self::MissingBatchResponse(_) => true,
+            // Missing batch response errors and timeouts can be retried.
+            Self::MissingBatchResponse(_) | Self::Timeout(_) => true,
             Self::HttpError(http_err) => {
                 http_err.is_rate_limit_err() || http_err.is_temporarily_unavailable()
             }
@@ -2,6 +2,7 @@ use crate::{TransportError, TransportResult};
 use serde::Serialize;
 use serde_json::value::{to_raw_value, RawValue};
 use std::future::Future;
+#[cfg(feature = "url")]
 use url::Url;
 
 /// Convert to a `Box<RawValue>` from a `Serialize` type, mapping the error
@@ -18,6 +19,7 @@ where
 /// The output of this function is best-efforts, and should be checked if
 /// possible. It simply returns `true` if the connection has no hostname,
 /// or the hostname is `localhost` or `127.0.0.1`.
+#[cfg(feature = "url")]
 pub fn guess_local_url(s: impl AsRef<str>) -> bool {
     fn _guess_local_url(url: &str) -> bool {
         url.parse::<Url>().map_or(false, |url| {